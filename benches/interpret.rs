@@ -0,0 +1,40 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use bfuck::{interpret, process_code};
+use bfuck::io::VecIo;
+
+
+const HELLO_WORLD: &str = include_str!("../examples/hello-world.bf");
+const FIBONACCI: &str = include_str!("../examples/fibonacci.bf");
+const MANDELBROT: &str = include_str!("../examples/mandelbrot.bf");
+/// The Mandelbrot set at half the resolution (64 * 24 characters), a quarter of the work of [MANDELBROT].
+const MANDELBROT_SMALL: &str = include_str!("../examples/mandelbrot-small.bf");
+
+/// Copies the cell 255 * 255 times, exercising the `AddTo` and `AddToCopy` tokens.
+const ADD_TO_COPY: &str = "-[>-[>+[->+>+<<]>>[-<<+>>]<<<-]<-]";
+
+
+fn bench_program(c: &mut Criterion, name: &str, code: &str) {
+    let tokens = process_code(code).unwrap();
+    c.bench_function(name, |b| b.iter(|| interpret(&tokens, &mut VecIo::default())));
+}
+
+fn bench_interpret(c: &mut Criterion) {
+    bench_program(c, "interpret hello-world", HELLO_WORLD);
+    bench_program(c, "interpret fibonacci", FIBONACCI);
+    bench_program(c, "interpret add-to-copy", ADD_TO_COPY);
+}
+
+fn bench_interpret_mandelbrot(c: &mut Criterion) {
+    bench_program(c, "interpret mandelbrot-small", MANDELBROT_SMALL);
+    bench_program(c, "interpret mandelbrot", MANDELBROT);
+}
+
+
+criterion_group!(benches, bench_interpret);
+criterion_group! {
+    name = slow_benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_interpret_mandelbrot
+}
+criterion_main!(benches, slow_benches);
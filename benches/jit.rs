@@ -0,0 +1,69 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use bfuck::process_code;
+use bfuck::io::VecIo;
+use bfuck::jit::{JitOptions, JitProgram};
+
+
+const HELLO_WORLD: &str = include_str!("../examples/hello-world.bf");
+const FIBONACCI: &str = include_str!("../examples/fibonacci.bf");
+const MANDELBROT: &str = include_str!("../examples/mandelbrot.bf");
+/// The Mandelbrot set at half the resolution (64 * 24 characters), a quarter of the work of [MANDELBROT].
+const MANDELBROT_SMALL: &str = include_str!("../examples/mandelbrot-small.bf");
+
+/// Copies the cell 255 * 255 times, exercising the `AddTo` and `AddToCopy` tokens.
+const ADD_TO_COPY: &str = "-[>-[>+[->+>+<<]>>[-<<+>>]<<<-]<-]";
+
+/// Prints 32 * 255 * 255 newlines (about 2 MB), dominated by the output.
+const PRINT_HEAVY: &str = ">>>++++++++++<<<++++++++++++++++++++++++++++++++[>-[>-[>.<-]<-]<-]";
+
+/// Runs the loops nested 255 * 255 * 255 times after a scan for a zero cell (the data pointer range is unknown
+/// to the static analysis, but speculated by [JitProgram::compile_pgo]).
+const SCAN_THEN_LOOP: &str = "+[>]-[>-[>-[>++>+>+++<<<-]<-]<-]";
+
+
+fn bench_program(c: &mut Criterion, name: &str, code: &str) {
+    let tokens = process_code(code).unwrap();
+    let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+    c.bench_function(name, |b| b.iter(|| program.run_with_io(&mut VecIo::default())));
+}
+
+/// Benchmark the program writing to the standard output (measures the buffering of the output).
+fn bench_program_stdout(c: &mut Criterion, name: &str, code: &str) {
+    let tokens = process_code(code).unwrap();
+    let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+    c.bench_function(name, |b| b.iter(|| program.run()));
+}
+
+/// Benchmark the program recompiled with the profile of its instrumented run (see [JitProgram::compile_pgo]).
+fn bench_program_pgo(c: &mut Criterion, name: &str, code: &str) {
+    let tokens = process_code(code).unwrap();
+    let instrumented = JitProgram::compile(&tokens, &JitOptions { instrument: true, ..JitOptions::default() }).unwrap();
+    let (_, profile) = instrumented.run_instrumented(&mut VecIo::default()).unwrap();
+    let program = JitProgram::compile_pgo(&tokens, &JitOptions::default(), &profile).unwrap();
+    c.bench_function(name, |b| b.iter(|| program.run_with_io(&mut VecIo::default())));
+}
+
+fn bench_jit(c: &mut Criterion) {
+    bench_program(c, "jit hello-world", HELLO_WORLD);
+    bench_program(c, "jit fibonacci", FIBONACCI);
+    bench_program(c, "jit add-to-copy", ADD_TO_COPY);
+    bench_program_stdout(c, "jit print-heavy", PRINT_HEAVY);
+    bench_program(c, "jit scan-then-loop", SCAN_THEN_LOOP);
+    bench_program_pgo(c, "jit scan-then-loop pgo", SCAN_THEN_LOOP);
+}
+
+fn bench_jit_mandelbrot(c: &mut Criterion) {
+    bench_program(c, "jit mandelbrot-small", MANDELBROT_SMALL);
+    bench_program(c, "jit mandelbrot", MANDELBROT);
+    bench_program_pgo(c, "jit mandelbrot pgo", MANDELBROT);
+}
+
+
+criterion_group!(benches, bench_jit);
+criterion_group! {
+    name = slow_benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_jit_mandelbrot
+}
+criterion_main!(benches, slow_benches);
@@ -0,0 +1,83 @@
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+use bfuck::process_code;
+use bfuck::code::passes::{self, TokensWithLoc};
+
+
+/// Balanced snippet containing every pattern recognized by the optimization passes (and a comment).
+const MIXED_UNIT: &str = "+++++[-]>++[->>+<<]>>[->+>+<<<]<<[[-]]>,.[>+<-]< loop\n";
+
+/// The benchmarked inputs: (name, Brainfuck code).
+fn inputs() -> Vec<(&'static str, String)> {
+    vec![
+        ("mixed 100B", repeat_to_size(MIXED_UNIT, 100)),
+        ("mixed 10KB", repeat_to_size(MIXED_UNIT, 10_000)),
+        // stresses merge_adjacent, every pair of tokens cancels out
+        ("alternating 1MB", repeat_to_size("+-", 1_000_000)),
+    ]
+}
+
+/// Repeat the (balanced) unit until the code has at least `size` bytes.
+fn repeat_to_size(unit: &str, size: usize) -> String {
+    unit.repeat(size.div_ceil(unit.len()))
+}
+
+
+fn bench_process_code(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process_code");
+    for (name, code) in inputs() {
+        group.throughput(Throughput::Bytes(code.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &code, |b, code| b.iter(|| process_code(code).unwrap()));
+    }
+    group.finish();
+}
+
+fn bench_passes(c: &mut Criterion) {
+    // every pass gets the tokens in the same state as in process_code (output of the preceding passes)
+    let mut group = c.benchmark_group("passes");
+    for (name, code) in inputs() {
+        group.throughput(Throughput::Bytes(code.len() as u64));
+
+        let tokens = passes::tokenize(&code);
+        group.bench_with_input(BenchmarkId::new("merge_adjacent", name), &tokens, |b, tokens| {
+            b.iter_batched(|| tokens.clone(), passes::merge_adjacent, BatchSize::LargeInput)
+        });
+
+        let tokens = passes::merge_adjacent(tokens);
+        passes::check_loops(&tokens).unwrap();
+        bench_mut_pass(&mut group, "clear_cell", name, &tokens, passes::clear_cell);
+
+        let mut tokens = tokens;
+        passes::clear_cell(&mut tokens);
+        bench_mut_pass(&mut group, "add_to", name, &tokens, passes::add_to);
+
+        passes::add_to(&mut tokens);
+        bench_mut_pass(&mut group, "add_to_copy", name, &tokens, passes::add_to_copy);
+
+        passes::add_to_copy(&mut tokens);
+        bench_mut_pass(&mut group, "add_to_copy3", name, &tokens, passes::add_to_copy3);
+
+        passes::add_to_copy3(&mut tokens);
+        bench_mut_pass(&mut group, "consecutive_loop_elimination", name, &tokens, passes::consecutive_loop_elimination);
+
+        passes::consecutive_loop_elimination(&mut tokens);
+        bench_mut_pass(&mut group, "calculate_jumps", name, &tokens, |tokens| passes::calculate_jumps(tokens));
+    }
+    group.finish();
+}
+
+fn bench_mut_pass<M: criterion::measurement::Measurement>(
+    group: &mut criterion::BenchmarkGroup<M>,
+    pass: &str,
+    input_name: &str,
+    tokens: &TokensWithLoc,
+    pass_fn: fn(&mut TokensWithLoc),
+) {
+    group.bench_with_input(BenchmarkId::new(pass, input_name), tokens, |b, tokens| {
+        b.iter_batched_ref(|| tokens.clone(), pass_fn, BatchSize::LargeInput)
+    });
+}
+
+
+criterion_group!(benches, bench_process_code, bench_passes);
+criterion_main!(benches);
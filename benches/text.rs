@@ -0,0 +1,50 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use bfuck::process_code;
+use bfuck::interpret::interpret_profiled;
+use bfuck::io::VecIo;
+use bfuck::text::{text_2_bf, text_2_bf_huffman};
+
+
+/// The natural-language sample, repeated to 1 KB.
+const PROSE: &str = "The quick brown fox jumps over the lazy dog. \
+Brainfuck is an esoteric programming language created in 1993 by Urban Muller. \
+It consists of only eight simple commands, a data pointer and an instruction pointer. \
+While it is fully Turing complete, it is not intended for practical use, \
+but to challenge and amuse programmers.\n";
+
+/// The generator of the Brainfuck code printing the text.
+type Generator = fn(&str) -> Result<String, bfuck::Error>;
+
+/// The compared generators: (name, generator).
+const GENERATORS: [(&str, Generator); 2] = [
+    ("text_2_bf", text_2_bf),
+    ("text_2_bf_huffman", text_2_bf_huffman),
+];
+
+
+/// Repeat the text until it has exactly `size` bytes.
+fn repeat_to_size(text: &str, size: usize) -> String {
+    text.repeat(size.div_ceil(text.len()))[..size].to_string()
+}
+
+fn bench_text(c: &mut Criterion) {
+    let text = repeat_to_size(PROSE, 1_000);
+
+    // the generated code is compared by its length and by the number of the executed instructions
+    for (name, generate) in GENERATORS {
+        let bf_code = generate(&text).unwrap();
+        let executed = interpret_profiled::<true>(&process_code(&bf_code).unwrap(), &mut VecIo::default()).iter().sum::<u64>();
+        println!("{}: {} commands, {} instructions executed", name, bf_code.len(), executed);
+    }
+
+    let mut group = c.benchmark_group("text 1KB");
+    for (name, generate) in GENERATORS {
+        group.bench_function(name, |b| b.iter(|| generate(&text).unwrap()));
+    }
+    group.finish();
+}
+
+
+criterion_group!(benches, bench_text);
+criterion_main!(benches);
@@ -0,0 +1,102 @@
+//! Differential fuzzing of the interpreter and the JIT-compiler.
+//!
+//! The fuzz input is the Brainfuck code, optionally followed by a zero byte and the program input.
+//! Both backends run the processed code with the same input and their outputs and final states must match.
+//!
+//! Run with `cargo fuzz run diff_interp_jit corpus/diff_interp_jit seeds/diff_interp_jit` (from the `fuzz` directory).
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use bfuck::code::process_code_bytes;
+use bfuck::interpret::interpret;
+use bfuck::io::VecIo;
+use bfuck::jit::{JitOptions, JitProgram};
+
+
+/// The maximal nesting depth of the loops (limits the number of executed instructions).
+const MAX_DEPTH: usize = 2;
+
+
+fuzz_target!(|data: &[u8]| {
+    let (code, input) = match data.iter().position(|&byte| byte == 0) {
+        Some(i) => (&data[..i], &data[i + 1..]),
+        None => (data, &[][..]),
+    };
+
+    // programs that are not proven to terminate are skipped (both backends would loop forever)
+    if !terminates(code) {
+        return;
+    }
+    let Ok(tokens) = process_code_bytes(code) else {
+        return;
+    };
+
+    let mut interpret_io = VecIo::new(input);
+    let interpret_state = interpret(&tokens, &mut interpret_io);
+
+    let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+    let mut jit_io = VecIo::new(input);
+    let jit_state = program.run_with_io(&mut jit_io);
+
+    assert_eq!(interpret_io.output, jit_io.output, "the outputs differ");
+    assert_eq!(interpret_io.position, jit_io.position, "the consumed inputs differ");
+    assert_eq!(interpret_state, jit_state, "the final states differ");
+});
+
+
+/// Check whether the program surely terminates.
+///
+/// Every loop has to return the data pointer to its counter cell, change the counter cell only by a single
+/// decrement per iteration (no input or nested loop may touch it) and the loops can be nested at most [MAX_DEPTH] levels deep.
+/// Unmatched brackets are accepted (the program is rejected by the parser anyway).
+fn terminates(code: &[u8]) -> bool {
+    let mut commands = code.iter().copied().filter(|byte| b"+-<>,.[]".contains(byte));
+    while let Some(command) = commands.next() {
+        if command == b'[' && modified_cells(&mut commands, MAX_DEPTH).is_none() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Find the cells (offsets relative to the counter cell) modified by the loop, whose opening bracket was already consumed.
+/// # Returns
+/// * The modified cells, if the loop surely terminates.
+/// * `None` - If the loop isn't proven to terminate.
+fn modified_cells(commands: &mut impl Iterator<Item = u8>, depth: usize) -> Option<Vec<isize>> {
+    if depth == 0 {
+        return None;
+    }
+
+    let mut offset = 0_isize;
+    let mut counter_change = 0_u8;
+    let mut modified = vec![0];
+
+    while let Some(command) = commands.next() {
+        match command {
+            b'+' | b'-' if offset == 0 => {
+                counter_change = counter_change.wrapping_add(if command == b'+' { 1 } else { u8::MAX });
+            },
+            b'+' | b'-' => modified.push(offset),
+            b'>' => offset += 1,
+            b'<' => offset -= 1,
+            b',' if offset == 0 => return None,
+            b',' => modified.push(offset),
+            b'[' => {
+                for cell in modified_cells(commands, depth - 1)? {
+                    if offset + cell == 0 {
+                        return None;
+                    }
+                    modified.push(offset + cell);
+                }
+            },
+            b']' => return (offset == 0 && counter_change == u8::MAX).then_some(modified),
+            _ => {},
+        }
+    }
+
+    // unmatched open bracket
+    Some(modified)
+}
@@ -0,0 +1,50 @@
+//! Fuzzing of the Brainfuck parser and optimizer.
+//!
+//! Processing any input may fail, but it must never panic. Valid UTF-8 inputs are processed as text
+//! (and must give the same result as processing them as bytes, unless they contain non-ASCII characters).
+//!
+//! Run with `cargo fuzz run process_code corpus/process_code seeds/process_code` (from the `fuzz` directory).
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use bfuck::code::{process_code, process_code_bytes, process_code_with_stats, tokens_to_bf, Token};
+
+
+fuzz_target!(|data: &[u8]| {
+    let bytes_result = process_code_bytes(data);
+
+    if let Ok(code) = std::str::from_utf8(data) {
+        let text_result = process_code(code);
+        if code.is_ascii() {
+            assert_eq!(text_result, bytes_result, "processing as text and as bytes differs");
+        }
+
+        if let Ok(tokens) = &text_result {
+            let (stats_tokens, stats) = process_code_with_stats(code).unwrap();
+            assert_eq!(&stats_tokens, tokens);
+            assert_eq!(stats.optimized_token_count, tokens.len());
+        }
+    }
+
+    if let Ok(tokens) = bytes_result {
+        check_jumps(&tokens);
+
+        // processing the regenerated code must succeed (the brackets are still balanced)
+        let regenerated = process_code(&tokens_to_bf(&tokens)).unwrap();
+        check_jumps(&regenerated);
+    }
+});
+
+
+/// Check that the jump distances of the brackets point to the matching brackets.
+fn check_jumps(tokens: &[Token]) {
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            Token::OpenBr(jmp) => assert_eq!(tokens[i + jmp], Token::CloseBr(jmp)),
+            Token::CloseBr(jmp) => assert_eq!(tokens[i - jmp], Token::OpenBr(jmp)),
+            _ => {},
+        }
+    }
+}
@@ -2,7 +2,9 @@
 
 
 
-use crate::error::Error;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::{Error, Warning};
 
 
 
@@ -12,8 +14,68 @@ pub const STORAGE_SIZE: usize = 30_000;
 /// The processed Brainfuck code.
 pub type TokenStream = Vec<Token>;
 
+/// The configuration of the Brainfuck parser.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ParseConfig {
+    /// The character that is parsed as [Token::Debug].
+    ///
+    /// `None` (default) means that there is no debug instruction, so the code is parsed as standard Brainfuck.
+    /// Usually set to `Some('#')`.
+    pub debug_instruction: Option<char>,
+}
+
+/// The statistics collected while processing the Brainfuck code.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ParseStatistics {
+    /// The number of tokens before any optimization (the number of Brainfuck commands in the code).
+    pub raw_token_count: usize,
+    /// The number of tokens after all optimizations.
+    pub optimized_token_count: usize,
+    /// The number of tokens of each type (e.g. `"Add"`, `"ClearCell"`) after all optimizations.
+    pub token_counts: HashMap<&'static str, usize>,
+    /// The number of clear cell patterns (`[-]`) found (including the ones followed by an addition, `[-]+++`).
+    pub clear_cells_found: usize,
+    /// The number of add to patterns (`[->>+<<]`) found.
+    pub add_to_found: usize,
+    /// The warnings about the suspicious code (in the order of the code).
+    pub warnings: Vec<Warning>,
+    /// The location (line and column) in the code of each token (the first command of the optimized pattern),
+    /// used to report the errors of the running program (see [JitProgram::with_locations](crate::jit::JitProgram::with_locations)).
+    pub locations: Vec<(usize, usize)>,
+}
+
+/// The state of the Brainfuck program after it finished.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FinalState {
+    /// The final position of the data pointer.
+    pub data_ptr: usize,
+    /// The value of the current cell (conventionally the "result" of the program).
+    pub cell: u8,
+}
+
+/// The statistics collected while executing the Brainfuck program
+/// (by [interpret_with_stats](crate::interpret::interpret_with_stats) and [JitProgram::run_instrumented](crate::jit::JitProgram::run_instrumented)).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExecStats {
+    /// The total number of jumps back to the start of a loop.
+    pub back_edges: u64,
+    /// The number of iterations of each loop which was entered, by the index of its [Token::OpenBr].
+    pub loop_iterations: BTreeMap<usize, u64>,
+    /// The number of bytes read (the reads past the end of input are not counted).
+    pub bytes_read: u64,
+    /// The number of bytes written.
+    pub bytes_written: u64,
+}
+
 /// The enum representing a parsed Brainfuck command.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+///
+/// Implements [Hash], so tokens can be counted in a [HashMap]
+/// and a whole [TokenStream] can be hashed (e.g. as a cache key).
+///
+/// Tokens are ordered by the variant (in the order of declaration) and then by the payload,
+/// so they can be sorted or used as keys of a [BTreeMap].
+/// The order of the variants must be kept when adding new ones.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Token {
     /// *Addition*
     ///
@@ -21,7 +83,7 @@ pub enum Token {
     ///
     /// Subtraction is represented as ```Add(255)``` since `n - 1 = n + 255 (mod 256)`.
     ///
-    /// Adjacent additions are merged.
+    /// Adjacent additions are merged (modulo 256, except by [MergeAdjacentWide]).
     Add(u8),
 
     /// *Move*
@@ -81,11 +143,201 @@ pub enum Token {
     ///
     /// The current cell is set to 0.
     AddToCopy(usize, usize),
+
+    /// *Debug*
+    ///
+    /// Print the data pointer and the value of the current cell to the `stderr`.
+    ///
+    /// Only generated if [ParseConfig::debug_instruction] is set.
+    Debug,
+
+    /// *Set cell*
+    ///
+    /// Set the current cell in the array to the value (`u8`).
+    ///
+    /// The same as [Token::ClearCell] followed by [Token::Add] (with the wider cells the value is the change as `i8`).
+    SetCell(u8),
+
+    /// *Add to copy 3*
+    ///
+    /// Add the value of the current cell to the cells at the given distances (one after another, so a repeated
+    /// distance receives the value twice).
+    /// Negative direction is represented the same as in [Token::Move].
+    ///
+    /// The same as [Token::AddToCopy], but adds to 3 cells. Loops with more destinations (or other multipliers)
+    /// are left as they are, they would need a general linear combination token.
+    ///
+    /// The current cell is set to 0.
+    AddToCopy3(usize, usize, usize),
+}
+impl Token {
+    /// The number of the variants of [Token] (the length of the array indexed by [Token::variant_index]).
+    pub const VARIANT_COUNT: usize = 12;
+
+    /// The index of the variant of the token (in the order of declaration, the payload is ignored).
+    /// # Returns
+    /// * `usize` - The index of the variant, less than [Token::VARIANT_COUNT].
+    /// # Example
+    /// ```
+    /// use bfuck::code::Token;
+    ///
+    /// assert_eq!(Token::Add(3).variant_index(), Token::Add(255).variant_index());
+    /// assert_eq!(Token::Output.variant_index(), 3);
+    /// ```
+    pub fn variant_index(&self) -> usize {
+        match self {
+            Token::Add(_) => 0,
+            Token::Move(_) => 1,
+            Token::Input => 2,
+            Token::Output => 3,
+            Token::OpenBr(_) => 4,
+            Token::CloseBr(_) => 5,
+            Token::ClearCell => 6,
+            Token::AddTo(_) => 7,
+            Token::AddToCopy(_, _) => 8,
+            Token::Debug => 9,
+            Token::SetCell(_) => 10,
+            Token::AddToCopy3(_, _, _) => 11,
+        }
+    }
+}
+
+/// An optimization pass run by [process_code_with_pipeline].
+///
+/// The pass works on the tokens with their locations (line and column) in the original code.
+/// It must keep the program equivalent and the brackets matched (their distances are calculated after all passes,
+/// so the [Token::OpenBr] and [Token::CloseBr] payloads are not valid yet).
+pub trait OptimizationPass {
+    /// Run the pass.
+    /// # Arguments
+    /// `tokens` - A mutable vector of tokens with their locations (line and column) in the original code.
+    fn run(&self, tokens: &mut Vec<(Token, usize, usize)>);
+}
+
+/// The optimization pass merging the adjacent additions and moves (see [process_code]).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MergeAdjacent;
+impl OptimizationPass for MergeAdjacent {
+    fn run(&self, tokens: &mut Vec<(Token, usize, usize)>) {
+        *tokens = merge_adjacent(std::mem::take(tokens));
+    }
+}
+
+/// The optimization pass merging the adjacent additions and moves like [MergeAdjacent], but the additions only while
+/// their sum stays in the range `-128..=127` (the value as `i8`), so each [Token::Add] is the exact change of the cell
+/// also for the cells wider than 8 bits (see [OptimizationPipeline::wide_cells]).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MergeAdjacentWide;
+impl OptimizationPass for MergeAdjacentWide {
+    fn run(&self, tokens: &mut Vec<(Token, usize, usize)>) {
+        *tokens = merge_adjacent_with(std::mem::take(tokens), true);
+    }
+}
+
+/// The optimization pass replacing the clear cell loops (`[-]`) with [Token::ClearCell] or [Token::SetCell].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ClearCell;
+impl OptimizationPass for ClearCell {
+    fn run(&self, tokens: &mut Vec<(Token, usize, usize)>) {
+        clear_cell(tokens);
+    }
+}
+
+/// The optimization pass replacing the add to loops (`[->>+<<]`) with [Token::AddTo].
+///
+/// Matches only the merged additions and moves, so it should run after [MergeAdjacent].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct AddTo;
+impl OptimizationPass for AddTo {
+    fn run(&self, tokens: &mut Vec<(Token, usize, usize)>) {
+        add_to(tokens);
+    }
+}
+
+/// The optimization pass replacing the add to copy loops (`[->>+>+<<<]`) with [Token::AddToCopy].
+///
+/// Matches only the merged additions and moves, so it should run after [MergeAdjacent].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct AddToCopy;
+impl OptimizationPass for AddToCopy {
+    fn run(&self, tokens: &mut Vec<(Token, usize, usize)>) {
+        add_to_copy(tokens);
+    }
+}
+
+/// The optimization pass replacing the add to copy loops with 3 destinations (`[->>+>+>+<<<<]`) with [Token::AddToCopy3].
+///
+/// Matches only the merged additions and moves, so it should run after [MergeAdjacent].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct AddToCopy3;
+impl OptimizationPass for AddToCopy3 {
+    fn run(&self, tokens: &mut Vec<(Token, usize, usize)>) {
+        add_to_copy3(tokens);
+    }
+}
+
+/// The optimization pass removing the loops which never run, because they follow another loop (`[-][-]`, see [process_code]).
+///
+/// Matches the loops already replaced by [ClearCell], [AddTo], [AddToCopy] and [AddToCopy3] too, so it should run after them.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ConsecutiveLoopElimination;
+impl OptimizationPass for ConsecutiveLoopElimination {
+    fn run(&self, tokens: &mut Vec<(Token, usize, usize)>) {
+        consecutive_loop_elimination(tokens);
+    }
+}
+
+/// The sequence of the optimization passes run by [process_code_with_pipeline].
+///
+/// The default pipeline runs the passes of [process_code]: [MergeAdjacent], [ClearCell], [AddTo], [AddToCopy],
+/// [AddToCopy3] and [ConsecutiveLoopElimination]. The passes can be reordered, left out, repeated or mixed with custom [OptimizationPass]es.
+///
+/// Checking the loops and calculating the jumps are not passes: the brackets are always checked
+/// before the first pass and the jumps are always calculated after the last one.
+pub struct OptimizationPipeline {
+    /// The passes in the order in which they are run.
+    pub passes: Vec<Box<dyn OptimizationPass>>,
+}
+impl Default for OptimizationPipeline {
+    fn default() -> Self {
+        Self {
+            passes: vec![
+                Box::new(MergeAdjacent),
+                Box::new(ClearCell),
+                Box::new(AddTo),
+                Box::new(AddToCopy),
+                Box::new(AddToCopy3),
+                Box::new(ConsecutiveLoopElimination),
+            ],
+        }
+    }
+}
+impl OptimizationPipeline {
+    /// Create the pipeline for the cells wider than 8 bits (see [JitOptions::cell_width](crate::jit::JitOptions::cell_width)),
+    /// the default passes with [MergeAdjacentWide] instead of [MergeAdjacent].
+    ///
+    /// The default merging adds the values modulo 256 (e.g. 256 `+` commands are removed), which is exact only for the 8-bit cells.
+    /// # Returns
+    /// * [OptimizationPipeline] - The pipeline.
+    /// # Example
+    /// ```
+    /// use bfuck::code::{process_code, process_code_with_pipeline, OptimizationPipeline, Token};
+    ///
+    /// let code = "+".repeat(300);
+    /// assert_eq!(process_code(&code).unwrap(), vec![Token::Add(44)]);
+    /// assert_eq!(process_code_with_pipeline(&code, &OptimizationPipeline::wide_cells()).unwrap(), vec![Token::Add(127), Token::Add(127), Token::Add(46)]);
+    /// ```
+    pub fn wide_cells() -> Self {
+        let mut pipeline = Self::default();
+        pipeline.passes[0] = Box::new(MergeAdjacentWide);
+        pipeline
+    }
 }
 
 
 
 /// Process raw Brainfuck code into token stream.
+/// The byte order mark (`U+FEFF`) at the start of the code is skipped, so it doesn't shift the columns of the first line.
 /// # Arguments
 /// `code` - A string slice that holds the Brainfuck code.
 /// # Returns
@@ -118,32 +370,557 @@ pub enum Token {
 /// ]);
 /// ```
 pub fn process_code(code: &str) -> Result<TokenStream, Error> {
+    process_code_with_stats(code).map(|(tokens, _)| tokens)
+}
+
+/// Process raw Brainfuck code into token stream, and collect the statistics about the processing.
+/// # Arguments
+/// `code` - A string slice that holds the Brainfuck code.
+/// # Returns
+/// * ([TokenStream], [ParseStatistics]) - The generated token stream and the statistics, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnmatchedOpenBr(usize, usize)` - There is an unmatched open bracket at the given line and column.
+/// * `UnmatchedCloseBr(usize, usize)` - There is an unmatched close bracket at the given line and column.
+/// # Example
+/// ```
+/// use bfuck::code::process_code_with_stats;
+///
+/// let (tokens, stats) = process_code_with_stats("++[-]>").unwrap();
+///
+/// assert_eq!(stats.raw_token_count, 6);
+/// assert_eq!(stats.optimized_token_count, tokens.len());
+/// assert_eq!(stats.token_counts["ClearCell"], 1);
+/// assert_eq!(stats.clear_cells_found, 1);
+/// ```
+pub fn process_code_with_stats(code: &str) -> Result<(TokenStream, ParseStatistics), Error> {
+    process_code_with_config_and_stats(code, &ParseConfig::default())
+}
+
+/// Process raw Brainfuck code into token stream, using the given parser configuration.
+/// # Arguments
+/// `code` - A string slice that holds the Brainfuck code.
+/// `config` - The [ParseConfig] used for parsing.
+/// # Returns
+/// * [TokenStream] - The generated token stream, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnmatchedOpenBr(usize, usize)` - There is an unmatched open bracket at the given line and column.
+/// * `UnmatchedCloseBr(usize, usize)` - There is an unmatched close bracket at the given line and column.
+/// # Example
+/// ```
+/// use bfuck::code::{process_code_with_config, ParseConfig, Token};
+///
+/// let config = ParseConfig { debug_instruction: Some('#') };
+/// let tokens = process_code_with_config("+#.", &config).unwrap();
+///
+/// assert_eq!(tokens, vec![Token::Add(1), Token::Debug, Token::Output]);
+/// ```
+pub fn process_code_with_config(code: &str, config: &ParseConfig) -> Result<TokenStream, Error> {
+    process_code_with_config_and_stats(code, config).map(|(tokens, _)| tokens)
+}
+
+/// Process raw Brainfuck code into token stream, using the given parser configuration,
+/// and collect the statistics of the processing (see [process_code_with_stats]).
+/// # Arguments
+/// `code` - A string slice that holds the Brainfuck code.
+/// `config` - The [ParseConfig] used for parsing.
+/// # Returns
+/// * ([TokenStream], [ParseStatistics]) - The generated token stream and the statistics, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnmatchedOpenBr(usize, usize)` - There is an unmatched open bracket at the given line and column.
+/// * `UnmatchedCloseBr(usize, usize)` - There is an unmatched close bracket at the given line and column.
+/// # Example
+/// ```
+/// use bfuck::code::{process_code_with_config_and_stats, ParseConfig, Token};
+/// use bfuck::error::Warning;
+///
+/// let config = ParseConfig { debug_instruction: Some('#') };
+/// let (tokens, stats) = process_code_with_config_and_stats("+[#]", &config).unwrap();
+///
+/// assert_eq!(tokens, vec![Token::Add(1), Token::OpenBr(2), Token::Debug, Token::CloseBr(2)]);
+/// assert_eq!(stats.warnings, vec![Warning::TriviallyInfinite(1, 2)]);
+/// ```
+pub fn process_code_with_config_and_stats(code: &str, config: &ParseConfig) -> Result<(TokenStream, ParseStatistics), Error> {
+    process_tokens(tokenize_str(code, config))
+}
+
+/// Process raw Brainfuck code, given as bytes, into token stream.
+/// Useful for the source files which are not valid UTF-8 (e.g. Latin-1 encoded).
+/// Only the printable ASCII bytes (`0x20` - `0x7E`) can be Brainfuck commands,
+/// all other bytes are treated as comments. Columns are counted in bytes,
+/// after the UTF-8 byte order mark (`EF BB BF`) at the start of the code, if any.
+/// # Arguments
+/// `code` - A byte slice that holds the Brainfuck code.
+/// # Returns
+/// * [TokenStream] - The generated token stream, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnmatchedOpenBr(usize, usize)` - There is an unmatched open bracket at the given line and column.
+/// * `UnmatchedCloseBr(usize, usize)` - There is an unmatched close bracket at the given line and column.
+/// # Example
+/// ```
+/// use bfuck::code::{process_code_bytes, Token};
+///
+/// // "+ñ." encoded in Latin-1
+/// let tokens = process_code_bytes(b"+\xF1.").unwrap();
+///
+/// assert_eq!(tokens, vec![Token::Add(1), Token::Output]);
+/// ```
+pub fn process_code_bytes(code: &[u8]) -> Result<TokenStream, Error> {
+    process_code_bytes_with_config(code, &ParseConfig::default())
+}
+
+/// Process raw Brainfuck code, given as bytes, into token stream, using the given parser configuration.
+/// See [process_code_bytes] for details.
+/// # Arguments
+/// `code` - A byte slice that holds the Brainfuck code.
+/// `config` - The [ParseConfig] used for parsing.
+/// # Returns
+/// * [TokenStream] - The generated token stream, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnmatchedOpenBr(usize, usize)` - There is an unmatched open bracket at the given line and column.
+/// * `UnmatchedCloseBr(usize, usize)` - There is an unmatched close bracket at the given line and column.
+pub fn process_code_bytes_with_config(code: &[u8], config: &ParseConfig) -> Result<TokenStream, Error> {
+    process_code_bytes_with_config_and_stats(code, config).map(|(tokens, _)| tokens)
+}
+
+/// Process raw Brainfuck code, given as bytes, into token stream, using the given parser configuration,
+/// and collect the statistics of the processing. See [process_code_bytes] for details.
+/// # Arguments
+/// `code` - A byte slice that holds the Brainfuck code.
+/// `config` - The [ParseConfig] used for parsing.
+/// # Returns
+/// * ([TokenStream], [ParseStatistics]) - The generated token stream and the statistics, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnmatchedOpenBr(usize, usize)` - There is an unmatched open bracket at the given line and column.
+/// * `UnmatchedCloseBr(usize, usize)` - There is an unmatched close bracket at the given line and column.
+pub fn process_code_bytes_with_config_and_stats(code: &[u8], config: &ParseConfig) -> Result<(TokenStream, ParseStatistics), Error> {
 
     // vector of tokens with their locations (line and column) in the original brainfuck code
     let mut tokens_with_loc = Vec::new();
 
+    // skip the byte order mark
+    let code = code.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(code);
+
     // generate tokens from brainfuck code
+    for (i, line) in code.split(|&byte| byte == b'\n').enumerate() {
+        for (j, &byte) in line.iter().enumerate() {
+            // non-printable and non-ASCII bytes are always comments
+            if !(0x20..=0x7E).contains(&byte) {
+                continue;
+            }
+            if let Some(token) = tokenize(byte as char, config) {
+                tokens_with_loc.push((token, i + 1, j + 1));
+            }
+        }
+    }
+
+    process_tokens(tokens_with_loc)
+}
+
+/// Process raw Brainfuck code into token stream, running the optimization passes of the given pipeline.
+/// The loops are always checked before the first pass (so the errors don't depend on the passes)
+/// and the jumps are always calculated after the last pass.
+/// # Arguments
+/// `code` - A string slice that holds the Brainfuck code.
+/// `pipeline` - The [OptimizationPipeline] to run.
+/// # Returns
+/// * [TokenStream] - The generated token stream, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnmatchedOpenBr(usize, usize)` - There is an unmatched open bracket at the given line and column.
+/// * `UnmatchedCloseBr(usize, usize)` - There is an unmatched close bracket at the given line and column.
+/// # Panics
+/// If a pass leaves the brackets unmatched.
+/// # Example
+/// ```
+/// use bfuck::code::{process_code, process_code_with_pipeline, AddTo, MergeAdjacent, OptimizationPipeline, Token};
+///
+/// // only merge the adjacent commands and detect the add to loops
+/// let pipeline = OptimizationPipeline { passes: vec![Box::new(MergeAdjacent), Box::new(AddTo)] };
+/// let tokens = process_code_with_pipeline("[-]>>[->+<]", &pipeline).unwrap();
+/// assert_eq!(tokens, vec![Token::OpenBr(2), Token::Add(u8::MAX), Token::CloseBr(2), Token::Move(2), Token::AddTo(1)]);
+///
+/// // the default pipeline is the same as process_code
+/// let tokens = process_code_with_pipeline("[-]>>[->+<]", &OptimizationPipeline::default()).unwrap();
+/// assert_eq!(tokens, process_code("[-]>>[->+<]").unwrap());
+/// ```
+pub fn process_code_with_pipeline(code: &str, pipeline: &OptimizationPipeline) -> Result<TokenStream, Error> {
+    let mut tokens_with_loc = tokenize_str(code, &ParseConfig::default());
+
+    // check whether the loops are correct
+    check_loops(&tokens_with_loc)?;
+
+    for pass in &pipeline.passes {
+        pass.run(&mut tokens_with_loc);
+    }
+    assert!(check_loops(&tokens_with_loc).is_ok(), "An optimization pass left unmatched brackets.");
+
+    // calculate the distances for the open and close brackets (used in interpreter for jumps)
+    calculate_jumps(&mut tokens_with_loc);
+
+    Ok(tokens_with_loc.into_iter().map(|(token, _, _)| token).collect())
+}
+
+/// Convert the tokens back to Brainfuck code.
+/// The generated code is equivalent to the tokens (processing it results in the same tokens, up to the optimizations).
+/// [Token::Debug] is converted to `#`.
+/// # Arguments
+/// * `tokens` - The tokens to convert.
+/// # Returns
+/// * [String] - The Brainfuck code.
+/// # Example
+/// ```
+/// use bfuck::code::{process_code, tokens_to_bf};
+///
+/// let tokens = process_code("+++[->>+<<] comment").unwrap();
+/// assert_eq!(tokens_to_bf(&tokens), "+++[->>+<<]");
+/// ```
+pub fn tokens_to_bf(tokens: &[Token]) -> String {
+    let mut code = String::new();
+
+    for token in tokens {
+        match *token {
+            Token::Add(n) => push_add(&mut code, n),
+            Token::Move(n) => push_move(&mut code, n),
+            Token::Input => code.push(','),
+            Token::Output => code.push('.'),
+            Token::OpenBr(_) => code.push('['),
+            Token::CloseBr(_) => code.push(']'),
+            Token::ClearCell => code.push_str("[-]"),
+            Token::AddTo(n) => {
+                code.push_str("[-");
+                push_move(&mut code, n);
+                code.push('+');
+                push_move(&mut code, STORAGE_SIZE - n);
+                code.push(']');
+            },
+            Token::AddToCopy(n1, n2) => {
+                code.push_str("[-");
+                push_move(&mut code, n1);
+                code.push('+');
+                push_move(&mut code, (n2 + STORAGE_SIZE - n1) % STORAGE_SIZE);
+                code.push('+');
+                push_move(&mut code, STORAGE_SIZE - n2);
+                code.push(']');
+            },
+            Token::Debug => code.push('#'),
+            Token::SetCell(n) => {
+                code.push_str("[-]");
+                push_add(&mut code, n);
+            },
+            Token::AddToCopy3(n1, n2, n3) => {
+                code.push_str("[-");
+                push_move(&mut code, n1);
+                code.push('+');
+                push_move(&mut code, (n2 + STORAGE_SIZE - n1) % STORAGE_SIZE);
+                code.push('+');
+                push_move(&mut code, (n3 + STORAGE_SIZE - n2) % STORAGE_SIZE);
+                code.push('+');
+                push_move(&mut code, STORAGE_SIZE - n3);
+                code.push(']');
+            },
+        }
+    }
+
+    code
+}
+
+/// Append the shortest Brainfuck code for the addition to the code.
+/// # Arguments
+/// `code` - The code to append to.
+/// `n` - The added value.
+fn push_add(code: &mut String, n: u8) {
+    if n <= 128 {
+        code.extend(std::iter::repeat_n('+', n as usize));
+    } else {
+        code.extend(std::iter::repeat_n('-', 256 - n as usize));
+    }
+}
+
+/// Append the shortest Brainfuck code for the move to the code.
+/// # Arguments
+/// `code` - The code to append to.
+/// `n` - The move distance (modulo [STORAGE_SIZE]).
+fn push_move(code: &mut String, n: usize) {
+    let n = n % STORAGE_SIZE;
+    if n <= STORAGE_SIZE / 2 {
+        code.extend(std::iter::repeat_n('>', n));
+    } else {
+        code.extend(std::iter::repeat_n('<', STORAGE_SIZE - n));
+    }
+}
+
+/// Convert the move distance of the token to the signed distance (the distances above `STORAGE_SIZE / 2` are negative).
+/// # Arguments
+/// `n` - The move distance (modulo [STORAGE_SIZE]).
+/// # Returns
+/// * `isize` - The signed distance.
+pub(crate) fn signed_distance(n: usize) -> isize {
+    if n <= STORAGE_SIZE / 2 {
+        n as isize
+    } else {
+        -((STORAGE_SIZE - n) as isize)
+    }
+}
+
+/// Run-length encode the tokens.
+/// Consecutive identical tokens are grouped together with their count.
+/// Since adjacent [Token::Add] and [Token::Move] tokens are already merged by [process_code],
+/// this mostly compresses the repeated [Token::Input], [Token::Output] and [Token::ClearCell] tokens.
+/// # Arguments
+/// * `tokens` - The tokens to encode.
+/// # Returns
+/// * `Vec<(Token, usize)>` - The runs of identical tokens and their lengths (always at least `1`).
+/// # Example
+/// ```
+/// use bfuck::code::{process_code, rle_decode, rle_encode, Token};
+///
+/// let tokens = process_code("+...>,,").unwrap();
+/// let rle = rle_encode(&tokens);
+/// assert_eq!(rle, vec![(Token::Add(1), 1), (Token::Output, 3), (Token::Move(1), 1), (Token::Input, 2)]);
+/// assert_eq!(rle_decode(&rle), tokens);
+/// ```
+pub fn rle_encode(tokens: &[Token]) -> Vec<(Token, usize)> {
+    let mut rle: Vec<(Token, usize)> = Vec::new();
+    for &token in tokens {
+        match rle.last_mut() {
+            Some((last, count)) if *last == token => *count += 1,
+            _ => rle.push((token, 1)),
+        }
+    }
+    rle
+}
+
+/// Decode the run-length encoded tokens (the inverse of [rle_encode]).
+/// # Arguments
+/// * `rle` - The runs of identical tokens and their lengths.
+/// # Returns
+/// * [TokenStream] - The decoded tokens.
+pub fn rle_decode(rle: &[(Token, usize)]) -> TokenStream {
+    rle.iter()
+        .flat_map(|&(token, count)| std::iter::repeat_n(token, count))
+        .collect()
+}
+
+/// Split the tokens into the top-level segments.
+/// Each segment is either a single token outside of the loops, or a complete top-level loop
+/// (from its [Token::OpenBr] to its [Token::CloseBr], including the nested loops).
+/// The jumps are relative, so they remain valid within the segments.
+/// # Arguments
+/// * `tokens` - The tokens to split (with matched brackets, e.g. the output of [process_code]).
+/// # Returns
+/// * `Vec<TokenStream>` - The segments, whose concatenation equals the tokens.
+/// # Example
+/// ```
+/// use bfuck::code::{process_code, split_at_loops, Token};
+///
+/// let tokens = process_code("++[>.<-].").unwrap();
+/// assert_eq!(split_at_loops(&tokens), vec![
+///     vec![Token::Add(2)],
+///     vec![Token::OpenBr(5), Token::Move(1), Token::Output, Token::Move(29_999), Token::Add(255), Token::CloseBr(5)],
+///     vec![Token::Output],
+/// ]);
+/// ```
+pub fn split_at_loops(tokens: &[Token]) -> Vec<TokenStream> {
+    let mut segments = Vec::new();
+    let mut depth = 0_usize;
+    let mut loop_start = 0;
+    for (i, &token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenBr(_) => {
+                if depth == 0 {
+                    loop_start = i;
+                }
+                depth += 1;
+            },
+            Token::CloseBr(_) => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    segments.push(tokens[loop_start..=i].to_vec());
+                }
+            },
+            _ if depth == 0 => segments.push(vec![token]),
+            _ => {},
+        }
+    }
+
+    // unmatched open bracket, the rest of the tokens is one segment
+    if depth > 0 {
+        segments.push(tokens[loop_start..].to_vec());
+    }
+
+    segments
+}
+
+/// Replace the token at the given index with the replacement tokens.
+/// The replacement may be empty (deletes the token) or contain multiple tokens (expands the token).
+/// The jump distances of all brackets are recalculated after the replacement.
+/// # Arguments
+/// * `tokens` - The tokens to patch.
+/// * `index` - The index of the replaced token.
+/// * `replacement` - The tokens inserted in place of the replaced token (their jump distances are ignored).
+/// # Returns
+/// * [TokenStream] - The patched tokens.
+/// # Panics
+/// * If `index` is out of bounds.
+/// * If the brackets of the patched tokens are unmatched.
+/// # Example
+/// ```
+/// use bfuck::code::{patch, Token};
+///
+/// let tokens = vec![Token::OpenBr(2), Token::ClearCell, Token::CloseBr(2)];
+/// assert_eq!(
+///     patch(&tokens, 1, &[Token::Add(5), Token::Output]),
+///     vec![Token::OpenBr(3), Token::Add(5), Token::Output, Token::CloseBr(3)],
+/// );
+/// ```
+pub fn patch(tokens: &[Token], index: usize, replacement: &[Token]) -> TokenStream {
+    assert!(index < tokens.len(), "The index {} is out of bounds for {} tokens.", index, tokens.len());
+
+    let mut patched: Vec<(Token, usize, usize)> = tokens[..index].iter()
+        .chain(replacement)
+        .chain(&tokens[index + 1..])
+        .map(|&token| (token, 0, 0))
+        .collect();
+    assert!(check_loops(&patched).is_ok(), "The patched tokens have unmatched brackets.");
+
+    calculate_jumps(&mut patched);
+    patched.into_iter().map(|(token, _, _)| token).collect()
+}
+
+/// Normalize the tokens to the canonical form, so that the equivalent programs written differently have the same tokens.
+///
+/// The rules are applied until none of them matches:
+/// * the adjacent [Token::Add] tokens are merged, and so are the adjacent [Token::Move] tokens,
+/// * `Add(0)` and `Move(0)` are removed,
+/// * [Token::ClearCell] immediately followed by another [Token::ClearCell] is collapsed to one,
+/// * `SetCell(0)` is replaced with [Token::ClearCell],
+/// * the empty loop (`OpenBr` immediately followed by `CloseBr`) is removed where the current cell is known to be zero
+///   (at the start of the program, after [Token::ClearCell] and after another loop). Elsewhere the empty loop never
+///   terminates if the cell is not zero, so removing it would change the behavior.
+///
+/// Unlike [process_code], the additions are merged regardless of their sum (`Add(127)` and `Add(10)` become `Add(137)`).
+/// The normalized tokens behave the same as the original ones.
+/// # Arguments
+/// * `token_stream` - The tokens to normalize (with matched brackets, e.g. the output of [process_code]).
+/// # Returns
+/// * [TokenStream] - The normalized tokens.
+/// # Example
+/// ```
+/// use bfuck::code::{normalize, process_code, Token};
+///
+/// assert_eq!(normalize(process_code("++++----").unwrap()), vec![]);
+/// assert_eq!(normalize(process_code("[-][-]").unwrap()), vec![Token::ClearCell]);
+/// assert_eq!(normalize(vec![Token::Add(200), Token::Add(56), Token::SetCell(0), Token::OpenBr(1), Token::CloseBr(1)]), vec![Token::ClearCell]);
+/// ```
+pub fn normalize(token_stream: TokenStream) -> TokenStream {
+    let mut normalized: Vec<(Token, usize, usize)> = Vec::with_capacity(token_stream.len());
+    for token in token_stream {
+        let last = normalized.last().map(|&(last, _, _)| last);
+        match (last, token) {
+            (Some(Token::Add(m)), Token::Add(n)) => {
+                normalized.pop();
+                if m.wrapping_add(n) != 0 {
+                    normalized.push((Token::Add(m.wrapping_add(n)), 0, 0));
+                }
+            },
+            (Some(Token::Move(m)), Token::Move(n)) => {
+                normalized.pop();
+                if (m + n) % STORAGE_SIZE != 0 {
+                    normalized.push((Token::Move((m + n) % STORAGE_SIZE), 0, 0));
+                }
+            },
+            (_, Token::Add(0) | Token::Move(0)) => {},
+            (Some(Token::ClearCell), Token::ClearCell | Token::SetCell(0)) => {},
+            (_, Token::SetCell(0)) => normalized.push((Token::ClearCell, 0, 0)),
+            (Some(Token::OpenBr(_)), Token::CloseBr(_)) if cell_known_zero(&normalized[..normalized.len() - 1]) => {
+                normalized.pop();
+            },
+            _ => normalized.push((token, 0, 0)),
+        }
+    }
+
+    calculate_jumps(&mut normalized);
+    normalized.into_iter().map(|(token, _, _)| token).collect()
+}
+
+/// Check whether the current cell is known to be zero after the normalized tokens (see [normalize]).
+/// # Arguments
+/// * `tokens` - The normalized tokens.
+/// # Returns
+/// * `bool` - `true` if the current cell is surely zero.
+fn cell_known_zero(tokens: &[(Token, usize, usize)]) -> bool {
+    matches!(tokens.last(), None | Some((Token::ClearCell | Token::CloseBr(_), _, _)))
+}
+
+/// Generate tokens with their locations (line and column) from Brainfuck code
+/// (the byte order mark at the start of the code is skipped).
+/// # Arguments
+/// `code` - A string slice that holds the Brainfuck code.
+/// `config` - The [ParseConfig] used for parsing.
+/// # Returns
+/// * Vec<([Token], usize, usize)> - The generated tokens with their locations.
+fn tokenize_str(code: &str, config: &ParseConfig) -> Vec<(Token, usize, usize)> {
+    let mut tokens_with_loc = Vec::new();
+    let code = code.strip_prefix('\u{FEFF}').unwrap_or(code);
+
     for (i, line) in code.lines().enumerate() {
         for (j, character) in line.chars().enumerate() {
-            match character {
-                '+' => tokens_with_loc.push((Token::Add(1), i + 1, j + 1)),
-                '-' => tokens_with_loc.push((Token::Add(u8::MAX), i + 1, j + 1)),
-                '<' => tokens_with_loc.push((Token::Move(STORAGE_SIZE - 1), i + 1, j + 1)),
-                '>' => tokens_with_loc.push((Token::Move(1), i + 1, j + 1)),
-                ',' => tokens_with_loc.push((Token::Input, i + 1, j + 1)),
-                '.' => tokens_with_loc.push((Token::Output, i + 1, j + 1)),
-                '[' => tokens_with_loc.push((Token::OpenBr(0), i + 1, j + 1)),  // set distance to 0 (calculated at the end)
-                ']' => tokens_with_loc.push((Token::CloseBr(0), i + 1, j + 1)),  // set distance to 0 (calculated at the end)
-                _ => {},  // Ignore all other characters (comments, etc.)
+            if let Some(token) = tokenize(character, config) {
+                tokens_with_loc.push((token, i + 1, j + 1));
             }
         }
     }
 
+    tokens_with_loc
+}
+
+/// Convert a single character of Brainfuck code into a token.
+/// # Arguments
+/// `character` - The character to convert.
+/// `config` - The [ParseConfig] used for parsing.
+/// # Returns
+/// * [Token] - The token, if the character is a command.
+/// * `None` - If the character is a comment.
+fn tokenize(character: char, config: &ParseConfig) -> Option<Token> {
+    match character {
+        '+' => Some(Token::Add(1)),
+        '-' => Some(Token::Add(u8::MAX)),
+        '<' => Some(Token::Move(STORAGE_SIZE - 1)),
+        '>' => Some(Token::Move(1)),
+        ',' => Some(Token::Input),
+        '.' => Some(Token::Output),
+        '[' => Some(Token::OpenBr(0)),  // set distance to 0 (calculated at the end)
+        ']' => Some(Token::CloseBr(0)),  // set distance to 0 (calculated at the end)
+        c if Some(c) == config.debug_instruction => Some(Token::Debug),
+        _ => None,  // Ignore all other characters (comments, etc.)
+    }
+}
+
+/// Check and optimize the tokens generated from the Brainfuck code.
+/// # Arguments
+/// `tokens_with_loc` - A vector of tokens with their locations (line and column) in the original
+/// # Returns
+/// * ([TokenStream], [ParseStatistics]) - The generated token stream and the statistics, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnmatchedOpenBr(usize, usize)` - There is an unmatched open bracket at the given line and column.
+/// * `UnmatchedCloseBr(usize, usize)` - There is an unmatched close bracket at the given line and column.
+fn process_tokens(mut tokens_with_loc: Vec<(Token, usize, usize)>) -> Result<(TokenStream, ParseStatistics), Error> {
+
+    // count the tokens before optimizations
+    let raw_token_count = tokens_with_loc.len();
+
     // merge adjacent tokens
     tokens_with_loc = merge_adjacent(tokens_with_loc);
 
-    // check whether the loops are correct
-    check_loops(&tokens_with_loc)?;
+    // check whether the loops are correct (and find the trivially infinite loops)
+    let warnings = check_loops(&tokens_with_loc)?;
     
     // optimize clear cell instruction ([-])
     clear_cell(&mut tokens_with_loc);
@@ -154,11 +931,58 @@ pub fn process_code(code: &str) -> Result<TokenStream, Error> {
     // optimize add to copy instruction ([->>+>+<<<])
     add_to_copy(&mut tokens_with_loc);
 
+    // optimize add to copy 3 instruction ([->>+>+>+<<<<])
+    add_to_copy3(&mut tokens_with_loc);
+
+    // remove the loops which never run, since they follow a loop ([-][-])
+    consecutive_loop_elimination(&mut tokens_with_loc);
+
     // calculate the distances for the open and close brackets (used in interpreter for jumps)
     calculate_jumps(&mut tokens_with_loc);
 
-    // remove location information and return the token stream
-    Ok(tokens_with_loc.into_iter().map(|(token, _, _)| token).collect())
+    // separate the location information
+    let (tokens, locations): (TokenStream, Vec<_>) = tokens_with_loc.into_iter().map(|(token, row, col)| (token, (row, col))).unzip();
+
+    // collect the statistics
+    // optimized tokens are generated only by their optimization passes, so their counts are the numbers of found patterns
+    // (the patterns removed as the dead code are not counted)
+    let mut token_counts = HashMap::new();
+    for token in &tokens {
+        *token_counts.entry(token_name(token)).or_insert(0) += 1;
+    }
+    let stats = ParseStatistics {
+        raw_token_count,
+        optimized_token_count: tokens.len(),
+        clear_cells_found: ["ClearCell", "SetCell"].iter().filter_map(|name| token_counts.get(name)).sum(),
+        add_to_found: token_counts.get("AddTo").copied().unwrap_or(0),
+        token_counts,
+        warnings,
+        locations,
+    };
+
+    Ok((tokens, stats))
+}
+
+/// Get the name of the token type.
+/// # Arguments
+/// `token` - The token.
+/// # Returns
+/// * `&'static str` - The name of the token variant (e.g. `"Add"`).
+fn token_name(token: &Token) -> &'static str {
+    match token {
+        Token::Add(_) => "Add",
+        Token::Move(_) => "Move",
+        Token::Input => "Input",
+        Token::Output => "Output",
+        Token::OpenBr(_) => "OpenBr",
+        Token::CloseBr(_) => "CloseBr",
+        Token::ClearCell => "ClearCell",
+        Token::AddTo(_) => "AddTo",
+        Token::AddToCopy(_, _) => "AddToCopy",
+        Token::Debug => "Debug",
+        Token::SetCell(_) => "SetCell",
+        Token::AddToCopy3(_, _, _) => "AddToCopy3",
+    }
 }
 
 /// Merge adjacent addition and move tokens.
@@ -169,16 +993,31 @@ pub fn process_code(code: &str) -> Result<TokenStream, Error> {
 /// `tokens` - A vector of tokens with their locations (line and column) in the original
 /// # Returns
 /// * Vec<([Token], usize, usize)> - The optimized token stream.
-fn merge_adjacent(tokens: Vec<(Token, usize, usize)>) -> Vec<(Token, usize, usize)> {
+fn merge_adjacent(tokens: Vec<(Token, usize, usize)>) -> Vec<(Token, usize, usize)> {
+    merge_adjacent_with(tokens, false)
+}
+
+/// Merge adjacent addition and move tokens, see [merge_adjacent].
+/// # Arguments
+/// `tokens` - A vector of tokens with their locations (line and column) in the original
+/// `wide` - Whether the additions are merged only while their sum (as `i8`) doesn't overflow (see [MergeAdjacentWide]).
+/// # Returns
+/// * Vec<([Token], usize, usize)> - The optimized token stream.
+fn merge_adjacent_with(tokens: Vec<(Token, usize, usize)>, wide: bool) -> Vec<(Token, usize, usize)> {
     let mut optimized_tokens = Vec::new();
 
     for token in tokens.into_iter() {
         match optimized_tokens.last_mut() {
             Some((Token::Add(n), _, _)) => {
                 if let Token::Add(m) = token.0 {
-                    *n = n.wrapping_add(m);
-                    if *n == 0 {
-                        optimized_tokens.pop();
+                    let sum = if wide { (*n as i8).checked_add(m as i8).map(|sum| sum as u8) } else { Some(n.wrapping_add(m)) };
+                    match sum {
+                        Some(0) => {
+                            optimized_tokens.pop();
+                        },
+                        Some(sum) => *n = sum,
+                        // the sum is out of the range of i8, so the addition starts a new token
+                        None => optimized_tokens.push(token),
                     }
                 } else {
                     optimized_tokens.push(token);
@@ -201,31 +1040,49 @@ fn merge_adjacent(tokens: Vec<(Token, usize, usize)>) -> Vec<(Token, usize, usiz
     optimized_tokens
 }
 
-/// Check if the loops are correct (brackets are matched).
+/// Check if the loops are correct (brackets are matched) and find the trivially infinite loops.
+/// A loop is trivially infinite if its body contains only outputs and debug tokens (or nothing),
+/// so the current cell can't reach zero once the loop is entered (e.g. `+[]` or `+[.]`).
 /// # Arguments
 /// `tokens` - A slice of tokens with their locations (line and column) in the original
 /// # Returns
-/// * `()` - If the loops are correct.
+/// * Vec<[Warning]> - The warnings about the trivially infinite loops, if the loops are correct.
 /// * [Error] - If the loops are incorrect.
 /// # Errors
 /// * `UnmatchedOpenBr(usize, usize)` - There is an unmatched open bracket at the given line and column.
 /// * `UnmatchedCloseBr(usize, usize)` - There is an unmatched close bracket at the given line and column.
-fn check_loops(tokens: &[(Token, usize, usize)]) -> Result<(), Error> {
-    let mut loop_stack = Vec::new();
+fn check_loops(tokens: &[(Token, usize, usize)]) -> Result<Vec<Warning>, Error> {
+    let mut warnings = Vec::new();
+    // the locations of the open brackets and whether their bodies can change the current cell
+    let mut loop_stack: Vec<(usize, usize, bool)> = Vec::new();
 
     for (token, row, col) in tokens.iter() {
         match token {
-            Token::OpenBr(_) => loop_stack.push((*row, *col)),
-            Token::CloseBr(_) => if loop_stack.pop().is_none() {
-                return Err(Error::UnmatchedCloseBr(*row, *col));
-            }
-            _ => {},
+            Token::OpenBr(_) => {
+                if let Some((_, _, changes_cell)) = loop_stack.last_mut() {
+                    *changes_cell = true;
+                }
+                loop_stack.push((*row, *col, false));
+            },
+            Token::CloseBr(_) => {
+                let (open_row, open_col, changes_cell) = loop_stack.pop().ok_or(Error::UnmatchedCloseBr(*row, *col))?;
+                if !changes_cell {
+                    warnings.push(Warning::TriviallyInfinite(open_row, open_col));
+                }
+            },
+            Token::Output | Token::Debug => {},
+            _ => {
+                if let Some((_, _, changes_cell)) = loop_stack.last_mut() {
+                    *changes_cell = true;
+                }
+            },
         }
     }
 
     match loop_stack.pop() {
-        Some((row, col)) => Err(Error::UnmatchedOpenBr(row, col)),
-        None => Ok(()),
+        Some((row, col, _)) => Err(Error::UnmatchedOpenBr(row, col)),
+        // the infinite loops contain no loops, so the warnings are in the order of the code
+        None => Ok(warnings),
     }
 }
 
@@ -254,6 +1111,7 @@ fn calculate_jumps(tokens: &mut [(Token, usize, usize)]) {
 /// Detects the pattern `[-]` and replaces it with `ClearCell`.
 /// Inside the loop there can be any addition/subtraction, cell still gets cleared, eventually.
 /// It doesn't matter if there is a loop around the clear cell, it will still be optimized.
+/// If the clear cell is followed by an addition (`[-]+++`), both are replaced with `SetCell`.
 fn clear_cell(tokens: &mut Vec<(Token, usize, usize)>) {
     let mut i = tokens.len();
     while let Some(new_i) = i.checked_sub(1) {
@@ -284,6 +1142,12 @@ fn clear_cell(tokens: &mut Vec<(Token, usize, usize)>) {
                             _ => break,
                         }
                     }
+
+                    // fold the following addition (there is at most one after merging modulo 256, see MergeAdjacentWide for the others)
+                    if let Some(&(Token::Add(n), _, _)) = tokens.get(i + 1) {
+                        tokens[i].0 = Token::SetCell(n);
+                        tokens.remove(i + 1);
+                    }
                 }
             }
         }
@@ -389,10 +1253,148 @@ fn add_to_copy(tokens: &mut Vec<(Token, usize, usize)>) {
 }
 
 
+/// Optimization - Add to copy 3.
+/// Detects the pattern like `[->>+>+>+<<<<]` and replaces it with `AddToCopy3(2, 3, 4)`.
+/// The loops whose second destination is the current cell (`[->+<+>>+<<]`) are left alone, they never terminate.
+/// It doesn't matter if there is a loop around the add to copy, it will still be optimized.
+fn add_to_copy3(tokens: &mut Vec<(Token, usize, usize)>) {
+    let mut i = tokens.len();
+    while let Some(new_i) = i.checked_sub(1) {
+        i = new_i;
+        if tokens.len() - i < 10 {
+            continue;
+        }
+
+        let (Token::OpenBr(_), Token::Add(u8::MAX), Token::Move(m1), Token::Add(1), Token::Move(m2),
+            Token::Add(1), Token::Move(m3), Token::Add(1), Token::Move(m4), Token::CloseBr(_)) = (
+            tokens[i].0, tokens[i + 1].0, tokens[i + 2].0, tokens[i + 3].0, tokens[i + 4].0,
+            tokens[i + 5].0, tokens[i + 6].0, tokens[i + 7].0, tokens[i + 8].0, tokens[i + 9].0,
+        ) else {
+            continue;
+        };
+
+        let n1 = m1;
+        let n2 = (n1 + m2) % STORAGE_SIZE;
+        let n3 = (n2 + m3) % STORAGE_SIZE;
+        if n2 == 0 || (n3 + m4) % STORAGE_SIZE != 0 {
+            continue;
+        }
+
+        tokens[i].0 = Token::AddToCopy3(n1, n2, n3);  // replace first token with AddToCopy3()
+        tokens.drain((i + 1)..=(i + 9));  // remove other tokens
+
+        // check if there is a loop (or multiple loops) around the add to copy, if so, remove it
+        while i != 0 && i != tokens.len() - 1 {
+            match (tokens[i - 1].0, tokens[i + 1].0) {
+                (Token::OpenBr(_), Token::CloseBr(_)) => {
+                    i -= 1;  // move to the opening bracket position
+                    tokens[i].0 = tokens[i + 1].0;  // set opening bracket as the AddToCopy3
+                    tokens.drain((i + 1)..=(i + 2));  // remove old AddToCopy3 and closing bracket
+                },
+                _ => break,
+            }
+        }
+    }
+}
+
+/// Optimization - Consecutive loop elimination.
+/// A loop exits only when the current cell is zero, so the loop which immediately follows it never runs and is removed
+/// (`[-][-]`, `[>+<-][<]`). The same holds after the clear cell and add to tokens, which are the optimized loops,
+/// so the following clear cell (`[-][-]`) is removed too. The removed loops may be nested and there may be several of them.
+fn consecutive_loop_elimination(tokens: &mut Vec<(Token, usize, usize)>) {
+    let mut kept = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        // the current cell is zero after the loops and their optimized forms
+        let cell_zero = matches!(
+            kept.last(),
+            Some((Token::CloseBr(_) | Token::ClearCell | Token::AddTo(_) | Token::AddToCopy(_, _) | Token::AddToCopy3(_, _, _), _, _)),
+        );
+
+        match tokens[i].0 {
+            Token::OpenBr(_) if cell_zero => {
+                // skip the whole loop
+                let mut depth = 0;
+                loop {
+                    match tokens[i].0 {
+                        Token::OpenBr(_) => depth += 1,
+                        Token::CloseBr(_) => depth -= 1,
+                        _ => (),
+                    }
+                    i += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            },
+            Token::ClearCell if cell_zero => i += 1,
+            _ => {
+                kept.push(tokens[i]);
+                i += 1;
+            },
+        }
+    }
+    *tokens = kept;
+}
+
+
+
+/// The individual stages of [process_code], working on tokens with their locations (line and column).
+/// Exposed only for the benchmarks, not a part of the public API.
+#[doc(hidden)]
+pub mod passes {
+    use super::*;
+
+    /// Tokens with their locations (line and column) in the original code.
+    pub type TokensWithLoc = Vec<(Token, usize, usize)>;
+
+    pub fn tokenize(code: &str) -> TokensWithLoc {
+        tokenize_str(code, &ParseConfig::default())
+    }
+
+    pub fn merge_adjacent(tokens: TokensWithLoc) -> TokensWithLoc {
+        super::merge_adjacent(tokens)
+    }
+
+    pub fn check_loops(tokens: &[(Token, usize, usize)]) -> Result<Vec<Warning>, Error> {
+        super::check_loops(tokens)
+    }
+
+    pub fn clear_cell(tokens: &mut TokensWithLoc) {
+        super::clear_cell(tokens)
+    }
+
+    pub fn add_to(tokens: &mut TokensWithLoc) {
+        super::add_to(tokens)
+    }
+
+    pub fn add_to_copy(tokens: &mut TokensWithLoc) {
+        super::add_to_copy(tokens)
+    }
+
+    pub fn add_to_copy3(tokens: &mut TokensWithLoc) {
+        super::add_to_copy3(tokens)
+    }
+
+    pub fn consecutive_loop_elimination(tokens: &mut TokensWithLoc) {
+        super::consecutive_loop_elimination(tokens)
+    }
+
+    pub fn calculate_jumps(tokens: &mut [(Token, usize, usize)]) {
+        super::calculate_jumps(tokens)
+    }
+}
+
+
 
 #[cfg(test)]
 mod tests {
+    use std::collections::{BTreeMap, HashMap, HashSet};
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
     use super::*;
+    use crate::interpret::interpret_from;
+    use crate::io::{EofPolicy, VecIo};
 
     #[test]
     fn test_process_code() {
@@ -411,6 +1413,376 @@ mod tests {
             Token::Add(u8::MAX),
             Token::CloseBr(7),
         ]);
+
+        // the cell is cleared and set at once
+        assert_eq!(process_code("[-]+++").unwrap(), vec![Token::SetCell(3)]);
+        assert_eq!(process_code(">[+]--<").unwrap(), vec![Token::Move(1), Token::SetCell(254), Token::Move(STORAGE_SIZE - 1)]);
+
+        // the additions are merged modulo 256 before they are folded (in the range of i8 for the wide cells)
+        let code = format!("[-]{}", "+".repeat(130));
+        assert_eq!(process_code(&code).unwrap(), vec![Token::SetCell(130)]);
+        assert_eq!(process_code_with_pipeline(&code, &OptimizationPipeline::wide_cells()).unwrap(), vec![Token::SetCell(127), Token::Add(3)]);
+    }
+
+    #[test]
+    fn test_process_code_with_config() {
+        //! Test the process_code_with_config function.
+
+        let code = "+#[#-]";
+        let tokens = process_code_with_config(code, &ParseConfig::default()).unwrap();
+        assert_eq!(tokens, vec![
+            Token::Add(1),
+            Token::ClearCell,
+        ]);
+
+        let config = ParseConfig { debug_instruction: Some('#') };
+        let tokens = process_code_with_config(code, &config).unwrap();
+        assert_eq!(tokens, vec![
+            Token::Add(1),
+            Token::Debug,
+            Token::OpenBr(3),
+            Token::Debug,
+            Token::Add(u8::MAX),
+            Token::CloseBr(3),
+        ]);
+    }
+
+    #[test]
+    fn test_process_code_bytes() {
+        //! Test the process_code_bytes function.
+
+        // "Grüße: ++[->+<]\nÀ bientôt ." encoded in Latin-1
+        let mut code = b"Gr\xFC\xDFe: ++[->+<]\n".to_vec();
+        code.extend_from_slice(b"\xC0 bient\xF4t .");
+        assert!(std::str::from_utf8(&code).is_err());
+
+        let tokens = process_code_bytes(&code).unwrap();
+        assert_eq!(tokens, vec![
+            Token::Add(2),
+            Token::AddTo(1),
+            Token::Output,
+        ]);
+
+        // columns are counted in bytes
+        let code = b"\xE9\xE9]";
+        assert_eq!(process_code_bytes(code), Err(Error::UnmatchedCloseBr(1, 3)));
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        //! Test that the byte order mark at the start of the code is skipped.
+
+        let tokens = process_code("+[->+<].").unwrap();
+        assert_eq!(process_code("\u{FEFF}+[->+<].").unwrap(), tokens);
+        assert_eq!(process_code_bytes(b"\xEF\xBB\xBF+[->+<].").unwrap(), tokens);
+
+        // the columns of the first line are counted from the first character after it
+        assert_eq!(process_code("\u{FEFF}+]"), Err(Error::UnmatchedCloseBr(1, 2)));
+        assert_eq!(process_code_bytes(b"\xEF\xBB\xBF+]"), Err(Error::UnmatchedCloseBr(1, 2)));
+        assert_eq!(process_code_with_config("\u{FEFF}[", &ParseConfig::default()), Err(Error::UnmatchedOpenBr(1, 1)));
+
+        // only at the start of the code
+        assert_eq!(process_code("+\u{FEFF}]"), Err(Error::UnmatchedCloseBr(1, 3)));
+    }
+
+    #[test]
+    fn test_process_code_with_stats() {
+        //! Test the process_code_with_stats function.
+
+        let (tokens, stats) = process_code_with_stats("+[-]").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Add(1),
+            Token::ClearCell,
+        ]);
+        assert_eq!(stats.clear_cells_found, 1);
+        assert_eq!(stats.add_to_found, 0);
+        assert_eq!(stats.raw_token_count, 4);
+        assert_eq!(stats.optimized_token_count, 2);
+
+        let (tokens, stats) = process_code_with_stats("[-]").unwrap();
+        assert_eq!(stats.clear_cells_found, 1);
+        assert_eq!(stats.optimized_token_count, tokens.len());
+        assert!(stats.optimized_token_count < stats.raw_token_count);
+
+        let (_, stats) = process_code_with_stats(">>+++[->+<]<<[->>+<<]..").unwrap();
+        assert_eq!(stats.raw_token_count, 23);
+        assert_eq!(stats.add_to_found, 2);
+        assert_eq!(stats.token_counts, HashMap::from([
+            ("Move", 2),
+            ("Add", 1),
+            ("AddTo", 2),
+            ("Output", 2),
+        ]));
+        assert!(stats.warnings.is_empty());
+
+        // the trivially infinite loops are reported (also after the additions cancel out), but still processed
+        let (tokens, stats) = process_code_with_stats("+[]\n>+[+-.]").unwrap();
+        assert_eq!(stats.warnings, vec![Warning::TriviallyInfinite(1, 2), Warning::TriviallyInfinite(2, 3)]);
+        assert_eq!(tokens, process_code("+[]>+[.]").unwrap());
+    }
+
+    #[test]
+    fn test_tokens_to_bf() {
+        //! Test the tokens_to_bf function.
+
+        assert_eq!(tokens_to_bf(&[]), "");
+        assert_eq!(tokens_to_bf(&[
+            Token::Add(3),
+            Token::Add(254),
+            Token::Move(2),
+            Token::Move(STORAGE_SIZE - 3),
+            Token::Input,
+            Token::Output,
+            Token::Debug,
+        ]), "+++-->><<<,.#");
+
+        for code in ["+[-]>", "[-]+++", "[-]--", "[->>+<<]", "[-<<+>>]", "[->+>+<<]", "[-<+<<+>>>]", "+[>+[-]<-]", ">+[->>+>+<<<]", "[->>+>+>+<<<<]", "[->+>>+<<<<<+>>]"] {
+            let tokens = process_code(code).unwrap();
+            assert_eq!(tokens_to_bf(&tokens), code);
+        }
+    }
+
+    #[test]
+    fn test_token_hash() {
+        //! Test counting the tokens in a HashMap and hashing the token streams.
+
+        let tokens = process_code("+>+>+<<[->+<]>[-]<.").unwrap();
+        let mut counts = HashMap::new();
+        for token in &tokens {
+            *counts.entry(*token).or_insert(0_usize) += 1;
+        }
+        assert_eq!(counts[&Token::Add(1)], 3);
+        assert_eq!(counts[&Token::Move(1)], 3);
+        assert_eq!(counts[&Token::ClearCell], 1);
+        assert_eq!(counts.values().sum::<usize>(), tokens.len());
+
+        let unique: HashSet<Token> = tokens.iter().copied().collect();
+        assert_eq!(unique.len(), counts.len());
+
+        let hash = |token_stream: &TokenStream| {
+            let mut hasher = DefaultHasher::new();
+            token_stream.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&tokens), hash(&process_code("+ > + > + << [->+<] > [-] < . comment").unwrap()));
+        assert_ne!(hash(&tokens), hash(&process_code("+>+>+<<[->+<]>[-]<,").unwrap()));
+    }
+
+    #[test]
+    fn test_token_ord() {
+        //! Test the ordering of the tokens.
+
+        let mut tokens = [Token::Output, Token::Add(1), Token::Input];
+        tokens.sort();
+        assert_eq!(tokens, [Token::Add(1), Token::Input, Token::Output]);
+
+        let mut tokens = vec![
+            Token::AddToCopy(2, 1),
+            Token::SetCell(0),
+            Token::ClearCell,
+            Token::AddTo(3),
+            Token::CloseBr(0),
+            Token::AddToCopy(1, 5),
+            Token::OpenBr(9),
+            Token::Move(2),
+            Token::Output,
+            Token::Add(255),
+            Token::Input,
+            Token::Move(1),
+            Token::Add(1),
+            Token::AddTo(1),
+            Token::AddToCopy3(1, 2, 3),
+        ];
+        tokens.sort();
+        assert_eq!(tokens, vec![
+            Token::Add(1),
+            Token::Add(255),
+            Token::Move(1),
+            Token::Move(2),
+            Token::Input,
+            Token::Output,
+            Token::OpenBr(9),
+            Token::CloseBr(0),
+            Token::ClearCell,
+            Token::AddTo(1),
+            Token::AddTo(3),
+            Token::AddToCopy(1, 5),
+            Token::AddToCopy(2, 1),
+            Token::SetCell(0),
+            Token::AddToCopy3(1, 2, 3),
+        ]);
+
+        // sorted histogram
+        let mut histogram = BTreeMap::new();
+        for token in process_code(",[.,]+++.").unwrap() {
+            *histogram.entry(token).or_insert(0_u64) += 1;
+        }
+        assert_eq!(histogram.into_iter().collect::<Vec<_>>(), vec![
+            (Token::Add(3), 1),
+            (Token::Input, 2),
+            (Token::Output, 2),
+            (Token::OpenBr(3), 1),
+            (Token::CloseBr(3), 1),
+        ]);
+    }
+
+    #[test]
+    fn test_rle() {
+        //! Test the rle_encode and rle_decode functions.
+
+        assert_eq!(rle_encode(&[]), vec![]);
+        assert_eq!(rle_decode(&[]), vec![]);
+
+        let tokens = process_code(",,,[-]>[-]<[>.....<-]..").unwrap();
+        let rle = rle_encode(&tokens);
+        assert_eq!(rle, vec![
+            (Token::Input, 3),
+            (Token::ClearCell, 1),
+            (Token::Move(1), 1),
+            (Token::ClearCell, 1),
+            (Token::Move(STORAGE_SIZE - 1), 1),
+            (Token::OpenBr(9), 1),
+            (Token::Move(1), 1),
+            (Token::Output, 5),
+            (Token::Move(STORAGE_SIZE - 1), 1),
+            (Token::Add(u8::MAX), 1),
+            (Token::CloseBr(9), 1),
+            (Token::Output, 2),
+        ]);
+        assert_eq!(rle_decode(&rle), tokens);
+
+        // unmerged tokens
+        let tokens = vec![Token::Add(1), Token::Add(1), Token::Debug, Token::Add(1), Token::AddTo(2), Token::AddTo(2)];
+        let rle = rle_encode(&tokens);
+        assert_eq!(rle, vec![(Token::Add(1), 2), (Token::Debug, 1), (Token::Add(1), 1), (Token::AddTo(2), 2)]);
+        assert_eq!(rle_decode(&rle), tokens);
+    }
+
+    #[test]
+    fn test_split_at_loops() {
+        //! Test the split_at_loops function.
+
+        let tokens = vec![Token::Add(2), Token::OpenBr(3), Token::Move(1), Token::CloseBr(3), Token::Output];
+        assert_eq!(split_at_loops(&tokens), vec![
+            vec![Token::Add(2)],
+            vec![Token::OpenBr(3), Token::Move(1), Token::CloseBr(3)],
+            vec![Token::Output],
+        ]);
+        assert_eq!(split_at_loops(&[]), Vec::<TokenStream>::new());
+
+        for code in ["", "+", "[.]", "++[>[-]<.[>]]>.[.][[.].]<", ",[.[-],]", "+[>+[>+[-]<-]<-]+[.]"] {
+            let tokens = process_code(code).unwrap();
+            let segments = split_at_loops(&tokens);
+            assert_eq!(segments.concat(), tokens, "code: {}", code);
+
+            // every segment is a single token outside of the loops or a complete loop
+            for segment in &segments {
+                match segment[..] {
+                    [Token::OpenBr(open), .., Token::CloseBr(close)] => {
+                        assert_eq!(open, segment.len() - 1);
+                        assert_eq!(close, segment.len() - 1);
+                    },
+                    [token] => assert!(!matches!(token, Token::OpenBr(_) | Token::CloseBr(_))),
+                    _ => panic!("invalid segment {:?}", segment),
+                }
+            }
+        }
+        assert_eq!(split_at_loops(&process_code("++[>[-]<.[>]]>.").unwrap()).len(), 4);
+    }
+
+    #[test]
+    fn test_patch() {
+        //! Test the patch function.
+
+        assert_eq!(patch(&[Token::ClearCell], 0, &[Token::Add(5)]), vec![Token::Add(5)]);
+
+        // replacement inside of the loop
+        let tokens = vec![Token::Add(2), Token::OpenBr(2), Token::ClearCell, Token::CloseBr(2), Token::Output];
+        assert_eq!(
+            patch(&tokens, 2, &[Token::Add(5)]),
+            vec![Token::Add(2), Token::OpenBr(2), Token::Add(5), Token::CloseBr(2), Token::Output],
+        );
+
+        // deletion
+        assert_eq!(patch(&tokens, 2, &[]), vec![Token::Add(2), Token::OpenBr(1), Token::CloseBr(1), Token::Output]);
+        assert_eq!(patch(&tokens, 0, &[]), vec![Token::OpenBr(2), Token::ClearCell, Token::CloseBr(2), Token::Output]);
+
+        // expansion with a nested loop (the jump distances of the replacement are ignored)
+        let replacement = [Token::Move(1), Token::OpenBr(0), Token::Add(255), Token::CloseBr(0), Token::Move(29_999)];
+        assert_eq!(
+            patch(&tokens, 2, &replacement),
+            vec![
+                Token::Add(2), Token::OpenBr(6), Token::Move(1), Token::OpenBr(2), Token::Add(255),
+                Token::CloseBr(2), Token::Move(29_999), Token::CloseBr(6), Token::Output,
+            ],
+        );
+
+        // expansion of the token to the whole loop
+        let tokens = process_code("+[-]>[-]").unwrap();
+        assert_eq!(
+            patch(&tokens, 1, &[Token::OpenBr(0), Token::Add(255), Token::CloseBr(0)]),
+            vec![Token::Add(1), Token::OpenBr(2), Token::Add(255), Token::CloseBr(2), Token::Move(1), Token::ClearCell],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "unmatched brackets")]
+    fn test_patch_unmatched() {
+        //! Test that patch panics if the brackets become unmatched.
+
+        patch(&[Token::OpenBr(2), Token::ClearCell, Token::CloseBr(2)], 2, &[Token::Output]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        //! Test the normalize function.
+
+        assert_eq!(normalize(process_code("++++----").unwrap()), vec![]);
+        assert_eq!(normalize(process_code("[-][-]").unwrap()), vec![Token::ClearCell]);
+        assert_eq!(normalize(process_code("><<>").unwrap()), vec![]);
+
+        // the tokens are merged after the removed ones
+        assert_eq!(
+            normalize(vec![Token::Add(1), Token::Move(2), Token::Move(STORAGE_SIZE - 2), Token::Add(2)]),
+            vec![Token::Add(3)],
+        );
+        assert_eq!(
+            normalize(vec![Token::ClearCell, Token::Add(5), Token::Add(251), Token::SetCell(0), Token::ClearCell]),
+            vec![Token::ClearCell],
+        );
+
+        // the additions are merged regardless of their sum
+        assert_eq!(normalize(process_code(&"+".repeat(300)).unwrap()), vec![Token::Add(44)]);
+
+        // the empty loops are removed only if the current cell is zero
+        assert_eq!(
+            normalize(vec![Token::OpenBr(1), Token::CloseBr(1), Token::Add(1), Token::OpenBr(1), Token::CloseBr(1)]),
+            vec![Token::Add(1), Token::OpenBr(1), Token::CloseBr(1)],
+        );
+        assert_eq!(
+            normalize(vec![
+                Token::Add(1), Token::OpenBr(5), Token::Move(1), Token::OpenBr(1), Token::CloseBr(1), Token::Move(STORAGE_SIZE - 1), Token::CloseBr(5),
+                Token::OpenBr(3), Token::Add(1), Token::Add(255), Token::CloseBr(3), Token::Output,
+            ]),
+            vec![Token::Add(1), Token::OpenBr(5), Token::Move(1), Token::OpenBr(1), Token::CloseBr(1), Token::Move(STORAGE_SIZE - 1), Token::CloseBr(5), Token::Output],
+        );
+
+        // the normalization doesn't change the behavior and can't be improved further
+        for code in [
+            include_str!("../examples/hello-world.bf"),
+            include_str!("../examples/factorial.bf"),
+            include_str!("../examples/fizzbuzz.bf"),
+        ] {
+            let tokens = process_code(code).unwrap();
+            let normalized = normalize(tokens.clone());
+            assert_eq!(normalize(normalized.clone()), normalized);
+
+            let mut io = VecIo::default();
+            crate::interpret::interpret(&tokens, &mut io);
+            let mut normalized_io = VecIo::default();
+            crate::interpret::interpret(&normalized, &mut normalized_io);
+            assert_eq!(normalized_io.output, io.output);
+        }
     }
 
     #[test]
@@ -433,6 +1805,16 @@ mod tests {
             (Token::Add(2), 1, 1),
             (Token::Move(2), 1, 3),
         ]);
+
+        // the additions are merged modulo 256, for the wide cells the sum stays in the range of i8
+        let tokens: Vec<_> = (0..300).map(|i| (Token::Add(1), 1, i + 1)).collect();
+        assert_eq!(merge_adjacent(tokens.clone()), vec![(Token::Add(44), 1, 257)]);
+        assert_eq!(merge_adjacent_with(tokens, true), vec![(Token::Add(127), 1, 1), (Token::Add(127), 1, 128), (Token::Add(46), 1, 255)]);
+        let tokens: Vec<_> = (0..200).map(|i| (Token::Add(255), 1, i + 1)).collect();
+        assert_eq!(merge_adjacent(tokens.clone()), vec![(Token::Add(56), 1, 1)]);
+        assert_eq!(merge_adjacent_with(tokens, true), vec![(Token::Add(128), 1, 1), (Token::Add(184), 1, 129)]);
+        let tokens = (0..256).map(|i| (Token::Add(1), 1, i + 1)).collect();
+        assert_eq!(merge_adjacent(tokens), Vec::new());
     }
 
     #[test]
@@ -447,7 +1829,22 @@ mod tests {
             (Token::CloseBr(1), 1, 5),
             (Token::CloseBr(3), 1, 6),
         ];
-        assert_eq!(check_loops(&tokens), Ok(()));
+        // the empty loops never terminate, the loop containing a loop is not reported
+        assert_eq!(check_loops(&tokens), Ok(vec![Warning::TriviallyInfinite(1, 1), Warning::TriviallyInfinite(1, 4)]));
+
+        let tokens = vec![
+            (Token::OpenBr(3), 1, 1),
+            (Token::Output, 1, 2),
+            (Token::Debug, 1, 3),
+            (Token::CloseBr(3), 1, 4),
+            (Token::OpenBr(2), 2, 1),
+            (Token::Add(2), 2, 2),
+            (Token::CloseBr(2), 2, 3),
+            (Token::OpenBr(2), 3, 1),
+            (Token::Input, 3, 2),
+            (Token::CloseBr(2), 3, 3),
+        ];
+        assert_eq!(check_loops(&tokens), Ok(vec![Warning::TriviallyInfinite(1, 1)]));
 
         let tokens = vec![
             (Token::OpenBr(1), 1, 1),
@@ -464,6 +1861,48 @@ mod tests {
         assert_eq!(check_loops(&tokens), Err(Error::UnmatchedOpenBr(1, 1)));
     }
     
+    #[test]
+    fn test_consecutive_loop_elimination() {
+        //! Test the consecutive_loop_elimination function.
+
+        assert_eq!(process_code("[-][-]").unwrap(), vec![Token::ClearCell]);
+        assert_eq!(process_code("+[-][-][+]>").unwrap(), vec![Token::Add(1), Token::ClearCell, Token::Move(1)]);
+
+        // any loop after a loop never runs (even with moves inside, the current cell is zero)
+        assert_eq!(
+            process_code("+[->+<][.>]..").unwrap(),
+            vec![Token::Add(1), Token::AddTo(1), Token::Output, Token::Output],
+        );
+        assert_eq!(
+            process_code(",[.,][[,.]>[-]][-]").unwrap(),
+            vec![Token::Input, Token::OpenBr(3), Token::Output, Token::Input, Token::CloseBr(3)],
+        );
+
+        // the loop after other tokens may run
+        assert_eq!(
+            process_code("[-]+[-]").unwrap(),
+            vec![Token::SetCell(1), Token::ClearCell],
+        );
+        assert_eq!(
+            process_code("+[.-]>[.-]").unwrap(),
+            vec![
+                Token::Add(1), Token::OpenBr(3), Token::Output, Token::Add(u8::MAX), Token::CloseBr(3),
+                Token::Move(1), Token::OpenBr(3), Token::Output, Token::Add(u8::MAX), Token::CloseBr(3),
+            ],
+        );
+
+        // the locations of the kept tokens are preserved
+        let mut tokens = vec![
+            (Token::ClearCell, 1, 1),
+            (Token::OpenBr(0), 1, 4),
+            (Token::Output, 1, 5),
+            (Token::CloseBr(0), 1, 6),
+            (Token::Output, 2, 1),
+        ];
+        consecutive_loop_elimination(&mut tokens);
+        assert_eq!(tokens, vec![(Token::ClearCell, 1, 1), (Token::Output, 2, 1)]);
+    }
+
     #[test]
     fn test_calculate_jumps() {
         //! Test the calculate_jumps function.
@@ -573,8 +2012,27 @@ mod tests {
         ];
         clear_cell(&mut tokens);
         assert_eq!(tokens, vec![
-            (Token::ClearCell, 1, 1),
-            (Token::Add(u8::MAX), 1, 8),
+            (Token::SetCell(u8::MAX), 1, 1),
+        ]);
+
+        // [-]+++.[-]>
+        let mut tokens = vec![
+            (Token::OpenBr(2), 1, 1),
+            (Token::Add(u8::MAX), 1, 2),
+            (Token::CloseBr(2), 1, 3),
+            (Token::Add(3), 1, 4),
+            (Token::Output, 1, 7),
+            (Token::OpenBr(2), 1, 8),
+            (Token::Add(u8::MAX), 1, 9),
+            (Token::CloseBr(2), 1, 10),
+            (Token::Move(1), 1, 11),
+        ];
+        clear_cell(&mut tokens);
+        assert_eq!(tokens, vec![
+            (Token::SetCell(3), 1, 1),
+            (Token::Output, 1, 7),
+            (Token::ClearCell, 1, 8),
+            (Token::Move(1), 1, 11),
         ]);
     }
     
@@ -723,4 +2181,124 @@ mod tests {
             (Token::Move(STORAGE_SIZE - 1), 1, 10),
         ]);
     }
+
+    #[test]
+    fn test_add_to_copy3() {
+        //! Test the add_to_copy3 function.
+
+        // [->>+>+>+<<<<]
+        let mut tokens = vec![
+            (Token::OpenBr(9), 1, 1),
+            (Token::Add(u8::MAX), 1, 2),
+            (Token::Move(2), 1, 3),
+            (Token::Add(1), 1, 4),
+            (Token::Move(1), 1, 5),
+            (Token::Add(1), 1, 6),
+            (Token::Move(1), 1, 7),
+            (Token::Add(1), 1, 8),
+            (Token::Move(STORAGE_SIZE - 4), 1, 9),
+            (Token::CloseBr(9), 1, 10),
+        ];
+        add_to_copy3(&mut tokens);
+        assert_eq!(tokens, vec![
+            (Token::AddToCopy3(2, 3, 4), 1, 1),
+        ]);
+
+        // [[->+>>+<<<<<+>>]] (the destinations on both sides)
+        let mut tokens = vec![
+            (Token::OpenBr(11), 1, 1),
+            (Token::OpenBr(9), 1, 2),
+            (Token::Add(u8::MAX), 1, 3),
+            (Token::Move(1), 1, 4),
+            (Token::Add(1), 1, 5),
+            (Token::Move(2), 1, 6),
+            (Token::Add(1), 1, 7),
+            (Token::Move(STORAGE_SIZE - 5), 1, 8),
+            (Token::Add(1), 1, 9),
+            (Token::Move(2), 1, 10),
+            (Token::CloseBr(9), 1, 11),
+            (Token::CloseBr(11), 1, 12),
+        ];
+        add_to_copy3(&mut tokens);
+        assert_eq!(tokens, vec![
+            (Token::AddToCopy3(1, 3, STORAGE_SIZE - 2), 1, 1),
+        ]);
+
+        // the loops with non-zero net movement or returning to the current cell in between are not replaced
+        for code in ["[->+>+>+<<]", "[->+<+>>+<<]"] {
+            let tokens = process_code(code).unwrap();
+            assert!(!tokens.iter().any(|token| matches!(token, Token::AddToCopy3(_, _, _))), "code: {}", code);
+        }
+
+        // the cell is added to all three destinations
+        let tokens = process_code("[->>+>+>+<<<<]").unwrap();
+        assert_eq!(tokens, vec![Token::AddToCopy3(2, 3, 4)]);
+        let mut storage = [0; STORAGE_SIZE];
+        storage[..5].copy_from_slice(&[3, 0, 0, 0, 0]);
+        interpret_from(&tokens, &mut storage, 0, 0, &mut VecIo::default(), EofPolicy::Zero, &mut || false);
+        assert_eq!(storage[..5], [0, 0, 3, 3, 3]);
+
+        // the repeated destination receives the value twice
+        let tokens = process_code("+++[->+>+<+<]").unwrap();
+        assert_eq!(tokens, vec![Token::Add(3), Token::AddToCopy3(1, 2, 1)]);
+        let mut storage = [0; STORAGE_SIZE];
+        interpret_from(&tokens, &mut storage, 0, 0, &mut VecIo::default(), EofPolicy::Zero, &mut || false);
+        assert_eq!(storage[..3], [0, 6, 3]);
+    }
+
+    #[test]
+    fn test_process_code_with_pipeline() {
+        //! Test the process_code_with_pipeline function with the reordered, missing and custom passes.
+
+        let codes = [
+            "+[-]>>[->+<]<<",
+            "++[>+++[>,.<-]<-]>>[-]++++[->+>+<<]",
+            ">+>>[[-]]>[-]---[-<<+>>+>+>+<<<]",
+            "++++[->>+>+>+<<<<]>>.>.>.",
+        ];
+
+        // removes the additions of zero (e.g. left after the other custom passes)
+        struct RemoveZeroAdds;
+        impl OptimizationPass for RemoveZeroAdds {
+            fn run(&self, tokens: &mut Vec<(Token, usize, usize)>) {
+                tokens.retain(|(token, _, _)| *token != Token::Add(0));
+            }
+        }
+
+        let pipelines = [
+            vec![],
+            vec![Box::new(MergeAdjacent) as Box<dyn OptimizationPass>],
+            // the add to loops are found before the clear cell loops
+            vec![Box::new(MergeAdjacent), Box::new(AddToCopy3), Box::new(AddToCopy), Box::new(AddTo), Box::new(ClearCell)],
+            // the patterns are matched only on the merged tokens
+            vec![Box::new(ClearCell), Box::new(AddTo), Box::new(MergeAdjacent)],
+            vec![Box::new(MergeAdjacent), Box::new(ClearCell), Box::new(ClearCell), Box::new(RemoveZeroAdds)],
+        ].map(|passes| OptimizationPipeline { passes });
+
+        for code in codes {
+            let expected_tokens = process_code(code).unwrap();
+            assert_eq!(process_code_with_pipeline(code, &OptimizationPipeline::default()).unwrap(), expected_tokens);
+
+            // every order of the passes gives an equivalent program
+            let run = |tokens: &[Token]| {
+                let mut io = VecIo::new(b"ab");
+                let mut storage = [0; STORAGE_SIZE];
+                let stop = interpret_from(tokens, &mut storage, 0, 0, &mut io, EofPolicy::Zero, &mut || false);
+                (io, storage[..16].to_vec(), stop)
+            };
+            let expected = run(&expected_tokens);
+            for pipeline in &pipelines {
+                assert_eq!(run(&process_code_with_pipeline(code, pipeline).unwrap()), expected, "code: {}", code);
+            }
+        }
+
+        // the order matters for the resulting tokens
+        assert_eq!(process_code_with_pipeline("[--]", &pipelines[2]).unwrap(), vec![Token::ClearCell]);
+        assert_eq!(process_code_with_pipeline("[--]", &pipelines[3]).unwrap(), vec![Token::OpenBr(2), Token::Add(254), Token::CloseBr(2)]);
+        assert_eq!(process_code_with_pipeline("[-]", &pipelines[0]).unwrap(), vec![Token::OpenBr(2), Token::Add(u8::MAX), Token::CloseBr(2)]);
+
+        // the loops are checked before the passes
+        assert_eq!(process_code_with_pipeline("+[", &pipelines[0]), Err(Error::UnmatchedOpenBr(1, 2)));
+        assert_eq!(process_code_with_pipeline("\n]", &pipelines[1]), Err(Error::UnmatchedCloseBr(2, 1)));
+    }
 }
@@ -0,0 +1,1751 @@
+//! Generation of the Cranelift IR, shared by the JIT-compiler and the compiler.
+
+
+
+use std::collections::{BTreeMap, HashMap};
+
+use cranelift::codegen::ir;
+use cranelift::codegen::isa::TargetIsa;
+use cranelift::frontend::Switch;
+use cranelift::prelude::*;
+
+use crate::code::{signed_distance, STORAGE_SIZE, Token};
+use crate::io::EofPolicy;
+use crate::jit::CellWidth;
+
+
+
+/// The final data pointer written by the function built with fuel, when the fuel runs out.
+pub(crate) const FUEL_EXHAUSTED: usize = usize::MAX;
+
+/// The final data pointer written by the function built with strict bounds, when the data pointer would leave the array.
+pub(crate) const POINTER_OUT_OF_BOUNDS: usize = usize::MAX - 1;
+
+/// The index of the counter of the jumps back to the start of a loop (in the counters of the instrumented function).
+pub(crate) const BACK_EDGES_COUNTER: usize = 0;
+
+/// The index of the counter of the bytes read (in the counters of the instrumented function).
+pub(crate) const BYTES_READ_COUNTER: usize = 1;
+
+/// The index of the counter of the bytes written (in the counters of the instrumented function).
+pub(crate) const BYTES_WRITTEN_COUNTER: usize = 2;
+
+/// The index of the iteration counter of the first loop (in the counters of the instrumented function),
+/// the counters of the other loops follow in the order of their opening brackets.
+pub(crate) const LOOP_COUNTERS: usize = 3;
+
+/// The optional features of the built function.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct FunctionConfig<'a> {
+    /// Whether to limit the number of loop iterations (see [build_function]).
+    pub(crate) fuel: bool,
+    /// Whether the execution can be resumed inside a loop (see [build_function]).
+    pub(crate) resumable: bool,
+    /// Whether to stop the execution when the data pointer would leave the array, instead of wrapping it (see [build_function]).
+    pub(crate) strict_bounds: bool,
+    /// Whether the function runs on the array provided by the host (see [build_function]).
+    pub(crate) host_tape: bool,
+    /// Whether the function counts the executed loop iterations and the bytes read and written (see [build_function]).
+    pub(crate) instrument: bool,
+    /// Whether to replace the loops with the statically known trip count by straight-line code (see [fold_constant_loops]),
+    /// and the maximal trip count of the unrolled loops. Ignored if the function is resumable, runs on the host tape,
+    /// has fuel, strict bounds or is instrumented.
+    pub(crate) unroll_limit: Option<usize>,
+    /// The value stored to the current cell when the read function signals the end of input.
+    pub(crate) eof: EofPolicy,
+    /// The width of the cells (the array holds [STORAGE_SIZE] cells of this width).
+    /// The loops with the known trip count are replaced only with 8-bit cells.
+    pub(crate) cell_width: CellWidth,
+    /// The indices of the opening brackets of the loops whose data pointer range is speculated (see [build_function]).
+    /// Must be empty if the function is resumable, has fuel, strict bounds or is instrumented.
+    pub(crate) hot_loops: &'a [usize],
+}
+
+/// The functions called by the generated code.
+pub(crate) struct RuntimeFunctions {
+    /// The function that reads a byte (accepts the context pointer, returns `i32`, negative at the end of input).
+    pub(crate) read: Callee,
+    /// The function that writes a byte (accepts the context pointer and `i8`).
+    pub(crate) write: Callee,
+    /// The function that writes a byte the given number of times (accepts the context pointer, `i8` and the pointer-sized count),
+    /// called for the consecutive outputs. If `None`, the `write` function is called for each of them.
+    pub(crate) write_repeated: Option<Callee>,
+    /// The function that flushes the buffered output (accepts the context pointer), called before every read,
+    /// before every debug call and before the function returns. No flush calls are emitted if `None`.
+    pub(crate) flush: Option<Callee>,
+    /// The function called for [Token::Debug] (accepts the array pointer, its length, the data pointer
+    /// and the size of a cell in bytes), the debug tokens are ignored if `None`.
+    pub(crate) debug: Option<Callee>,
+}
+
+/// The token to emit, with the range of the data pointer before it.
+type PlannedToken = (Token, Option<(usize, usize)>);
+
+/// The function called by the generated code (for input and output).
+pub(crate) enum Callee {
+    /// The function at the given absolute address (used by the JIT-compiler).
+    Address(*const ()),
+    /// The function with the given name, defined in the same object (used by the compiler).
+    External(ir::UserExternalName),
+}
+impl Callee {
+    /// Declare the function in the function being built.
+    /// # Arguments
+    /// * `builder` - The builder of the function.
+    /// * `signature` - The signature of the called function.
+    /// * `ptr_type` - The target pointer type.
+    /// # Returns
+    /// * [DeclaredCallee] - The function that can be called from the function being built.
+    fn declare(&self, builder: &mut FunctionBuilder, signature: Signature, ptr_type: Type) -> DeclaredCallee {
+        match self {
+            Callee::Address(address) => {
+                let sig_ref = builder.import_signature(signature);
+                let address = builder.ins().iconst(ptr_type, *address as i64);
+                DeclaredCallee::Indirect(sig_ref, address)
+            },
+            Callee::External(name) => {
+                let signature = builder.import_signature(signature);
+                let name_ref = builder.func.declare_imported_user_function(name.clone());
+                let func_ref = builder.import_function(ir::ExtFuncData {
+                    name: ir::ExternalName::user(name_ref),
+                    signature,
+                    colocated: true,
+                });
+                DeclaredCallee::Direct(func_ref)
+            },
+        }
+    }
+}
+
+/// The [Callee] declared in the function being built.
+enum DeclaredCallee {
+    /// Called indirectly, through the address.
+    Indirect(ir::SigRef, Value),
+    /// Called directly (relocatable call).
+    Direct(ir::FuncRef),
+}
+impl DeclaredCallee {
+    /// Call the function.
+    /// # Arguments
+    /// * `builder` - The builder of the function.
+    /// * `args` - The arguments of the call.
+    /// # Returns
+    /// * [ir::Inst] - The call instruction.
+    fn call(&self, builder: &mut FunctionBuilder, args: &[Value]) -> ir::Inst {
+        match *self {
+            DeclaredCallee::Indirect(sig_ref, address) => builder.ins().call_indirect(sig_ref, address, args),
+            DeclaredCallee::Direct(func_ref) => builder.ins().call(func_ref, args),
+        }
+    }
+}
+
+
+
+/// Build the Cranelift function that executes the tokens.
+/// The function accepts three parameters - pointer to array of [STORAGE_SIZE] cells (of the `cell_width`) filled with zeros,
+/// pointer to which the final data pointer is written and the input/output context pointer (passed to the read and write functions).
+/// It returns the value (the low byte) of the final current cell.
+///
+/// If the function is `resumable`, it accepts the next parameter - the number of the loop (counted from 1 in the order
+/// of the opening brackets) in whose body the execution starts, or `0` to start at the beginning.
+/// The initial data pointer is then read from the data pointer parameter and the array doesn't have to be zeroed.
+///
+/// If the function runs on the `host_tape`, the initial data pointer is read from the data pointer parameter too
+/// and the array may hold any values (nothing is assumed about the cells or the data pointer).
+///
+/// If `fuel` is enabled, the function accepts the last parameter - the fuel (`i64`), the number of allowed jumps
+/// back to the start of a loop. When the loop would be repeated without any fuel left, the function returns immediately
+/// and writes [FUEL_EXHAUSTED] as the final data pointer. Without fuel, no extra code is emitted.
+///
+/// If `strict_bounds` is enabled, the function accepts the last parameter - the pointer to which the index
+/// of the offending token is written. Every move and every destination of [Token::AddTo], [Token::AddToCopy] and [Token::AddToCopy3]
+/// that isn't provably inside the array is checked. When the data pointer would leave the array, the function returns
+/// immediately and writes [POINTER_OUT_OF_BOUNDS] as the final data pointer.
+///
+/// If there are `hot_loops`, the function accepts the same last parameter as with `strict_bounds`. The range of the data pointer
+/// in each hot loop whose range is unknown is speculated (see [speculate_ranges]), so the loop is compiled without the wrap checks
+/// and its cells are addressed by the constant offsets. Before the loop is entered, a guard checks that the data pointer is inside
+/// the speculated range. If it isn't, the function returns immediately, writes the index of the opening bracket to the parameter
+/// and the data pointer as the final data pointer, so the caller can continue the execution from the loop in the generic way.
+///
+/// If the function is instrumented (`instrument`), it accepts the last parameter - the pointer to the zeroed `u64` counters,
+/// which are incremented during the execution (see [BACK_EDGES_COUNTER], [BYTES_READ_COUNTER], [BYTES_WRITTEN_COUNTER]
+/// and [LOOP_COUNTERS]). Without instrumentation, no extra code is emitted.
+/// # Arguments
+/// * `tokens` - The tokens to compile.
+/// * `target_isa` - The target ISA.
+/// * `runtime` - The functions called by the generated code (for input and output).
+/// * `config` - The optional features of the function.
+/// # Returns
+/// * [ir::Function] - The built function.
+pub(crate) fn build_function(
+    tokens: &[Token],
+    target_isa: &dyn TargetIsa,
+    runtime: &RuntimeFunctions,
+    config: FunctionConfig,
+) -> ir::Function {
+    // find target pointer type
+    let ptr_type = target_isa.pointer_type();
+
+    // find the type of the cells and their size (the data pointer is scaled by it)
+    let cell_type = cell_type(config.cell_width);
+    let cell_bytes = config.cell_width.bytes() as i64;
+
+    // find target call convention
+    let call_conv = isa::CallConv::triple_default(target_isa.triple());
+
+    // create function with a signature
+    // function accepts three parameters - pointer to array of STORAGE_SIZE length and filled with zero bytes,
+    // pointer to which the final data pointer is written and the input/output context pointer,
+    // it returns the value of the final current cell
+    let mut signature = Signature::new(call_conv);
+    signature.params.push(AbiParam::new(ptr_type));
+    signature.params.push(AbiParam::new(ptr_type));
+    signature.params.push(AbiParam::new(ptr_type));
+    if config.resumable {
+        signature.params.push(AbiParam::new(ptr_type));
+    }
+    if config.fuel {
+        signature.params.push(AbiParam::new(types::I64));
+    }
+    let speculate = !config.hot_loops.is_empty();
+    assert!(
+        !speculate || !(config.resumable || config.fuel || config.strict_bounds || config.instrument),
+        "The speculated function can't be resumable, have fuel, strict bounds or be instrumented.",
+    );
+    if config.strict_bounds || speculate {
+        signature.params.push(AbiParam::new(ptr_type));
+    }
+    if config.instrument {
+        signature.params.push(AbiParam::new(ptr_type));
+    }
+    signature.returns.push(AbiParam::new(types::I8));
+    let mut function = ir::Function::with_name_signature(ir::UserFuncName::default(), signature);
+
+    // create function builder
+    let mut func_ctx = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut function, &mut func_ctx);
+
+    // create memory flags (needed for load and store instructions)
+    let mem_flags = MemFlags::new();
+
+    // START of building the function
+
+    // define initial block
+    let first_block = builder.create_block();
+    builder.seal_block(first_block);
+    builder.append_block_params_for_function_params(first_block);
+    builder.switch_to_block(first_block);
+
+    // get the memory address of the start of the array (received as a parameter to the function)
+    let memory_address = builder.block_params(first_block)[0];
+
+    // get the address to which the final data pointer is written (received as a parameter to the function)
+    let data_ptr_out = builder.block_params(first_block)[1];
+
+    // get the input/output context pointer (received as a parameter to the function)
+    let io_ctx = builder.block_params(first_block)[2];
+
+    // get the optional parameters
+    let mut optional_params = builder.block_params(first_block)[3..].iter().copied();
+    let entry = config.resumable.then(|| optional_params.next().unwrap());
+    let initial_fuel = config.fuel.then(|| optional_params.next().unwrap());
+    let index_out = (config.strict_bounds || speculate).then(|| optional_params.next().unwrap());
+    let counters = config.instrument.then(|| optional_params.next().unwrap());
+
+    // declare data pointer variable and initialize it with zero (or the value passed to the resumable function or with the host tape)
+    let data_ptr = Variable::new(0);
+    builder.declare_var(data_ptr, ptr_type);
+    let initial_ptr = if config.resumable || config.host_tape {
+        builder.ins().load(ptr_type, mem_flags, data_ptr_out, 0)
+    } else {
+        builder.ins().iconst(ptr_type, 0)
+    };
+    builder.def_var(data_ptr, initial_ptr);
+
+    // declare fuel variable (initialized with the parameter) and the block executed when the fuel runs out
+    let fuel = initial_fuel.map(|initial_fuel| {
+        let fuel_var = Variable::new(1);
+        builder.declare_var(fuel_var, types::I64);
+        builder.def_var(fuel_var, initial_fuel);
+        (fuel_var, builder.create_block())
+    });
+
+    // declare the block executed when the data pointer would leave the array (accepts the index of the token)
+    let out_of_bounds = index_out.filter(|_| config.strict_bounds).map(|index_out| {
+        let out_of_bounds_block = builder.create_block();
+        builder.append_block_param(out_of_bounds_block, ptr_type);
+        (index_out, out_of_bounds_block)
+    });
+
+    // declare the block executed when the data pointer is outside of the speculated range of a loop
+    // (accepts the index of the opening bracket and the data pointer)
+    let deopt = index_out.filter(|_| speculate).map(|index_out| {
+        let deopt_block = builder.create_block();
+        builder.append_block_param(deopt_block, ptr_type);
+        builder.append_block_param(deopt_block, ptr_type);
+        (index_out, deopt_block)
+    });
+
+    // input and output functionality is achieved by calling external read and write functions
+
+    // declare signature for read function
+    let mut read_sig = Signature::new(call_conv);
+    read_sig.params.push(AbiParam::new(ptr_type));
+    read_sig.returns.push(AbiParam::new(types::I32));
+    let read_fn = runtime.read.declare(&mut builder, read_sig, ptr_type);
+
+    // declare signature for write function
+    let mut write_sig = Signature::new(call_conv);
+    write_sig.params.push(AbiParam::new(ptr_type));
+    write_sig.params.push(AbiParam::new(types::I8));
+    let write_fn = runtime.write.declare(&mut builder, write_sig, ptr_type);
+
+    // declare signature for the function writing the repeated bytes (only if there are consecutive outputs)
+    let write_repeated_fn = runtime.write_repeated.as_ref()
+        .filter(|_| tokens.windows(2).any(|pair| pair == [Token::Output, Token::Output]))
+        .map(|write_repeated| {
+            let mut write_repeated_sig = Signature::new(call_conv);
+            write_repeated_sig.params.push(AbiParam::new(ptr_type));
+            write_repeated_sig.params.push(AbiParam::new(types::I8));
+            write_repeated_sig.params.push(AbiParam::new(ptr_type));
+            write_repeated.declare(&mut builder, write_repeated_sig, ptr_type)
+        });
+
+    // declare signature for flush function
+    let flush_fn = runtime.flush.as_ref().map(|flush| {
+        let mut flush_sig = Signature::new(call_conv);
+        flush_sig.params.push(AbiParam::new(ptr_type));
+        flush.declare(&mut builder, flush_sig, ptr_type)
+    });
+
+    // declare signature for debug function (only if there are debug tokens)
+    let debug_fn = runtime.debug.as_ref().filter(|_| tokens.contains(&Token::Debug)).map(|debug| {
+        let mut debug_sig = Signature::new(call_conv);
+        debug_sig.params.push(AbiParam::new(ptr_type));
+        debug_sig.params.push(AbiParam::new(ptr_type));
+        debug_sig.params.push(AbiParam::new(ptr_type));
+        debug_sig.params.push(AbiParam::new(ptr_type));
+        debug.declare(&mut builder, debug_sig, ptr_type)
+    });
+
+    // the bodies of the loops, created in advance if the execution can start in them
+    // (the dispatch is emitted after all values used in the whole function are defined)
+    let mut loop_bodies = Vec::new().into_iter();
+    if let Some(entry) = entry {
+        let bodies: Vec<_> = tokens.iter()
+            .filter(|token| matches!(token, Token::OpenBr(_)))
+            .map(|_| builder.create_block())
+            .collect();
+
+        // jump to the body of the selected loop, or to the beginning
+        let start_block = builder.create_block();
+        let mut switch = Switch::new();
+        for (i, &body) in bodies.iter().enumerate() {
+            switch.set_entry(i as u128 + 1, body);
+        }
+        switch.emit(&mut builder, entry, start_block);
+
+        builder.seal_block(start_block);
+        builder.switch_to_block(start_block);
+        loop_bodies = bodies.into_iter();
+    }
+
+    // stack for tracking loop blocks
+    let mut stack = Vec::new();
+
+    // possible values of the data pointer before each token (used to skip the wrap checks)
+    // the data pointer is unknown when the execution starts inside a loop or on the host tape
+    let mut ranges = if config.resumable || config.host_tape { vec![None; tokens.len()] } else { pointer_ranges(tokens) };
+
+    // the speculated ranges of the hot loops (guarded at their opening brackets)
+    let guards = speculate_ranges(tokens, &mut ranges, config.hot_loops);
+
+    // the opening brackets of the guarded loops are marked with their indices (the jump distances are not used here),
+    // so they are found after the loops are folded
+    let marked_tokens: Vec<Token>;
+    let tokens = if guards.is_empty() {
+        tokens
+    } else {
+        marked_tokens = tokens.iter()
+            .enumerate()
+            .map(|(i, &token)| if let Token::OpenBr(_) = token { Token::OpenBr(i) } else { token })
+            .collect();
+        &marked_tokens
+    };
+
+    // the emitted tokens, the loops with the known trip count are replaced by straight-line code
+    // (not when the loop iterations are limited or counted, the execution starts inside a loop or on the host tape or the moves are checked,
+    // so the index of the planned token is also the index of the original token)
+    let plan: Vec<PlannedToken> = match config.unroll_limit {
+        Some(unroll_limit) if !config.resumable && !config.host_tape && !config.fuel && !config.strict_bounds && !config.instrument && config.cell_width == CellWidth::U8 => {
+            fold_constant_loops(tokens, &ranges, unroll_limit)
+        },
+        _ => tokens.iter().copied().zip(ranges).collect(),
+    };
+
+    // address of the current cell computed in the current block and the offset of the current cell from it
+    // (invalidated by the moves that wrap around the array and at the block boundaries)
+    let mut cell_cache = None;
+
+    // the number of the next loop (its iteration counter follows the counters of the previous loops)
+    let mut loop_number = 0;
+
+    // iterate over tokens and generate code for each token
+    let mut plan = plan.into_iter().enumerate().peekable();
+    while let Some((i, (token, range))) = plan.next() {
+        match token {
+            Token::Add(n) => {
+                // get the address of the current cell (memory_address + data_ptr)
+                let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+
+                // load the value from the current cell (in array)
+                let cell_value = builder.ins().load(cell_type, mem_flags, cell_address, offset);
+                // add n (as the signed change of the cell) to the value
+                let cell_value = builder.ins().iadd_imm(cell_value, i64::from(n as i8));
+
+                // store the new value back to the cell
+                builder.ins().store(mem_flags, cell_value, cell_address, offset);
+            },
+            Token::Move(n) if range.and_then(|range| shift_range(range, n)).is_some() => {
+                // the move provably doesn't wrap around the array, so the new pointer value is == old_value + n
+                let ptr_val = builder.use_var(data_ptr);
+                let ptr_val = builder.ins().iadd_imm(ptr_val, signed_distance(n) as i64);
+                builder.def_var(data_ptr, ptr_val);
+
+                // the address of the new cell is at the constant offset from the cached address
+                if let Some((_, offset)) = &mut cell_cache {
+                    *offset += (signed_distance(n) as i64 * cell_bytes) as i32;
+                }
+            },
+            Token::Move(n) if out_of_bounds.is_some() => {
+                // strict bounds - the new pointer value is == old_value + n, if it stays inside the array
+                let ptr_val = builder.use_var(data_ptr);
+                let ptr_val = builder.ins().iadd_imm(ptr_val, signed_distance(n) as i64);
+                let outside = outside_array(&mut builder, ptr_val);
+                branch_out_of_bounds(&mut builder, out_of_bounds, outside, i);
+                builder.def_var(data_ptr, ptr_val);
+
+                // the new cell is inside the array, so it is at the constant offset from the cached address
+                if let Some((_, offset)) = &mut cell_cache {
+                    *offset += (signed_distance(n) as i64 * cell_bytes) as i32;
+                }
+            },
+            Token::Move(n) => {
+                // load the data pointer value
+                let ptr_val = builder.use_var(data_ptr);
+
+                // the new pointer value is == (old_value + n) % STORAGE_SIZE
+                // but since remainder operation is expensive, we can calculate
+                // both (old_value + n) and (old_value + n - STORAGE_SIZE) and then
+                // select the correct value based on the condition (old_value + n < STORAGE_SIZE)
+
+                // old_value + n
+                let ptr_plus = builder.ins().iadd_imm(ptr_val, n as i64);
+                // old_value + n - STORAGE_SIZE
+                let ptr_wrapped = builder.ins().iadd_imm(ptr_val, n as i64 - STORAGE_SIZE as i64);
+
+                // compare (old_value + n) with STORAGE_SIZE
+                let cmp = builder.ins().icmp_imm(IntCC::SignedLessThan, ptr_plus, STORAGE_SIZE as i64);
+
+                // select the correct value based on the condition
+                let ptr_val = builder.ins().select(cmp, ptr_plus, ptr_wrapped);
+
+                // store the new data pointer value
+                builder.def_var(data_ptr, ptr_val);
+                cell_cache = None;
+            },
+            Token::Input => {
+                // get the address of the current cell (memory_address + data_ptr)
+                let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+
+                // flush the buffered output and call the read function
+                call_flush(&mut builder, flush_fn.as_ref(), io_ctx);
+                let read_res = read_fn.call(&mut builder, &[io_ctx]);
+                // get the result of the read function (negative at the end of input)
+                let read_res = builder.inst_results(read_res)[0];
+                let is_eof = builder.ins().icmp_imm(IntCC::SignedLessThan, read_res, 0);
+                if let Some(counters) = counters {
+                    // count the byte only if it was read
+                    let is_read = builder.ins().icmp_imm(IntCC::SignedGreaterThanOrEqual, read_res, 0);
+                    let is_read = builder.ins().uextend(types::I64, is_read);
+                    increment_counter(&mut builder, counters, BYTES_READ_COUNTER, is_read);
+                }
+                let byte = builder.ins().ireduce(types::I8, read_res);
+                // the byte is stored to the wider cells with the upper bits zeroed
+                let byte = if cell_type == types::I8 { byte } else { builder.ins().uextend(cell_type, byte) };
+
+                match eof_value(config.eof, config.cell_width) {
+                    Some(eof_value) => {
+                        // select the value stored at the end of input
+                        let eof_value = builder.ins().iconst(cell_type, eof_value);
+                        let byte = builder.ins().select(is_eof, eof_value, byte);
+                        builder.ins().store(mem_flags, byte, cell_address, offset);
+                    },
+                    None => {
+                        // skip the store at the end of input
+                        // (the cached cell address is defined before the branch, so it stays valid after it)
+                        let store_block = builder.create_block();
+                        let after_block = builder.create_block();
+                        builder.ins().brif(is_eof, after_block, &[], store_block, &[]);
+
+                        builder.switch_to_block(store_block);
+                        builder.seal_block(store_block);
+                        builder.ins().store(mem_flags, byte, cell_address, offset);
+                        builder.ins().jump(after_block, &[]);
+
+                        builder.switch_to_block(after_block);
+                        builder.seal_block(after_block);
+                    },
+                }
+            },
+            Token::Output => {
+                // the consecutive outputs write the same cell (nothing changes it in between)
+                let mut count = 1;
+                while plan.next_if(|(_, (token, _))| *token == Token::Output).is_some() {
+                    count += 1;
+                }
+
+                // get the address of the current cell (memory_address + data_ptr)
+                let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+                // load the value from the cell (only once for all the outputs)
+                let cell_value = builder.ins().load(cell_type, mem_flags, cell_address, offset);
+
+                if let Some(counters) = counters {
+                    let count = builder.ins().iconst(types::I64, count);
+                    increment_counter(&mut builder, counters, BYTES_WRITTEN_COUNTER, count);
+                }
+
+                // call the write function with the value (the low byte) from the cell
+                let cell_value = low_byte(&mut builder, cell_value);
+                match &write_repeated_fn {
+                    Some(write_repeated_fn) if count > 1 => {
+                        let count = builder.ins().iconst(ptr_type, count);
+                        write_repeated_fn.call(&mut builder, &[io_ctx, cell_value, count]);
+                    },
+                    _ => {
+                        for _ in 0..count {
+                            write_fn.call(&mut builder, &[io_ctx, cell_value]);
+                        }
+                    },
+                }
+            },
+            Token::OpenBr(index) => {
+                // the speculated loop is entered only if the data pointer is inside its range
+                if let Some(&(low, high)) = guards.get(&index) {
+                    let (_, deopt_block) = deopt.unwrap();
+                    let ptr_val = builder.use_var(data_ptr);
+                    // ptr_val < low wraps around to the huge unsigned value
+                    let distance = builder.ins().iadd_imm(ptr_val, -(low as i64));
+                    let outside = builder.ins().icmp_imm(IntCC::UnsignedGreaterThan, distance, (high - low) as i64);
+                    let index = builder.ins().iconst(ptr_type, index as i64);
+                    let continue_block = builder.create_block();
+                    builder.ins().brif(outside, deopt_block, &[index, ptr_val], continue_block, &[]);
+                    builder.seal_block(continue_block);
+                    builder.switch_to_block(continue_block);
+                }
+
+                // create two new blocks - one for the loop body and one for the code after the loop
+                let inner_block = loop_bodies.next().unwrap_or_else(|| builder.create_block());
+                let after_block = builder.create_block();
+
+                // get the address of the current cell (memory_address + data_ptr)
+                let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+                // load the value from the cell
+                let cell_value = builder.ins().load(cell_type, mem_flags, cell_address, offset);
+
+                // compare the value from the cell with zero
+                // if the value is zero, jump to the block after the loop, otherwise jump to the loop body
+                let eq_zero_cmp = builder.ins().icmp_imm(IntCC::Equal, cell_value, 0);
+                builder.ins().brif(eq_zero_cmp, after_block, &[], inner_block, &[]);
+
+                // switch to the loop body block (because next command will be inside the loop body)
+                builder.switch_to_block(inner_block);
+                cell_cache = None;
+
+                // every iteration starts in the loop body (entered from the opening bracket or by the jump back)
+                if let Some(counters) = counters {
+                    let one = builder.ins().iconst(types::I64, 1);
+                    increment_counter(&mut builder, counters, LOOP_COUNTERS + loop_number, one);
+                }
+                loop_number += 1;
+
+                // push the loop blocks to the stack
+                stack.push((inner_block, after_block));
+            },
+            Token::CloseBr(_) => {
+                // get the loop blocks from the stack (guaranteed to be there because loops are checked for correctness in the parser)
+                let (inner_block, after_block) = stack.pop().unwrap();
+
+                // get the address of the current cell (memory_address + data_ptr)
+                let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+                // load the value from the cell
+                let cell_value = builder.ins().load(cell_type, mem_flags, cell_address, offset);
+
+                // compare the value from the cell with zero
+                // if the value is zero, jump to the block after the loop, otherwise jump to the loop body (next iteration)
+                let eq_zero_cmp = builder.ins().icmp_imm(IntCC::Equal, cell_value, 0);
+                if fuel.is_none() && counters.is_none() {
+                    builder.ins().brif(eq_zero_cmp, after_block, &[], inner_block, &[]);
+                } else {
+                    // the jump back is counted and consumes fuel in a separate block
+                    let back_edge_block = builder.create_block();
+                    builder.ins().brif(eq_zero_cmp, after_block, &[], back_edge_block, &[]);
+                    builder.seal_block(back_edge_block);
+                    builder.switch_to_block(back_edge_block);
+
+                    if let Some(counters) = counters {
+                        let one = builder.ins().iconst(types::I64, 1);
+                        increment_counter(&mut builder, counters, BACK_EDGES_COUNTER, one);
+                    }
+
+                    match fuel {
+                        None => {
+                            builder.ins().jump(inner_block, &[]);
+                        },
+                        Some((fuel_var, exhausted_block)) => {
+                            // the next iteration consumes one unit of fuel, if there is none left, jump to the exhausted block
+                            let fuel_val = builder.use_var(fuel_var);
+                            let remaining = builder.ins().iadd_imm(fuel_val, -1);
+                            builder.def_var(fuel_var, remaining);
+                            builder.ins().brif(fuel_val, inner_block, &[], exhausted_block, &[]);
+                        },
+                    }
+                }
+
+                // now all jumps to these blocks are defined, so we can seal them
+                builder.seal_block(inner_block);
+                builder.seal_block(after_block);
+
+                // switch to the block after the loop (where next command will be)
+                builder.switch_to_block(after_block);
+                cell_cache = None;
+            },
+            Token::ClearCell => {
+                // get the address of the current cell (memory_address + data_ptr)
+                let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+            
+                // create a constant zero value
+                let zero = builder.ins().iconst(cell_type, 0);
+
+                // store the zero value to the cell
+                builder.ins().store(mem_flags, zero, cell_address, offset);
+            },
+            Token::SetCell(n) => {
+                // get the address of the current cell (memory_address + data_ptr)
+                let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+
+                // the value is sign-extended to the wider cells (the same as the cleared cell with the added value)
+                let value = (n as i8 as i64) & ((1 << cell_type.bits()) - 1);
+                let value = builder.ins().iconst(cell_type, value);
+                builder.ins().store(mem_flags, value, cell_address, offset);
+            },
+            Token::AddTo(n) if range.and_then(|range| shift_range(range, n)).is_some() => {
+                // the destination cell provably doesn't wrap around the array,
+                // so it is addressed by the constant offset from the current cell
+                let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+                let new_offset = offset + (signed_distance(n) as i64 * cell_bytes) as i32;
+
+                // load values from the original and new cells
+                let original_value = builder.ins().load(cell_type, mem_flags, cell_address, offset);
+                let new_value = builder.ins().load(cell_type, mem_flags, cell_address, new_offset);
+
+                // add the original value to the new value and store it back to its cell
+                let new_value = builder.ins().iadd(new_value, original_value);
+                builder.ins().store(mem_flags, new_value, cell_address, new_offset);
+
+                // zero the original cell
+                let zero = builder.ins().iconst(cell_type, 0);
+                builder.ins().store(mem_flags, zero, cell_address, offset);
+            },
+            Token::AddTo(n) => {
+                // strict bounds - the original loop moves to the destination cell only if the current cell isn't zero
+                if out_of_bounds.is_some() {
+                    let ptr_val = builder.use_var(data_ptr);
+                    let destination = builder.ins().iadd_imm(ptr_val, signed_distance(n) as i64);
+                    let outside = outside_array(&mut builder, destination);
+                    let outside = nonzero_cell_and(&mut builder, &mut cell_cache, (memory_address, cell_type, cell_bytes), data_ptr, outside);
+                    branch_out_of_bounds(&mut builder, out_of_bounds, outside, i);
+                }
+
+                // load the data pointer value
+                let ptr_val = builder.use_var(data_ptr);
+            
+                // calculate the pointer of the destination cell
+                // ptr_value + n
+                let ptr_plus = builder.ins().iadd_imm(ptr_val, n as i64);
+                // ptr_value + n - STORAGE_SIZE
+                let ptr_wrapped = builder.ins().iadd_imm(ptr_val, n as i64 - STORAGE_SIZE as i64);
+                // compare (ptr_value + n) with STORAGE_SIZE
+                let cmp = builder.ins().icmp_imm(IntCC::SignedLessThan, ptr_plus, STORAGE_SIZE as i64);
+                // select the correct value based on the condition
+                let new_loc = builder.ins().select(cmp, ptr_plus, ptr_wrapped);
+
+                // calculate the addresses of the original and new cells
+                let (original_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+                let new_address = cell_address(&mut builder, memory_address, new_loc, cell_bytes);
+
+                // load values from the original and new cells
+                let original_value = builder.ins().load(cell_type, mem_flags, original_address, offset);
+                let new_value = builder.ins().load(cell_type, mem_flags, new_address, 0);
+            
+                // add the original value to the new value
+                let new_value = builder.ins().iadd(new_value, original_value);
+
+                // store the new value back to its cell
+                builder.ins().store(mem_flags, new_value, new_address, 0);
+            
+                // zero the original cell
+                let zero = builder.ins().iconst(cell_type, 0);
+                builder.ins().store(mem_flags, zero, original_address, offset);
+            },
+            Token::AddToCopy(n1, n2) if range.is_some_and(|range| shift_range(range, n1).is_some() && shift_range(range, n2).is_some()) => {
+                // the destination cells provably don't wrap around the array,
+                // so they are addressed by the constant offsets from the current cell
+                let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+                let new_offset_1 = offset + (signed_distance(n1) as i64 * cell_bytes) as i32;
+                let new_offset_2 = offset + (signed_distance(n2) as i64 * cell_bytes) as i32;
+
+                // load values from the original and new cells
+                let original_value = builder.ins().load(cell_type, mem_flags, cell_address, offset);
+                let new_value_1 = builder.ins().load(cell_type, mem_flags, cell_address, new_offset_1);
+                let new_value_2 = builder.ins().load(cell_type, mem_flags, cell_address, new_offset_2);
+
+                // add the original value to the new values and store them back to their cells
+                let new_value_1 = builder.ins().iadd(new_value_1, original_value);
+                let new_value_2 = builder.ins().iadd(new_value_2, original_value);
+                builder.ins().store(mem_flags, new_value_1, cell_address, new_offset_1);
+                builder.ins().store(mem_flags, new_value_2, cell_address, new_offset_2);
+
+                // zero the original cell
+                let zero = builder.ins().iconst(cell_type, 0);
+                builder.ins().store(mem_flags, zero, cell_address, offset);
+            },
+            Token::AddToCopy(n1, n2) => {
+                // strict bounds - the original loop moves to the destination cells only if the current cell isn't zero
+                if out_of_bounds.is_some() {
+                    let ptr_val = builder.use_var(data_ptr);
+                    let destination_1 = builder.ins().iadd_imm(ptr_val, signed_distance(n1) as i64);
+                    let destination_2 = builder.ins().iadd_imm(ptr_val, signed_distance(n2) as i64);
+                    let outside_1 = outside_array(&mut builder, destination_1);
+                    let outside_2 = outside_array(&mut builder, destination_2);
+                    let outside = builder.ins().bor(outside_1, outside_2);
+                    let outside = nonzero_cell_and(&mut builder, &mut cell_cache, (memory_address, cell_type, cell_bytes), data_ptr, outside);
+                    branch_out_of_bounds(&mut builder, out_of_bounds, outside, i);
+                }
+
+                // load the data pointer value
+                let ptr_val = builder.use_var(data_ptr);
+
+                // calculate the pointer of the destination cells
+            
+                // ptr_value + n1
+                let ptr_plus = builder.ins().iadd_imm(ptr_val, n1 as i64);
+                // ptr_value + n1 - STORAGE_SIZE
+                let ptr_wrapped = builder.ins().iadd_imm(ptr_val, n1 as i64 - STORAGE_SIZE as i64);
+                // compare (ptr_value + n1) with STORAGE_SIZE
+                let cmp = builder.ins().icmp_imm(IntCC::SignedLessThan, ptr_plus, STORAGE_SIZE as i64);
+                // select the correct value based on the condition
+                let new_loc_1 = builder.ins().select(cmp, ptr_plus, ptr_wrapped);
+            
+                // ptr_value + n2
+                let ptr_plus = builder.ins().iadd_imm(ptr_val, n2 as i64);
+                // ptr_value + n2 - STORAGE_SIZE
+                let ptr_wrapped = builder.ins().iadd_imm(ptr_val, n2 as i64 - STORAGE_SIZE as i64);
+                // compare (ptr_value + n2) with STORAGE_SIZE
+                let cmp = builder.ins().icmp_imm(IntCC::SignedLessThan, ptr_plus, STORAGE_SIZE as i64);
+                // select the correct value based on the condition
+                let new_loc_2 = builder.ins().select(cmp, ptr_plus, ptr_wrapped);
+
+                // calculate the addresses of the original and new cells
+                let (original_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+                let new_address_1 = cell_address(&mut builder, memory_address, new_loc_1, cell_bytes);
+                let new_address_2 = cell_address(&mut builder, memory_address, new_loc_2, cell_bytes);
+            
+                // load values from the original and new cells
+                let original_value = builder.ins().load(cell_type, mem_flags, original_address, offset);
+                let new_value_1 = builder.ins().load(cell_type, mem_flags, new_address_1, 0);
+                let new_value_2 = builder.ins().load(cell_type, mem_flags, new_address_2, 0);
+
+                // add the original value to the new values
+                let new_value_1 = builder.ins().iadd(new_value_1, original_value);
+                let new_value_2 = builder.ins().iadd(new_value_2, original_value);
+
+                // store the new values back to their cells
+                builder.ins().store(mem_flags, new_value_1, new_address_1, 0);
+                builder.ins().store(mem_flags, new_value_2, new_address_2, 0);
+
+                // zero the original cell
+                let zero = builder.ins().iconst(cell_type, 0);
+                builder.ins().store(mem_flags, zero, original_address, offset);
+            },
+            Token::AddToCopy3(n1, n2, n3) if range.is_some_and(|range| [n1, n2, n3].iter().all(|&n| shift_range(range, n).is_some())) => {
+                // the destination cells provably don't wrap around the array,
+                // so they are addressed by the constant offsets from the current cell
+                let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+                let original_value = builder.ins().load(cell_type, mem_flags, cell_address, offset);
+
+                // add the original value to the new values one after another (a destination may repeat)
+                for n in [n1, n2, n3] {
+                    let new_offset = offset + (signed_distance(n) as i64 * cell_bytes) as i32;
+                    let new_value = builder.ins().load(cell_type, mem_flags, cell_address, new_offset);
+                    let new_value = builder.ins().iadd(new_value, original_value);
+                    builder.ins().store(mem_flags, new_value, cell_address, new_offset);
+                }
+
+                // zero the original cell
+                let zero = builder.ins().iconst(cell_type, 0);
+                builder.ins().store(mem_flags, zero, cell_address, offset);
+            },
+            Token::AddToCopy3(n1, n2, n3) => {
+                let destinations = [n1, n2, n3];
+
+                // strict bounds - the original loop moves to the destination cells only if the current cell isn't zero
+                if out_of_bounds.is_some() {
+                    let ptr_val = builder.use_var(data_ptr);
+                    let [outside_1, outside_2, outside_3] = destinations.map(|n| {
+                        let destination = builder.ins().iadd_imm(ptr_val, signed_distance(n) as i64);
+                        outside_array(&mut builder, destination)
+                    });
+                    let outside = builder.ins().bor(outside_1, outside_2);
+                    let outside = builder.ins().bor(outside, outside_3);
+                    let outside = nonzero_cell_and(&mut builder, &mut cell_cache, (memory_address, cell_type, cell_bytes), data_ptr, outside);
+                    branch_out_of_bounds(&mut builder, out_of_bounds, outside, i);
+                }
+
+                // load the data pointer value and the original cell
+                let ptr_val = builder.use_var(data_ptr);
+                let (original_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+                let original_value = builder.ins().load(cell_type, mem_flags, original_address, offset);
+
+                // add the original value to the new values one after another (a destination may repeat)
+                for n in destinations {
+                    // ptr_value + n, wrapped around the array
+                    let ptr_plus = builder.ins().iadd_imm(ptr_val, n as i64);
+                    let ptr_wrapped = builder.ins().iadd_imm(ptr_val, n as i64 - STORAGE_SIZE as i64);
+                    let cmp = builder.ins().icmp_imm(IntCC::SignedLessThan, ptr_plus, STORAGE_SIZE as i64);
+                    let new_loc = builder.ins().select(cmp, ptr_plus, ptr_wrapped);
+
+                    let new_address = cell_address(&mut builder, memory_address, new_loc, cell_bytes);
+                    let new_value = builder.ins().load(cell_type, mem_flags, new_address, 0);
+                    let new_value = builder.ins().iadd(new_value, original_value);
+                    builder.ins().store(mem_flags, new_value, new_address, 0);
+                }
+
+                // zero the original cell
+                let zero = builder.ins().iconst(cell_type, 0);
+                builder.ins().store(mem_flags, zero, original_address, offset);
+            },
+            Token::Debug => {
+                // call the debug function with the array, its length and the data pointer (ignored without the function)
+                if let Some(debug_fn) = &debug_fn {
+                    call_flush(&mut builder, flush_fn.as_ref(), io_ctx);
+                    let ptr_val = builder.use_var(data_ptr);
+                    let tape_len = builder.ins().iconst(ptr_type, STORAGE_SIZE as i64);
+                    let cell_size = builder.ins().iconst(ptr_type, cell_bytes);
+                    debug_fn.call(&mut builder, &[memory_address, tape_len, ptr_val, cell_size]);
+                }
+            },
+        }
+    }
+
+    // flush the buffered output and write the final data pointer
+    call_flush(&mut builder, flush_fn.as_ref(), io_ctx);
+    let ptr_val = builder.use_var(data_ptr);
+    builder.ins().store(mem_flags, ptr_val, data_ptr_out, 0);
+
+    // return the value of the final current cell
+    let (cell_address, offset) = current_cell(&mut builder, &mut cell_cache, memory_address, data_ptr, cell_bytes);
+    let cell_value = builder.ins().load(cell_type, mem_flags, cell_address, offset);
+    let cell_value = low_byte(&mut builder, cell_value);
+    builder.ins().return_(&[cell_value]);
+
+    // when the fuel runs out, mark the final data pointer and return
+    if let Some((_, exhausted_block)) = fuel {
+        builder.seal_block(exhausted_block);
+        builder.switch_to_block(exhausted_block);
+        call_flush(&mut builder, flush_fn.as_ref(), io_ctx);
+        let marker = builder.ins().iconst(ptr_type, FUEL_EXHAUSTED as i64);
+        builder.ins().store(mem_flags, marker, data_ptr_out, 0);
+        let zero = builder.ins().iconst(types::I8, 0);
+        builder.ins().return_(&[zero]);
+    }
+
+    // when the data pointer would leave the array, write the index of the token, mark the final data pointer and return
+    if let Some((index_out, out_of_bounds_block)) = out_of_bounds {
+        builder.seal_block(out_of_bounds_block);
+        builder.switch_to_block(out_of_bounds_block);
+        let index = builder.block_params(out_of_bounds_block)[0];
+        call_flush(&mut builder, flush_fn.as_ref(), io_ctx);
+        builder.ins().store(mem_flags, index, index_out, 0);
+        let marker = builder.ins().iconst(ptr_type, POINTER_OUT_OF_BOUNDS as i64);
+        builder.ins().store(mem_flags, marker, data_ptr_out, 0);
+        let zero = builder.ins().iconst(types::I8, 0);
+        builder.ins().return_(&[zero]);
+    }
+
+    // when the data pointer is outside of the speculated range, write the index of the loop and the data pointer and return
+    if let Some((index_out, deopt_block)) = deopt {
+        builder.seal_block(deopt_block);
+        builder.switch_to_block(deopt_block);
+        let index = builder.block_params(deopt_block)[0];
+        let ptr_val = builder.block_params(deopt_block)[1];
+        call_flush(&mut builder, flush_fn.as_ref(), io_ctx);
+        builder.ins().store(mem_flags, index, index_out, 0);
+        builder.ins().store(mem_flags, ptr_val, data_ptr_out, 0);
+        let zero = builder.ins().iconst(types::I8, 0);
+        builder.ins().return_(&[zero]);
+    }
+
+    // finalize the function
+    builder.finalize();
+
+    // END of building the function
+
+    function
+}
+
+/// Split the tokens into the parts compiled as separate functions (see [build_driver]).
+/// The tokens are split only at the top level (between the loops), so a loop never straddles two parts.
+/// Each part has at most `max_tokens` tokens, unless it is a single top-level loop longer than that.
+/// # Arguments
+/// * `tokens` - The tokens.
+/// * `max_tokens` - The maximal number of tokens in a part.
+/// # Returns
+/// * `Vec<&[Token]>` - The consecutive parts of the tokens (a single part if the brackets are unmatched).
+pub(crate) fn partition(tokens: &[Token], max_tokens: usize) -> Vec<&[Token]> {
+    let mut parts = Vec::new();
+
+    // the start of the current part and the end of its last top-level token or loop
+    let mut start = 0;
+    let mut boundary = 0;
+    let mut depth = 0_usize;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenBr(_) => depth += 1,
+            Token::CloseBr(_) => depth = depth.saturating_sub(1),
+            _ => {},
+        }
+        if depth == 0 {
+            // the part would be too long with this token or loop, so it ends before it
+            if i + 1 - start > max_tokens && boundary > start {
+                parts.push(&tokens[start..boundary]);
+                start = boundary;
+            }
+            boundary = i + 1;
+        }
+    }
+    parts.push(&tokens[start..]);
+
+    parts
+}
+
+/// Build the Cranelift function that executes the parts of the tokens (see [partition]) one after another.
+/// The function has the same parameters and return value as the function built by [build_function] without any optional features.
+///
+/// The parts are the functions built by [build_function] with `host_tape`, so each of them reads the initial data pointer
+/// and writes the final data pointer through the shared data pointer parameter. They are called indirectly through the table
+/// of their addresses, which has to be filled before the function runs. The value returned by the last part is returned.
+///
+/// If the function runs on the `host_tape`, the initial data pointer is read from the data pointer parameter,
+/// otherwise the data pointer starts at zero.
+/// # Arguments
+/// * `parts` - The number of the parts.
+/// * `part_table` - The table of the addresses of the parts (valid for the whole execution of the function).
+/// * `target_isa` - The target ISA.
+/// * `host_tape` - Whether the function runs on the array provided by the host.
+/// # Returns
+/// * [ir::Function] - The built function.
+pub(crate) fn build_driver(parts: usize, part_table: *const usize, target_isa: &dyn TargetIsa, host_tape: bool) -> ir::Function {
+    let ptr_type = target_isa.pointer_type();
+    let call_conv = isa::CallConv::triple_default(target_isa.triple());
+
+    // the driver and the parts accept the array pointer, the data pointer pointer and the input/output context pointer
+    let mut signature = Signature::new(call_conv);
+    signature.params.push(AbiParam::new(ptr_type));
+    signature.params.push(AbiParam::new(ptr_type));
+    signature.params.push(AbiParam::new(ptr_type));
+    signature.returns.push(AbiParam::new(types::I8));
+    let mut function = ir::Function::with_name_signature(ir::UserFuncName::default(), signature.clone());
+
+    let mut func_ctx = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut function, &mut func_ctx);
+    let mem_flags = MemFlags::new();
+
+    let block = builder.create_block();
+    builder.seal_block(block);
+    builder.append_block_params_for_function_params(block);
+    builder.switch_to_block(block);
+    let params = builder.block_params(block).to_vec();
+
+    // the parts read the initial data pointer, so it is zeroed unless it was passed by the host
+    if !host_tape {
+        let zero = builder.ins().iconst(ptr_type, 0);
+        builder.ins().store(mem_flags, zero, params[1], 0);
+    }
+
+    // call the parts in order, each continuing from the data pointer written by the previous one
+    let part_sig = builder.import_signature(signature);
+    let table = builder.ins().iconst(ptr_type, part_table as i64);
+    let mut cell_value = builder.ins().iconst(types::I8, 0);
+    for i in 0..parts {
+        let address = builder.ins().load(ptr_type, mem_flags, table, (i * ptr_type.bytes() as usize) as i32);
+        let call = builder.ins().call_indirect(part_sig, address, &params);
+        cell_value = builder.inst_results(call)[0];
+    }
+    builder.ins().return_(&[cell_value]);
+
+    builder.finalize();
+
+    function
+}
+
+/// Get the address of the current cell, reusing the address cached in the current block.
+/// # Arguments
+/// * `builder` - The builder of the function.
+/// * `cell_cache` - The cached address and the offset of the current cell from it.
+/// * `memory_address` - The address of the array.
+/// * `data_ptr` - The data pointer variable.
+/// * `cell_bytes` - The size of a cell in bytes.
+/// # Returns
+/// * `(Value, i32)` - The address and the offset of the current cell (to be used in load and store instructions).
+fn current_cell(builder: &mut FunctionBuilder, cell_cache: &mut Option<(Value, i32)>, memory_address: Value, data_ptr: Variable, cell_bytes: i64) -> (Value, i32) {
+    *cell_cache.get_or_insert_with(|| {
+        let ptr_val = builder.use_var(data_ptr);
+        (cell_address(builder, memory_address, ptr_val, cell_bytes), 0)
+    })
+}
+
+/// Get the address of the cell (memory_address + ptr_val * cell_bytes).
+/// # Arguments
+/// * `builder` - The builder of the function.
+/// * `memory_address` - The address of the array.
+/// * `ptr_val` - The index of the cell.
+/// * `cell_bytes` - The size of a cell in bytes.
+/// # Returns
+/// * [Value] - The address of the cell.
+fn cell_address(builder: &mut FunctionBuilder, memory_address: Value, ptr_val: Value, cell_bytes: i64) -> Value {
+    let offset = if cell_bytes == 1 { ptr_val } else { builder.ins().imul_imm(ptr_val, cell_bytes) };
+    builder.ins().iadd(memory_address, offset)
+}
+
+/// The Cranelift type of the cells.
+/// # Arguments
+/// * `cell_width` - The width of the cells.
+/// # Returns
+/// * [Type] - The integer type of the width.
+fn cell_type(cell_width: CellWidth) -> Type {
+    match cell_width {
+        CellWidth::U8 => types::I8,
+        CellWidth::U16 => types::I16,
+        CellWidth::U32 => types::I32,
+    }
+}
+
+/// Truncate the value of the cell to its low byte (the passed and returned values are bytes).
+/// # Arguments
+/// * `builder` - The builder of the function.
+/// * `cell_value` - The value of the cell.
+/// # Returns
+/// * [Value] - The low byte of the value.
+fn low_byte(builder: &mut FunctionBuilder, cell_value: Value) -> Value {
+    if builder.func.dfg.value_type(cell_value) == types::I8 {
+        cell_value
+    } else {
+        builder.ins().ireduce(types::I8, cell_value)
+    }
+}
+
+/// The value stored to the cell at the end of input.
+/// # Arguments
+/// * `eof` - The [EofPolicy].
+/// * `cell_width` - The width of the cells ([EofPolicy::Max] sets all bits of the cell).
+/// # Returns
+/// * `Some(i64)` - The stored value.
+/// * `None` - If the cell is left unchanged.
+fn eof_value(eof: EofPolicy, cell_width: CellWidth) -> Option<i64> {
+    match eof {
+        EofPolicy::Max => Some((1 << (8 * cell_width.bytes())) - 1),
+        _ => eof.value().map(i64::from),
+    }
+}
+
+/// Call the flush function (if there is one).
+/// # Arguments
+/// * `builder` - The builder of the function.
+/// * `flush_fn` - The declared flush function.
+/// * `io_ctx` - The input/output context pointer.
+fn call_flush(builder: &mut FunctionBuilder, flush_fn: Option<&DeclaredCallee>, io_ctx: Value) {
+    if let Some(flush_fn) = flush_fn {
+        flush_fn.call(builder, &[io_ctx]);
+    }
+}
+
+/// Add the amount to the counter (in the instrumented function).
+/// # Arguments
+/// * `builder` - The builder of the function.
+/// * `counters` - The pointer to the counters.
+/// * `counter` - The index of the counter.
+/// * `amount` - The added amount (`i64`).
+fn increment_counter(builder: &mut FunctionBuilder, counters: Value, counter: usize, amount: Value) {
+    let offset = (counter * 8) as i32;
+    let value = builder.ins().load(types::I64, MemFlags::trusted(), counters, offset);
+    let value = builder.ins().iadd(value, amount);
+    builder.ins().store(MemFlags::trusted(), value, counters, offset);
+}
+
+/// Check whether the data pointer is outside of the array (strict bounds).
+/// # Arguments
+/// * `builder` - The builder of the function.
+/// * `ptr_val` - The data pointer (the pointers below zero are compared as huge unsigned values).
+/// # Returns
+/// * [Value] - The result of the comparison.
+fn outside_array(builder: &mut FunctionBuilder, ptr_val: Value) -> Value {
+    builder.ins().icmp_imm(IntCC::UnsignedGreaterThanOrEqual, ptr_val, STORAGE_SIZE as i64)
+}
+
+/// Combine the condition with the check that the current cell isn't zero.
+/// # Arguments
+/// * `builder` - The builder of the function.
+/// * `cell_cache` - The cached address and the offset of the current cell from it.
+/// * `(memory_address, cell_type, cell_bytes)` - The address of the array, the type of the cells and their size.
+/// * `data_ptr` - The data pointer variable.
+/// * `condition` - The condition.
+/// # Returns
+/// * [Value] - Whether the condition holds and the current cell isn't zero.
+fn nonzero_cell_and(
+    builder: &mut FunctionBuilder,
+    cell_cache: &mut Option<(Value, i32)>,
+    (memory_address, cell_type, cell_bytes): (Value, Type, i64),
+    data_ptr: Variable,
+    condition: Value,
+) -> Value {
+    let (cell_address, offset) = current_cell(builder, cell_cache, memory_address, data_ptr, cell_bytes);
+    let cell_value = builder.ins().load(cell_type, MemFlags::new(), cell_address, offset);
+    let nonzero = builder.ins().icmp_imm(IntCC::NotEqual, cell_value, 0);
+    builder.ins().band(condition, nonzero)
+}
+
+/// Jump to the out-of-bounds block if the condition holds, otherwise continue in a new block.
+/// # Arguments
+/// * `builder` - The builder of the function.
+/// * `out_of_bounds` - The pointer to which the index of the token is written and the out-of-bounds block.
+/// * `condition` - Whether the data pointer would leave the array.
+/// * `index` - The index of the checked token.
+fn branch_out_of_bounds(builder: &mut FunctionBuilder, out_of_bounds: Option<(Value, Block)>, condition: Value, index: usize) {
+    let (index_out, out_of_bounds_block) = out_of_bounds.unwrap();
+    let ptr_type = builder.func.dfg.value_type(index_out);
+    let index = builder.ins().iconst(ptr_type, index as i64);
+    let continue_block = builder.create_block();
+    builder.ins().brif(condition, out_of_bounds_block, &[index], continue_block, &[]);
+    builder.seal_block(continue_block);
+    builder.switch_to_block(continue_block);
+}
+
+/// Calculate the possible values of the data pointer before each token.
+/// The data pointer starts at `0`. A loop keeps the known range only if its body provably returns the data pointer
+/// to the same cell (net movement is zero), otherwise the range after the loop start is unknown.
+/// # Arguments
+/// * `tokens` - The tokens.
+/// # Returns
+/// * Vec<Option<(usize, usize)>> - The inclusive range of the data pointer before each token (`None` if unknown).
+fn pointer_ranges(tokens: &[Token]) -> Vec<Option<(usize, usize)>> {
+    pointer_ranges_from(tokens, (0, 0))
+}
+
+/// Calculate the possible values of the data pointer before each token, starting in the given range (see [pointer_ranges]).
+/// # Arguments
+/// * `tokens` - The tokens.
+/// * `start` - The inclusive range of the data pointer before the first token.
+/// # Returns
+/// * Vec<Option<(usize, usize)>> - The inclusive range of the data pointer before each token (`None` if unknown).
+fn pointer_ranges_from(tokens: &[Token], start: (usize, usize)) -> Vec<Option<(usize, usize)>> {
+    let Some(matching) = matching_brackets(tokens) else {
+        return vec![None; tokens.len()];
+    };
+
+    let mut ranges = Vec::with_capacity(tokens.len());
+    let mut range = Some(start);
+    let mut range_stack = Vec::new();
+    for (i, &token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenBr(_) => {
+                if net_movement(tokens, i + 1, matching[i], &matching) != Some(0) {
+                    range = None;
+                }
+                ranges.push(range);
+                range_stack.push(range);
+            },
+            Token::CloseBr(_) => {
+                ranges.push(range);
+                // the loop is exited with the data pointer at the same cell as at the loop start
+                range = range_stack.pop().unwrap();
+            },
+            Token::Move(n) => {
+                ranges.push(range);
+                range = range.and_then(|range| shift_range(range, n));
+            },
+            _ => ranges.push(range),
+        }
+    }
+
+    ranges
+}
+
+/// Speculate the range of the data pointer in the hot loops whose range is unknown.
+/// The loop is speculated if its body (including the nested loops) returns the data pointer to the same cell
+/// without wrapping it around the array. Its cells then span a fixed window around the data pointer at the loop start,
+/// and the range of the data pointer at the loop start is every position at which the window fits inside the array.
+/// The ranges inside the loop are recalculated from it, the range after the loop stays the same.
+/// # Arguments
+/// * `tokens` - The tokens.
+/// * `ranges` - The range of the data pointer before each token (see [pointer_ranges]), updated with the speculated ranges.
+/// * `hot_loops` - The indices of the opening brackets of the hot loops (the outer loops first).
+/// # Returns
+/// * `HashMap<usize, (usize, usize)>` - The speculated range by the index of the opening bracket (checked by the guard).
+fn speculate_ranges(tokens: &[Token], ranges: &mut [Option<(usize, usize)>], hot_loops: &[usize]) -> HashMap<usize, (usize, usize)> {
+    let mut guards = HashMap::new();
+    let Some(matching) = matching_brackets(tokens) else {
+        return guards;
+    };
+
+    for &open_br in hot_loops {
+        // the loops inside the speculated loops already have the known range
+        if !matches!(tokens.get(open_br), Some(Token::OpenBr(_))) || ranges[open_br].is_some() {
+            continue;
+        }
+        let close_br = matching[open_br];
+
+        // the offsets of the accessed cells from the data pointer at the loop start
+        let (mut offset, mut lowest, mut highest) = (0_isize, 0_isize, 0_isize);
+        let mut offset_stack = Vec::new();
+        let mut balanced = true;
+        for &token in &tokens[open_br + 1..close_br] {
+            let destinations = match token {
+                Token::Move(n) => {
+                    offset += signed_distance(n);
+                    vec![offset]
+                },
+                Token::AddTo(n) => vec![offset + signed_distance(n)],
+                Token::AddToCopy(n1, n2) => vec![offset + signed_distance(n1), offset + signed_distance(n2)],
+                Token::AddToCopy3(n1, n2, n3) => vec![offset + signed_distance(n1), offset + signed_distance(n2), offset + signed_distance(n3)],
+                Token::OpenBr(_) => {
+                    offset_stack.push(offset);
+                    Vec::new()
+                },
+                Token::CloseBr(_) => {
+                    balanced &= offset_stack.pop() == Some(offset);
+                    Vec::new()
+                },
+                _ => Vec::new(),
+            };
+            for destination in destinations {
+                lowest = lowest.min(destination);
+                highest = highest.max(destination);
+            }
+        }
+        if !balanced || offset != 0 || highest - lowest >= STORAGE_SIZE as isize {
+            continue;
+        }
+
+        let guard = (lowest.unsigned_abs(), STORAGE_SIZE - 1 - highest as usize);
+        for (range, loop_range) in ranges[open_br..=close_br].iter_mut().zip(pointer_ranges_from(&tokens[open_br..=close_br], guard)) {
+            *range = loop_range;
+        }
+        guards.insert(open_br, guard);
+    }
+
+    guards
+}
+
+/// Find the matching brackets (the jump distances in the tokens are not trusted).
+/// # Arguments
+/// * `tokens` - The tokens.
+/// # Returns
+/// * `Some(Vec<usize>)` - The index of the matching bracket for each bracket (other tokens have `0`).
+/// * `None` - If the brackets are unmatched.
+fn matching_brackets(tokens: &[Token]) -> Option<Vec<usize>> {
+    let mut matching = vec![0; tokens.len()];
+    let mut loop_stack = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenBr(_) => loop_stack.push(i),
+            Token::CloseBr(_) => {
+                let open_br = loop_stack.pop()?;
+                matching[open_br] = i;
+                matching[i] = open_br;
+            },
+            _ => {},
+        }
+    }
+
+    loop_stack.is_empty().then_some(matching)
+}
+
+/// Replace the loops with the statically known trip count by straight-line code.
+/// The values of the cells are tracked from the start (where all cells are zero) until the first loop that is kept,
+/// and inside the loops from the last bracket (e.g. after `[-]+++`).
+/// A loop whose current cell is known to be zero is removed. A loop with the known non-zero current cell
+/// whose body contains no input, output or loop, returns the data pointer to the same cell
+/// and changes the current cell only by additions, terminates after the known number of iterations.
+/// If its body contains only additions and moves, it is replaced by the total effect of all the iterations,
+/// otherwise the body is repeated if the number of iterations is at most `unroll_limit`.
+/// # Arguments
+/// * `tokens` - The tokens.
+/// * `ranges` - The range of the data pointer before each token (see [pointer_ranges]).
+/// * `unroll_limit` - The maximal number of iterations of the unrolled loop.
+/// # Returns
+/// * `Vec<PlannedToken>` - The tokens to emit with the ranges of the data pointer before them.
+fn fold_constant_loops(tokens: &[Token], ranges: &[Option<(usize, usize)>], unroll_limit: usize) -> Vec<PlannedToken> {
+    let Some(matching) = matching_brackets(tokens) else {
+        return tokens.iter().copied().zip(ranges.iter().copied()).collect();
+    };
+
+    let mut plan = Vec::with_capacity(tokens.len());
+    let mut known = KnownCells::zeroed();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i];
+        match token {
+            Token::OpenBr(_) => {
+                let close_br = matching[i];
+                let body = &tokens[i + 1..close_br];
+                if let Some(replacement) = replace_loop(body, &ranges[i + 1..close_br], ranges[i], known.get(0), unroll_limit) {
+                    for &(token, _) in &replacement {
+                        known.apply(token);
+                    }
+                    plan.extend(replacement);
+                    i = close_br + 1;
+                    continue;
+                }
+
+                // the values in the body depend on the iteration
+                plan.push((token, ranges[i]));
+                known = KnownCells::unknown();
+            },
+            Token::CloseBr(_) => {
+                // the loop may change any cell, but it is exited only when the current cell is zero
+                plan.push((token, ranges[i]));
+                known = KnownCells::unknown();
+                known.set(0, Some(0));
+            },
+            _ => {
+                plan.push((token, ranges[i]));
+                known.apply(token);
+            },
+        }
+        i += 1;
+    }
+
+    plan
+}
+
+/// Replace the loop by straight-line code if its trip count is known (see [fold_constant_loops]).
+/// # Arguments
+/// * `body` - The tokens of the loop body.
+/// * `body_ranges` - The range of the data pointer before each token of the body.
+/// * `range` - The range of the data pointer at the start of the loop.
+/// * `guard` - The value of the current cell at the start of the loop (`None` if unknown).
+/// * `unroll_limit` - The maximal number of iterations of the unrolled loop.
+/// # Returns
+/// * `Some(Vec<PlannedToken>)` - The tokens replacing the loop.
+/// * `None` - If the loop is kept.
+fn replace_loop(body: &[Token], body_ranges: &[Option<(usize, usize)>], range: Option<(usize, usize)>, guard: Option<u8>, unroll_limit: usize) -> Option<Vec<PlannedToken>> {
+    let guard = guard?;
+    if guard == 0 {
+        return Some(Vec::new());
+    }
+
+    // find the change of the current cell and the total additions to the other cells in one iteration
+    let mut offset = 0;
+    let mut guard_change = 0_u8;
+    let mut additions = BTreeMap::new();
+    let mut only_additions = true;
+    for &token in body {
+        match token {
+            Token::Add(n) if offset == 0 => guard_change = guard_change.wrapping_add(n),
+            Token::Add(n) => {
+                let addition: &mut u8 = additions.entry(offset).or_default();
+                *addition = addition.wrapping_add(n);
+            },
+            Token::Move(n) => offset = (offset + n) % STORAGE_SIZE,
+            Token::ClearCell | Token::SetCell(_) if offset != 0 => only_additions = false,
+            Token::AddTo(n) if offset != 0 && (offset + n) % STORAGE_SIZE != 0 => only_additions = false,
+            Token::AddToCopy(n1, n2) if offset != 0 && (offset + n1) % STORAGE_SIZE != 0 && (offset + n2) % STORAGE_SIZE != 0 => only_additions = false,
+            Token::AddToCopy3(n1, n2, n3) if offset != 0 && [n1, n2, n3].iter().all(|&n| (offset + n) % STORAGE_SIZE != 0) => only_additions = false,
+            _ => return None,
+        }
+    }
+    if offset != 0 {
+        return None;
+    }
+
+    // the loop never terminates if the current cell never reaches zero
+    let trip_count = (1..256_usize).find(|&k| guard.wrapping_add(guard_change.wrapping_mul(k as u8)) == 0)?;
+
+    if only_additions {
+        // add the total of all the iterations to each cell and clear the current cell
+        let mut replacement = Vec::new();
+        let mut range = range;
+        let mut offset = 0;
+        let targets = additions.into_iter()
+            .map(|(target, addition)| (target, addition.wrapping_mul(trip_count as u8)))
+            .filter(|&(_, addition)| addition != 0);
+        let mut moves = Vec::new();
+        for (target, addition) in targets {
+            moves.push(((target + STORAGE_SIZE - offset) % STORAGE_SIZE, Some(addition)));
+            offset = target;
+        }
+        if offset != 0 {
+            moves.push((STORAGE_SIZE - offset, None));
+        }
+        for (distance, addition) in moves {
+            replacement.push((Token::Move(distance), range));
+            range = range.and_then(|range| shift_range(range, distance));
+            if let Some(addition) = addition {
+                replacement.push((Token::Add(addition), range));
+            }
+        }
+        replacement.push((Token::ClearCell, range));
+        Some(replacement)
+    } else if trip_count <= unroll_limit {
+        // repeat the body (the current cell is zero after the last iteration)
+        let iteration = body.iter().copied().zip(body_ranges.iter().copied());
+        Some(iteration.cycle().take(body.len() * trip_count).collect())
+    } else {
+        None
+    }
+}
+
+/// The known values of the cells (see [fold_constant_loops]).
+struct KnownCells {
+    /// The position of the data pointer (relative to the position where the tracking started).
+    position: usize,
+    /// The values of the cells at the positions (`None` if unknown).
+    values: HashMap<usize, Option<u8>>,
+    /// Whether the cells without the entry in `values` are zero (otherwise they are unknown).
+    zeroed: bool,
+}
+impl KnownCells {
+    /// Create the tracking where all cells are zero.
+    /// # Returns
+    /// * [KnownCells] - The tracking.
+    fn zeroed() -> Self {
+        Self { position: 0, values: HashMap::new(), zeroed: true }
+    }
+
+    /// Create the tracking where all cells are unknown.
+    /// # Returns
+    /// * [KnownCells] - The tracking.
+    fn unknown() -> Self {
+        Self { position: 0, values: HashMap::new(), zeroed: false }
+    }
+
+    /// Get the value of the cell.
+    /// # Arguments
+    /// * `n` - The distance of the cell from the current cell.
+    /// # Returns
+    /// * `Option<u8>` - The value of the cell (`None` if unknown).
+    fn get(&self, n: usize) -> Option<u8> {
+        match self.values.get(&((self.position + n) % STORAGE_SIZE)) {
+            Some(&value) => value,
+            None => self.zeroed.then_some(0),
+        }
+    }
+
+    /// Set the value of the cell.
+    /// # Arguments
+    /// * `n` - The distance of the cell from the current cell.
+    /// * `value` - The value of the cell (`None` if unknown).
+    fn set(&mut self, n: usize, value: Option<u8>) {
+        self.values.insert((self.position + n) % STORAGE_SIZE, value);
+    }
+
+    /// Add the value of the current cell to the cell.
+    /// # Arguments
+    /// * `n` - The distance of the cell from the current cell.
+    fn add_current_to(&mut self, n: usize) {
+        let sum = self.get(n).zip(self.get(0)).map(|(value, current)| value.wrapping_add(current));
+        self.set(n, sum);
+    }
+
+    /// Update the values with the effect of the token (other than a bracket).
+    /// # Arguments
+    /// * `token` - The executed token.
+    fn apply(&mut self, token: Token) {
+        match token {
+            Token::Add(n) => self.set(0, self.get(0).map(|value| value.wrapping_add(n))),
+            Token::Move(n) => self.position = (self.position + n) % STORAGE_SIZE,
+            Token::Input => self.set(0, None),
+            Token::ClearCell => self.set(0, Some(0)),
+            Token::SetCell(n) => self.set(0, Some(n)),
+            Token::AddTo(n) => {
+                self.add_current_to(n);
+                self.set(0, Some(0));
+            },
+            Token::AddToCopy(n1, n2) => {
+                self.add_current_to(n1);
+                self.add_current_to(n2);
+                self.set(0, Some(0));
+            },
+            Token::AddToCopy3(n1, n2, n3) => {
+                self.add_current_to(n1);
+                self.add_current_to(n2);
+                self.add_current_to(n3);
+                self.set(0, Some(0));
+            },
+            Token::Output | Token::Debug | Token::OpenBr(_) | Token::CloseBr(_) => {},
+        }
+    }
+}
+
+/// Calculate the net movement (modulo [STORAGE_SIZE]) of the data pointer over the tokens.
+/// # Arguments
+/// * `tokens` - The tokens.
+/// * `start` - The index of the first token.
+/// * `end` - The index after the last token.
+/// * `matching` - The indices of the matching brackets.
+/// # Returns
+/// * `Some(usize)` - The net movement.
+/// * `None` - If the net movement is unknown (there is a loop with non-zero net movement).
+fn net_movement(tokens: &[Token], start: usize, end: usize, matching: &[usize]) -> Option<usize> {
+    let mut movement = 0;
+    let mut i = start;
+    while i < end {
+        match tokens[i] {
+            Token::Move(n) => movement = (movement + n) % STORAGE_SIZE,
+            Token::OpenBr(_) => {
+                if net_movement(tokens, i + 1, matching[i], matching)? != 0 {
+                    return None;
+                }
+                i = matching[i];
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+
+    Some(movement)
+}
+
+/// Move the range of the data pointer.
+/// # Arguments
+/// * `range` - The inclusive range of the data pointer.
+/// * `n` - The move distance.
+/// # Returns
+/// * `Some((usize, usize))` - The moved range, if no value in the range wraps around the array.
+/// * `None` - If some value in the range may wrap around the array.
+fn shift_range((low, high): (usize, usize), n: usize) -> Option<(usize, usize)> {
+    let delta = signed_distance(n);
+    let low = low.checked_add_signed(delta)?;
+    let high = high.checked_add_signed(delta)?;
+    (high < STORAGE_SIZE).then_some((low, high))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use cranelift::codegen::settings;
+    use target_lexicon::Triple;
+
+    use super::*;
+    use crate::code::process_code;
+
+    /// Build the function for the host (with the dummy runtime functions) and return its Cranelift IR.
+    fn build_clif(code: &str, write_repeated: bool) -> String {
+        let target_isa = isa::lookup(Triple::host()).unwrap().finish(settings::Flags::new(settings::builder())).unwrap();
+        let tokens = process_code(code).unwrap();
+        let callee = || Callee::Address(std::ptr::null());
+        let runtime = RuntimeFunctions {
+            read: callee(),
+            write: callee(),
+            write_repeated: write_repeated.then(callee),
+            flush: None,
+            debug: None,
+        };
+        build_function(&tokens, &*target_isa, &runtime, FunctionConfig::default()).display().to_string()
+    }
+
+    #[test]
+    fn test_pointer_ranges() {
+        //! Test the pointer_ranges function.
+
+        let tokens = process_code(">>+[->+<]<").unwrap();
+        assert_eq!(pointer_ranges(&tokens), vec![
+            Some((0, 0)),
+            Some((2, 2)),
+            Some((2, 2)),
+            Some((2, 2)),
+        ]);
+
+        // moving left of the start wraps around the array
+        let tokens = process_code("<+>+").unwrap();
+        assert_eq!(pointer_ranges(&tokens), vec![Some((0, 0)), None, None, None]);
+
+        // loop with non-zero net movement
+        let tokens = process_code(">+[>+]<").unwrap();
+        assert_eq!(pointer_ranges(&tokens), vec![
+            Some((0, 0)),
+            Some((1, 1)),
+            None,
+            None,
+            None,
+            None,
+            None,
+        ]);
+
+        // nested loop with non-zero net movement makes the outer loop unknown
+        let tokens = process_code(">+[>[>]<<-]>").unwrap();
+        assert!(pointer_ranges(&tokens)[2..].iter().all(Option::is_none));
+
+        // balanced loops keep the range
+        let tokens = process_code(">+[>+[>+<-]<-]>+").unwrap();
+        assert_eq!(pointer_ranges(&tokens).last().unwrap(), &Some((2, 2)));
+    }
+
+    #[test]
+    fn test_speculate_ranges() {
+        //! Test the speculate_ranges function.
+
+        // the loop after the scan accesses the cells from one to the left to two to the right of its start
+        let tokens = process_code("+[>]-[<+>>>+<<-]>").unwrap();
+        let mut ranges = pointer_ranges(&tokens);
+        let guards = speculate_ranges(&tokens, &mut ranges, &[5]);
+        assert_eq!(guards, HashMap::from([(5, (1, STORAGE_SIZE - 3))]));
+        assert_eq!(ranges[5..=7], [Some((1, STORAGE_SIZE - 3)), Some((1, STORAGE_SIZE - 3)), Some((0, STORAGE_SIZE - 4))]);
+        // the range after the loop is still unknown
+        assert_eq!(ranges[tokens.len() - 1], None);
+
+        // the nested loops are covered by the guard of the outer loop
+        let tokens = process_code("+[>]-[>-[>++<-]<-]").unwrap();
+        let mut ranges = pointer_ranges(&tokens);
+        assert_eq!(speculate_ranges(&tokens, &mut ranges, &[5, 8]).into_keys().collect::<Vec<_>>(), [5]);
+        assert!(ranges[5..].iter().all(Option::is_some));
+
+        // the loops with the known range, the unbalanced loops and the other tokens aren't speculated
+        for (code, hot_loops) in [("-[>+<-]", [1]), ("+[>]-[>+>]", [5]), ("+[>]-[>[<]>-]", [5]), ("+[>]-[>+<-]", [4])] {
+            let tokens = process_code(code).unwrap();
+            let mut ranges = pointer_ranges(&tokens);
+            assert!(speculate_ranges(&tokens, &mut ranges, &hot_loops).is_empty(), "code: {}", code);
+            assert_eq!(ranges, pointer_ranges(&tokens), "code: {}", code);
+        }
+    }
+
+    #[test]
+    fn test_partition() {
+        //! Test the partition function.
+
+        // the parts end only between the top-level tokens and loops
+        let tokens = process_code(",.>,[->+<.]>.<<,").unwrap();
+        let lens = |parts: Vec<&[Token]>| parts.iter().map(|part| part.len()).collect::<Vec<_>>();
+        assert_eq!(lens(partition(&tokens, 4)), [4, 7, 4]);
+        assert_eq!(lens(partition(&tokens, 7)), [4, 7, 4]);
+        assert_eq!(lens(partition(&tokens, 11)), [11, 4]);
+        assert_eq!(lens(partition(&tokens, tokens.len())), [tokens.len()]);
+        assert_eq!(partition(&tokens, 4).concat(), tokens);
+
+        // the loop longer than the limit is kept whole
+        assert_eq!(lens(partition(&tokens, 1)), [1, 1, 1, 1, 7, 1, 1, 1, 1]);
+        assert_eq!(lens(partition(&process_code("+[-[>]<]").unwrap(), 2)), [1, 7]);
+    }
+
+    #[test]
+    fn test_offset_addressing() {
+        //! Test that the cells at the constant offsets are addressed by the load and store offset immediates.
+
+        // AddTo(2) in the middle of the array
+        let clif = build_clif(">>>>>+[->>+<<]", false);
+        assert!(clif.lines().any(|line| line.contains("load.i8") && line.ends_with("+2")), "{}", clif);
+        assert!(clif.lines().any(|line| line.contains("store") && line.ends_with("+2")), "{}", clif);
+        assert!(!clif.contains("select"), "{}", clif);
+
+        // AddTo(2) with unknown data pointer (near the edge) falls back to the wrap computation
+        let clif = build_clif("+[>]+[->>+<<]", false);
+        assert!(clif.contains("select"), "{}", clif);
+    }
+
+    #[test]
+    fn test_repeated_outputs() {
+        //! Test that the consecutive outputs load the cell once and call the function writing the repeated bytes.
+
+        let count = |clif: &str, pattern: &str| clif.lines().filter(|line| line.contains(pattern)).count();
+
+        let clif = build_clif(",.....>.", true);
+        // one call for the read, one for the repeated outputs and one for the single output
+        assert_eq!(count(&clif, "call_indirect"), 3, "{}", clif);
+        // one load for each group of outputs and one for the final cell
+        assert_eq!(count(&clif, "load.i8"), 3, "{}", clif);
+        assert!(clif.contains("iconst.i64 5") || clif.contains("iconst.i32 5"), "{}", clif);
+
+        // without the function, the loaded value is written by the separate calls
+        let clif = build_clif(",.....>.", false);
+        assert_eq!(count(&clif, "call_indirect"), 7, "{}", clif);
+        assert_eq!(count(&clif, "load.i8"), 3, "{}", clif);
+    }
+
+    #[test]
+    fn test_fold_constant_loops() {
+        //! Test the fold_constant_loops function.
+
+        let plan = |code: &str, unroll_limit: usize| {
+            let tokens = process_code(code).unwrap();
+            fold_constant_loops(&tokens, &pointer_ranges(&tokens), unroll_limit)
+                .into_iter()
+                .map(|(token, _)| token)
+                .collect::<Vec<_>>()
+        };
+        let back = |n: usize| Token::Move(STORAGE_SIZE - n);
+
+        // closed form
+        assert_eq!(plan("++++++++[>++++++++<-]>.", 16), vec![
+            Token::Add(8),
+            Token::Move(1), Token::Add(64), back(1), Token::ClearCell,
+            Token::Move(1),
+            Token::Output,
+        ]);
+        assert_eq!(plan("+++[>++>>-<<<-]", 0), vec![
+            Token::Add(3),
+            Token::Move(1), Token::Add(6), Token::Move(2), Token::Add(253), back(3), Token::ClearCell,
+        ]);
+
+        // wrap-around trip counts (255 and 86 iterations)
+        assert_eq!(plan("-[>+<-]", 0), vec![Token::Add(255), Token::Move(1), Token::Add(255), back(1), Token::ClearCell]);
+        assert_eq!(plan("--[>+<+++]", 0), vec![Token::Add(254), Token::Move(1), Token::Add(86), back(1), Token::ClearCell]);
+
+        // the loop skipped at the start
+        assert_eq!(plan("[>+<.]+.", 16), vec![Token::Add(1), Token::Output]);
+
+        // unrolled within the limit
+        let body = process_code(">[-]+<-").unwrap();
+        assert_eq!(plan("+++[>[-]+<-]", 3), [vec![Token::Add(3)], body.clone(), body.clone(), body].concat());
+        assert_eq!(plan("+++[>[-]+<-]", 2), process_code("+++[>[-]+<-]").unwrap());
+
+        // the loops that are kept
+        for code in [
+            // never terminates
+            "+[>+<--]",
+            "+[]",
+            // unknown current cell
+            ",[>+<-]",
+            // input, output and nested loops
+            "+[>,<-]",
+            "+[>.<-]",
+            "+[>[-<]<-]",
+            // unbalanced
+            "+[>+>-]",
+            // changes the current cell by other tokens
+            "+[>[-]<[-]]",
+            "+[>+<[->+<]]",
+        ] {
+            assert_eq!(plan(code, 16), process_code(code).unwrap(), "code: {}", code);
+        }
+
+        // the values are tracked after the loop (only the current cell is known) and inside the loop body
+        let folded = [Token::Move(1), Token::Add(2), back(1), Token::ClearCell];
+        let tokens = process_code(",[>+<-]++[>+<-]").unwrap();
+        assert_eq!(plan(",[>+<-]++[>+<-]", 16), [&tokens[..8], &folded].concat());
+        let tokens = process_code(",[>[-]++[>+<-]<-]").unwrap();
+        assert_eq!(plan(",[>[-]++[>+<-]<-]", 16), [&tokens[..4], &folded, &tokens[10..]].concat());
+    }
+}
@@ -1,34 +1,595 @@
-// use std::path::Path;
-// use std::process::{Command, exit, Stdio};
-// use super::transpile;
-// 
-// 
-// pub fn compile(brainfuck_code: String, src_file: &Path, dst_folder: &Path, force: bool) {
-//     transpile(brainfuck_code, src_file, dst_folder, force);
-// 
-//     let check_cargo = Command::new("cargo")
-//         .arg("--version") // get cargo version
-//         .stdin(Stdio::null())
-//         .stdout(Stdio::null())
-//         .stderr(Stdio::null())
-//         .status() // run and save exit status
-//         .expect("Error running Cargo.");
-// 
-//     if !check_cargo.success() {
-//         eprintln!("Error running Cargo.");
-//         exit(1);
-//     }
-// 
-//     let cargo_build = Command::new("cargo")
-//         .arg("build")
-//         .arg("--release")
-//         .current_dir(dst_folder)
-//         .status()
-//         .expect("Error compiling with Cargo.");
-// 
-//     if !cargo_build.success() {
-//         eprintln!("Error compiling with Cargo.");
-//         exit(1);
-//     }
-// }
-// 
\ No newline at end of file
+//! The Brainfuck compiler (ahead-of-time compilation to a native executable).
+
+
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use cranelift::codegen::{ir, Context};
+use cranelift::prelude::*;
+use cranelift_module::{default_libcall_names, DataDescription, FuncId, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+use target_lexicon::Triple;
+
+use crate::code::{STORAGE_SIZE, Token};
+use crate::codegen::{build_function, Callee, FunctionConfig, RuntimeFunctions};
+use crate::error::Error;
+use crate::io::EofPolicy;
+
+
+
+/// The options for the compiler.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompileOptions {
+    /// The value stored to the current cell when the `,` command reads past the end of input.
+    pub eof: EofPolicy,
+    /// The target triple (e.g. `x86_64-pc-windows-gnu`), `None` to compile for the host.
+    ///
+    /// The targets with a different architecture than the host require the `cross` feature.
+    /// The linker has to support the target (set the `CC` environment variable to a cross-compiler).
+    pub target: Option<String>,
+}
+
+/// Compile provided tokens to a native executable.
+/// The tokens are compiled to an object file for the host, which is then linked using the system C compiler
+/// (`cc`, or the one specified by the `CC` environment variable).
+/// # Arguments
+/// * `tokens` - The tokens to compile.
+/// * `dst_file` - The path of the executable.
+/// # Returns
+/// * [CompileResult] - The path and the size of the executable, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnsupportedTarget` - The host platform is not supported by the code generator.
+/// * `EmitFailed` - The object file couldn't be written.
+/// * `LinkingFailed` - The object file couldn't be linked (or the linked executable couldn't be found).
+/// # Example
+/// ```no_run
+/// use std::path::Path;
+/// use bfuck::process_code;
+/// use bfuck::compile::compile;
+///
+/// let tokens = process_code("++++++++[>++++++++<-]>+.").unwrap();
+/// let result = compile(&tokens, Path::new("print_a")).unwrap();
+/// println!("{} bytes", result.binary_size_bytes);
+/// ```
+pub fn compile(tokens: &[Token], dst_file: &Path) -> Result<CompileResult, Error> {
+    compile_with_eof(tokens, dst_file, EofPolicy::default())
+}
+
+/// Compile provided tokens to a native executable with the given behavior at the end of input.
+/// # Arguments
+/// * `tokens` - The tokens to compile.
+/// * `dst_file` - The path of the executable.
+/// * `eof` - The [EofPolicy] applied when the `,` command reads past the end of input.
+/// # Returns
+/// * [CompileResult] - The path and the size of the executable, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * See [compile].
+pub fn compile_with_eof(tokens: &[Token], dst_file: &Path, eof: EofPolicy) -> Result<CompileResult, Error> {
+    compile_with_progress(tokens, dst_file, &CompileOptions { eof, ..CompileOptions::default() }, &mut |_| {})
+}
+
+/// Compile provided tokens to an executable for the given target.
+/// # Arguments
+/// * `tokens` - The tokens to compile.
+/// * `dst_file` - The path of the executable.
+/// * `target_triple` - The target triple (see [CompileOptions::target]).
+/// # Returns
+/// * [CompileResult] - The path and the size of the executable, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnsupportedTarget` - The target triple is empty, invalid or not supported by the code generator.
+/// * See [compile].
+/// # Example
+/// ```no_run
+/// use std::path::Path;
+/// use bfuck::process_code;
+/// use bfuck::compile::compile_cross;
+///
+/// // requires the `cross` feature (unless the host is AArch64) and a linker for the target in the `CC` variable
+/// let tokens = process_code("++++++++[>++++++++<-]>+.").unwrap();
+/// compile_cross(&tokens, Path::new("print_a"), "aarch64-unknown-linux-gnu").unwrap();
+/// ```
+pub fn compile_cross(tokens: &[Token], dst_file: &Path, target_triple: &str) -> Result<CompileResult, Error> {
+    let options = CompileOptions { target: Some(target_triple.to_string()), ..CompileOptions::default() };
+    compile_with_progress(tokens, dst_file, &options, &mut |_| {})
+}
+
+/// The executable created by the compiler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileResult {
+    /// The path of the executable (with the `.exe` extension, if the linker added it).
+    pub binary_path: PathBuf,
+    /// The size of the executable in bytes.
+    pub binary_size_bytes: u64,
+}
+
+/// The stage of the compilation, reported by [compile_with_progress].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileStage {
+    /// The object file is being generated from the tokens.
+    Generating,
+    /// The object file is being linked to an executable.
+    Linking,
+    /// The executable with the given path and size (in bytes) was created.
+    Done {
+        /// The path of the executable.
+        binary_path: PathBuf,
+        /// The size of the executable in bytes.
+        size: u64,
+    },
+    /// The compilation failed with the given error message.
+    Failed(String),
+}
+
+/// Compile provided tokens to a native executable and report the progress.
+/// The callback is invoked before the code generation starts, before the linker is run and when the compilation
+/// finishes or fails (see [CompileStage]).
+/// # Arguments
+/// * `tokens` - The tokens to compile.
+/// * `dst_file` - The path of the executable.
+/// * `options` - The [CompileOptions] used for compilation.
+/// * `on_progress` - Called at the start of each [CompileStage].
+/// # Returns
+/// * [CompileResult] - The path and the size of the executable, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * See [compile_cross].
+/// # Example
+/// ```no_run
+/// use std::path::Path;
+/// use bfuck::process_code;
+/// use bfuck::compile::{compile_with_progress, CompileOptions};
+///
+/// let tokens = process_code("++++++++[>++++++++<-]>+.").unwrap();
+/// let options = CompileOptions::default();
+/// compile_with_progress(&tokens, Path::new("print_a"), &options, &mut |stage| eprintln!("{:?}", stage)).unwrap();
+/// ```
+pub fn compile_with_progress(
+    tokens: &[Token],
+    dst_file: &Path,
+    options: &CompileOptions,
+    on_progress: &mut dyn FnMut(CompileStage),
+) -> Result<CompileResult, Error> {
+    let result = generate_and_link(tokens, dst_file, options, on_progress);
+    match &result {
+        Ok(compiled) => on_progress(CompileStage::Done { binary_path: compiled.binary_path.clone(), size: compiled.binary_size_bytes }),
+        Err(err) => on_progress(CompileStage::Failed(err.to_string())),
+    }
+    result
+}
+
+/// Generate the object file and link it (see [compile_with_progress]).
+/// # Arguments
+/// * `tokens` - The tokens to compile.
+/// * `dst_file` - The path of the executable.
+/// * `options` - The [CompileOptions] used for compilation.
+/// * `on_progress` - Called at the start of the generation and the linking.
+/// # Returns
+/// * [CompileResult] - The path and the size of the executable, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+fn generate_and_link(
+    tokens: &[Token],
+    dst_file: &Path,
+    options: &CompileOptions,
+    on_progress: &mut dyn FnMut(CompileStage),
+) -> Result<CompileResult, Error> {
+    on_progress(CompileStage::Generating);
+    let object = compile_object(tokens, options)?;
+
+    let object_file = write_object_file(&object)?;
+
+    on_progress(CompileStage::Linking);
+    let result = link(&object_file, dst_file);
+    let _ = fs::remove_file(&object_file);
+    result?;
+
+    // the linkers for Windows append the extension if it is missing
+    let binary_path = if dst_file.exists() { dst_file.to_path_buf() } else { dst_file.with_extension("exe") };
+    let metadata = fs::metadata(&binary_path)
+        .map_err(|err| Error::LinkingFailed(format!("couldn't find the executable `{}`: {}", binary_path.display(), err)))?;
+
+    Ok(CompileResult { binary_path, binary_size_bytes: metadata.len() })
+}
+
+/// The number of the object files created by this process (makes their names unique, see [write_object_file]).
+static OBJECT_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Write the object file to the temporary directory, under a name not used by any other compilation
+/// (the concurrent compilations in this process get different numbers, a file left by another process is never overwritten).
+/// # Arguments
+/// * `object` - The contents of the object file.
+/// # Returns
+/// * [PathBuf] - The path of the object file, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `EmitFailed` - The object file couldn't be written.
+fn write_object_file(object: &[u8]) -> Result<PathBuf, Error> {
+    loop {
+        let number = OBJECT_FILES.fetch_add(1, Ordering::Relaxed);
+        let object_file = env::temp_dir().join(format!("bfuck-{}-{}.o", process::id(), number));
+        match OpenOptions::new().write(true).create_new(true).open(&object_file) {
+            Ok(mut file) => {
+                if let Err(err) = file.write_all(object) {
+                    let _ = fs::remove_file(&object_file);
+                    return Err(Error::EmitFailed(err.kind()));
+                }
+                return Ok(object_file);
+            },
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(Error::EmitFailed(err.kind())),
+        }
+    }
+}
+
+/// Compile provided tokens to an object file for the target.
+/// The object file defines the `main` function and depends only on the C standard library.
+/// # Arguments
+/// * `tokens` - The tokens to compile.
+/// * `options` - The [CompileOptions] used for compilation.
+/// # Returns
+/// * [Vec<u8>] - The contents of the object file, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `UnsupportedTarget` - The target triple is empty, invalid or not supported by the code generator.
+fn compile_object(tokens: &[Token], options: &CompileOptions) -> Result<Vec<u8>, Error> {
+    // set compilation flags
+    let mut flag_builder = settings::builder();
+    flag_builder.set("opt_level", "speed_and_size").unwrap();
+    flag_builder.set("is_pic", "true").unwrap();
+    let flags = settings::Flags::new(flag_builder);
+
+    // set target ISA
+    let triple = match options.target.as_deref().map(str::trim) {
+        None => Triple::host(),
+        Some("") => return Err(Error::UnsupportedTarget),
+        Some(target) => Triple::from_str(target).map_err(|_| Error::UnsupportedTarget)?,
+    };
+    let target_isa = match isa::lookup(triple) {
+        Ok(isa_builder) => isa_builder.finish(flags).map_err(|_| Error::UnsupportedTarget)?,
+        Err(_) => return Err(Error::UnsupportedTarget),
+    };
+    let ptr_type = target_isa.pointer_type();
+    let call_conv = target_isa.default_call_conv();
+
+    let object_builder = ObjectBuilder::new(target_isa.clone(), "bfuck", default_libcall_names())
+        .map_err(|_| Error::UnsupportedTarget)?;
+    let mut module = ObjectModule::new(object_builder);
+
+    // declare functions of the C standard library
+    let mut getchar_sig = Signature::new(call_conv);
+    getchar_sig.returns.push(AbiParam::new(types::I32));
+    let getchar = module.declare_function("getchar", Linkage::Import, &getchar_sig).unwrap();
+
+    let mut putchar_sig = Signature::new(call_conv);
+    putchar_sig.params.push(AbiParam::new(types::I32));
+    putchar_sig.returns.push(AbiParam::new(types::I32));
+    let putchar = module.declare_function("putchar", Linkage::Import, &putchar_sig).unwrap();
+
+    let mut fflush_sig = Signature::new(call_conv);
+    fflush_sig.params.push(AbiParam::new(ptr_type));
+    fflush_sig.returns.push(AbiParam::new(types::I32));
+    let fflush = module.declare_function("fflush", Linkage::Import, &fflush_sig).unwrap();
+
+    // declare runtime functions (they behave the same as getchar and putchar from the io module)
+    // the input/output context pointer they accept is not used
+    let mut read_sig = Signature::new(call_conv);
+    read_sig.params.push(AbiParam::new(ptr_type));
+    read_sig.returns.push(AbiParam::new(types::I32));
+    let read = module.declare_function("bf_getchar", Linkage::Local, &read_sig).unwrap();
+
+    let mut write_sig = Signature::new(call_conv);
+    write_sig.params.push(AbiParam::new(ptr_type));
+    write_sig.params.push(AbiParam::new(types::I8));
+    let write = module.declare_function("bf_putchar", Linkage::Local, &write_sig).unwrap();
+
+    // declare the function generated from the tokens
+    let mut bf_main_sig = Signature::new(call_conv);
+    bf_main_sig.params.push(AbiParam::new(ptr_type));
+    bf_main_sig.params.push(AbiParam::new(ptr_type));
+    bf_main_sig.params.push(AbiParam::new(ptr_type));
+    bf_main_sig.returns.push(AbiParam::new(types::I8));
+    let bf_main = module.declare_function("bf_main", Linkage::Local, &bf_main_sig).unwrap();
+
+    // declare the entry point
+    let mut main_sig = Signature::new(call_conv);
+    main_sig.returns.push(AbiParam::new(types::I32));
+    let main = module.declare_function("main", Linkage::Export, &main_sig).unwrap();
+
+    // declare the memory (zero-initialized array of STORAGE_SIZE length)
+    let tape = module.declare_data("bf_tape", Linkage::Local, true, false).unwrap();
+    let mut tape_description = DataDescription::new();
+    tape_description.define_zeroinit(STORAGE_SIZE);
+    module.define_data(tape, &tape_description).unwrap();
+
+    let mut ctx = Context::new();
+    let mut func_ctx = FunctionBuilderContext::new();
+
+    // define bf_getchar: flush the output, skip '\r' characters and return -1 (EOF) at the end of input
+    ctx.func = ir::Function::with_name_signature(user_func_name(read), read_sig);
+    {
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let getchar = module.declare_func_in_func(getchar, builder.func);
+        let fflush = module.declare_func_in_func(fflush, builder.func);
+
+        let entry_block = builder.create_block();
+        let read_block = builder.create_block();
+        let return_block = builder.create_block();
+
+        builder.append_block_params_for_function_params(entry_block);
+        builder.switch_to_block(entry_block);
+        builder.seal_block(entry_block);
+        let null = builder.ins().iconst(ptr_type, 0);
+        builder.ins().call(fflush, &[null]);
+        builder.ins().jump(read_block, &[]);
+
+        builder.switch_to_block(read_block);
+        let call = builder.ins().call(getchar, &[]);
+        let char = builder.inst_results(call)[0];
+        let is_cr = builder.ins().icmp_imm(IntCC::Equal, char, i64::from(b'\r'));
+        builder.ins().brif(is_cr, read_block, &[], return_block, &[]);
+        builder.seal_block(read_block);
+
+        builder.switch_to_block(return_block);
+        builder.seal_block(return_block);
+        builder.ins().return_(&[char]);
+
+        builder.finalize();
+    }
+    module.define_function(read, &mut ctx).unwrap();
+    module.clear_context(&mut ctx);
+
+    // define bf_putchar: print the byte (every byte, so the programs can print UTF-8 text)
+    ctx.func = ir::Function::with_name_signature(user_func_name(write), write_sig);
+    {
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let putchar = module.declare_func_in_func(putchar, builder.func);
+
+        let entry_block = builder.create_block();
+        builder.append_block_params_for_function_params(entry_block);
+        builder.switch_to_block(entry_block);
+        builder.seal_block(entry_block);
+        let byte = builder.block_params(entry_block)[1];
+        let char = builder.ins().uextend(types::I32, byte);
+        builder.ins().call(putchar, &[char]);
+        builder.ins().return_(&[]);
+
+        builder.finalize();
+    }
+    module.define_function(write, &mut ctx).unwrap();
+    module.clear_context(&mut ctx);
+
+    // define bf_main (the output is already buffered by the C runtime, so there is no flush function)
+    ctx.func = build_function(
+        tokens,
+        &*target_isa,
+        &RuntimeFunctions {
+            read: Callee::External(user_external_name(read)),
+            write: Callee::External(user_external_name(write)),
+            write_repeated: None,
+            flush: None,
+            debug: None,
+        },
+        FunctionConfig { eof: options.eof, ..FunctionConfig::default() },
+    );
+    ctx.func.name = user_func_name(bf_main);
+    module.define_function(bf_main, &mut ctx).unwrap();
+    module.clear_context(&mut ctx);
+
+    // define main: call bf_main with the memory (the final data pointer is written to the stack slot) and return 0
+    // the runtime functions don't use the input/output context, so it is null
+    ctx.func = ir::Function::with_name_signature(user_func_name(main), main_sig);
+    {
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let bf_main = module.declare_func_in_func(bf_main, builder.func);
+        let tape = module.declare_data_in_func(tape, builder.func);
+
+        let entry_block = builder.create_block();
+        builder.switch_to_block(entry_block);
+        builder.seal_block(entry_block);
+        let tape_address = builder.ins().symbol_value(ptr_type, tape);
+        let data_ptr_slot = builder.create_sized_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, ptr_type.bytes()));
+        let data_ptr_address = builder.ins().stack_addr(ptr_type, data_ptr_slot, 0);
+        let io_ctx = builder.ins().iconst(ptr_type, 0);
+        builder.ins().call(bf_main, &[tape_address, data_ptr_address, io_ctx]);
+        let zero = builder.ins().iconst(types::I32, 0);
+        builder.ins().return_(&[zero]);
+
+        builder.finalize();
+    }
+    module.define_function(main, &mut ctx).unwrap();
+    module.clear_context(&mut ctx);
+
+    Ok(module.finish().emit().unwrap())
+}
+
+/// Link the object file to an executable using the system C compiler.
+/// # Arguments
+/// * `object_file` - The path of the object file.
+/// * `dst_file` - The path of the executable.
+/// # Returns
+/// * `()` - If [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `LinkingFailed` - The C compiler couldn't be run or it failed.
+fn link(object_file: &Path, dst_file: &Path) -> Result<(), Error> {
+    let linker = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+
+    let output = Command::new(&linker)
+        .arg(object_file)
+        .arg("-o")
+        .arg(dst_file)
+        .output()
+        .map_err(|err| Error::LinkingFailed(format!("couldn't run `{}`: {}", linker, err)))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::LinkingFailed(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+    }
+}
+
+/// The name of the function declared in the module (used to reference it from other functions).
+fn user_external_name(func_id: FuncId) -> ir::UserExternalName {
+    ir::UserExternalName::new(0, func_id.as_u32())
+}
+
+/// The name of the function defined in the module.
+fn user_func_name(func_id: FuncId) -> ir::UserFuncName {
+    ir::UserFuncName::User(user_external_name(func_id))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::process_code;
+
+    #[test]
+    fn test_compile() {
+        //! Test compiling the code to an executable and running it.
+
+        // skip the test if there is no C compiler to link with
+        if Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+
+        let dst_file = env::temp_dir().join(format!("bfuck-test-compile-{}", process::id()));
+        let tokens = process_code("++++++++[>++++++++<-]>+.>,[.,]").unwrap();
+        let result = compile(&tokens, &dst_file).unwrap();
+        assert_eq!(result.binary_path, dst_file);
+        assert!(result.binary_path.exists());
+        assert!(result.binary_size_bytes > 0);
+
+        let mut child = Command::new(&dst_file)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        {
+            use std::io::Write;
+            child.stdin.take().unwrap().write_all(b"bf\r\n").unwrap();
+        }
+        let output = child.wait_with_output().unwrap();
+        fs::remove_file(&dst_file).unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"Abf\n");
+    }
+
+    #[test]
+    fn test_compile_eof() {
+        //! Test that the compiled executable applies the EOF policy.
+
+        if Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+
+        let tokens = process_code(",.>+++++,+.>>++<+++[>,+.-<-]").unwrap();
+        let expected = [
+            (EofPolicy::Zero, b"a\x01\x01\x01\x01"),
+            (EofPolicy::Max, b"a\x00\x00\x00\x00"),
+            (EofPolicy::Unchanged, b"a\x06\x03\x03\x03"),
+        ];
+        for (eof, expected_stdout) in expected {
+            let dst_file = env::temp_dir().join(format!("bfuck-test-compile-eof-{}", process::id()));
+            compile_with_eof(&tokens, &dst_file, eof).unwrap();
+
+            let mut child = Command::new(&dst_file)
+                .stdin(process::Stdio::piped())
+                .stdout(process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            {
+                use std::io::Write;
+                child.stdin.take().unwrap().write_all(b"a").unwrap();
+            }
+            let output = child.wait_with_output().unwrap();
+            fs::remove_file(&dst_file).unwrap();
+
+            assert!(output.status.success());
+            assert_eq!(output.stdout, expected_stdout, "policy: {:?}", eof);
+        }
+    }
+
+    #[test]
+    fn test_compile_object_targets() {
+        //! Test compiling the object file for the given targets.
+
+        let tokens = process_code("++++++++[>++++++++<-]>+.>,[.,]").unwrap();
+        let compile = |target: &str| compile_object(&tokens, &CompileOptions { target: Some(target.to_string()), ..CompileOptions::default() });
+
+        // the target triple is validated
+        for target in ["", "  ", "not-a-target"] {
+            assert_eq!(compile(target), Err(Error::UnsupportedTarget), "target: {:?}", target);
+        }
+
+        // the object file format follows the operating system of the target
+        let arch = Triple::host().architecture;
+        let object = compile(&format!("{}-unknown-linux-gnu", arch)).unwrap();
+        assert_eq!(object[..4], *b"\x7fELF");
+        let object = compile(&format!("{}-apple-darwin", arch)).unwrap();
+        assert_eq!(object[..4], [0xcf, 0xfa, 0xed, 0xfe]);
+    }
+
+    #[test]
+    #[cfg(feature = "cross")]
+    fn test_compile_object_cross() {
+        //! Test compiling the object file for the other architectures (the ELF machine field is checked).
+
+        let tokens = process_code("++++++++[>++++++++<-]>+.>,[.,]").unwrap();
+        for (target, machine) in [("x86_64-unknown-linux-gnu", 62_u16), ("aarch64-unknown-linux-gnu", 183), ("riscv64gc-unknown-linux-gnu", 243)] {
+            let object = compile_object(&tokens, &CompileOptions { target: Some(target.to_string()), ..CompileOptions::default() }).unwrap();
+            assert_eq!(u16::from_le_bytes([object[18], object[19]]), machine, "target: {}", target);
+        }
+    }
+
+    #[test]
+    fn test_write_object_file() {
+        //! Test that each object file gets its own name, also when they are written from several threads at once.
+
+        let files = std::thread::scope(|scope| {
+            let handles = (0..8_u8).map(|i| scope.spawn(move || (i, write_object_file(&[i; 16]).unwrap()))).collect::<Vec<_>>();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+        });
+        for (i, object_file) in &files {
+            assert_eq!(fs::read(object_file).unwrap(), [*i; 16]);
+        }
+        for (_, object_file) in &files {
+            fs::remove_file(object_file).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_compile_with_progress() {
+        //! Test that the stages are reported in order.
+
+        if Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+
+        let tokens = process_code("+.").unwrap();
+        let dst_file = env::temp_dir().join(format!("bfuck-test-compile-progress-{}", process::id()));
+        let mut stages = Vec::new();
+        let result = compile_with_progress(&tokens, &dst_file, &CompileOptions::default(), &mut |stage| stages.push(stage)).unwrap();
+        let size = fs::metadata(&dst_file).unwrap().len();
+        fs::remove_file(&dst_file).unwrap();
+        assert_eq!(result, CompileResult { binary_path: dst_file.clone(), binary_size_bytes: size });
+        assert_eq!(stages, [CompileStage::Generating, CompileStage::Linking, CompileStage::Done { binary_path: dst_file, size }]);
+
+        // the linker fails, because the directory of the executable doesn't exist
+        let dst_file = env::temp_dir().join(format!("bfuck-test-missing-{}", process::id())).join("executable");
+        let mut stages = Vec::new();
+        let err = compile_with_progress(&tokens, &dst_file, &CompileOptions::default(), &mut |stage| stages.push(stage)).unwrap_err();
+        assert!(matches!(err, Error::LinkingFailed(_)));
+        assert_eq!(stages, [CompileStage::Generating, CompileStage::Linking, CompileStage::Failed(err.to_string())]);
+    }
+}
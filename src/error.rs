@@ -1,12 +1,15 @@
-//! Module containing the Error enum for errors that can occur in this crate.
+//! Module containing the Error enum for errors that can occur in this crate, and the Warning enum for suspicious code.
 
 
 use std::error::Error as StdError;
 use std::fmt::Display;
+use std::io::ErrorKind;
+
+use crate::code::STORAGE_SIZE;
 
 
 /// Error enum for errors that can occur in this crate.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
     /// The input contains a non-ASCII character.
     NonASCIIChar(char, usize, usize),
@@ -18,16 +21,172 @@ pub enum Error {
     UnsupportedPlatformJIT,
     /// The target platform is not supported.
     UnsupportedTarget,
+    /// The emitted code (Cranelift IR, disassembly, perf map or Brainfuck code generated from text) couldn't be written.
+    EmitFailed(ErrorKind),
+    /// The disassembly is not supported (the `capstone` feature is not enabled or the architecture is not supported).
+    DisassemblyUnsupported,
+    /// The compiled object file couldn't be linked to an executable.
+    LinkingFailed(String),
+    /// The program exceeded the step limit (ran out of fuel).
+    StepLimitExceeded,
+    /// The data pointer would leave the array at the command at the given line and column (in the strict bounds mode).
+    /// If the locations of the tokens aren't known (see [JitProgram::with_locations](crate::jit::JitProgram::with_locations)),
+    /// the line is 0 and the column is the index of the token.
+    PointerOutOfBounds {
+        /// The line of the command (0 if unknown).
+        row: usize,
+        /// The column of the command (the index of the token if the line is unknown).
+        col: usize,
+    },
+    /// The tape provided by the host has the given length, shorter than the array the program was compiled for.
+    TapeTooShort(usize),
+    /// The initial tape has the given length, longer than the array of the interpreter.
+    TapeTooLong(usize),
+    /// The given initial data pointer is outside of the array.
+    InvalidDataPointer(usize),
+    /// The executable memory for the JIT-compiled code couldn't be mapped.
+    MappingFailed(ErrorKind),
+    /// The sandboxed execution is not supported on the current platform (or the requested limit isn't).
+    UnsupportedPlatform,
+    /// The sandbox (the pipes or the child process) couldn't be set up.
+    SandboxFailed(ErrorKind),
+    /// The program run without input reads the input (`,` at the given line and column).
+    UnexpectedInput(usize, usize),
+    /// The generated Brainfuck code doesn't print the intended bytes (the index of the first wrong or missing byte).
+    GenerationMismatch(usize),
+    /// The generated Brainfuck code can't be limited to the given number of cells (the budget and the fewest cells it needs).
+    CellBudgetTooSmall(usize, usize),
+    /// The error that occurred in the given file (the file name is prepended to the message, e.g. `file.bf: Unmatched '[' ...`).
+    WithFile(Box<Error>, String),
 }
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Error::NonASCIIChar(c, row, col) => write!(f, "Non-ASCII character '{}' at line {}, column {}.", c, row, col),
+            Error::NonASCIIChar(c, row, col) => match suggest_replacement(*c) {
+                Some(replacement) => write!(f, "Non-ASCII character '{}' at line {}, column {} (did you mean '{}'?).", c, row, col, replacement),
+                None => write!(f, "Non-ASCII character '{}' at line {}, column {}.", c, row, col),
+            },
             Error::UnmatchedOpenBr(row, col) => write!(f, "Unmatched '[' at line {}, column {}.", row, col),
             Error::UnmatchedCloseBr(row, col) => write!(f, "Unmatched ']' at line {}, column {}.", row, col),
             Error::UnsupportedPlatformJIT => write!(f, "The current platform is not supported for JIT-compilation, use interpreter instead."),
             Error::UnsupportedTarget => write!(f, "The target platform is not supported."),
+            Error::EmitFailed(kind) => write!(f, "Error writing the emitted code: {}.", kind),
+            Error::DisassemblyUnsupported => write!(f, "The disassembly is not supported, build with the `capstone` feature on x86_64 or aarch64."),
+            Error::LinkingFailed(msg) => write!(f, "Error linking the executable: {}", msg),
+            Error::StepLimitExceeded => write!(f, "The program exceeded the step limit."),
+            Error::PointerOutOfBounds { row: 0, col } => write!(f, "The data pointer left the array at token {}.", col),
+            Error::PointerOutOfBounds { row, col } => write!(f, "The data pointer left the array at line {}, column {}.", row, col),
+            Error::TapeTooShort(len) => write!(f, "The tape has {} cells, but the program needs at least {}.", len, STORAGE_SIZE),
+            Error::TapeTooLong(len) => write!(f, "The tape has {} cells, but the array has only {}.", len, STORAGE_SIZE),
+            Error::InvalidDataPointer(ptr) => write!(f, "The data pointer {} is outside of the array of {} cells.", ptr, STORAGE_SIZE),
+            Error::MappingFailed(kind) => write!(f, "The executable memory for the JIT-compiled code couldn't be mapped: {}.", kind),
+            Error::UnsupportedPlatform => write!(f, "The sandboxed execution is not supported on the current platform."),
+            Error::SandboxFailed(kind) => write!(f, "Error setting up the sandbox: {}.", kind),
+            Error::UnexpectedInput(row, col) => write!(f, "The program reads the input at line {}, column {}, but there is no input.", row, col),
+            Error::GenerationMismatch(index) => write!(f, "The generated code doesn't print the intended byte at index {}.", index),
+            Error::CellBudgetTooSmall(budget, needed) => write!(f, "The generated code can't use only {} cells, it needs at least {} cells.", budget, needed),
+            Error::WithFile(err, file) => write!(f, "{}: {}", file, err),
+        }
+    }
+}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::WithFile(err, _) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Suggest the ASCII replacement for the common Unicode lookalike character
+/// (e.g. the typographic quotes and dashes, or the non-breaking space, usually inserted by the text editors).
+/// # Arguments
+/// * `c` - The non-ASCII character.
+/// # Returns
+/// * [char] - The ASCII character that looks like it.
+/// * `None` - If there is no such character.
+/// # Example
+/// ```
+/// use bfuck::error::suggest_replacement;
+///
+/// assert_eq!(suggest_replacement('\u{2019}'), Some('\''));
+/// assert_eq!(suggest_replacement('\u{A0}'), Some(' '));
+/// assert_eq!(suggest_replacement('Ž'), None);
+/// ```
+pub fn suggest_replacement(c: char) -> Option<char> {
+    match c {
+        '\u{2018}' | '\u{2019}' | '\u{201B}' | '\u{2032}' | '\u{00B4}' | '\u{02BC}' => Some('\''),
+        '\u{201C}' | '\u{201D}' | '\u{201F}' | '\u{2033}' => Some('"'),
+        '\u{201A}' => Some(','),
+        '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2014}' | '\u{2015}' | '\u{2212}' => Some('-'),
+        '\u{00A0}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => Some(' '),
+        '\u{2039}' => Some('<'),
+        '\u{203A}' => Some('>'),
+        '\u{00D7}' => Some('x'),
+        '\u{2044}' | '\u{2215}' => Some('/'),
+        // the fullwidth forms of the ASCII characters
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0),
+        _ => None,
+    }
+}
+
+/// Warning enum for suspicious code found while processing it (the code is still processed).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The loop opened at the given line and column never terminates once entered
+    /// (its body neither changes the current cell nor moves the data pointer, e.g. `+[]`).
+    TriviallyInfinite(usize, usize),
+}
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Warning::TriviallyInfinite(row, col) => write!(f, "The loop at line {}, column {} never terminates once entered.", row, col),
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source() {
+        //! Test the source of the errors.
+
+        let err = Error::WithFile(Box::new(Error::UnsupportedPlatformJIT), "test.bf".to_string());
+        assert_eq!(err.to_string(), "test.bf: The current platform is not supported for JIT-compilation, use interpreter instead.");
+        assert_eq!(err.source().map(|source| source.to_string()), Some(Error::UnsupportedPlatformJIT.to_string()));
+
+        // the leaf errors have no source
+        assert!(Error::UnsupportedPlatformJIT.source().is_none());
+        assert!(Error::UnmatchedOpenBr(3, 5).source().is_none());
+    }
+
+    #[test]
+    fn test_suggest_replacement() {
+        //! Test the suggestions of the ASCII replacements.
+
+        assert!(Error::NonASCIIChar('\u{2018}', 1, 1).to_string().contains("did you mean '''"));
+        assert_eq!(
+            Error::NonASCIIChar('\u{201C}', 2, 7).to_string(),
+            "Non-ASCII character '\u{201C}' at line 2, column 7 (did you mean '\"'?).",
+        );
+        assert_eq!(Error::NonASCIIChar('Ž', 1, 1).to_string(), "Non-ASCII character 'Ž' at line 1, column 1.");
+
+        assert_eq!(suggest_replacement('\u{2014}'), Some('-'));
+        assert_eq!(suggest_replacement('\u{2009}'), Some(' '));
+        assert_eq!(suggest_replacement('\u{FF21}'), Some('A'));
+        assert_eq!(suggest_replacement('\u{FF5E}'), Some('~'));
+        assert_eq!(suggest_replacement('\u{FF5F}'), None);
+        assert_eq!(suggest_replacement('🐝'), None);
+
+        // the suggestions are printable ASCII
+        for c in '\u{80}'..='\u{FFFF}' {
+            if let Some(replacement) = suggest_replacement(c) {
+                assert!(replacement.is_ascii() && !replacement.is_ascii_control(), "{:?} -> {:?}", c, replacement);
+            }
         }
     }
 }
-impl StdError for Error {}
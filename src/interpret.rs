@@ -2,17 +2,25 @@
 
 
 
-use crate::code::{Token, TokenStream, STORAGE_SIZE};
-use crate::io::{getchar, putchar};
+use crate::code::{process_code, ExecStats, FinalState, Token, TokenStream, STORAGE_SIZE};
+use crate::error::Error;
+use crate::io::{debug_dump, EofPolicy, IoAdapter, VecIo};
 
 
 
-/// Interpret given token stream.
+/// The standard size of the array (the same as [STORAGE_SIZE]), used by [interpret].
+pub const STANDARD_SIZE: usize = STORAGE_SIZE;
+
+/// Interpret given token stream (on the array of [STANDARD_SIZE] cells).
 /// # Arguments
-/// * token_stream - The [TokenStream] to interpret.
+/// * token_stream - The [TokenStream](crate::code::TokenStream) to interpret.
+/// * io - The [IoAdapter] used for input and output (e.g. [StdIo](crate::io::StdIo)).
+/// # Returns
+/// * [FinalState] - The final data pointer and the value of the current cell.
 /// # Example
 /// ```
 /// use bfuck::{process_code, interpret};
+/// use bfuck::io::StdIo;
 ///
 /// // brainfuck code that prints "Brainfuck"
 /// let bf_code = "
@@ -26,70 +34,465 @@ use crate::io::{getchar, putchar};
 /// >>>>.<<<<<<.>>>.>>.<<<.>>>>>.<<<<<<.>>>.
 /// ";
 ///
-/// interpret(process_code(bf_code).unwrap());
+/// interpret(&process_code(bf_code).unwrap(), &mut StdIo);
+/// ```
+pub fn interpret(token_stream: &[Token], io: &mut dyn IoAdapter) -> FinalState {
+    interpret_const::<STANDARD_SIZE>(token_stream, io)
+}
+
+/// Interpret given token stream on the array of `N` cells (the data pointer wraps around them).
+///
+/// The moves above `STORAGE_SIZE / 2` are treated as the moves to the left (see [Token::Move]),
+/// so the move to the left by one cell wraps the data pointer from `0` to `N - 1` for any `N`. The size is known at compile time,
+/// so `interpret_const::<STANDARD_SIZE>` is the same as [interpret].
+/// # Arguments
+/// * token_stream - The [TokenStream](crate::code::TokenStream) to interpret.
+/// * io - The [IoAdapter] used for input and output (e.g. [StdIo](crate::io::StdIo)).
+/// # Returns
+/// * [FinalState] - The final data pointer and the value of the current cell.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::interpret::interpret_const;
+/// use bfuck::io::VecIo;
+///
+/// // the fifth move to the right returns to the first cell
+/// let tokens = process_code("+>>>>>.").unwrap();
+/// let mut io = VecIo::default();
+/// let state = interpret_const::<5>(&tokens, &mut io);
+/// assert_eq!(state.data_ptr, 0);
+/// assert_eq!(io.output, [1]);
+/// ```
+pub fn interpret_const<const N: usize>(token_stream: &[Token], io: &mut dyn IoAdapter) -> FinalState {
+    const { assert!(N > 0, "The array must have at least one cell.") };
+
+    // the array is allocated on the heap, so the large arrays don't overflow the stack
+    let mut storage: Box<[u8; N]> = vec![0_u8; N].into_boxed_slice().try_into().unwrap();
+    match interpret_from(token_stream, &mut storage, 0, 0, io, EofPolicy::default(), &mut || false) {
+        Stop::Finished(data_ptr) => FinalState { data_ptr, cell: storage[data_ptr] },
+        Stop::Paused { .. } => unreachable!("The interpretation is never paused."),
+    }
+}
+
+/// The output and the final state of the machine after the program was interpreted (see [run_interpret]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterpretResult {
+    /// The bytes written by the program.
+    pub output: Vec<u8>,
+    /// The final position of the data pointer.
+    pub final_data_ptr: usize,
+    /// The final values of all [STANDARD_SIZE] cells.
+    pub final_tape: Box<[u8]>,
+}
+
+/// Interpret given token stream with the given input and collect its output and the final array.
+/// # Arguments
+/// * token_stream - The [TokenStream] to interpret.
+/// * input - The input bytes (the `,` command reads `0` after them, see [EofPolicy::Zero]).
+/// # Returns
+/// * [InterpretResult] - The output, the final data pointer and the final array, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * None at the moment, the interpretation of a token stream always finishes or never returns.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::interpret::run_interpret;
+///
+/// let result = run_interpret(process_code(",+.>++").unwrap(), b"A").unwrap();
+/// assert_eq!(result.output, b"B");
+/// assert_eq!(result.final_data_ptr, 1);
+/// assert_eq!(result.final_tape[..3], [b'B', 2, 0]);
+/// ```
+pub fn run_interpret(token_stream: TokenStream, input: &[u8]) -> Result<InterpretResult, Error> {
+    let mut io = VecIo::new(input);
+    let mut storage: Box<[u8; STANDARD_SIZE]> = vec![0_u8; STANDARD_SIZE].into_boxed_slice().try_into().unwrap();
+    match interpret_from(&token_stream, &mut storage, 0, 0, &mut io, EofPolicy::default(), &mut || false) {
+        Stop::Finished(final_data_ptr) => Ok(InterpretResult { output: io.output, final_data_ptr, final_tape: storage }),
+        Stop::Paused { .. } => unreachable!("The interpretation is never paused."),
+    }
+}
+
+/// Process and interpret given Brainfuck code with the given input and return its output.
+/// # Arguments
+/// * code - The Brainfuck code.
+/// * input - The input bytes (the `,` command reads `0` after them, see [EofPolicy::Zero]).
+/// # Returns
+/// * [Vec<u8>] - The bytes written by the program, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * See [process_code].
+/// # Example
+/// ```
+/// use bfuck::interpret_from_str;
+///
+/// assert_eq!(interpret_from_str(",[.,]", b"hello"), Ok(b"hello".to_vec()));
+/// ```
+pub fn interpret_from_str(code: &str, input: &[u8]) -> Result<Vec<u8>, Error> {
+    Ok(run_interpret(process_code(code)?, input)?.output)
+}
+
+/// The configuration of the interpreter (see [interpret_with_config]).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InterpreterConfig {
+    /// The initial data pointer (must be less than [STANDARD_SIZE]).
+    ///
+    /// Useful for the programs expecting the data pointer at a parameter, or for running a part of a program in isolation.
+    pub initial_data_ptr: usize,
+    /// The initial values of the first cells of the array (at most [STANDARD_SIZE]), the other cells are zero.
+    pub initial_tape: Vec<u8>,
+    /// The value stored to the current cell when the `,` command reads past the end of input.
+    pub eof: EofPolicy,
+}
+
+/// Interpret given token stream with the given configuration (the initial data pointer and cells).
+/// # Arguments
+/// * token_stream - The [TokenStream](crate::code::TokenStream) to interpret.
+/// * io - The [IoAdapter] used for input and output (e.g. [StdIo](crate::io::StdIo)).
+/// * config - The [InterpreterConfig].
+/// # Returns
+/// * [FinalState] - The final data pointer and the value of the current cell, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `InvalidDataPointer` - The initial data pointer is not less than [STANDARD_SIZE].
+/// * `TapeTooLong` - The initial tape is longer than [STANDARD_SIZE].
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::interpret::{interpret_with_config, InterpreterConfig};
+/// use bfuck::io::VecIo;
+///
+/// // add the parameter at the data pointer to the cell to its right
+/// let tokens = process_code("[->+<]>.").unwrap();
+/// let config = InterpreterConfig { initial_data_ptr: 1, initial_tape: vec![0, 3, 4], ..InterpreterConfig::default() };
+/// let mut io = VecIo::default();
+/// interpret_with_config(&tokens, &mut io, &config).unwrap();
+/// assert_eq!(io.output, [7]);
+/// ```
+pub fn interpret_with_config(token_stream: &[Token], io: &mut dyn IoAdapter, config: &InterpreterConfig) -> Result<FinalState, Error> {
+    if config.initial_data_ptr >= STANDARD_SIZE {
+        return Err(Error::InvalidDataPointer(config.initial_data_ptr));
+    }
+    if config.initial_tape.len() > STANDARD_SIZE {
+        return Err(Error::TapeTooLong(config.initial_tape.len()));
+    }
+
+    let mut storage = [0_u8; STANDARD_SIZE];
+    storage[..config.initial_tape.len()].copy_from_slice(&config.initial_tape);
+    match interpret_from(token_stream, &mut storage, 0, config.initial_data_ptr, io, config.eof, &mut || false) {
+        Stop::Finished(data_ptr) => Ok(FinalState { data_ptr, cell: storage[data_ptr] }),
+        Stop::Paused { .. } => unreachable!("The interpretation is never paused."),
+    }
+}
+
+/// Interpret given token stream with the given behavior at the end of input.
+/// # Arguments
+/// * token_stream - The [TokenStream](crate::code::TokenStream) to interpret.
+/// * io - The [IoAdapter] used for input and output (e.g. [StdIo](crate::io::StdIo)).
+/// * eof - The [EofPolicy] applied when the `,` command reads past the end of input.
+/// # Returns
+/// * [FinalState] - The final data pointer and the value of the current cell.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::interpret::interpret_with_eof;
+/// use bfuck::io::{EofPolicy, VecIo};
+///
+/// let tokens = process_code("+++,.,.").unwrap();
+/// let mut io = VecIo::new(b"a");
+/// interpret_with_eof(&tokens, &mut io, EofPolicy::Unchanged);
+/// assert_eq!(io.output, b"aa");
+/// ```
+pub fn interpret_with_eof(token_stream: &[Token], io: &mut dyn IoAdapter, eof: EofPolicy) -> FinalState {
+    let mut storage = [0_u8; STORAGE_SIZE];
+    match interpret_from(token_stream, &mut storage, 0, 0, io, eof, &mut || false) {
+        Stop::Finished(data_ptr) => FinalState { data_ptr, cell: storage[data_ptr] },
+        Stop::Paused { .. } => unreachable!("The interpretation is never paused."),
+    }
+}
+
+/// Interpret given token stream and collect the statistics of the execution.
+/// # Arguments
+/// * token_stream - The [TokenStream](crate::code::TokenStream) to interpret.
+/// * io - The [IoAdapter] used for input and output (e.g. [StdIo](crate::io::StdIo)).
+/// * eof - The [EofPolicy] applied when the `,` command reads past the end of input.
+/// # Returns
+/// * [FinalState] - The final data pointer and the value of the current cell.
+/// * [ExecStats] - The statistics of the execution.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::interpret::interpret_with_stats;
+/// use bfuck::io::{EofPolicy, VecIo};
+///
+/// let tokens = process_code("+++[>,.<-]").unwrap();
+/// let mut io = VecIo::new(b"ab");
+/// let (_, stats) = interpret_with_stats(&tokens, &mut io, EofPolicy::Zero);
+/// assert_eq!(stats.back_edges, 2);
+/// assert_eq!(stats.loop_iterations[&1], 3);
+/// assert_eq!((stats.bytes_read, stats.bytes_written), (2, 3));
+/// ```
+pub fn interpret_with_stats(token_stream: &[Token], io: &mut dyn IoAdapter, eof: EofPolicy) -> (FinalState, ExecStats) {
+    let mut storage = [0_u8; STORAGE_SIZE];
+    let mut stats = ExecStats::default();
+    let mut io = CountingIo { io, bytes_read: 0, bytes_written: 0 };
+    let state = match interpret_from(token_stream, &mut storage, 0, 0, &mut io, eof, &mut stats) {
+        Stop::Finished(data_ptr) => FinalState { data_ptr, cell: storage[data_ptr] },
+        Stop::Paused { .. } => unreachable!("The interpretation is never paused."),
+    };
+    stats.bytes_read = io.bytes_read;
+    stats.bytes_written = io.bytes_written;
+    (state, stats)
+}
+
+/// Interpret given token stream and count the executed tokens of each variant.
+///
+/// The profiling is selected at compile time: with `PROFILE == false` the counting is optimized out
+/// (the interpretation is as fast as [interpret]) and the returned counts are all zero.
+/// # Arguments
+/// * token_stream - The [TokenStream](crate::code::TokenStream) to interpret.
+/// * io - The [IoAdapter] used for input and output (e.g. [StdIo](crate::io::StdIo)).
+/// # Returns
+/// * `[u64; Token::VARIANT_COUNT]` - The number of the executed tokens of each variant, indexed by [Token::variant_index].
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::code::Token;
+/// use bfuck::interpret::interpret_profiled;
+/// use bfuck::io::VecIo;
+///
+/// let tokens = process_code("++[>+.<-]").unwrap();
+/// let counts = interpret_profiled::<true>(&tokens, &mut VecIo::default());
+/// assert_eq!(counts[Token::Output.variant_index()], 2);
+/// assert_eq!(counts[Token::CloseBr(0).variant_index()], 2);
 /// ```
-pub fn interpret(token_stream: TokenStream) {
-    let mut ins_ptr = 0;
-    let mut data_ptr = 0;
+pub fn interpret_profiled<const PROFILE: bool>(token_stream: &[Token], io: &mut dyn IoAdapter) -> [u64; Token::VARIANT_COUNT] {
     let mut storage = [0_u8; STORAGE_SIZE];
+    let mut profile = Profile::<PROFILE> { counts: [0; Token::VARIANT_COUNT] };
+    match interpret_from(token_stream, &mut storage, 0, 0, io, EofPolicy::default(), &mut profile) {
+        Stop::Finished(_) => profile.counts,
+        Stop::Paused { .. } => unreachable!("The interpretation is never paused."),
+    }
+}
+
+/// The [IoAdapter] counting the bytes read and written by the wrapped adapter.
+struct CountingIo<'a> {
+    /// The wrapped adapter.
+    io: &'a mut dyn IoAdapter,
+    /// The number of bytes read (excluding the reads at the end of input).
+    bytes_read: u64,
+    /// The number of bytes written.
+    bytes_written: u64,
+}
+impl IoAdapter for CountingIo<'_> {
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.io.read_byte();
+        self.bytes_read += u64::from(byte.is_some());
+        byte
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.bytes_written += 1;
+        self.io.write_byte(byte);
+    }
+
+    fn flush(&mut self) {
+        self.io.flush();
+    }
+}
+
+/// The callbacks of [interpret_from] at the loop boundaries (the closures only pause the interpretation).
+pub(crate) trait LoopHooks {
+    /// Called when the loop is entered from its opening bracket (the current cell isn't zero).
+    /// # Arguments
+    /// * loop_start - The index of the opening bracket of the loop.
+    fn enter_loop(&mut self, _loop_start: usize) {}
+
+    /// Called before every jump back to the start of a loop.
+    /// # Arguments
+    /// * loop_start - The index of the opening bracket of the loop.
+    /// # Returns
+    /// * `bool` - Whether to pause the interpretation.
+    fn back_edge(&mut self, _loop_start: usize) -> bool {
+        false
+    }
 
+    /// Called before every executed token (the default does nothing, so the call is optimized out).
+    /// # Arguments
+    /// * token - The executed token.
+    #[inline(always)]
+    fn dispatch(&mut self, _token: &Token) {}
+}
+impl<F: FnMut() -> bool> LoopHooks for F {
+    fn back_edge(&mut self, _loop_start: usize) -> bool {
+        self()
+    }
+}
+impl LoopHooks for ExecStats {
+    fn enter_loop(&mut self, loop_start: usize) {
+        *self.loop_iterations.entry(loop_start).or_default() += 1;
+    }
+
+    fn back_edge(&mut self, loop_start: usize) -> bool {
+        self.back_edges += 1;
+        *self.loop_iterations.entry(loop_start).or_default() += 1;
+        false
+    }
+}
+
+/// The [LoopHooks] counting the executed tokens of each variant (only if `PROFILE` is `true`).
+struct Profile<const PROFILE: bool> {
+    /// The counts indexed by [Token::variant_index].
+    counts: [u64; Token::VARIANT_COUNT],
+}
+impl<const PROFILE: bool> LoopHooks for Profile<PROFILE> {
+    #[inline(always)]
+    fn dispatch(&mut self, token: &Token) {
+        if PROFILE {
+            self.counts[token.variant_index()] += 1;
+        }
+    }
+}
+
+/// The reason why [interpret_from] returned.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Stop {
+    /// The program finished with the given final data pointer.
+    Finished(usize),
+    /// The interpretation was paused before jumping back to the start of a loop.
+    Paused {
+        /// The index of the opening bracket of the loop (the execution continues after it).
+        loop_start: usize,
+        /// The data pointer.
+        data_ptr: usize,
+    },
+}
+
+/// Interpret given token stream from the given instruction, on the given array.
+/// # Arguments
+/// * token_stream - The [TokenStream](crate::code::TokenStream) to interpret.
+/// * storage - The array of `N` cells (the moves are converted to its size, see [wrap_move]).
+/// * ins_ptr - The index of the first interpreted token.
+/// * data_ptr - The initial data pointer.
+/// * io - The [IoAdapter] used for input and output.
+/// * eof - The [EofPolicy] applied at the end of input.
+/// * hooks - The [LoopHooks] called at the loop boundaries, the interpretation is paused before the jump back
+///   to the start of a loop if [LoopHooks::back_edge] returns `true`.
+/// # Returns
+/// * [Stop] - Whether the program finished or was paused.
+#[inline(always)]
+pub(crate) fn interpret_from<const N: usize>(
+    token_stream: &[Token],
+    storage: &mut [u8; N],
+    mut ins_ptr: usize,
+    mut data_ptr: usize,
+    io: &mut dyn IoAdapter,
+    eof: EofPolicy,
+    hooks: &mut impl LoopHooks,
+) -> Stop {
     // unsafe block because unchecked methods are used to eliminate bounds checks
     unsafe {
         while ins_ptr < token_stream.len() {
+            hooks.dispatch(token_stream.get_unchecked(ins_ptr));
             match *token_stream.get_unchecked(ins_ptr) {
                 Token::Add(n) => {
                     *storage.get_unchecked_mut(data_ptr) = storage.get_unchecked(data_ptr).wrapping_add(n)
                 },
                 Token::Move(n) => {
-                    data_ptr += n;
-                    if data_ptr >= STORAGE_SIZE {
-                        data_ptr -= STORAGE_SIZE;
+                    data_ptr += wrap_move::<N>(n);
+                    if data_ptr >= N {
+                        data_ptr -= N;
                     }
                 },
                 Token::Input => {
-                    *storage.get_unchecked_mut(data_ptr) = getchar()
+                    if let Some(byte) = io.read_byte().or(eof.value()) {
+                        *storage.get_unchecked_mut(data_ptr) = byte
+                    }
                 },
                 Token::Output => {
-                    putchar(*storage.get_unchecked(data_ptr));
+                    io.write_byte(*storage.get_unchecked(data_ptr));
                 },
                 Token::OpenBr(jmp) => {
                     // skip the loop if the current cell is 0
                     if *storage.get_unchecked(data_ptr) == 0 {
                         ins_ptr += jmp;
+                    } else {
+                        hooks.enter_loop(ins_ptr);
                     }
                 },
                 Token::CloseBr(jmp) => {
                     // return to the start of the loop if the current cell is not 0
                     if *storage.get_unchecked(data_ptr) != 0 {
+                        if hooks.back_edge(ins_ptr - jmp) {
+                            return Stop::Paused { loop_start: ins_ptr - jmp, data_ptr };
+                        }
                         ins_ptr -= jmp;
                     }
                 },
                 Token::ClearCell => {
                     *storage.get_unchecked_mut(data_ptr) = 0
                 },
+                Token::SetCell(n) => {
+                    *storage.get_unchecked_mut(data_ptr) = n
+                },
                 Token::AddTo(n) => {
-                    let mut new_loc = data_ptr + n;
-                    if new_loc >= STORAGE_SIZE {
-                        new_loc -= STORAGE_SIZE;
+                    let mut new_loc = data_ptr + wrap_move::<N>(n);
+                    if new_loc >= N {
+                        new_loc -= N;
                     }
                     *storage.get_unchecked_mut(new_loc) = storage.get_unchecked(data_ptr).wrapping_add(*storage.get_unchecked(new_loc));
                     *storage.get_unchecked_mut(data_ptr) = 0;
                 },
                 Token::AddToCopy(n1, n2) => {
-                    let mut new_loc = data_ptr + n1;
-                    if new_loc >= STORAGE_SIZE {
-                        new_loc -= STORAGE_SIZE;
+                    let mut new_loc = data_ptr + wrap_move::<N>(n1);
+                    if new_loc >= N {
+                        new_loc -= N;
                     }
                     *storage.get_unchecked_mut(new_loc) = storage.get_unchecked(data_ptr).wrapping_add(*storage.get_unchecked(new_loc));
-                    new_loc = data_ptr + n2;
-                    if new_loc >= STORAGE_SIZE {
-                        new_loc -= STORAGE_SIZE;
+                    new_loc = data_ptr + wrap_move::<N>(n2);
+                    if new_loc >= N {
+                        new_loc -= N;
                     }
                     *storage.get_unchecked_mut(new_loc) = storage.get_unchecked(data_ptr).wrapping_add(*storage.get_unchecked(new_loc));
                     *storage.get_unchecked_mut(data_ptr) = 0;
                 },
+                Token::AddToCopy3(n1, n2, n3) => {
+                    for n in [n1, n2, n3] {
+                        let mut new_loc = data_ptr + wrap_move::<N>(n);
+                        if new_loc >= N {
+                            new_loc -= N;
+                        }
+                        *storage.get_unchecked_mut(new_loc) = storage.get_unchecked(data_ptr).wrapping_add(*storage.get_unchecked(new_loc));
+                    }
+                    *storage.get_unchecked_mut(data_ptr) = 0;
+                },
+                Token::Debug => {
+                    debug_dump(storage, data_ptr);
+                },
             }
             ins_ptr += 1;
         }
     }
+
+    // the program finished, write the output buffered by the adapter
+    io.flush();
+    Stop::Finished(data_ptr)
+}
+
+/// Convert the move distance of the token (modulo [STORAGE_SIZE]) to the distance modulo `N`.
+/// The distances above `STORAGE_SIZE / 2` are the moves to the left (the same as in the JIT-compiler).
+/// # Arguments
+/// * n - The move distance of the token.
+/// # Returns
+/// * usize - The move distance in the array of `N` cells (less than `N`).
+#[inline(always)]
+fn wrap_move<const N: usize>(n: usize) -> usize {
+    if N == STORAGE_SIZE {
+        n
+    } else if n <= STORAGE_SIZE / 2 {
+        n % N
+    } else {
+        // -(STORAGE_SIZE - n) modulo N
+        N - 1 - (STORAGE_SIZE - n - 1) % N
+    }
 }
@@ -1,38 +1,201 @@
-//! Implementation of the C putchar and getchar functions in Rust.
+//! Implementation of the C putchar and getchar functions in Rust (and the debug print functions)
+//! and the input/output adapters used by the interpreter.
 
 
+use std::fmt::Display;
 use std::io::{self, Read, Write};
 use std::slice;
 
 
 /// Read a single byte from the standard input.
 /// # Returns
-/// * The byte read from the standard input.
+/// * The byte read from the standard input, `0` if there is no more input.
 pub extern "C" fn getchar() -> u8 {
+    read_stdin().unwrap_or(0)
+}
+
+/// Read a single byte from the standard input (skipping the `\r` characters).
+/// # Returns
+/// * `Some(u8)` - The byte read from the standard input.
+/// * `None` - If there is no more input (or it couldn't be read).
+fn read_stdin() -> Option<u8> {
     io::stdout().flush().unwrap();  // flush the output buffer before reading input
     let mut read_char = 0;
 
     loop {
         if let Err(err_kind) = io::stdin().lock().read_exact(slice::from_mut(&mut read_char)).map_err(|e| e.kind()) {
-            if err_kind == io::ErrorKind::UnexpectedEof {
-                read_char = 0;
-            } else {
+            if err_kind != io::ErrorKind::UnexpectedEof {
                 eprintln!("Error while reading input: {}", err_kind);
             }
+            return None;
         };
         if read_char != b'\r' {
-            break;
+            return Some(read_char);
         }
     }
-
-    read_char
 }
 
 /// Write a single byte to the standard output.
+///
+/// Every byte is written unchanged (including the bytes above 127), so the programs can print UTF-8 text.
+/// The output is line-buffered, it is flushed after every newline (and before reading the input).
 /// # Arguments
 /// * `byte` - The byte to be written to the standard output.
 pub extern "C" fn putchar(byte: u8) {
-    if byte < 128 {
-        io::stdout().write_all(&[byte]).unwrap();
+    let mut stdout = io::stdout();
+    stdout.write_all(&[byte]).unwrap();
+    if byte == b'\n' {
+        stdout.flush().unwrap();
+    }
+}
+
+/// The number of cells printed on each side of the current cell by [debug_dump].
+const DEBUG_WINDOW: usize = 4;
+
+/// Print the data pointer, the value of the current cell and the cells around it to the standard error.
+/// # Arguments
+/// * `tape` - The array of cells (of any width).
+/// * `data_ptr` - The data pointer.
+pub fn debug_dump<T: Display>(tape: &[T], data_ptr: usize) {
+    io::stdout().flush().unwrap();  // flush the output buffer so that debug output is in the correct place
+
+    let start = data_ptr.saturating_sub(DEBUG_WINDOW);
+    let end = (data_ptr + DEBUG_WINDOW + 1).min(tape.len());
+    let cells = tape[start..end].iter()
+        .enumerate()
+        .map(|(i, cell)| if start + i == data_ptr { format!("[{}]", cell) } else { cell.to_string() })
+        .collect::<Vec<_>>();
+    eprintln!("[DEBUG] ptr={} cell={} cells[{}..{}]: {}", data_ptr, tape[data_ptr], start, end, cells.join(" "));
+}
+
+
+
+/// The value stored to the current cell by the `,` command when there is no more input.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum EofPolicy {
+    /// Store `0`.
+    #[default]
+    Zero,
+    /// Store `255` (i.e. `-1`).
+    Max,
+    /// Leave the current cell unchanged.
+    Unchanged,
+}
+impl EofPolicy {
+    /// The value stored to the current cell at the end of input.
+    /// # Returns
+    /// * `Some(u8)` - The stored value.
+    /// * `None` - If the current cell is left unchanged.
+    pub fn value(self) -> Option<u8> {
+        match self {
+            EofPolicy::Zero => Some(0),
+            EofPolicy::Max => Some(u8::MAX),
+            EofPolicy::Unchanged => None,
+        }
+    }
+}
+
+/// The source of input and the destination of output for the Brainfuck program.
+pub trait IoAdapter {
+    /// Read a single byte (the `,` command).
+    /// # Returns
+    /// * `Some(u8)` - The byte read.
+    /// * `None` - If there is no more input (the current cell is then set according to the [EofPolicy]).
+    fn read_byte(&mut self) -> Option<u8>;
+
+    /// Write a single byte (the `.` command).
+    ///
+    /// The adapters may buffer the output, but they must flush it before the next [IoAdapter::read_byte]
+    /// (so the program's prompt is seen before it waits for the input).
+    /// # Arguments
+    /// * `byte` - The byte to be written.
+    fn write_byte(&mut self, byte: u8);
+
+    /// Write the bytes at once (used by the JIT-compiled code, which buffers its output).
+    ///
+    /// The default implementation writes the bytes one by one with [IoAdapter::write_byte].
+    /// # Arguments
+    /// * `bytes` - The bytes to be written.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+
+    /// Flush the buffered output (called when the program finishes).
+    ///
+    /// The default implementation does nothing, for the adapters which don't buffer the output.
+    fn flush(&mut self) {}
+}
+
+/// The [IoAdapter] using the standard input and output (through [getchar] and [putchar]).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct StdIo;
+impl IoAdapter for StdIo {
+    fn read_byte(&mut self) -> Option<u8> {
+        read_stdin()
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        putchar(byte)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        // the same as putchar, but with a single write
+        let mut stdout = io::stdout();
+        stdout.write_all(bytes).unwrap();
+        if bytes.contains(&b'\n') {
+            stdout.flush().unwrap();
+        }
+    }
+
+    fn flush(&mut self) {
+        io::stdout().flush().unwrap();
+    }
+}
+
+/// The [IoAdapter] reading the input from and writing the output to memory.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::interpret::interpret;
+/// use bfuck::io::VecIo;
+///
+/// let mut io = VecIo::new(b"abc");
+/// interpret(&process_code(",[+.,]").unwrap(), &mut io);
+/// assert_eq!(io.output, b"bcd");
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VecIo {
+    /// The input bytes.
+    pub input: Vec<u8>,
+    /// The position of the next input byte.
+    pub position: usize,
+    /// The output bytes.
+    pub output: Vec<u8>,
+}
+impl VecIo {
+    /// Create the adapter with the given input and empty output.
+    /// # Arguments
+    /// * `input` - The input bytes.
+    /// # Returns
+    /// * [VecIo] - The adapter.
+    pub fn new(input: &[u8]) -> Self {
+        Self { input: input.to_vec(), position: 0, output: Vec::new() }
+    }
+}
+impl IoAdapter for VecIo {
+    fn read_byte(&mut self) -> Option<u8> {
+        let byte = self.input.get(self.position).copied()?;
+        self.position += 1;
+        Some(byte)
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.output.push(byte);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
     }
 }
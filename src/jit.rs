@@ -1,17 +1,913 @@
 //! The Brainfuck JIT-compiler.
+//!
+//! # Supported platforms
+//! The code is generated by Cranelift for the host, using its default calling convention
+//! (the same as `extern "C"` functions of Rust, through which the compiled code calls the input and output).
+//!
+//! | OS                | x86_64 | aarch64 | riscv64 | s390x |
+//! |-------------------|--------|---------|---------|-------|
+//! | Linux             | yes    | yes     | yes     | yes   |
+//! | macOS             | yes    | yes     | -       | -     |
+//! | Windows           | yes    | no      | -       | -     |
+//! | other Unix (BSDs) | yes    | yes     | yes     | -     |
+//!
+//! On Windows the generated functions use the Windows x64 calling convention and probe the stack inline
+//! (the stack pages are committed one guard page at a time). Cranelift has no Windows calling convention for
+//! the other architectures. On macOS with Apple Silicon the code is mapped with `MAP_JIT` (see [JitProgram]).
+//!
+//! On the unsupported platforms the compilation fails with [Error::UnsupportedPlatformJIT],
+//! and if the executable memory can't be mapped (e.g. forbidden by the system policy), with [Error::MappingFailed].
+//! In both cases the program can be run by the [interpreter](mod@crate::interpret) instead.
 
 
 
+use std::fs;
+use std::io::{self, Write};
 use std::mem;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-use cranelift::codegen::{ir, verify_function};
+use cranelift::codegen::verify_function;
 use cranelift::prelude::*;
-use memmap2::MmapOptions;
+#[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+use memmap2::{Mmap, MmapOptions};
 use target_lexicon::Triple;
 
-use crate::code::{STORAGE_SIZE, Token, TokenStream};
+use crate::code::{process_code, ExecStats, FinalState, STORAGE_SIZE, Token, TokenStream};
+use crate::codegen::{
+    build_driver, build_function, partition, Callee, FunctionConfig, RuntimeFunctions,
+    BACK_EDGES_COUNTER, BYTES_READ_COUNTER, BYTES_WRITTEN_COUNTER, FUEL_EXHAUSTED, LOOP_COUNTERS, POINTER_OUT_OF_BOUNDS,
+};
 use crate::error::Error;
-use crate::io::{getchar, putchar};
+use crate::interpret::{interpret_from, Stop};
+use crate::io::{EofPolicy, IoAdapter, StdIo, VecIo};
+use crate::perf;
+
+
+
+/// The minimal number of iterations of a loop in the profile for which [JitProgram::compile_pgo] speculates its data pointer range.
+pub const HOT_LOOP_ITERATIONS: u64 = 1_000;
+
+/// The options for the JIT-compiler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitOptions {
+    /// The optimization level of the generated machine code.
+    pub opt_level: OptLevel,
+    /// Whether to verify the generated function before compiling it.
+    ///
+    /// Disabling the verifier makes the compilation faster, but the invalid function is no longer caught.
+    pub verify: bool,
+    /// Whether to limit the number of loop iterations, so the program can be run with [JitProgram::run_with_fuel].
+    ///
+    /// Every jump back to the start of a loop consumes one unit of fuel (a decrement and a branch).
+    /// Without fuel, no extra code is emitted.
+    pub fuel: bool,
+    /// Whether to stop the program with [Error::PointerOutOfBounds] when the data pointer would leave the array,
+    /// instead of wrapping it around (see [JitProgram::run_checked]).
+    ///
+    /// Every move that isn't provably inside the array is checked (a comparison and a branch), and the loops
+    /// with the known trip count aren't replaced by straight-line code. Without strict bounds, no extra code is emitted.
+    pub strict_bounds: bool,
+    /// Whether the program can run on the tape owned by the host, with [JitProgram::run_on].
+    ///
+    /// The compiled code then assumes nothing about the initial cells and the data pointer, so the moves
+    /// can't skip the wrap checks and the loops with the known trip count aren't replaced by straight-line code.
+    pub host_tape: bool,
+    /// Whether to count the loop iterations and the bytes read and written, so the program can be run
+    /// with [JitProgram::run_instrumented].
+    ///
+    /// Every loop iteration and every input and output updates the counters in memory, and the loops
+    /// with the known trip count aren't replaced by straight-line code. Without instrumentation, no extra code is emitted.
+    pub instrument: bool,
+    /// The maximal number of iterations of the unrolled loops.
+    ///
+    /// The loops whose number of iterations is known at compile time (the current cell is a known constant)
+    /// are replaced by straight-line code. Loops made only of additions and moves are replaced by their total effect,
+    /// other loops are unrolled if they have at most this many iterations.
+    /// This is disabled with [JitOptions::fuel] (every iteration consumes fuel).
+    pub unroll_limit: usize,
+    /// The value stored to the current cell when the `,` command reads past the end of input.
+    pub eof: EofPolicy,
+    /// The width of the cells of the array.
+    ///
+    /// Each [Token::Add] is compiled as the signed change of the cell (the value as `i8`), so for the cells wider than 8 bits
+    /// the code must be processed by [OptimizationPipeline::wide_cells](crate::code::OptimizationPipeline::wide_cells)
+    /// ([process_code] merges the additions modulo 256).
+    pub cell_width: CellWidth,
+    /// Where to write the Cranelift IR of the generated function (`None` to not write it).
+    ///
+    /// The IR is written before the function is verified, so it is available even if the verification fails.
+    pub emit_clif: Option<EmitTarget>,
+    /// Where to write the disassembly of the generated machine code (`None` to not write it).
+    ///
+    /// Requires the `capstone` feature.
+    pub emit_asm: Option<EmitTarget>,
+    /// Whether to register the compiled code with the Linux `perf` profiler (ignored on the other systems).
+    ///
+    /// The code is appended to the perf map (`/tmp/perf-<pid>.map`) and the jitdump (`/tmp/jit-<pid>.dump`),
+    /// so the samples are attributed to it by `perf report`, and `perf record -k mono` followed by
+    /// `perf inject --jit` can annotate its instructions.
+    pub perf_map: bool,
+    /// The maximal number of tokens compiled into a single function.
+    ///
+    /// The longer programs are split between the top-level loops into the parts of at most this many tokens
+    /// (a longer top-level loop is kept whole), each compiled into its own function, and a driver function
+    /// calls them one after another. This keeps the compile time and memory of the huge programs in check,
+    /// but the parts assume nothing about the cells and the data pointer at their start (as with [JitOptions::host_tape]).
+    /// The programs with [JitOptions::fuel], [JitOptions::strict_bounds] or [JitOptions::instrument]
+    /// and the programs compiled by [JitProgram::compile_pgo] with the hot loops are never split.
+    pub max_function_tokens: usize,
+}
+impl Default for JitOptions {
+    fn default() -> Self {
+        Self {
+            opt_level: OptLevel::default(),
+            verify: true,
+            fuel: false,
+            strict_bounds: false,
+            host_tape: false,
+            instrument: false,
+            unroll_limit: 16,
+            eof: EofPolicy::default(),
+            cell_width: CellWidth::default(),
+            emit_clif: None,
+            emit_asm: None,
+            perf_map: false,
+            max_function_tokens: 10_000,
+        }
+    }
+}
+
+/// The Cranelift optimization level.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OptLevel {
+    /// No optimizations, the fastest compilation (useful for huge programs dominated by input and output).
+    None,
+    /// Optimize for the speed of the generated code.
+    Speed,
+    /// Optimize for the speed and the size of the generated code.
+    #[default]
+    SpeedAndSize,
+}
+impl OptLevel {
+    /// The name of the level in Cranelift settings.
+    /// # Returns
+    /// * [&str] - The value of the `opt_level` setting.
+    fn as_setting(self) -> &'static str {
+        match self {
+            OptLevel::None => "none",
+            OptLevel::Speed => "speed",
+            OptLevel::SpeedAndSize => "speed_and_size",
+        }
+    }
+}
+
+/// The width of the cells of the array (the array always has [STORAGE_SIZE] cells).
+///
+/// The arithmetic wraps around at the width of the cells. The `,` command stores the byte read to the cell
+/// with the upper bits zeroed (and [EofPolicy::Max] sets all bits), the `.` command writes the low byte of the cell.
+/// The value of the current cell in the [FinalState] is the low byte too.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum CellWidth {
+    /// 8-bit cells.
+    #[default]
+    U8,
+    /// 16-bit cells.
+    U16,
+    /// 32-bit cells.
+    U32,
+}
+impl CellWidth {
+    /// The size of a cell.
+    /// # Returns
+    /// * [usize] - The size of a cell in bytes.
+    pub fn bytes(self) -> usize {
+        match self {
+            CellWidth::U8 => 1,
+            CellWidth::U16 => 2,
+            CellWidth::U32 => 4,
+        }
+    }
+}
+
+/// The destination of the emitted code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmitTarget {
+    /// Write to the standard error.
+    Stderr,
+    /// Write to the file at the given path.
+    File(PathBuf),
+}
+impl EmitTarget {
+    /// Write the text to the target.
+    /// # Arguments
+    /// * `text` - The text to write.
+    /// # Returns
+    /// * `()` - If [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `EmitFailed` - The text couldn't be written.
+    fn write(&self, text: &str) -> Result<(), Error> {
+        match self {
+            EmitTarget::Stderr => io::stderr().write_all(text.as_bytes()),
+            EmitTarget::File(path) => fs::write(path, text),
+        }.map_err(|err| Error::EmitFailed(err.kind()))
+    }
+}
+
+/// The metrics of the JIT-compilation of a program (see [JitProgram::stats]).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct JitStats {
+    /// The number of compiled tokens.
+    pub tokens: usize,
+    /// The number of the generated Cranelift functions (more than one if the program was split, see [JitOptions::max_function_tokens]).
+    pub functions: usize,
+    /// The number of instructions of the generated Cranelift functions.
+    pub clif_insts: usize,
+    /// The size of the compiled machine code in bytes.
+    pub code_bytes: usize,
+    /// The time spent generating, compiling and mapping the code.
+    pub compile_time: Duration,
+}
+
+/// The JIT-compiled Brainfuck program.
+///
+/// The program is [Send] and [Sync], so it can be compiled once and run concurrently from many threads.
+/// The compiled code is never written after it was mapped, and every run gets its own array and I/O context.
+/// On macOS with Apple Silicon the write protection of the code is toggled only on the compiling thread
+/// and only while the code is copied, so the compilation doesn't affect the programs running on other threads.
+#[derive(Debug)]
+pub struct JitProgram {
+    /// The executable memory containing the compiled function (the driver function if the program was split).
+    code_buffer: CodeBuffer,
+    /// The executable memory containing the parts of the split program, called by the driver function (empty if it wasn't split).
+    /// Only kept alive as long as the driver function.
+    _parts: Vec<CodeBuffer>,
+    /// The addresses of the parts, read by the driver function (only kept alive as long as it).
+    _part_table: Box<[usize]>,
+    /// Whether the function accepts the fuel (see [JitOptions::fuel]).
+    fuel: bool,
+    /// Whether the function checks the data pointer (see [JitOptions::strict_bounds]).
+    strict_bounds: bool,
+    /// Whether the function can run on the tape owned by the host (see [JitOptions::host_tape]).
+    host_tape: bool,
+    /// The indices of the opening brackets in the order of the loop counters (if the program is instrumented).
+    counted_loops: Option<Vec<usize>>,
+    /// The width of the cells of the array (see [JitOptions::cell_width]).
+    cell_width: CellWidth,
+    /// The indices of the opening brackets in the order of the loop numbers (if the program is resumable).
+    loop_starts: Option<Vec<usize>>,
+    /// The tokens and the EOF policy with which the interpreter continues when the data pointer is outside
+    /// of the speculated range of a loop (if the program was compiled with [JitProgram::compile_pgo]).
+    fallback: Option<(TokenStream, EofPolicy)>,
+    /// The location (line and column) in the code of each token, used to report where the data pointer left the array
+    /// (see [JitProgram::with_locations]).
+    locations: Option<Vec<(usize, usize)>>,
+    /// The metrics of the compilation.
+    stats: JitStats,
+}
+impl JitProgram {
+    /// JIT-compile provided tokens.
+    /// # Arguments
+    /// * `tokens` - The tokens to compile.
+    /// * `options` - The [JitOptions] used for compilation.
+    /// # Returns
+    /// * [JitProgram] - The compiled program, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `UnsupportedPlatformJIT` - The current platform is not supported for JIT-compilation, use interpreter instead.
+    /// * `EmitFailed` - The emitted code or the perf map couldn't be written.
+    /// * `DisassemblyUnsupported` - The disassembly was requested, but the `capstone` feature is not enabled.
+    /// * `MappingFailed` - The executable memory for the compiled code couldn't be mapped, use interpreter instead.
+    /// # Example
+    /// ```
+    /// use bfuck::process_code;
+    /// use bfuck::jit::{JitOptions, JitProgram};
+    ///
+    /// let tokens = process_code("++++++++[>++++++++<-]>+.").unwrap();
+    /// let program = JitProgram::compile(&tokens, &JitOptions::default()).expect("Unsupported platform.");
+    ///
+    /// // prints "A"
+    /// program.run();
+    /// ```
+    pub fn compile(tokens: &[Token], options: &JitOptions) -> Result<Self, Error> {
+        Self::compile_with(tokens, options, false, &[])
+    }
+
+    /// JIT-compile provided tokens, optimizing the hot loops of the profile (recorded by [JitProgram::run_instrumented]).
+    ///
+    /// The hot loops are the loops with at least [HOT_LOOP_ITERATIONS] iterations. If the range of the data pointer
+    /// in a hot loop isn't known statically (e.g. after a loop scanning for a zero cell), but its body returns
+    /// the data pointer to the same cell, the loop is compiled as if the cells it accesses were inside the array:
+    /// without the wrap checks of the moves and with the cells addressed by the constant offsets.
+    /// A guard at the loop start (a subtraction, a comparison and a branch) checks this assumption. If it fails,
+    /// the compiled code returns and the program continues from the loop in the interpreter, so the result is always
+    /// the same as of the program compiled by [JitProgram::compile].
+    ///
+    /// The loops are speculated only without [JitOptions::fuel], [JitOptions::strict_bounds] and [JitOptions::instrument]
+    /// and with the 8-bit cells, otherwise the program is compiled by [JitProgram::compile].
+    /// # Arguments
+    /// * `tokens` - The tokens to compile.
+    /// * `options` - The [JitOptions] used for compilation.
+    /// * `profile` - The statistics of a previous run of the program.
+    /// # Returns
+    /// * [JitProgram] - The compiled program, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * See [JitProgram::compile].
+    /// # Example
+    /// ```
+    /// use bfuck::process_code;
+    /// use bfuck::io::VecIo;
+    /// use bfuck::jit::{JitOptions, JitProgram};
+    ///
+    /// let tokens = process_code("-[>-[>>+<<-]<-]>>>.").unwrap();
+    /// let options = JitOptions { instrument: true, ..JitOptions::default() };
+    /// let instrumented = JitProgram::compile(&tokens, &options).expect("Unsupported platform.");
+    /// let (_, profile) = instrumented.run_instrumented(&mut VecIo::default()).unwrap();
+    ///
+    /// let program = JitProgram::compile_pgo(&tokens, &JitOptions::default(), &profile).unwrap();
+    /// let mut io = VecIo::default();
+    /// program.run_with_io(&mut io);
+    /// assert_eq!(io.output, [1]);
+    /// ```
+    pub fn compile_pgo(tokens: &[Token], options: &JitOptions, profile: &ExecStats) -> Result<Self, Error> {
+        // the guard needs no fuel, no other checks and the interpreter cells to continue
+        let hot_loops: Vec<usize> = if !options.fuel && !options.strict_bounds && !options.instrument && options.cell_width == CellWidth::U8 {
+            profile.loop_iterations.iter()
+                .filter(|(_, &iterations)| iterations >= HOT_LOOP_ITERATIONS)
+                .map(|(&loop_start, _)| loop_start)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Self::compile_with(tokens, options, false, &hot_loops)
+    }
+
+    /// JIT-compile provided tokens, so the execution can be resumed inside a loop (see [JitProgram::resume]).
+    /// # Arguments
+    /// * `tokens` - The tokens to compile.
+    /// * `options` - The [JitOptions] used for compilation.
+    /// # Returns
+    /// * [JitProgram] - The compiled program, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * See [JitProgram::compile].
+    pub(crate) fn compile_resumable(tokens: &[Token], options: &JitOptions) -> Result<Self, Error> {
+        Self::compile_with(tokens, options, true, &[])
+    }
+
+    /// JIT-compile provided tokens.
+    /// # Arguments
+    /// * `tokens` - The tokens to compile.
+    /// * `options` - The [JitOptions] used for compilation.
+    /// * `resumable` - Whether the execution can be resumed inside a loop.
+    /// * `hot_loops` - The indices of the opening brackets of the loops whose data pointer range is speculated
+    ///   (must be empty if the program is resumable, has fuel, strict bounds, is instrumented or has the wider cells).
+    /// # Returns
+    /// * [JitProgram] - The compiled program, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * See [JitProgram::compile].
+    fn compile_with(tokens: &[Token], options: &JitOptions, resumable: bool, hot_loops: &[usize]) -> Result<Self, Error> {
+        let start_time = Instant::now();
+
+        // set compilation flags
+        let mut flag_builder = settings::builder();
+        flag_builder.set("opt_level", options.opt_level.as_setting()).unwrap();
+        flag_builder.set("enable_verifier", if options.verify { "true" } else { "false" }).unwrap();
+        if cfg!(windows) {
+            // the stack grows only page by page on Windows, so the large frames must touch every page
+            flag_builder.set("enable_probestack", "true").unwrap();
+            flag_builder.set("probestack_strategy", "inline").unwrap();
+        }
+        let flags = settings::Flags::new(flag_builder);
+
+        // Cranelift supports the Windows calling convention only on x86_64
+        if cfg!(all(windows, not(target_arch = "x86_64"))) {
+            return Err(Error::UnsupportedPlatformJIT);
+        }
+
+        // set target ISA
+        let target_isa = match isa::lookup(Triple::host()).map(|isa_builder| isa_builder.finish(flags)) {
+            Ok(Ok(target_isa)) => target_isa,
+            _ => return Err(Error::UnsupportedPlatformJIT),
+        };
+
+        // the resumable programs (used by the tiered execution) always wrap the data pointer and use 8-bit cells
+        // (they already assume nothing about the array, but can't be run on the host tape)
+        let strict_bounds = options.strict_bounds && !resumable;
+        let host_tape = options.host_tape && !resumable;
+        let instrument = options.instrument && !resumable;
+        let cell_width = if resumable { CellWidth::U8 } else { options.cell_width };
+
+        // build the function
+        // input and output functionality is achieved by calling functions read_byte and write_byte,
+        // which forward the calls to the IoAdapter passed in the context (the output is buffered until flush_output is called),
+        // debug_dump prints the debug tokens
+        let runtime = RuntimeFunctions {
+            read: Callee::Address(read_byte as *const ()),
+            write: Callee::Address(write_byte as *const ()),
+            write_repeated: Some(Callee::Address(write_byte_repeated as *const ())),
+            flush: Some(Callee::Address(flush_output as *const ())),
+            debug: Some(Callee::Address(debug_dump as *const ())),
+        };
+        let config = FunctionConfig {
+            fuel: options.fuel,
+            resumable,
+            strict_bounds,
+            host_tape,
+            instrument,
+            unroll_limit: Some(options.unroll_limit),
+            eof: options.eof,
+            cell_width,
+            hot_loops,
+        };
+
+        // the huge programs without the extra parameters are split into the parts, called by the driver function
+        // (the parts run as if on the host tape, the data pointer and the cells are passed from one to the next)
+        let parts = if tokens.len() > options.max_function_tokens && !resumable && !options.fuel && !strict_bounds && !instrument && hot_loops.is_empty() {
+            partition(tokens, options.max_function_tokens)
+        } else {
+            vec![tokens]
+        };
+        let mut part_table = vec![0; if parts.len() > 1 { parts.len() } else { 0 }].into_boxed_slice();
+        let mut functions = Vec::new();
+        if parts.len() > 1 {
+            for part in &parts {
+                functions.push(build_function(part, &*target_isa, &runtime, FunctionConfig { host_tape: true, ..config }));
+            }
+            functions.push(build_driver(parts.len(), part_table.as_ptr(), &*target_isa, host_tape));
+        } else {
+            functions.push(build_function(tokens, &*target_isa, &runtime, config));
+        }
+
+        // Write the Cranelift IR of the functions (before verification, so it is available if the verification fails).
+        if let Some(target) = &options.emit_clif {
+            target.write(&functions.iter().map(|function| function.display().to_string()).collect::<Vec<_>>().join("\n"))?;
+        }
+
+        let clif_insts = functions.iter()
+            .flat_map(|function| function.layout.blocks().map(|block| function.layout.block_insts(block).count()))
+            .sum();
+
+        // Verify that the functions are correct before compiling.
+        // This shouldn't fail if we correctly wrote a code for generating the functions (which we did).
+        if options.verify {
+            for function in &functions {
+                assert_eq!(verify_function(function, &*target_isa), Ok(()), "The JIT function is not valid!");
+            }
+        }
+
+        // Compile the functions to machine code.
+        // Shouldn't fail since the functions are valid.
+        let compiled_functions: Vec<Vec<u8>> = functions.into_iter()
+            .map(|function| {
+                let mut compiled_code = Vec::new();
+                codegen::Context::for_function(function)
+                    .compile_and_emit(&*target_isa, &mut compiled_code, &mut codegen::control::ControlPlane::default())
+                    .unwrap();
+                compiled_code
+            })
+            .collect();
+
+        // Write the disassembly of the compiled machine code.
+        if let Some(target) = &options.emit_asm {
+            let disassembly = compiled_functions.iter().map(|compiled_code| disassemble(compiled_code)).collect::<Result<Vec<_>, _>>()?;
+            target.write(&disassembly.join("\n"))?;
+        }
+
+        // Map the compiled code into memory (the parts first, so the driver function can find them)
+        // and register it with the profiler.
+        let (compiled_code, compiled_parts) = compiled_functions.split_last().unwrap();
+        let mut part_buffers = Vec::with_capacity(compiled_parts.len());
+        for (i, compiled_part) in compiled_parts.iter().enumerate() {
+            let part_buffer = CodeBuffer::new(compiled_part)?;
+            part_table[i] = part_buffer.as_ptr() as usize;
+            if options.perf_map {
+                perf::register(part_buffer.as_ptr(), compiled_part, &format!("bf_part_{}", i)).map_err(|err| Error::EmitFailed(err.kind()))?;
+            }
+            part_buffers.push(part_buffer);
+        }
+        let code_buffer = CodeBuffer::new(compiled_code)?;
+        if options.perf_map {
+            perf::register(code_buffer.as_ptr(), compiled_code, "bf_main").map_err(|err| Error::EmitFailed(err.kind()))?;
+        }
+
+        let open_brackets = || {
+            tokens.iter()
+                .enumerate()
+                .filter(|(_, token)| matches!(token, Token::OpenBr(_)))
+                .map(|(i, _)| i)
+                .collect()
+        };
+        let loop_starts = resumable.then(open_brackets);
+        let counted_loops = instrument.then(open_brackets);
+        let fallback = (!hot_loops.is_empty()).then(|| (tokens.to_vec(), options.eof));
+        let stats = JitStats {
+            tokens: tokens.len(),
+            functions: compiled_functions.len(),
+            clif_insts,
+            code_bytes: compiled_functions.iter().map(Vec::len).sum(),
+            compile_time: start_time.elapsed(),
+        };
+
+        Ok(Self {
+            code_buffer,
+            _parts: part_buffers,
+            _part_table: part_table,
+            fuel: options.fuel,
+            strict_bounds,
+            host_tape,
+            counted_loops,
+            cell_width,
+            loop_starts,
+            fallback,
+            locations: None,
+            stats,
+        })
+    }
+
+    /// Attach the locations of the tokens in the code (see [ParseStatistics::locations](crate::code::ParseStatistics::locations)),
+    /// so [Error::PointerOutOfBounds] reports the line and column of the command at which the data pointer left the array.
+    /// Without them, the error reports the index of the token (with the line 0).
+    /// # Arguments
+    /// * `locations` - The location (line and column) of each compiled token.
+    /// # Returns
+    /// * [JitProgram] - The same program, reporting the locations.
+    /// # Example
+    /// ```
+    /// use bfuck::code::process_code_with_stats;
+    /// use bfuck::error::Error;
+    /// use bfuck::io::VecIo;
+    /// use bfuck::jit::{JitOptions, JitProgram};
+    ///
+    /// let (tokens, stats) = process_code_with_stats("+>+\n<<+").unwrap();
+    /// let options = JitOptions { strict_bounds: true, ..JitOptions::default() };
+    /// let program = JitProgram::compile(&tokens, &options).expect("Unsupported platform.").with_locations(stats.locations);
+    ///
+    /// assert_eq!(program.run_checked(&mut VecIo::default()), Err(Error::PointerOutOfBounds { row: 2, col: 1 }));
+    /// ```
+    pub fn with_locations(mut self, locations: Vec<(usize, usize)>) -> Self {
+        self.locations = Some(locations);
+        self
+    }
+
+    /// Get the metrics of the compilation (the size of the generated code and the time spent compiling it).
+    /// # Returns
+    /// * [JitStats] - The metrics.
+    /// # Example
+    /// ```
+    /// use bfuck::process_code;
+    /// use bfuck::jit::{JitOptions, JitProgram};
+    ///
+    /// let tokens = process_code("++++++++[>++++++++<-]>+.").unwrap();
+    /// let program = JitProgram::compile(&tokens, &JitOptions::default()).expect("Unsupported platform.");
+    ///
+    /// let stats = program.stats();
+    /// assert_eq!(stats.tokens, tokens.len());
+    /// assert!(stats.clif_insts > 0 && stats.code_bytes > 0);
+    /// ```
+    pub fn stats(&self) -> JitStats {
+        self.stats
+    }
+
+    /// Run the compiled program using the standard input and output.
+    /// # Returns
+    /// * [FinalState] - The final data pointer and the value of the current cell.
+    pub fn run(&self) -> FinalState {
+        self.run_with_io(&mut StdIo)
+    }
+
+    /// Run the compiled program using the given input and output.
+    /// # Arguments
+    /// * `io` - The [IoAdapter] used for input and output.
+    /// # Returns
+    /// * [FinalState] - The final data pointer and the value of the current cell.
+    /// # Panics
+    /// If the data pointer leaves the array (only with [JitOptions::strict_bounds], see [JitProgram::run_checked]).
+    /// # Example
+    /// ```
+    /// use bfuck::process_code;
+    /// use bfuck::io::VecIo;
+    /// use bfuck::jit::{JitOptions, JitProgram};
+    ///
+    /// let tokens = process_code(",[+.,]").unwrap();
+    /// let program = JitProgram::compile(&tokens, &JitOptions::default()).expect("Unsupported platform.");
+    ///
+    /// let mut io = VecIo::new(b"abc");
+    /// program.run_with_io(&mut io);
+    /// assert_eq!(io.output, b"bcd");
+    /// ```
+    pub fn run_with_io(&self, io: &mut dyn IoAdapter) -> FinalState {
+        self.run_checked(io).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Run the compiled program using the given input and output, stopping if the data pointer leaves the array.
+    /// # Arguments
+    /// * `io` - The [IoAdapter] used for input and output.
+    /// # Returns
+    /// * [FinalState] - The final data pointer and the value of the current cell, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `PointerOutOfBounds` - The data pointer would leave the array (only with [JitOptions::strict_bounds]).
+    /// # Example
+    /// ```
+    /// use bfuck::process_code;
+    /// use bfuck::error::Error;
+    /// use bfuck::io::VecIo;
+    /// use bfuck::jit::{JitOptions, JitProgram};
+    ///
+    /// let tokens = process_code("+>+<<+").unwrap();
+    /// let options = JitOptions { strict_bounds: true, ..JitOptions::default() };
+    /// let program = JitProgram::compile(&tokens, &options).expect("Unsupported platform.");
+    ///
+    /// assert_eq!(program.run_checked(&mut VecIo::default()), Err(Error::PointerOutOfBounds { row: 0, col: 3 }));
+    /// ```
+    pub fn run_checked(&self, io: &mut dyn IoAdapter) -> Result<FinalState, Error> {
+        // without the limit, the fuel practically never runs out (2^64 loop iterations)
+        self.execute_on_new_tape(io, u64::MAX).map(|(state, _)| state)
+    }
+
+    /// Run the compiled program using the given input and output, with the limited number of loop iterations.
+    /// # Arguments
+    /// * `fuel` - The number of allowed jumps back to the start of a loop.
+    /// * `io` - The [IoAdapter] used for input and output.
+    /// # Returns
+    /// * [FinalState] - The final data pointer and the value of the current cell, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `StepLimitExceeded` - The program ran out of fuel.
+    /// * `PointerOutOfBounds` - The data pointer would leave the array (only with [JitOptions::strict_bounds]).
+    /// # Panics
+    /// If the program was compiled without [JitOptions::fuel].
+    /// # Example
+    /// ```
+    /// use bfuck::process_code;
+    /// use bfuck::error::Error;
+    /// use bfuck::io::VecIo;
+    /// use bfuck::jit::{JitOptions, JitProgram};
+    ///
+    /// let tokens = process_code("+[]").unwrap();
+    /// let options = JitOptions { fuel: true, ..JitOptions::default() };
+    /// let program = JitProgram::compile(&tokens, &options).expect("Unsupported platform.");
+    ///
+    /// assert_eq!(program.run_with_fuel(1000, &mut VecIo::default()), Err(Error::StepLimitExceeded));
+    /// ```
+    pub fn run_with_fuel(&self, fuel: u64, io: &mut dyn IoAdapter) -> Result<FinalState, Error> {
+        assert!(self.fuel, "The program was compiled without fuel.");
+        self.execute_on_new_tape(io, fuel).map(|(state, _)| state)
+    }
+
+    /// Run the compiled program using the given input and output, and collect the statistics of the execution.
+    /// # Arguments
+    /// * `io` - The [IoAdapter] used for input and output.
+    /// # Returns
+    /// * ([FinalState], [ExecStats]) - The final data pointer and the value of the current cell
+    ///   and the statistics of the execution (the same as collected by the interpreter), if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `PointerOutOfBounds` - The data pointer would leave the array (only with [JitOptions::strict_bounds]).
+    /// # Panics
+    /// If the program was compiled without [JitOptions::instrument].
+    /// # Example
+    /// ```
+    /// use bfuck::process_code;
+    /// use bfuck::io::VecIo;
+    /// use bfuck::jit::{JitOptions, JitProgram};
+    ///
+    /// let tokens = process_code("+++[>,.<-]").unwrap();
+    /// let options = JitOptions { instrument: true, ..JitOptions::default() };
+    /// let program = JitProgram::compile(&tokens, &options).expect("Unsupported platform.");
+    ///
+    /// let (_, stats) = program.run_instrumented(&mut VecIo::new(b"ab")).unwrap();
+    /// assert_eq!(stats.back_edges, 2);
+    /// assert_eq!(stats.loop_iterations[&1], 3);
+    /// assert_eq!((stats.bytes_read, stats.bytes_written), (2, 3));
+    /// ```
+    pub fn run_instrumented(&self, io: &mut dyn IoAdapter) -> Result<(FinalState, ExecStats), Error> {
+        let counted_loops = self.counted_loops.as_ref().expect("The program was compiled without instrumentation.");
+        let (state, counters) = self.execute_on_new_tape(io, u64::MAX)?;
+
+        let stats = ExecStats {
+            back_edges: counters[BACK_EDGES_COUNTER],
+            loop_iterations: counted_loops.iter()
+                .zip(&counters[LOOP_COUNTERS..])
+                .filter(|(_, &iterations)| iterations > 0)
+                .map(|(&loop_start, &iterations)| (loop_start, iterations))
+                .collect(),
+            bytes_read: counters[BYTES_READ_COUNTER],
+            bytes_written: counters[BYTES_WRITTEN_COUNTER],
+        };
+        Ok((state, stats))
+    }
+
+    /// Run the compiled program on the tape owned by the host, using the standard input and output.
+    ///
+    /// The program uses the first [STORAGE_SIZE] cells of the tape (the data pointer wraps around them,
+    /// unless it was compiled with [JitOptions::strict_bounds]), the rest of the tape is left untouched.
+    /// The cells keep their values after the program finishes, so the host can read the results from them.
+    ///
+    /// The compiled code doesn't check the length of the tape, so this function makes sure
+    /// that the tape is at least as long as the array the code was compiled for.
+    /// # Arguments
+    /// * `tape` - The array of cells, at least [STORAGE_SIZE] long.
+    /// * `start_ptr` - The initial data pointer.
+    /// # Returns
+    /// * [FinalState] - The final data pointer and the value of the current cell, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `TapeTooShort` - The tape is shorter than [STORAGE_SIZE].
+    /// * `PointerOutOfBounds` - The data pointer would leave the array (only with [JitOptions::strict_bounds]).
+    /// # Panics
+    /// If the program wasn't compiled with [JitOptions::host_tape], it was compiled with the cells wider than 8 bits
+    /// or `start_ptr` is not less than [STORAGE_SIZE].
+    /// # Example
+    /// ```
+    /// use bfuck::process_code;
+    /// use bfuck::code::{FinalState, STORAGE_SIZE};
+    /// use bfuck::jit::{JitOptions, JitProgram};
+    ///
+    /// // add the cell to its right neighbour
+    /// let tokens = process_code("[->+<]").unwrap();
+    /// let options = JitOptions { host_tape: true, ..JitOptions::default() };
+    /// let program = JitProgram::compile(&tokens, &options).expect("Unsupported platform.");
+    ///
+    /// let mut tape = vec![0; STORAGE_SIZE];
+    /// tape[10] = 3;
+    /// tape[11] = 4;
+    /// assert_eq!(program.run_on(&mut tape, 10), Ok(FinalState { data_ptr: 10, cell: 0 }));
+    /// assert_eq!(tape[10..12], [0, 7]);
+    /// ```
+    pub fn run_on(&self, tape: &mut [u8], start_ptr: usize) -> Result<FinalState, Error> {
+        assert!(self.host_tape, "The program was compiled without the host tape.");
+        assert_eq!(self.cell_width, CellWidth::U8, "The program was compiled with the cells wider than 8 bits.");
+        assert!(start_ptr < STORAGE_SIZE, "The start pointer is outside of the array.");
+        if tape.len() < STORAGE_SIZE {
+            return Err(Error::TapeTooShort(tape.len()));
+        }
+        // the code accesses only the first STORAGE_SIZE cells, which are valid for the whole execution
+        self.execute(tape.as_mut_ptr(), start_ptr, None, &mut JitContext::new(&mut StdIo), u64::MAX, &mut self.new_counters())
+    }
+
+    /// Resume the execution of the (resumable) program inside a loop, after the loop condition was checked.
+    /// # Arguments
+    /// * `storage` - The array of cells.
+    /// * `data_ptr` - The data pointer.
+    /// * `loop_start` - The index of the opening bracket of the loop (the execution continues after it).
+    /// * `io` - The [IoAdapter] used for input and output.
+    /// # Returns
+    /// * [FinalState] - The final data pointer and the value of the current cell.
+    /// # Panics
+    /// If the program wasn't compiled with [JitProgram::compile_resumable] or there is no loop at `loop_start`.
+    pub(crate) fn resume(&self, storage: &mut [u8; STORAGE_SIZE], data_ptr: usize, loop_start: usize, io: &mut dyn IoAdapter) -> FinalState {
+        let loop_starts = self.loop_starts.as_ref().expect("The program is not resumable.");
+        let loop_number = loop_starts.binary_search(&loop_start).expect("There is no loop at the given index.") + 1;
+        // the resumable programs have 8-bit cells, no strict bounds and no counters, and the fuel never runs out
+        self.execute(storage.as_mut_ptr(), data_ptr, Some(loop_number), &mut JitContext::new(io), u64::MAX, &mut []).unwrap()
+    }
+
+    /// Execute the JIT function on the zeroed array.
+    /// # Arguments
+    /// * `io` - The [IoAdapter] used for input and output.
+    /// * `fuel` - The fuel passed to the function (ignored if it was compiled without fuel).
+    /// # Returns
+    /// * ([FinalState], [Vec<u64>]) - The final data pointer, the value of the current cell and the counters, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * See [JitProgram::execute].
+    fn execute_on_new_tape(&self, io: &mut dyn IoAdapter, fuel: u64) -> Result<(FinalState, Vec<u64>), Error> {
+        let mut tape = self.new_tape();
+        let mut counters = self.new_counters();
+        let state = self.execute(tape.as_mut_ptr().cast(), 0, None, &mut JitContext::new(io), fuel, &mut counters)?;
+        Ok((state, counters))
+    }
+
+    /// Create the zeroed array of [STORAGE_SIZE] cells of the compiled width.
+    /// # Returns
+    /// * [Vec<u32>] - The array (the elements are aligned for the cells of every width).
+    pub(crate) fn new_tape(&self) -> Vec<u32> {
+        vec![0; (STORAGE_SIZE * self.cell_width.bytes()).div_ceil(4)]
+    }
+
+    /// Create the zeroed counters of the instrumented program.
+    /// # Returns
+    /// * [Vec<u64>] - The counters (empty if the program is not instrumented).
+    pub(crate) fn new_counters(&self) -> Vec<u64> {
+        vec![0; self.counters_len()]
+    }
+
+    /// Get the number of the counters of the instrumented program.
+    /// # Returns
+    /// * [usize] - The number of the counters (zero if the program is not instrumented).
+    fn counters_len(&self) -> usize {
+        self.counted_loops.as_ref().map_or(0, |loops| LOOP_COUNTERS + loops.len())
+    }
+
+    /// Execute the JIT function.
+    /// # Arguments
+    /// * `memory` - The pointer to the array of [STORAGE_SIZE] cells (of the compiled width, aligned to their size).
+    /// * `data_ptr` - The initial data pointer (ignored unless the program is resumable or runs on the host tape).
+    /// * `loop_number` - The number of the loop in which the execution starts (ignored if the program is not resumable).
+    /// * `io` - The [JitContext] used for input and output (nothing is allocated during the execution besides it).
+    /// * `fuel` - The fuel passed to the function (ignored if it was compiled without fuel).
+    /// * `counters` - The zeroed counters (see [JitProgram::new_counters]), incremented if the program is instrumented.
+    /// # Returns
+    /// * [FinalState] - The final data pointer and the value of the current cell, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `StepLimitExceeded` - The program ran out of fuel.
+    /// * `PointerOutOfBounds` - The data pointer would leave the array.
+    pub(crate) fn execute(
+        &self,
+        memory: *mut u8,
+        mut data_ptr: usize,
+        loop_number: Option<usize>,
+        io: &mut JitContext,
+        fuel: u64,
+        counters: &mut [u64],
+    ) -> Result<FinalState, Error> {
+        type Tape = *mut u8;
+        type Io<'a, 'b> = *mut JitContext<'a, 'b>;
+        type Counters = *mut u64;
+
+        // the compiled code accesses all counters of the loops
+        assert_eq!(counters.len(), self.counters_len(), "The counters don't match the program.");
+        let counters = counters.as_mut_ptr();
+
+        // the function accepts the optional parameters only if it was compiled with them
+        let code = self.code_buffer.as_ptr();
+        let entry = loop_number.unwrap_or(0);
+        // the index of the offending token (strict bounds) or of the loop whose speculation failed (profile-guided)
+        let mut index = usize::MAX;
+        let cell = unsafe {
+            // the profile-guided function accepts the index pointer the same as with strict bounds (they are never combined)
+            let index_param = self.strict_bounds || self.fallback.is_some();
+            match (self.loop_starts.is_some(), self.fuel, index_param, self.counted_loops.is_some()) {
+                (false, false, false, false) => {
+                    let code_fn: unsafe extern "C" fn(Tape, *mut usize, Io) -> u8 = mem::transmute(code);
+                    code_fn(memory, &mut data_ptr, io)
+                },
+                (false, true, false, false) => {
+                    let code_fn: unsafe extern "C" fn(Tape, *mut usize, Io, u64) -> u8 = mem::transmute(code);
+                    code_fn(memory, &mut data_ptr, io, fuel)
+                },
+                (true, false, false, false) => {
+                    let code_fn: unsafe extern "C" fn(Tape, *mut usize, Io, usize) -> u8 = mem::transmute(code);
+                    code_fn(memory, &mut data_ptr, io, entry)
+                },
+                (true, true, false, false) => {
+                    let code_fn: unsafe extern "C" fn(Tape, *mut usize, Io, usize, u64) -> u8 = mem::transmute(code);
+                    code_fn(memory, &mut data_ptr, io, entry, fuel)
+                },
+                (false, false, true, false) => {
+                    let code_fn: unsafe extern "C" fn(Tape, *mut usize, Io, *mut usize) -> u8 = mem::transmute(code);
+                    code_fn(memory, &mut data_ptr, io, &mut index)
+                },
+                (false, true, true, false) => {
+                    let code_fn: unsafe extern "C" fn(Tape, *mut usize, Io, u64, *mut usize) -> u8 = mem::transmute(code);
+                    code_fn(memory, &mut data_ptr, io, fuel, &mut index)
+                },
+                (false, false, false, true) => {
+                    let code_fn: unsafe extern "C" fn(Tape, *mut usize, Io, Counters) -> u8 = mem::transmute(code);
+                    code_fn(memory, &mut data_ptr, io, counters)
+                },
+                (false, true, false, true) => {
+                    let code_fn: unsafe extern "C" fn(Tape, *mut usize, Io, u64, Counters) -> u8 = mem::transmute(code);
+                    code_fn(memory, &mut data_ptr, io, fuel, counters)
+                },
+                (false, false, true, true) => {
+                    let code_fn: unsafe extern "C" fn(Tape, *mut usize, Io, *mut usize, Counters) -> u8 = mem::transmute(code);
+                    code_fn(memory, &mut data_ptr, io, &mut index, counters)
+                },
+                (false, true, true, true) => {
+                    let code_fn: unsafe extern "C" fn(Tape, *mut usize, Io, u64, *mut usize, Counters) -> u8 = mem::transmute(code);
+                    code_fn(memory, &mut data_ptr, io, fuel, &mut index, counters)
+                },
+                (true, _, true, _) | (true, _, _, true) => unreachable!("The resumable programs have no strict bounds and no counters."),
+            }
+        };
+
+        match (data_ptr, &self.fallback) {
+            (FUEL_EXHAUSTED, _) => Err(Error::StepLimitExceeded),
+            (POINTER_OUT_OF_BOUNDS, _) => {
+                // without the locations, the index of the token is reported
+                let (row, col) = self.locations.as_ref().map_or((0, index), |locations| locations[index]);
+                Err(Error::PointerOutOfBounds { row, col })
+            },
+            (_, Some((tokens, eof))) if index != usize::MAX => {
+                // the data pointer is outside of the speculated range, the loop is executed by the interpreter
+                // (the profile-guided programs have 8-bit cells and the output was flushed before the function returned)
+                let storage = unsafe { &mut *memory.cast::<[u8; STORAGE_SIZE]>() };
+                match interpret_from(tokens, storage, index, data_ptr, io.io, *eof, &mut || false) {
+                    Stop::Finished(data_ptr) => Ok(FinalState { data_ptr, cell: storage[data_ptr] }),
+                    Stop::Paused { .. } => unreachable!("The interpretation is never paused."),
+                }
+            },
+            _ => Ok(FinalState { data_ptr, cell }),
+        }
+    }
+}
 
 
 
@@ -19,10 +915,11 @@ use crate::io::{getchar, putchar};
 /// # Arguments
 /// * token_stream - The [TokenStream] to compile.
 /// # Returns
-/// * `()` - If [Ok].
+/// * [FinalState] - The final data pointer and the value of the current cell, if [Ok].
 /// * [Error] - The encountered error, if [Err].
 /// # Errors
 /// * `UnsupportedPlatformJIT` - The current platform is not supported for JIT-compilation, use interpreter instead.
+/// * `MappingFailed` - The executable memory for the compiled code couldn't be mapped, use interpreter instead.
 /// # Example
 /// ```
 /// use bfuck::{process_code, jit};
@@ -41,316 +938,969 @@ use crate::io::{getchar, putchar};
 ///
 /// jit(process_code(bf_code).unwrap()).expect("Unsupported platform.");
 /// ```
-pub fn jit(token_stream: TokenStream) -> Result<(), Error> {
-    // set compilation flags
-    let mut flag_builder = settings::builder();
-    flag_builder.set("opt_level", "speed_and_size").unwrap();
-    let flags = settings::Flags::new(flag_builder);
-
-    // set target ISA
-    let target_isa = match isa::lookup(Triple::host()) {
-        Ok(isa_builder) => isa_builder.finish(flags).unwrap(),
-        Err(_) => return Err(Error::UnsupportedPlatformJIT),
-    };
+pub fn jit(token_stream: TokenStream) -> Result<FinalState, Error> {
+    Ok(JitProgram::compile(&token_stream, &JitOptions::default())?.run())
+}
 
-    // find target pointer type
-    let ptr_type = target_isa.pointer_type();
-
-    // find target call convention
-    let call_conv = isa::CallConv::triple_default(target_isa.triple());
-
-    // create JIT function with a signature
-    // function accepts one parameter - pointer to array of STORAGE_SIZE length and filled with zero bytes
-    let mut signature = Signature::new(call_conv);
-    signature.params.push(AbiParam::new(ptr_type));
-    let mut function = ir::Function::with_name_signature(ir::UserFuncName::default(), signature);
-
-    // create function builder
-    let mut func_ctx = FunctionBuilderContext::new();
-    let mut builder = FunctionBuilder::new(&mut function, &mut func_ctx);
-
-    // create memory flags (needed for load and store instructions)
-    let mem_flags = MemFlags::new();
-
-    // START of building the JIT function
-    
-    // define initial block
-    let first_block = builder.create_block();
-    builder.seal_block(first_block);
-    builder.append_block_params_for_function_params(first_block);
-    builder.switch_to_block(first_block);
-
-    // declare data pointer variable and initialize it with zero
-    let data_ptr = Variable::new(0);
-    builder.declare_var(data_ptr, ptr_type);
-    let zero = builder.ins().iconst(ptr_type, 0);
-    builder.def_var(data_ptr, zero);
-
-    // get the memory address of the start of the array (received as a parameter to the function)
-    let memory_address = builder.block_params(first_block)[0];
-
-    // input and output functionality is achieved by calling external functions getchar and putchar (defined in io module)
-
-    // declare signature for read function (getchar)
-    let mut read_sig = Signature::new(call_conv);
-    read_sig.returns.push(AbiParam::new(types::I8));
-    let read_sig = builder.import_signature(read_sig);
-
-    // declare address of the read function (getchar)
-    let read_address = builder.ins().iconst(ptr_type, getchar as *const () as i64);
-
-    // declare signature for write function (putchar)
-    let mut write_sig = Signature::new(call_conv);
-    write_sig.params.push(AbiParam::new(types::I8));
-    let write_sig = builder.import_signature(write_sig);
-
-    // declare address of the write function (putchar)
-    let write_address = builder.ins().iconst(ptr_type, putchar as *const () as i64);
-
-    // stack for tracking loop blocks
-    let mut stack = Vec::new();
-
-    // iterate over tokens and generate code for each token
-    for token in token_stream {
-        match token {
-            Token::Add(n) => {
-                // load the data pointer value
-                let ptr_val = builder.use_var(data_ptr);
-                // calculate cell address (memory_address + data_ptr)
-                let cell_address = builder.ins().iadd(memory_address, ptr_val);
-
-                // load the value from the current cell (in array)
-                let cell_value = builder.ins().load(types::I8, mem_flags, cell_address, 0);
-                // add n to the value
-                let cell_value = builder.ins().iadd_imm(cell_value, n as i64);
-
-                // store the new value back to the cell
-                builder.ins().store(mem_flags, cell_value, cell_address, 0);
-            },
-            Token::Move(n) => {
-                // load the data pointer value
-                let ptr_val = builder.use_var(data_ptr);
+/// Process, JIT-compile and run given Brainfuck code with the given input and return its output.
+/// # Arguments
+/// * code - The Brainfuck code.
+/// * input - The input bytes (the `,` command reads `0` after them, see [EofPolicy::Zero]).
+/// # Returns
+/// * [Vec<u8>] - The bytes written by the program, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * See [process_code] and [JitProgram::compile].
+/// # Example
+/// ```
+/// use bfuck::jit_from_str;
+///
+/// assert_eq!(jit_from_str(",[.,]", b"hello"), Ok(b"hello".to_vec()));
+/// ```
+pub fn jit_from_str(code: &str, input: &[u8]) -> Result<Vec<u8>, Error> {
+    let program = JitProgram::compile(&process_code(code)?, &JitOptions::default())?;
+    let mut io = VecIo::new(input);
+    program.run_checked(&mut io)?;
+    Ok(io.output)
+}
 
-                // the new pointer value is == (old_value + n) % STORAGE_SIZE
-                // but since remainder operation is expensive, we can calculate
-                // both (old_value + n) and (old_value + n - STORAGE_SIZE) and then
-                // select the correct value based on the condition (old_value + n < STORAGE_SIZE)
+/// The number of output bytes buffered by the JIT-compiled code before they are written.
+const OUTPUT_BUFFER_SIZE: usize = 8192;
 
-                // old_value + n
-                let ptr_plus = builder.ins().iadd_imm(ptr_val, n as i64);
-                // old_value + n - STORAGE_SIZE
-                let ptr_wrapped = builder.ins().iadd_imm(ptr_val, n as i64 - STORAGE_SIZE as i64);
+/// The input/output context passed to the JIT-compiled code.
+///
+/// The output is collected in the buffer and written to the [IoAdapter] at once when the buffer is full
+/// or when the compiled code flushes it (before every read, every debug dump and before it returns).
+pub(crate) struct JitContext<'a, 'b> {
+    /// The [IoAdapter] used for input and output.
+    io: &'a mut (dyn IoAdapter + 'b),
+    /// The buffered output.
+    output: Vec<u8>,
+}
+impl<'a, 'b> JitContext<'a, 'b> {
+    /// Create the context with the empty output buffer.
+    /// # Arguments
+    /// * `io` - The [IoAdapter] used for input and output.
+    pub(crate) fn new(io: &'a mut (dyn IoAdapter + 'b)) -> Self {
+        Self { io, output: Vec::with_capacity(OUTPUT_BUFFER_SIZE) }
+    }
 
-                // compare (old_value + n) with STORAGE_SIZE
-                let cmp = builder.ins().icmp_imm(IntCC::SignedLessThan, ptr_plus, STORAGE_SIZE as i64);
+    /// Write the buffered output to the [IoAdapter] and flush it (see [IoAdapter::flush]).
+    fn flush(&mut self) {
+        if !self.output.is_empty() {
+            self.io.write_bytes(&self.output);
+            self.io.flush();
+            self.output.clear();
+        }
+    }
+}
 
-                // select the correct value based on the condition
-                let ptr_val = builder.ins().select(cmp, ptr_plus, ptr_wrapped);
+/// Read a byte using the [IoAdapter] (called from the JIT-compiled code).
+/// # Arguments
+/// * `ctx` - The pointer to the [JitContext] passed to the JIT-compiled code.
+/// # Returns
+/// * The byte read, `-1` if there is no more input.
+extern "C" fn read_byte(ctx: *mut JitContext) -> i32 {
+    // the pointer is valid for the whole execution of the JIT-compiled code (see JitProgram::execute)
+    unsafe { (*ctx).io.read_byte().map_or(-1, i32::from) }
+}
 
-                // store the new data pointer value
-                builder.def_var(data_ptr, ptr_val);
-            },
-            Token::Input => {
-                // load the data pointer value
-                let ptr_val = builder.use_var(data_ptr);
-                // calculate cell address (memory_address + data_ptr)
-                let cell_address = builder.ins().iadd(memory_address, ptr_val);
-
-                // call the read function (getchar)
-                let read_res = builder
-                    .ins()
-                    .call_indirect(read_sig, read_address, &[]);
-                // get the result of the read function
-                let read_res = builder.inst_results(read_res)[0];
-
-                // store the read value to the cell
-                builder.ins().store(mem_flags, read_res, cell_address, 0);
-            },
-            Token::Output => {
-                // load the data pointer value
-                let ptr_val = builder.use_var(data_ptr);
-                // calculate cell address (memory_address + data_ptr)
-                let cell_address = builder.ins().iadd(memory_address, ptr_val);
-                // load the value from the cell
-                let cell_value = builder.ins().load(types::I8, mem_flags, cell_address, 0);
-
-                // call the write function (putchar) with the value from the cell
-                builder.ins().call_indirect(write_sig, write_address, &[cell_value]);
-            },
-            Token::OpenBr(_) => {
-                // create two new blocks - one for the loop body and one for the code after the loop
-                let inner_block = builder.create_block();
-                let after_block = builder.create_block();
-
-                // load the data pointer value
-                let ptr_val = builder.use_var(data_ptr);
-                // calculate cell address (memory_address + data_ptr)
-                let cell_address = builder.ins().iadd(memory_address, ptr_val);
-                // load the value from the cell
-                let cell_value = builder.ins().load(types::I8, mem_flags, cell_address, 0);
-
-                // compare the value from the cell with zero
-                // if the value is zero, jump to the block after the loop, otherwise jump to the loop body
-                let eq_zero_cmp = builder.ins().icmp_imm(IntCC::Equal, cell_value, 0);
-                builder.ins().brif(eq_zero_cmp, after_block, &[], inner_block, &[]);
-
-                // switch to the loop body block (because next command will be inside the loop body)
-                builder.switch_to_block(inner_block);
-
-                // push the loop blocks to the stack
-                stack.push((inner_block, after_block));
-            },
-            Token::CloseBr(_) => {
-                // get the loop blocks from the stack (guaranteed to be there because loops are checked for correctness in the parser)
-                let (inner_block, after_block) = stack.pop().unwrap();
-
-                // load the data pointer value
-                let ptr_val = builder.use_var(data_ptr);
-                // calculate cell address (memory_address + data_ptr)
-                let cell_address = builder.ins().iadd(memory_address, ptr_val);
-                // load the value from the cell
-                let cell_value = builder.ins().load(types::I8, mem_flags, cell_address, 0);
-
-                // compare the value from the cell with zero
-                // if the value is zero, jump to the block after the loop, otherwise jump to the loop body (next iteration)
-                let eq_zero_cmp = builder.ins().icmp_imm(IntCC::Equal, cell_value, 0);
-                builder.ins().brif(eq_zero_cmp, after_block, &[], inner_block, &[]);
-
-                // now all jumps to these blocks are defined, so we can seal them
-                builder.seal_block(inner_block);
-                builder.seal_block(after_block);
-
-                // switch to the block after the loop (where next command will be)
-                builder.switch_to_block(after_block);
-            },
-            Token::ClearCell => {
-                // load the data pointer value
-                let ptr_val = builder.use_var(data_ptr);
-                // calculate cell address (memory_address + data_ptr)
-                let cell_address = builder.ins().iadd(memory_address, ptr_val);
-                
-                // create a constant zero value
-                let zero = builder.ins().iconst(types::I8, 0);
-
-                // store the zero value to the cell
-                builder.ins().store(mem_flags, zero, cell_address, 0);
-            },
-            Token::AddTo(n) => {
-                // load the data pointer value
-                let ptr_val = builder.use_var(data_ptr);
-                
-                // calculate the pointer of the destination cell
-                // ptr_value + n
-                let ptr_plus = builder.ins().iadd_imm(ptr_val, n as i64);
-                // ptr_value + n - STORAGE_SIZE
-                let ptr_wrapped = builder.ins().iadd_imm(ptr_val, n as i64 - STORAGE_SIZE as i64);
-                // compare (ptr_value + n) with STORAGE_SIZE
-                let cmp = builder.ins().icmp_imm(IntCC::SignedLessThan, ptr_plus, STORAGE_SIZE as i64);
-                // select the correct value based on the condition
-                let new_loc = builder.ins().select(cmp, ptr_plus, ptr_wrapped);
-
-                // calculate the addresses of the original and new cells
-                let original_address = builder.ins().iadd(memory_address, ptr_val);
-                let new_address = builder.ins().iadd(memory_address, new_loc);
-
-                // load values from the original and new cells
-                let original_value = builder.ins().load(types::I8, mem_flags, original_address, 0);
-                let new_value = builder.ins().load(types::I8, mem_flags, new_address, 0);
-                
-                // add the original value to the new value
-                let new_value = builder.ins().iadd(new_value, original_value);
-
-                // store the new value back to its cell
-                builder.ins().store(mem_flags, new_value, new_address, 0);
-                
-                // zero the original cell
-                let zero = builder.ins().iconst(types::I8, 0);
-                builder.ins().store(mem_flags, zero, original_address, 0);
-            },
-            Token::AddToCopy(n1, n2) => {
-                // load the data pointer value
-                let ptr_val = builder.use_var(data_ptr);
-
-                // calculate the pointer of the destination cells
-                
-                // ptr_value + n1
-                let ptr_plus = builder.ins().iadd_imm(ptr_val, n1 as i64);
-                // ptr_value + n1 - STORAGE_SIZE
-                let ptr_wrapped = builder.ins().iadd_imm(ptr_val, n1 as i64 - STORAGE_SIZE as i64);
-                // compare (ptr_value + n1) with STORAGE_SIZE
-                let cmp = builder.ins().icmp_imm(IntCC::SignedLessThan, ptr_plus, STORAGE_SIZE as i64);
-                // select the correct value based on the condition
-                let new_loc_1 = builder.ins().select(cmp, ptr_plus, ptr_wrapped);
-                
-                // ptr_value + n2
-                let ptr_plus = builder.ins().iadd_imm(ptr_val, n2 as i64);
-                // ptr_value + n2 - STORAGE_SIZE
-                let ptr_wrapped = builder.ins().iadd_imm(ptr_val, n2 as i64 - STORAGE_SIZE as i64);
-                // compare (ptr_value + n2) with STORAGE_SIZE
-                let cmp = builder.ins().icmp_imm(IntCC::SignedLessThan, ptr_plus, STORAGE_SIZE as i64);
-                // select the correct value based on the condition
-                let new_loc_2 = builder.ins().select(cmp, ptr_plus, ptr_wrapped);
-
-                // calculate the addresses of the original and new cells
-                let original_address = builder.ins().iadd(memory_address, ptr_val);
-                let new_address_1 = builder.ins().iadd(memory_address, new_loc_1);
-                let new_address_2 = builder.ins().iadd(memory_address, new_loc_2);
-                
-                // load values from the original and new cells
-                let original_value = builder.ins().load(types::I8, mem_flags, original_address, 0);
-                let new_value_1 = builder.ins().load(types::I8, mem_flags, new_address_1, 0);
-                let new_value_2 = builder.ins().load(types::I8, mem_flags, new_address_2, 0);
-
-                // add the original value to the new values
-                let new_value_1 = builder.ins().iadd(new_value_1, original_value);
-                let new_value_2 = builder.ins().iadd(new_value_2, original_value);
-
-                // store the new values back to their cells
-                builder.ins().store(mem_flags, new_value_1, new_address_1, 0);
-                builder.ins().store(mem_flags, new_value_2, new_address_2, 0);
-
-                // zero the original cell
-                let zero = builder.ins().iconst(types::I8, 0);
-                builder.ins().store(mem_flags, zero, original_address, 0);
-            },
+/// Append a byte to the output buffer, writing the buffer if it is full (called from the JIT-compiled code).
+/// # Arguments
+/// * `ctx` - The pointer to the [JitContext] passed to the JIT-compiled code.
+/// * `byte` - The byte to write.
+extern "C" fn write_byte(ctx: *mut JitContext, byte: u8) {
+    // the pointer is valid for the whole execution of the JIT-compiled code (see JitProgram::execute)
+    let ctx = unsafe { &mut *ctx };
+    ctx.output.push(byte);
+    if ctx.output.len() >= OUTPUT_BUFFER_SIZE {
+        ctx.flush();
+    }
+}
+
+/// Append a byte repeatedly to the output buffer, writing the buffer whenever it is full (called from the JIT-compiled code).
+/// # Arguments
+/// * `ctx` - The pointer to the [JitContext] passed to the JIT-compiled code.
+/// * `byte` - The byte to write.
+/// * `count` - The number of times the byte is written.
+extern "C" fn write_byte_repeated(ctx: *mut JitContext, byte: u8, count: usize) {
+    // the pointer is valid for the whole execution of the JIT-compiled code (see JitProgram::execute)
+    let ctx = unsafe { &mut *ctx };
+    for _ in 0..count {
+        ctx.output.push(byte);
+        if ctx.output.len() >= OUTPUT_BUFFER_SIZE {
+            ctx.flush();
         }
     }
+}
 
-    // return instruction to the end of the function
-    builder.ins().return_(&[]);
+/// Write the buffered output using the [IoAdapter] (called from the JIT-compiled code).
+/// # Arguments
+/// * `ctx` - The pointer to the [JitContext] passed to the JIT-compiled code.
+extern "C" fn flush_output(ctx: *mut JitContext) {
+    // the pointer is valid for the whole execution of the JIT-compiled code (see JitProgram::execute)
+    unsafe { (*ctx).flush() }
+}
 
-    // finalize the function
-    builder.finalize();
-    
-    // END of building the JIT function
+/// Print the debug dump of the array (called from the JIT-compiled code).
+/// # Arguments
+/// * `tape` - The pointer to the array of cells.
+/// * `tape_len` - The length of the array.
+/// * `data_ptr` - The data pointer.
+/// * `cell_bytes` - The size of a cell in bytes.
+extern "C" fn debug_dump(tape: *const u8, tape_len: usize, data_ptr: usize, cell_bytes: usize) {
+    // the array is valid and aligned for the whole execution of the JIT-compiled code (see JitProgram::execute)
+    unsafe {
+        match cell_bytes {
+            1 => crate::io::debug_dump(std::slice::from_raw_parts(tape, tape_len), data_ptr),
+            2 => crate::io::debug_dump(std::slice::from_raw_parts(tape.cast::<u16>(), tape_len), data_ptr),
+            _ => crate::io::debug_dump(std::slice::from_raw_parts(tape.cast::<u32>(), tape_len), data_ptr),
+        }
+    }
+}
 
-    // Verify that the function is correct before compiling.
-    // This shouldn't fail if we correctly wrote a code for generating the function (which we did).
-    assert_eq!(verify_function(&function, &*target_isa), Ok(()), "The JIT function is not valid!");
+/// The executable memory containing the JIT-compiled code.
+#[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+#[derive(Debug)]
+struct CodeBuffer(Mmap);
+#[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
+impl CodeBuffer {
+    /// Map the machine code into the executable memory.
+    /// # Arguments
+    /// * `code` - The machine code.
+    /// # Returns
+    /// * [CodeBuffer] - The mapped code, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `MappingFailed` - The memory couldn't be mapped or made executable.
+    fn new(code: &[u8]) -> Result<Self, Error> {
+        let mut buffer = MmapOptions::new()
+            .len(code.len())
+            .map_anon()
+            .map_err(|err| Error::MappingFailed(err.kind()))?;
+        buffer.copy_from_slice(code);
+        let buffer = buffer.make_exec().map_err(|err| Error::MappingFailed(err.kind()))?;
 
-    // Compile the function to machine code.
-    // Shouldn't fail since we verified the function.
-    let mut compiled_code = Vec::new();
-    codegen::Context::for_function(function)
-        .compile_and_emit(&*target_isa, &mut compiled_code, &mut codegen::control::ControlPlane::default())
-        .unwrap();
+        // On AArch64 the instruction cache is not coherent with the data cache,
+        // so it has to be flushed before the freshly written code is executed.
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            wasmtime_jit_icache_coherence::clear_cache(buffer.as_ptr().cast(), buffer.len()).unwrap();
+            wasmtime_jit_icache_coherence::pipeline_flush_mt().unwrap();
+        }
 
-    // Map the compiled code into memory.
-    let mut code_buffer = MmapOptions::new()
-        .len(compiled_code.len())
-        .map_anon()
-        .unwrap();
-    code_buffer.copy_from_slice(&compiled_code);
-    let code_buffer = code_buffer.make_exec().unwrap();
-    drop(compiled_code);
+        Ok(Self(buffer))
+    }
 
-    // Execute the JIT function.
-    unsafe {
-        let memory = [0_u8; STORAGE_SIZE];
-        let code_fn: unsafe extern "C" fn(*const u8) = mem::transmute(code_buffer.as_ptr());
-        code_fn(memory.as_ptr())
+    /// Get the pointer to the start of the code.
+    fn as_ptr(&self) -> *const u8 {
+        self.0.as_ptr()
+    }
+}
+
+/// The executable memory containing the JIT-compiled code.
+///
+/// With the hardened runtime on Apple Silicon, the anonymous memory can't be made executable after it was written,
+/// so the code is mapped with `MAP_JIT` and written while the write protection of the thread is disabled
+/// (the protection is a per-thread state, so the other threads never see the mapping writable).
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+#[derive(Debug)]
+struct CodeBuffer {
+    /// The start of the mapping.
+    ptr: *mut u8,
+    /// The length of the mapping.
+    len: usize,
+}
+// the code is never written after it was mapped
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+unsafe impl Send for CodeBuffer {}
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+unsafe impl Sync for CodeBuffer {}
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+impl CodeBuffer {
+    /// Map the machine code into the executable memory.
+    /// # Arguments
+    /// * `code` - The machine code.
+    /// # Returns
+    /// * [CodeBuffer] - The mapped code, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `MappingFailed` - The memory couldn't be mapped (e.g. the `allow-jit` entitlement is missing).
+    fn new(code: &[u8]) -> Result<Self, Error> {
+        extern "C" {
+            fn sys_icache_invalidate(start: *mut libc::c_void, len: libc::size_t);
+        }
+
+        unsafe {
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                code.len(),
+                libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
+                libc::MAP_PRIVATE | libc::MAP_ANON | libc::MAP_JIT,
+                -1,
+                0,
+            );
+            if ptr == libc::MAP_FAILED {
+                return Err(Error::MappingFailed(io::Error::last_os_error().kind()));
+            }
+
+            // the write protection is toggled only for the current thread
+            libc::pthread_jit_write_protect_np(0);
+            std::ptr::copy_nonoverlapping(code.as_ptr(), ptr.cast::<u8>(), code.len());
+            libc::pthread_jit_write_protect_np(1);
+            sys_icache_invalidate(ptr, code.len());
+
+            Ok(Self { ptr: ptr.cast(), len: code.len() })
+        }
+    }
+
+    /// Get the pointer to the start of the code.
+    fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+}
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+impl Drop for CodeBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr.cast(), self.len);
+        }
+    }
+}
+
+/// Disassemble the machine code compiled for the host.
+/// # Arguments
+/// * `code` - The machine code.
+/// # Returns
+/// * [String] - The disassembly, if [Ok].
+/// * [Error] - The encountered error, if [Err].
+/// # Errors
+/// * `DisassemblyUnsupported` - The host architecture is not supported by the disassembler.
+#[cfg(feature = "capstone")]
+fn disassemble(code: &[u8]) -> Result<String, Error> {
+    use capstone::prelude::*;
+
+    let builder = Capstone::new();
+    #[cfg(target_arch = "x86_64")]
+    let capstone = builder.x86().mode(arch::x86::ArchMode::Mode64).build();
+    #[cfg(target_arch = "aarch64")]
+    let capstone = builder.arm64().mode(arch::arm64::ArchMode::Arm).build();
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    let capstone: CsResult<Capstone> = {
+        drop(builder);
+        return Err(Error::DisassemblyUnsupported);
     };
+    let capstone = capstone.map_err(|_| Error::DisassemblyUnsupported)?;
+
+    let instructions = capstone.disasm_all(code, 0).map_err(|_| Error::DisassemblyUnsupported)?;
+    let mut disassembly = String::new();
+    for instruction in instructions.iter() {
+        disassembly.push_str(&format!("{}\n", instruction));
+    }
+
+    Ok(disassembly)
+}
+
+/// Disassemble the machine code compiled for the host.
+/// # Errors
+/// * `DisassemblyUnsupported` - Always, because the `capstone` feature is not enabled.
+#[cfg(not(feature = "capstone"))]
+fn disassemble(_code: &[u8]) -> Result<String, Error> {
+    Err(Error::DisassemblyUnsupported)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::code::{process_code, process_code_with_pipeline, process_code_with_stats, OptimizationPipeline};
+    use crate::interpret::{interpret, interpret_with_eof, interpret_with_stats};
+    use crate::io::VecIo;
+
+    #[test]
+    fn test_emit_clif() {
+        //! Test writing the Cranelift IR to the file.
+
+        let path = env::temp_dir().join(format!("bfuck-test-emit-clif-{}.clif", std::process::id()));
+        let options = JitOptions {
+            emit_clif: Some(EmitTarget::File(path.clone())),
+            ..JitOptions::default()
+        };
+
+        let tokens = process_code("+[->+<]>.").unwrap();
+        JitProgram::compile(&tokens, &options).unwrap();
+
+        let clif = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(clif.starts_with("function "));
+        assert!(clif.contains("call_indirect"));
+    }
+
+    #[test]
+    fn test_stats() {
+        //! Test that the compilation metrics are populated and grow with the program.
+
+        let compile = |code: &str, opt_level: OptLevel| {
+            let tokens = process_code(code).unwrap();
+            JitProgram::compile(&tokens, &JitOptions { opt_level, ..JitOptions::default() }).unwrap().stats()
+        };
+
+        let small = compile(",.", OptLevel::Speed);
+        assert_eq!(small.tokens, 2);
+        assert!(small.clif_insts > 0);
+        assert!(small.code_bytes > 0);
+        assert!(small.compile_time > Duration::ZERO);
+
+        let code = ",[>+++[->++<]<.-]".repeat(50);
+        let large = compile(&code, OptLevel::Speed);
+        assert_eq!(large.tokens, process_code(&code).unwrap().len());
+        assert!(large.clif_insts > 10 * small.clif_insts, "{:?} {:?}", small, large);
+        assert!(large.code_bytes > 10 * small.code_bytes, "{:?} {:?}", small, large);
+
+        // the same function is generated at every optimization level
+        assert_eq!(compile(&code, OptLevel::None).clif_insts, large.clif_insts);
+    }
+
+    #[test]
+    fn test_final_state() {
+        //! Test that the final state of the JIT-compiled program matches the interpreter.
+
+        let codes = [
+            "",
+            "+++",
+            "-",
+            "++>+++++[<+>-]",
+            "<<+",
+            "++++++++[>++++++++<-]>+",
+            "-[>-[>+[->+>+<<]>>[-<<+>>]<<<-]<-]>>>>",
+            "+[[-]>]<<+++",
+        ];
+        for code in codes {
+            let tokens = process_code(code).unwrap();
+            let state = JitProgram::compile(&tokens, &JitOptions::default()).unwrap().run();
+            assert_eq!(state, interpret(&tokens, &mut VecIo::default()), "code: {}", code);
+        }
+
+        let state = jit(process_code(">>+++>--").unwrap()).unwrap();
+        assert_eq!(state, FinalState { data_ptr: 3, cell: 254 });
+    }
+
+    #[test]
+    fn test_code_buffer() {
+        //! Smoke test of mapping the compiled code (done differently on macOS with Apple Silicon).
+
+        // the programs are mapped and unmapped repeatedly
+        for _ in 0..10 {
+            let program = JitProgram::compile(&process_code("++++++++[>++++++++<-]>+.").unwrap(), &JitOptions::default()).unwrap();
+            let mut io = VecIo::default();
+            assert_eq!(program.run_with_io(&mut io), FinalState { data_ptr: 1, cell: 65 });
+            assert_eq!(io.output, b"A");
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_windows() {
+        //! Test the compiled code end to end on Windows (the Windows x64 calling convention and the stack probes).
+
+        let tokens = process_code(",[.,]++++++++[>++++++++<-]>+...").unwrap();
+        let result = JitProgram::compile(&tokens, &JitOptions::default());
+        if cfg!(not(target_arch = "x86_64")) {
+            assert_eq!(result.err(), Some(Error::UnsupportedPlatformJIT));
+            return;
+        }
+
+        let mut interpret_io = VecIo::new(b"hello");
+        let expected = interpret_with_stats(&tokens, &mut interpret_io, EofPolicy::Zero);
+        let mut jit_io = VecIo::new(b"hello");
+        assert_eq!(result.unwrap().run_with_io(&mut jit_io), expected.0);
+        assert_eq!(jit_io, interpret_io);
+
+        // the most parameters (the ones after the fourth are passed on the stack, above the shadow space)
+        let options = JitOptions { instrument: true, fuel: true, strict_bounds: true, ..JitOptions::default() };
+        let program = JitProgram::compile(&tokens, &options).unwrap();
+        let mut jit_io = VecIo::new(b"hello");
+        assert_eq!(program.run_instrumented(&mut jit_io), Ok(expected));
+        assert_eq!(jit_io, interpret_io);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_perf_map() {
+        //! Test that the compiled code is registered in the perf map and the jitdump.
+
+        let options = JitOptions { perf_map: true, ..JitOptions::default() };
+        let program = JitProgram::compile(&process_code("+[->+<]>.").unwrap(), &options).unwrap();
+        let address = program.code_buffer.as_ptr() as u64;
+
+        let perf_map = fs::read_to_string(format!("/tmp/perf-{}.map", std::process::id())).unwrap();
+        assert!(perf_map.lines().any(|line| line.starts_with(&format!("{:x} ", address)) && line.ends_with(" bf_main")));
+
+        // the records follow the 40-byte header, the address of the code is at the offset 32 of the record
+        let jitdump = fs::read(format!("/tmp/jit-{}.dump", std::process::id())).unwrap();
+        assert_eq!(&jitdump[..4], &0x4A69_5444_u32.to_le_bytes());
+        let mut offset = 40;
+        let mut found = false;
+        while offset < jitdump.len() {
+            let total_size = u32::from_le_bytes(jitdump[offset + 4..offset + 8].try_into().unwrap()) as usize;
+            found |= u64::from_le_bytes(jitdump[offset + 32..offset + 40].try_into().unwrap()) == address;
+            offset += total_size;
+        }
+        assert!(found);
+    }
+
+    #[test]
+    fn test_run_with_io() {
+        //! Test that the JIT-compiled program uses the given input and output like the interpreter.
+
+        let codes = [",[.,]", ",>,>,[<]>.>.", "+++[>,.<-]", ",[->+>+<<]>.>.", ",>,>,<<.>+.>-.<<[-]>>>,<<<.>.>.>."];
+        for code in codes {
+            let tokens = process_code(code).unwrap();
+            let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+            for input in [&b""[..], b"a", b"hello", b"\x00\xff\x80"] {
+                let mut jit_io = VecIo::new(input);
+                let mut interpret_io = VecIo::new(input);
+                assert_eq!(program.run_with_io(&mut jit_io), interpret(&tokens, &mut interpret_io));
+                assert_eq!(jit_io, interpret_io, "code: {}", code);
+            }
+        }
+    }
+
+    /// The [IoAdapter] recording the order of the reads and the writes.
+    #[derive(Default)]
+    struct LoggingIo {
+        /// `None` for a read, the written bytes for a write.
+        log: Vec<Option<Vec<u8>>>,
+    }
+    impl IoAdapter for LoggingIo {
+        fn read_byte(&mut self) -> Option<u8> {
+            self.log.push(None);
+            Some(b'a')
+        }
+
+        fn write_byte(&mut self, byte: u8) {
+            self.log.push(Some(vec![byte]));
+        }
+
+        fn write_bytes(&mut self, bytes: &[u8]) {
+            self.log.push(Some(bytes.to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_buffered_output() {
+        //! Test that the output is written in chunks and flushed before every read and at the end.
+
+        let tokens = process_code(&format!("+++[>{}<-]", "+.".repeat(7000))).unwrap();
+        let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+        let mut io = LoggingIo::default();
+        program.run_with_io(&mut io);
+        let lengths: Vec<_> = io.log.iter().map(|write| write.as_ref().unwrap().len()).collect();
+        assert_eq!(lengths, [OUTPUT_BUFFER_SIZE, OUTPUT_BUFFER_SIZE, 21000 - 2 * OUTPUT_BUFFER_SIZE]);
+
+        let tokens = process_code("+.+.,.,,.").unwrap();
+        let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+        let mut io = LoggingIo::default();
+        program.run_with_io(&mut io);
+        assert_eq!(io.log, [Some(vec![1, 2]), None, Some(vec![b'a']), None, None, Some(vec![b'a'])]);
+
+        // the output is flushed when the fuel runs out too
+        let tokens = process_code("+[.]").unwrap();
+        let program = JitProgram::compile(&tokens, &JitOptions { fuel: true, ..JitOptions::default() }).unwrap();
+        let mut io = VecIo::default();
+        assert_eq!(program.run_with_fuel(5, &mut io), Err(Error::StepLimitExceeded));
+        assert_eq!(io.output, [1; 6]);
+    }
+
+    #[test]
+    fn test_repeated_outputs() {
+        //! Test that the consecutive outputs match the interpreter (also when they fill the output buffer).
+
+        let codes = [String::from("+.....>.+..,..[-.]"), format!("+{}>++{}", ".".repeat(9000), ".".repeat(3))];
+        for code in codes {
+            let tokens = process_code(&code).unwrap();
+            let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+            let mut jit_io = VecIo::new(b"a");
+            let mut interpret_io = VecIo::new(b"a");
+            assert_eq!(program.run_with_io(&mut jit_io), interpret(&tokens, &mut interpret_io));
+            assert_eq!(jit_io, interpret_io);
+        }
+
+        let tokens = process_code(&format!("+{}", ".".repeat(9000))).unwrap();
+        let mut io = LoggingIo::default();
+        JitProgram::compile(&tokens, &JitOptions::default()).unwrap().run_with_io(&mut io);
+        assert_eq!(io.log, [Some(vec![1; OUTPUT_BUFFER_SIZE]), Some(vec![1; 9000 - OUTPUT_BUFFER_SIZE])]);
+    }
+
+    #[test]
+    fn test_eof_policies() {
+        //! Test that the JIT-compiled program applies the EOF policy like the interpreter.
+
+        // reads past the end of the input into a cell with a known value and inside of a loop
+        let tokens = process_code(",.>,.>+++++,.>>++<+++[>,.<-]>.").unwrap();
+        let expected = [
+            (EofPolicy::Zero, b"ab\x00\x00\x00\x00\x00"),
+            (EofPolicy::Max, b"ab\xff\xff\xff\xff\xff"),
+            (EofPolicy::Unchanged, b"ab\x05\x02\x02\x02\x02"),
+        ];
+        for (eof, expected_output) in expected {
+            for unroll_limit in [0, 16] {
+                let options = JitOptions { eof, unroll_limit, ..JitOptions::default() };
+                let program = JitProgram::compile(&tokens, &options).unwrap();
+                let mut jit_io = VecIo::new(b"ab");
+                let mut interpret_io = VecIo::new(b"ab");
+                assert_eq!(program.run_with_io(&mut jit_io), interpret_with_eof(&tokens, &mut interpret_io, eof));
+                assert_eq!(jit_io, interpret_io, "policy: {:?}", eof);
+                assert_eq!(jit_io.output, expected_output, "policy: {:?}", eof);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wrapping_moves() {
+        //! Test that the moves around the edges of the array match the interpreter.
+
+        let far_right = ">".repeat(STORAGE_SIZE - 1);
+        let codes = [
+            String::from("<+.<<++.>>>."),
+            String::from("+>+>+<<<<+[>]<."),
+            String::from("<++[->>+<<]>>."),
+            String::from("+[<+>-]<<<<+>>>."),
+            format!("{}+>++>+++<<.>.>.", far_right),
+            format!("+++[{}-]", far_right),
+            format!(">+[<{}{}>-]<.", far_right, far_right),
+        ];
+        for code in codes {
+            let tokens = process_code(&code).unwrap();
+            let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+            let mut jit_io = VecIo::default();
+            let mut interpret_io = VecIo::default();
+            assert_eq!(program.run_with_io(&mut jit_io), interpret(&tokens, &mut interpret_io), "code: {}", code);
+            assert_eq!(jit_io, interpret_io, "code: {}", code);
+        }
+    }
+
+    #[test]
+    fn test_many_programs() {
+        //! Stress test compiling and running many distinct programs (newly mapped code must always be executed correctly).
+
+        for i in 0..256_usize {
+            let code = format!("{}[>{}<-]>.{}", "+".repeat(i % 17 + 1), "+".repeat(i % 13 + 1), ".>".repeat(i % 5));
+            let tokens = process_code(&code).unwrap();
+            let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+            let mut jit_io = VecIo::default();
+            let mut interpret_io = VecIo::default();
+            assert_eq!(program.run_with_io(&mut jit_io), interpret(&tokens, &mut interpret_io), "code: {}", code);
+            assert_eq!(jit_io, interpret_io, "code: {}", code);
+        }
+    }
+
+    #[test]
+    fn test_opt_levels() {
+        //! Test that every optimization level (with and without the verifier) produces a program that matches the interpreter.
+
+        let codes = [",[.,]", "++++++++[>++++++++<-]>+.", ",[->+>+<<]>.>.", "-[>-[>+[->+>+<<]>>[-<<+>>]<<<-]<-]>>>>.", "<<+.[-]>>."];
+        for opt_level in [OptLevel::None, OptLevel::Speed, OptLevel::SpeedAndSize] {
+            for verify in [true, false] {
+                let options = JitOptions { opt_level, verify, ..JitOptions::default() };
+                for code in codes {
+                    let tokens = process_code(code).unwrap();
+                    let program = JitProgram::compile(&tokens, &options).unwrap();
+                    let mut jit_io = VecIo::new(b"hello");
+                    let mut interpret_io = VecIo::new(b"hello");
+                    assert_eq!(program.run_with_io(&mut jit_io), interpret(&tokens, &mut interpret_io), "code: {}, options: {:?}", code, options);
+                    assert_eq!(jit_io, interpret_io, "code: {}, options: {:?}", code, options);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cell_widths() {
+        //! Test that the wider cells match the interpreter when no cell wraps and that they wrap at their width.
+
+        let codes = [",[.,]", "++++++++[>++++++++<-]>+.", ",[->+>+<<]>.>.", "<<+.[-]>>.", "+++[>+>++<<-]>#>.", "[-]+++.[-]->[-]+<[>+<+]>."];
+        let wrapping = format!("{}[.[-]]>{}[>{}<-]>[.[-]]-.", "+".repeat(256), "+".repeat(256), "+".repeat(256));
+        let expected = [(CellWidth::U8, &b"\xff"[..]), (CellWidth::U16, b"\x00\xff"), (CellWidth::U32, b"\x00\x00\xff")];
+        for (cell_width, expected_output) in expected {
+            let options = JitOptions { cell_width, ..JitOptions::default() };
+            for code in codes {
+                let tokens = process_code_with_pipeline(code, &OptimizationPipeline::wide_cells()).unwrap();
+                let program = JitProgram::compile(&tokens, &options).unwrap();
+                let mut jit_io = VecIo::new(b"hello");
+                let mut interpret_io = VecIo::new(b"hello");
+                assert_eq!(program.run_with_io(&mut jit_io), interpret(&tokens, &mut interpret_io), "code: {}, width: {:?}", code, cell_width);
+                assert_eq!(jit_io, interpret_io, "code: {}, width: {:?}", code, cell_width);
+            }
+
+            let program = JitProgram::compile(&process_code_with_pipeline(&wrapping, &OptimizationPipeline::wide_cells()).unwrap(), &options).unwrap();
+            let mut io = VecIo::default();
+            assert_eq!(program.run_with_io(&mut io), FinalState { data_ptr: 2, cell: 255 }, "width: {:?}", cell_width);
+            assert_eq!(io.output, expected_output, "width: {:?}", cell_width);
+        }
+
+        // the end of input sets all bits of the wide cell
+        let tokens = process_code(",+.").unwrap();
+        let options = JitOptions { cell_width: CellWidth::U32, eof: EofPolicy::Max, ..JitOptions::default() };
+        let mut io = VecIo::default();
+        JitProgram::compile(&tokens, &options).unwrap().run_with_io(&mut io);
+        assert_eq!(io.output, [0]);
+    }
+
+    #[test]
+    fn test_split_functions() {
+        //! Test that the programs split into multiple functions match the interpreter.
+
+        let codes = [",[.,]>,.", "++++++++[>++++++++<-]>+.>+.", ",[->+>+<<]>.>.", "-[>-[>+[->+>+<<]>>[-<<+>>]<<<-]<-]>>>>.", "<<+.[-]>>.+[<]>.", "+++[>+>++<<-]>#>."];
+        for max_function_tokens in [1, 3, 5] {
+            for cell_width in [CellWidth::U8, CellWidth::U16] {
+                let options = JitOptions { max_function_tokens, cell_width, ..JitOptions::default() };
+                for code in codes {
+                    let tokens = process_code_with_pipeline(code, &OptimizationPipeline::wide_cells()).unwrap();
+                    let program = JitProgram::compile(&tokens, &options).unwrap();
+                    assert!(program.stats().functions > 1, "code: {}, options: {:?}", code, options);
+                    let mut jit_io = VecIo::new(b"hello");
+                    let mut interpret_io = VecIo::new(b"hello");
+                    assert_eq!(program.run_with_io(&mut jit_io), interpret(&tokens, &mut interpret_io), "code: {}, options: {:?}", code, options);
+                    assert_eq!(jit_io, interpret_io, "code: {}, options: {:?}", code, options);
+                }
+            }
+        }
+
+        // the split program on the host tape starts from the given data pointer
+        let tokens = process_code("[->+<]>>+<[->+<]").unwrap();
+        let options = JitOptions { host_tape: true, max_function_tokens: 2, ..JitOptions::default() };
+        let program = JitProgram::compile(&tokens, &options).unwrap();
+        assert_eq!(program.stats().functions, 4);
+        let mut tape = vec![0; STORAGE_SIZE];
+        tape[10] = 3;
+        assert_eq!(program.run_on(&mut tape, 10), Ok(FinalState { data_ptr: 11, cell: 0 }));
+        assert_eq!(tape[10..13], [0, 0, 4]);
+
+        // the programs with the extra parameters are never split
+        let tokens = process_code("+[>+<-]>[>+<-]>.").unwrap();
+        for options in [JitOptions { fuel: true, ..JitOptions::default() }, JitOptions { strict_bounds: true, ..JitOptions::default() }] {
+            let program = JitProgram::compile(&tokens, &JitOptions { max_function_tokens: 1, ..options }).unwrap();
+            assert_eq!(program.stats().functions, 1);
+        }
+    }
+
+    #[test]
+    fn test_split_huge_program() {
+        //! Test that the huge program is split and compiled in a reasonable time.
+
+        let tokens = process_code(&",[.-]>".repeat(35_000)).unwrap();
+        assert!(tokens.len() >= 200_000);
+
+        let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+        let stats = program.stats();
+        assert!(stats.functions > 20, "{:?}", stats);
+        assert!(stats.compile_time < Duration::from_secs(60), "{:?}", stats);
+
+        let mut jit_io = VecIo::new(b"\x03\x02");
+        let mut interpret_io = VecIo::new(b"\x03\x02");
+        assert_eq!(program.run_with_io(&mut jit_io), interpret(&tokens, &mut interpret_io));
+        assert_eq!(jit_io, interpret_io);
+        assert_eq!(jit_io.output, [3, 2, 1, 2, 1]);
+    }
+
+    #[test]
+    fn test_run_with_fuel() {
+        //! Test that the program runs out of fuel after the given number of loop iterations.
+
+        let options = JitOptions { fuel: true, ..JitOptions::default() };
+
+        // 3 iterations = 2 jumps back to the start of the loop
+        let tokens = process_code("+++[>+.<-]").unwrap();
+        let program = JitProgram::compile(&tokens, &options).unwrap();
+        let mut jit_io = VecIo::default();
+        let mut interpret_io = VecIo::default();
+        assert_eq!(program.run_with_fuel(2, &mut jit_io), Ok(interpret(&tokens, &mut interpret_io)));
+        assert_eq!(jit_io, interpret_io);
+        assert_eq!(program.run_with_fuel(1, &mut VecIo::default()), Err(Error::StepLimitExceeded));
+        assert_eq!(program.run_with_io(&mut VecIo::default()), interpret(&tokens, &mut VecIo::default()));
+
+        // the iterations of the nested loops are counted too
+        let tokens = process_code("++[>++[>+.<-]<-]").unwrap();
+        let program = JitProgram::compile(&tokens, &options).unwrap();
+        assert!(program.run_with_fuel(3, &mut VecIo::default()).is_ok());
+        assert_eq!(program.run_with_fuel(2, &mut VecIo::default()), Err(Error::StepLimitExceeded));
+
+        // infinite loops are stopped
+        for code in ["+[]", "+[>+<]", "+[.>[-]<]"] {
+            let program = JitProgram::compile(&process_code(code).unwrap(), &options).unwrap();
+            assert_eq!(program.run_with_fuel(10_000, &mut VecIo::default()), Err(Error::StepLimitExceeded), "code: {}", code);
+        }
+    }
+
+    #[test]
+    fn test_strict_bounds() {
+        //! Test that the program with strict bounds stops when the data pointer would leave the array.
+
+        let options = JitOptions { strict_bounds: true, ..JitOptions::default() };
+        let cases = [
+            ("<", 0),
+            ("+>+<<+", 3),
+            ("+[>+]", 2),
+            ("+[-<+>]", 1),
+            ("+[->+<<+>]", 1),
+            ("+[->+>+<<<+>]", 1),
+        ];
+        for (code, index) in cases {
+            let program = JitProgram::compile(&process_code(code).unwrap(), &options).unwrap();
+            assert_eq!(program.run_checked(&mut VecIo::default()), Err(Error::PointerOutOfBounds { row: 0, col: index }), "code: {}", code);
+        }
+
+        // with the locations, the line and column of the command are reported (the first command of the optimized token)
+        let cases = [
+            ("+>+\n<<+", (2, 1)),
+            ("+[\n  -<<+>>]", (1, 2)),
+            ("+\n[>+]", (2, 2)),
+        ];
+        for (code, (row, col)) in cases {
+            let (tokens, stats) = process_code_with_stats(code).unwrap();
+            let program = JitProgram::compile(&tokens, &options).unwrap().with_locations(stats.locations);
+            assert_eq!(program.run_checked(&mut VecIo::default()), Err(Error::PointerOutOfBounds { row, col }), "code: {:?}", code);
+        }
+
+        // the programs staying inside the array match the interpreter
+        let codes = ["[-<+>]", "[->+<<+>]", ">>+[->+>+<+<]>.>.", ">>+[<]>+[>>+<<-]", "++[>++[>+.<-]<-]>[<]", ",>,>,[<]>.>."];
+        for code in codes {
+            let tokens = process_code(code).unwrap();
+            let program = JitProgram::compile(&tokens, &options).unwrap();
+            let mut jit_io = VecIo::new(b"ab");
+            let mut interpret_io = VecIo::new(b"ab");
+            assert_eq!(program.run_checked(&mut jit_io), Ok(interpret(&tokens, &mut interpret_io)), "code: {}", code);
+            assert_eq!(jit_io, interpret_io, "code: {}", code);
+        }
+
+        // the fuel and the bounds are checked together
+        let program = JitProgram::compile(&process_code("+[>+]").unwrap(), &JitOptions { fuel: true, ..options }).unwrap();
+        assert_eq!(program.run_with_fuel(100, &mut VecIo::default()), Err(Error::StepLimitExceeded));
+        assert_eq!(program.run_with_fuel(u64::MAX, &mut VecIo::default()), Err(Error::PointerOutOfBounds { row: 0, col: 2 }));
+    }
+
+    #[test]
+    fn test_run_instrumented() {
+        //! Test that the instrumented program collects the same statistics as the interpreter (also with fuel and strict bounds).
 
-    // Return success after executing the JIT function.
-    Ok(())
+        let codes = [",[.,]", "++++++++[>++++++++<-]>+.", "-[>-[>+[->+>+<<]>>[-<<+>>]<<<-]<-]>>>>.", "++[>+++[>,.<-]<-]", "[.]..."];
+        for code in codes {
+            let tokens = process_code(code).unwrap();
+            let mut interpret_io = VecIo::new(b"hello");
+            let (expected_state, expected_stats) = interpret_with_stats(&tokens, &mut interpret_io, EofPolicy::Zero);
+
+            for (fuel, strict_bounds) in [(false, false), (true, false), (false, true), (true, true)] {
+                let options = JitOptions { instrument: true, fuel, strict_bounds, ..JitOptions::default() };
+                let program = JitProgram::compile(&tokens, &options).unwrap();
+                let mut jit_io = VecIo::new(b"hello");
+                let (state, stats) = program.run_instrumented(&mut jit_io).unwrap();
+                assert_eq!(state, expected_state, "code: {}, options: {:?}", code, options);
+                assert_eq!(stats, expected_stats, "code: {}, options: {:?}", code, options);
+                assert_eq!(jit_io, interpret_io, "code: {}, options: {:?}", code, options);
+
+                // the counters start from zero on every run
+                assert_eq!(program.run_instrumented(&mut VecIo::new(b"hello")).unwrap().1, expected_stats);
+            }
+        }
+
+        // the instrumented program still runs out of fuel
+        let options = JitOptions { instrument: true, fuel: true, ..JitOptions::default() };
+        let program = JitProgram::compile(&process_code("+[]").unwrap(), &options).unwrap();
+        assert_eq!(program.run_with_fuel(10, &mut VecIo::default()), Err(Error::StepLimitExceeded));
+
+        let tokens = process_code("++[>+++[>,.<-]<-]").unwrap();
+        let (_, stats) = interpret_with_stats(&tokens, &mut VecIo::new(b"abcd"), EofPolicy::Zero);
+        assert_eq!(stats, ExecStats {
+            back_edges: 5,
+            loop_iterations: [(1, 2), (4, 6)].into(),
+            bytes_read: 4,
+            bytes_written: 6,
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "without instrumentation")]
+    fn test_run_instrumented_without_instrumentation() {
+        //! Test that running the program compiled without instrumentation panics.
+
+        let program = JitProgram::compile(&process_code("+.").unwrap(), &JitOptions::default()).unwrap();
+        let _ = program.run_instrumented(&mut VecIo::default());
+    }
+
+    #[test]
+    fn test_run_on() {
+        //! Test that the program runs on the tape owned by the host and leaves the results in it.
+
+        let options = JitOptions { host_tape: true, ..JitOptions::default() };
+
+        // convert the string to uppercase in place
+        let tokens = process_code(&format!("[{}>]", "-".repeat(32))).unwrap();
+        let program = JitProgram::compile(&tokens, &options).unwrap();
+        let mut tape = vec![1; STORAGE_SIZE + 10];
+        tape[100..106].copy_from_slice(b"hello\0");
+        assert_eq!(program.run_on(&mut tape, 100), Ok(FinalState { data_ptr: 105, cell: 0 }));
+        assert_eq!(&tape[100..106], b"HELLO\0");
+        assert_eq!(tape[99], 1);
+
+        // the known loops must not be folded (the cells aren't zero) and the data pointer wraps around the array
+        let tokens = process_code("[->+<]>>+[-]+>+").unwrap();
+        let program = JitProgram::compile(&tokens, &options).unwrap();
+        let mut tape = vec![5; STORAGE_SIZE + 1];
+        assert_eq!(program.run_on(&mut tape, STORAGE_SIZE - 2), Ok(FinalState { data_ptr: 1, cell: 6 }));
+        assert_eq!(tape[STORAGE_SIZE - 2..], [0, 10, 5]);
+        assert_eq!(tape[..2], [1, 6]);
+
+        // the program runs on the zeroed array as before
+        assert_eq!(program.run_with_io(&mut VecIo::default()), FinalState { data_ptr: 3, cell: 1 });
+
+        // the cell is added to three cells (also when the destinations wrap around the array)
+        let program = JitProgram::compile(&process_code("[->>+>+>+<<<<]").unwrap(), &options).unwrap();
+        let mut tape = vec![0; STORAGE_SIZE];
+        tape[..5].copy_from_slice(&[3, 0, 0, 0, 0]);
+        assert_eq!(program.run_on(&mut tape, 0), Ok(FinalState { data_ptr: 0, cell: 0 }));
+        assert_eq!(tape[..5], [0, 0, 3, 3, 3]);
+        tape[STORAGE_SIZE - 3] = 2;
+        assert_eq!(program.run_on(&mut tape, STORAGE_SIZE - 3), Ok(FinalState { data_ptr: STORAGE_SIZE - 3, cell: 0 }));
+        assert_eq!(tape[STORAGE_SIZE - 3..], [0, 0, 2]);
+        assert_eq!(tape[..2], [2, 2]);
+
+        assert_eq!(program.run_on(&mut [0; 100], 0), Err(Error::TapeTooShort(100)));
+
+        let options = JitOptions { host_tape: true, strict_bounds: true, ..JitOptions::default() };
+        let program = JitProgram::compile(&process_code("+[<+]").unwrap(), &options).unwrap();
+        let mut tape = vec![0; STORAGE_SIZE];
+        assert_eq!(program.run_on(&mut tape, 3), Err(Error::PointerOutOfBounds { row: 0, col: 2 }));
+        assert_eq!(tape[..4], [1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_compile_pgo() {
+        //! Test that the profile-guided programs match the interpreter, also when the speculated range is wrong.
+
+        let profile = |tokens: &[Token]| {
+            let options = JitOptions { instrument: true, ..JitOptions::default() };
+            let program = JitProgram::compile(tokens, &options).unwrap();
+            program.run_instrumented(&mut VecIo::new(b"hello")).unwrap().1
+        };
+
+        let codes = [
+            // the hot loop after the scan for a zero cell (its range is unknown)
+            "+[>]-[>-[>++>+<<-]<-]>>.>.",
+            // the hot loop at the end of the array (the guard fails and the interpreter continues)
+            "+[<]<<-[>-[>++>+<<-]<-]>>.>.",
+            // the hot loop guards the nested loop, the input and output after it
+            "+[>]++++[>-[>-[>+>++<<-]<-]<-]>>>.>.,[.,]",
+            // the loop that doesn't return the data pointer isn't speculated
+            "+[>]-[>-[>+>]<[<]<-]>.",
+        ];
+        for code in codes {
+            let tokens = process_code(code).unwrap();
+            let profile = profile(&tokens);
+            assert!(profile.loop_iterations.values().any(|&iterations| iterations >= HOT_LOOP_ITERATIONS), "code: {}", code);
+
+            let program = JitProgram::compile_pgo(&tokens, &JitOptions::default(), &profile).unwrap();
+            for input in [b"".as_slice(), b"hello"] {
+                let mut jit_io = VecIo::new(input);
+                let mut interpret_io = VecIo::new(input);
+                assert_eq!(program.run_with_io(&mut jit_io), interpret(&tokens, &mut interpret_io), "code: {}", code);
+                assert_eq!(jit_io, interpret_io, "code: {}", code);
+            }
+        }
+
+        // the hot loop is guarded
+        let path = env::temp_dir().join(format!("bfuck-test-compile-pgo-{}.clif", std::process::id()));
+        let options = JitOptions { emit_clif: Some(EmitTarget::File(path.clone())), ..JitOptions::default() };
+        let tokens = process_code(codes[0]).unwrap();
+        JitProgram::compile_pgo(&tokens, &options, &profile(&tokens)).unwrap();
+        let clif = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(clif.contains("icmp_imm ugt"), "{}", clif);
+
+        // the guard fails on the host tape near its end
+        let tokens = process_code("-[>-[>++>+<<-]<-]").unwrap();
+        let options = JitOptions { host_tape: true, ..JitOptions::default() };
+        let generic = JitProgram::compile(&tokens, &options).unwrap();
+        let program = JitProgram::compile_pgo(&tokens, &options, &profile(&tokens)).unwrap();
+        for start_ptr in [0, 100, STORAGE_SIZE - 3, STORAGE_SIZE - 1] {
+            let mut expected_tape = vec![7; STORAGE_SIZE];
+            let mut tape = expected_tape.clone();
+            assert_eq!(program.run_on(&mut tape, start_ptr), generic.run_on(&mut expected_tape, start_ptr));
+            assert_eq!(tape, expected_tape, "start: {}", start_ptr);
+        }
+
+        // nothing is speculated with strict bounds
+        let tokens = process_code(codes[1]).unwrap();
+        let options = JitOptions { strict_bounds: true, ..JitOptions::default() };
+        let program = JitProgram::compile_pgo(&tokens, &options, &profile(&tokens)).unwrap();
+        assert_eq!(program.run_checked(&mut VecIo::default()), Err(Error::PointerOutOfBounds { row: 0, col: 2 }));
+    }
+
+    #[test]
+    fn test_constant_loops() {
+        //! Test that the loops with the known trip count (folded or unrolled) match the interpreter.
+
+        let codes = [
+            "++++++++[>++++++++<-]>+.",
+            "+++[>++>>-<<<-]>.>.>.",
+            // wrap-around trip counts
+            "-[>+<-]>.",
+            "--[>+<+++]>.",
+            "+[>-<+]>.",
+            "-[>++++<-]>.[-]+++++[<+++>-]<.",
+            // unrolled loops
+            "+++[>[-]+>>+[-<+>]<<<-]>.>.>.",
+            "++[>++[->+<]+<-]>.>.",
+            "-[>[-]+>+[->+<]<<-]>.>.>.",
+            // skipped loop at the start, loops after and inside the kept loops
+            "[>+<.]++[>+++<-]>.",
+            ",[>+<-]++[>+<-]>.",
+            ",[>[-]++[>+<-]<-]>>.",
+            "+++[>++++[>+++<-]<-]>>.",
+            // near the edges of the array
+            "<++[<+++>-]<.",
+            "++[<<+++>>-]<<.>>>>>+++[<<<<<+>>>>>-]<<<<<.",
+            // known values after the input are discarded
+            "+,[>+<-]>.",
+        ];
+        for code in codes {
+            let tokens = process_code(code).unwrap();
+            let mut expected_io = VecIo::new(b"\x05");
+            let expected_state = interpret(&tokens, &mut expected_io);
+
+            for unroll_limit in [0, 2, 16, 300] {
+                let options = JitOptions { unroll_limit, ..JitOptions::default() };
+                let program = JitProgram::compile(&tokens, &options).unwrap();
+                let mut io = VecIo::new(b"\x05");
+                assert_eq!(program.run_with_io(&mut io), expected_state, "code: {}, unroll limit: {}", code, unroll_limit);
+                assert_eq!(io, expected_io, "code: {}, unroll limit: {}", code, unroll_limit);
+            }
+        }
+    }
+
+    #[test]
+    fn test_emit_asm() {
+        //! Test writing the disassembly of the machine code.
+
+        let path = env::temp_dir().join(format!("bfuck-test-emit-asm-{}.s", std::process::id()));
+        let options = JitOptions {
+            emit_asm: Some(EmitTarget::File(path.clone())),
+            ..JitOptions::default()
+        };
+
+        let tokens = process_code("+[->+<]>.").unwrap();
+        let result = JitProgram::compile(&tokens, &options);
+
+        if cfg!(all(feature = "capstone", any(target_arch = "x86_64", target_arch = "aarch64"))) {
+            result.unwrap();
+            let asm = fs::read_to_string(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert!(!asm.is_empty());
+        } else {
+            assert_eq!(result.unwrap_err(), Error::DisassemblyUnsupported);
+        }
+    }
 }
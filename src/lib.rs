@@ -1,10 +1,17 @@
 pub mod code;
+mod codegen;
 pub mod compile;
 pub mod error;
 pub mod interpret;
 pub mod io;
 pub mod jit;
+mod perf;
+#[cfg(feature = "sandbox")]
+pub mod sandbox;
+pub mod static_analysis;
 pub mod text;
+pub mod tiered;
+pub mod tools;
 
 
 
@@ -15,7 +22,7 @@ pub use code::process_code;
 pub use error::Error;
 
 #[doc(inline)]
-pub use interpret::interpret;
+pub use interpret::{interpret, interpret_from_str};
 
 #[doc(inline)]
-pub use jit::jit;
+pub use jit::{jit, jit_from_str};
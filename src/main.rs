@@ -1,13 +1,20 @@
 use std::fs;
+use std::io::{self, Write};
+use std::str;
 use std::path::{Path, PathBuf};
 use std::process::exit;
 
 use clap::{Arg, ArgAction, command, value_parser};
 
-use bfuck::interpret::interpret;
-use bfuck::code::process_code;
-use bfuck::jit::jit;
-use bfuck::text::text_2_bf;
+use bfuck::compile::{compile_with_progress, CompileOptions, CompileStage};
+use bfuck::error::Error;
+use bfuck::interpret::{interpret_with_eof, interpret_with_stats};
+use bfuck::io::{EofPolicy, StdIo};
+use bfuck::code::{process_code_bytes_with_config_and_stats, process_code_with_config_and_stats, ExecStats, ParseConfig, TokenStream};
+use bfuck::jit::{EmitTarget, JitOptions, JitProgram, OptLevel};
+use bfuck::text::{bytes_2_bf_with_options, text_2_bf_to, CostTarget, NewlinePolicy, TextCodeStyle, TextOptions};
+use bfuck::tiered::run_tiered;
+use bfuck::tools::{annotate_bf, format_bf, lint};
 
 fn main() {
     let argv = command!()
@@ -23,15 +30,22 @@ fn main() {
             .long("interpret")
             .action(ArgAction::SetTrue)
             .help("Interpret Brainfuck code. [default]")
-            .conflicts_with_all(["jit", "compile", "text_cvt"])
+            .conflicts_with_all(["jit", "compile", "text_cvt", "tier", "format", "annotate", "lint"])
             .required(false)
         )
+        .arg(Arg::new("tier")
+            .long("tier")
+            .value_name("MODE")
+            .help("Execute code using tiered execution: `auto` starts interpreting immediately and switches to the JIT-compiled code when it is ready.")
+            .conflicts_with_all(["interpret", "jit", "compile", "text_cvt", "format", "annotate", "lint"])
+            .value_parser(["auto"])
+        )
         .arg(Arg::new("jit")
             .short('j')
             .long("jit")
             .action(ArgAction::SetTrue)
             .help("Execute code using Just-in-time (JIT) compilation.")
-            .conflicts_with_all(["interpret", "compile", "text_cvt"])
+            .conflicts_with_all(["interpret", "compile", "text_cvt", "tier", "format", "annotate", "lint"])
             .required(false)
         )
         .arg(Arg::new("compile")
@@ -39,23 +53,224 @@ fn main() {
             .long("compile")
             .action(ArgAction::SetTrue)
             .help("Compile code to executable.")
-            .conflicts_with_all(["interpret", "jit", "text_cvt"])
+            .conflicts_with_all(["interpret", "jit", "text_cvt", "tier", "format", "annotate", "lint"])
             .required(false)
         )
         .arg(Arg::new("dst_file")
             .value_name("DST_FILE")
             .help("The compiled file.")
-            .requires("compile")
-            .requires("text_cvt")
-            .conflicts_with_all(["interpret", "jit"])
+            .required_if_eq_any([("compile", "true"), ("text_cvt", "true")])
+            .conflicts_with_all(["interpret", "jit", "tier", "format", "annotate", "lint"])
             .value_parser(value_parser!(PathBuf))
         )
+        .arg(Arg::new("target")
+            .long("target")
+            .value_name("TRIPLE")
+            .help("The target triple of the compiled executable (e.g. `x86_64-pc-windows-gnu`), the linker is specified by the `CC` environment variable.")
+            .requires("compile")
+        )
         .arg(Arg::new("text_cvt")
             .short('t')
             .long("text_cvt")
             .action(ArgAction::SetTrue)
             .help("Converts the text file to Brainfuck code file which prints that text.")
-            .conflicts_with_all(["interpret", "jit", "compile"])
+            .conflicts_with_all(["interpret", "jit", "compile", "tier", "format", "annotate", "lint"])
+            .required(false)
+        )
+        .arg(Arg::new("format")
+            .long("format")
+            .action(ArgAction::SetTrue)
+            .help("Format Brainfuck code (each bracket on its own line, the loop bodies indented) and write it to stdout.")
+            .conflicts_with_all(["interpret", "jit", "compile", "text_cvt", "tier"])
+            .required(false)
+        )
+        .arg(Arg::new("annotate")
+            .long("annotate")
+            .action(ArgAction::SetTrue)
+            .help("Write the processed Brainfuck code to stdout with a comment explaining each command (one per line).")
+            .conflicts_with_all(["interpret", "jit", "compile", "text_cvt", "tier", "format"])
+            .required(false)
+        )
+        .arg(Arg::new("lint")
+            .long("lint")
+            .action(ArgAction::SetTrue)
+            .help("Print the style and correctness issues found in the processed Brainfuck code to stdout.")
+            .conflicts_with_all(["interpret", "jit", "compile", "text_cvt", "tier", "format", "annotate"])
+            .required(false)
+        )
+        .arg(Arg::new("line_width")
+            .long("line-width")
+            .value_name("WIDTH")
+            .help("The maximum number of characters in a line of the formatted code.")
+            .default_value("80")
+            .requires("format")
+            .value_parser(value_parser!(usize))
+        )
+        .arg(Arg::new("emit_clif")
+            .long("emit-clif")
+            .value_name("FILE")
+            .help("Write the Cranelift IR of the JIT-compiled code to the file (or stderr if no file is given, `--emit-clif=FILE`).")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("-")
+            .requires("jit")
+            .value_parser(value_parser!(PathBuf))
+        )
+        .arg(Arg::new("emit_asm")
+            .long("emit-asm")
+            .value_name("FILE")
+            .help("Write the disassembly of the JIT-compiled code to the file (or stderr if no file is given, `--emit-asm=FILE`). Requires the `capstone` feature.")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("-")
+            .requires("jit")
+            .value_parser(value_parser!(PathBuf))
+        )
+        .arg(Arg::new("jit_opt_level")
+            .long("jit-opt-level")
+            .value_name("LEVEL")
+            .help("The optimization level of the JIT-compiled code (`none` compiles the fastest).")
+            .default_value("speed_and_size")
+            .requires("jit")
+            .value_parser(["none", "speed", "speed_and_size"])
+        )
+        .arg(Arg::new("strict_bounds")
+            .long("strict-bounds")
+            .action(ArgAction::SetTrue)
+            .help("Stop the JIT-compiled code with an error when the data pointer would leave the array (instead of wrapping it around).")
+            .requires("jit")
+            .required(false)
+        )
+        .arg(Arg::new("jit_perf_map")
+            .long("jit-perf-map")
+            .action(ArgAction::SetTrue)
+            .help("Register the JIT-compiled code with the Linux `perf` profiler (write /tmp/perf-<pid>.map and /tmp/jit-<pid>.dump).")
+            .requires("jit")
+            .required(false)
+        )
+        .arg(Arg::new("jit_stats")
+            .long("jit-stats")
+            .action(ArgAction::SetTrue)
+            .help("Print the metrics of the JIT-compilation (tokens, functions, Cranelift instructions, code size and compile time) to stderr before running.")
+            .requires("jit")
+            .required(false)
+        )
+        .arg(Arg::new("no_run")
+            .long("no-run")
+            .action(ArgAction::SetTrue)
+            .help("JIT-compile the code, but don't run it.")
+            .requires("jit")
+            .required(false)
+        )
+        .arg(Arg::new("eof")
+            .long("eof")
+            .value_name("POLICY")
+            .help("The value stored to the current cell when ',' reads past the end of input (`unchanged` leaves the cell as it is).")
+            .default_value("zero")
+            .conflicts_with_all(["text_cvt", "format", "annotate", "lint"])
+            .value_parser(["zero", "max", "unchanged"])
+        )
+        .arg(Arg::new("stats")
+            .long("stats")
+            .action(ArgAction::SetTrue)
+            .help("Print the statistics of the execution (loop iterations, bytes read and written) to stderr when interpreting or JIT-compiling.")
+            .conflicts_with_all(["compile", "text_cvt", "tier", "no_run", "format", "annotate", "lint"])
+            .required(false)
+        )
+        .arg(Arg::new("debug")
+            .short('d')
+            .long("debug")
+            .action(ArgAction::SetTrue)
+            .help("Parse '#' as a debug instruction which prints the data pointer and the cells around it to stderr (ignored when compiling).")
+            .conflicts_with_all(["text_cvt", "format"])
+            .required(false)
+        )
+        .arg(Arg::new("utf8")
+            .long("utf8")
+            .action(ArgAction::SetTrue)
+            .help("Convert any text to Brainfuck code which prints its UTF-8 bytes (instead of rejecting the non-ASCII characters).")
+            .requires("text_cvt")
+            .required(false)
+        )
+        .arg(Arg::new("binary")
+            .long("binary")
+            .action(ArgAction::SetTrue)
+            .help("Convert any file to Brainfuck code which prints its bytes exactly as they are (the file is not read as text).")
+            .requires("text_cvt")
+            .conflicts_with_all(["utf8", "newlines"])
+            .required(false)
+        )
+        .arg(Arg::new("text_style")
+            .long("text-style")
+            .value_name("STYLE")
+            .help("The strategy of the Brainfuck code converted from the text (`table` stores each distinct character in its own cell, `single-cell` changes one cell between the characters, `auto` picks the shorter code).")
+            .default_value("table")
+            .requires("text_cvt")
+            .value_parser(["table", "single-cell", "auto"])
+        )
+        .arg(Arg::new("text_target")
+            .long("text-target")
+            .value_name("TARGET")
+            .help("What the Brainfuck code converted from the text is optimized for (`size` generates the shortest code, `runtime` generates the code without loops, which runs the fastest).")
+            .default_value("size")
+            .requires("text_cvt")
+            .value_parser(["size", "runtime"])
+        )
+        .arg(Arg::new("newlines")
+            .long("newlines")
+            .value_name("POLICY")
+            .help("How the line endings of the text are printed by the Brainfuck code converted from it (`preserve` prints them as they are, `crlf-to-lf` prints '\\r\\n' as '\\n', `lf` drops all '\\r', `crlf` prints all of them as '\\r\\n').")
+            .default_value("crlf-to-lf")
+            .requires("text_cvt")
+            .value_parser(["preserve", "crlf-to-lf", "lf", "crlf"])
+        )
+        .arg(Arg::new("no_verify")
+            .long("no-verify")
+            .action(ArgAction::SetTrue)
+            .help("Don't run the Brainfuck code converted from the text to check that it prints that text.")
+            .requires("text_cvt")
+            .required(false)
+        )
+        .arg(Arg::new("max_cells")
+            .long("max-cells")
+            .value_name("CELLS")
+            .help("The most cells the Brainfuck code converted from the text may use (the close characters share a cell if there are too many).")
+            .requires("text_cvt")
+            .value_parser(value_parser!(usize))
+        )
+        .arg(Arg::new("portable")
+            .long("portable")
+            .action(ArgAction::SetTrue)
+            .help("Convert the text to Brainfuck code which never wraps the cells around, so it runs also with the non-wrapping or bigger cells.")
+            .requires("text_cvt")
+            .required(false)
+        )
+        .arg(Arg::new("reuse_repeats")
+            .long("reuse-repeats")
+            .action(ArgAction::SetTrue)
+            .help("Print the blocks of the text repeated right after each other by a loop in the Brainfuck code converted from the text.")
+            .requires("text_cvt")
+            .required(false)
+        )
+        .arg(Arg::new("annotate_text")
+            .long("annotate-text")
+            .action(ArgAction::SetTrue)
+            .help("Convert the text to legible Brainfuck code, one line for each character, with the comments saying what each line does.")
+            .requires("text_cvt")
+            .required(false)
+        )
+        .arg(Arg::new("wrap")
+            .long("wrap")
+            .value_name("WIDTH")
+            .help("Break the Brainfuck code converted from the text into the lines of at most WIDTH characters.")
+            .requires("text_cvt")
+            .value_parser(value_parser!(usize))
+        )
+        .arg(Arg::new("text_cleanup")
+            .long("text-cleanup")
+            .action(ArgAction::SetTrue)
+            .help("Clear the cells used by the Brainfuck code converted from the text and return the pointer to the first cell after printing.")
+            .requires("text_cvt")
             .required(false)
         )
         .get_matches();
@@ -71,60 +286,250 @@ fn main() {
     let jit_flag: bool = argv.get_flag("jit");
     let compile_flag: bool = argv.get_flag("compile");
     let text_cvt_flag: bool = argv.get_flag("text_cvt");
+    let tier_flag: bool = argv.contains_id("tier");
+    let debug_flag: bool = argv.get_flag("debug");
+    let no_run_flag: bool = argv.get_flag("no_run");
+    let stats_flag: bool = argv.get_flag("stats");
+    let format_flag: bool = argv.get_flag("format");
+    let annotate_flag: bool = argv.get_flag("annotate");
+    let lint_flag: bool = argv.get_flag("lint");
+
+    // path "-" means that the emitted code is written to stderr
+    let emit_target = |id: &str| {
+        argv.get_one::<PathBuf>(id).map(|path| {
+            if path.as_os_str() == "-" {
+                EmitTarget::Stderr
+            } else {
+                EmitTarget::File(path.clone())
+            }
+        })
+    };
+    let opt_level = match argv.get_one::<String>("jit_opt_level").map(String::as_str) {
+        Some("none") => OptLevel::None,
+        Some("speed") => OptLevel::Speed,
+        _ => OptLevel::SpeedAndSize,
+    };
+    let eof = match argv.get_one::<String>("eof").map(String::as_str) {
+        Some("max") => EofPolicy::Max,
+        Some("unchanged") => EofPolicy::Unchanged,
+        _ => EofPolicy::Zero,
+    };
+    let jit_options = JitOptions {
+        opt_level,
+        eof,
+        strict_bounds: argv.get_flag("strict_bounds"),
+        perf_map: argv.get_flag("jit_perf_map"),
+        instrument: stats_flag,
+        emit_clif: emit_target("emit_clif"),
+        emit_asm: emit_target("emit_asm"),
+        ..JitOptions::default()
+    };
     
-    if !(interpret_flag || jit_flag || compile_flag || text_cvt_flag) {
+    if !(interpret_flag || jit_flag || compile_flag || text_cvt_flag || tier_flag || format_flag || annotate_flag || lint_flag) {
         interpret_flag = true;
     }
 
-    let src_text = match fs::read_to_string(src_file) {
-        Ok(text) => text,
+    let parse_config = ParseConfig {
+        debug_instruction: if debug_flag { Some('#') } else { None },
+    };
+
+    let src_bytes = match fs::read(src_file) {
+        Ok(bytes) => bytes,
         Err(err) => {
             eprintln!("Error reading the file: {}", err);
             exit(1);
         },
     };
 
-    if interpret_flag {
-        let token_stream = match process_code(&src_text) {
-            Ok(tokens) => tokens,
+    // parse the source file as text if it is valid UTF-8, otherwise as raw bytes (e.g. Latin-1 encoded files)
+    // the warnings (e.g. about the loops which never terminate) are printed, but don't stop the program
+    // the locations of the tokens are kept for the errors of the running program
+    let parse_source_with_locations = || -> (TokenStream, Vec<(usize, usize)>) {
+        let result = match str::from_utf8(&src_bytes) {
+            Ok(src_text) => process_code_with_config_and_stats(src_text, &parse_config),
+            Err(_) => process_code_bytes_with_config_and_stats(&src_bytes, &parse_config),
+        };
+        match result {
+            Ok((tokens, stats)) => {
+                for warning in stats.warnings {
+                    eprintln!("{}: Warning: {}", src_file.to_string_lossy(), warning);
+                }
+                (tokens, stats.locations)
+            },
             Err(err) => {
-                eprintln!("{}", err);
+                eprintln!("{}", Error::WithFile(Box::new(err), src_file.to_string_lossy().to_string()));
                 exit(1);
             },
-        };
-        interpret(token_stream);
+        }
+    };
+    let parse_source = || parse_source_with_locations().0;
+
+    let interpret_tokens = |token_stream: &TokenStream| {
+        if stats_flag {
+            let (_, stats) = interpret_with_stats(token_stream, &mut StdIo, eof);
+            print_stats(&stats);
+        } else {
+            interpret_with_eof(token_stream, &mut StdIo, eof);
+        }
+    };
+
+    if interpret_flag {
+        interpret_tokens(&parse_source());
+    } else if tier_flag {
+        let token_stream = parse_source();
+        run_tiered(&token_stream, &mut StdIo, eof);
     } else if jit_flag {
-        let token_stream = match process_code(&src_text) {
-            Ok(tokens) => tokens,
+        let (token_stream, locations) = parse_source_with_locations();
+        let program = match JitProgram::compile(&token_stream, &jit_options) {
+            Ok(program) => program.with_locations(locations),
+            // the code can't be executed on this platform, so the program is interpreted instead
+            Err(err @ (Error::UnsupportedPlatformJIT | Error::MappingFailed(_))) => {
+                eprintln!("{} Falling back to the interpreter.", err);
+                if !no_run_flag {
+                    interpret_tokens(&token_stream);
+                }
+                return;
+            },
             Err(err) => {
                 eprintln!("{}", err);
                 exit(1);
             },
         };
-        if let Err(err) = jit(token_stream) {
+        if argv.get_flag("jit_stats") {
+            let jit_stats = program.stats();
+            eprintln!(
+                "[JIT] tokens: {}, functions: {}, Cranelift instructions: {}, code size: {} bytes, compile time: {:?}",
+                jit_stats.tokens, jit_stats.functions, jit_stats.clif_insts, jit_stats.code_bytes, jit_stats.compile_time,
+            );
+        }
+        if stats_flag {
+            match program.run_instrumented(&mut StdIo) {
+                Ok((_, stats)) => print_stats(&stats),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1);
+                },
+            }
+        } else if !no_run_flag {
+            if let Err(err) = program.run_checked(&mut StdIo) {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        }
+    } else if compile_flag {
+        let token_stream = parse_source();
+        // report the stages on stderr (the error is printed below)
+        let mut report_progress = |stage| match stage {
+            CompileStage::Generating => eprint!("Generating code..."),
+            CompileStage::Linking => eprint!(" linking..."),
+            CompileStage::Done { binary_path, size } => eprintln!(" done: {} ({} bytes).", binary_path.display(), size),
+            CompileStage::Failed(_) => eprintln!(" failed."),
+        };
+        let compile_options = CompileOptions { eof, target: argv.get_one::<String>("target").cloned() };
+        if let Err(err) = compile_with_progress(&token_stream, dst_file, &compile_options, &mut report_progress) {
             eprintln!("{}", err);
             exit(1);
         }
-    } else if compile_flag {
-        let _token_stream = match process_code(&src_text) {
-            Ok(tokens) => tokens,
+    } else if text_cvt_flag {
+        let style = match argv.get_one::<String>("text_style").map(String::as_str) {
+            Some("single-cell") => TextCodeStyle::SingleCell,
+            Some("auto") => TextCodeStyle::Auto,
+            _ => TextCodeStyle::Table,
+        };
+        let target = match argv.get_one::<String>("text_target").map(String::as_str) {
+            Some("runtime") => CostTarget::RunTime,
+            _ => CostTarget::CodeSize,
+        };
+        let newlines = match argv.get_one::<String>("newlines").map(String::as_str) {
+            Some("preserve") => NewlinePolicy::Preserve,
+            Some("lf") => NewlinePolicy::Lf,
+            Some("crlf") => NewlinePolicy::Crlf,
+            _ => NewlinePolicy::CrlfToLf,
+        };
+        let text_options = TextOptions {
+            style,
+            utf8: argv.get_flag("utf8"),
+            target,
+            newlines,
+            cleanup: argv.get_flag("text_cleanup"),
+            verify: !argv.get_flag("no_verify"),
+            max_cells: argv.get_one::<usize>("max_cells").copied(),
+            portable: argv.get_flag("portable"),
+            reuse_repeats: argv.get_flag("reuse_repeats"),
+            annotate: argv.get_flag("annotate_text"),
+            wrap_width: argv.get_one::<usize>("wrap").copied(),
+        };
+        if argv.get_flag("binary") {
+            let bf_code = match bytes_2_bf_with_options(&src_bytes, &text_options) {
+                Ok(bf_code) => bf_code,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1);
+                },
+            };
+
+            if let Err(err) = fs::write(dst_file, bf_code) {
+                eprintln!("Error writing to the file: {}", err);
+                exit(1);
+            }
+        } else {
+            let src_text = match str::from_utf8(&src_bytes) {
+                Ok(text) => text,
+                Err(err) => {
+                    eprintln!("Error reading the file: {}", err);
+                    exit(1);
+                },
+            };
+            let dst = match fs::File::create(dst_file) {
+                Ok(dst) => dst,
+                Err(err) => {
+                    eprintln!("Error writing to the file: {}", err);
+                    exit(1);
+                },
+            };
+
+            // the code is streamed to the file, so the huge texts don't need its whole code in memory
+            if let Err(err) = text_2_bf_to(src_text, io::BufWriter::new(dst), &text_options) {
+                let _ = fs::remove_file(dst_file);
+                match err {
+                    Error::EmitFailed(_) => eprintln!("{}", err),
+                    err => eprintln!("{}", Error::WithFile(Box::new(err), src_file.to_string_lossy().to_string())),
+                }
+                exit(1);
+            }
+        }
+    } else if lint_flag {
+        for lint in lint(&parse_source()) {
+            println!("{}", lint);
+        }
+    } else if annotate_flag {
+        print!("{}", annotate_bf(&parse_source()));
+    } else if format_flag {
+        let src_text = match str::from_utf8(&src_bytes) {
+            Ok(text) => text,
             Err(err) => {
-                eprintln!("{}", err);
+                eprintln!("Error reading the file: {}", err);
                 exit(1);
             },
         };
-    } else if text_cvt_flag {
-        let bf_code = match text_2_bf(&src_text) {
-            Ok(bf_code) => bf_code,
+
+        match format_bf(src_text, *argv.get_one::<usize>("line_width").unwrap(), "    ") {
+            Ok(formatted) => println!("{}", formatted),
             Err(err) => {
-                eprintln!("{}", err);
+                eprintln!("{}", Error::WithFile(Box::new(err), src_file.to_string_lossy().to_string()));
                 exit(1);
             },
-        };
-        
-        if let Err(err) = fs::write(dst_file, bf_code) {
-            eprintln!("Error writing to the file: {}", err);
-            exit(1);
         }
     }
 }
+
+/// Print the statistics of the execution to stderr.
+/// # Arguments
+/// * `stats` - The statistics of the execution.
+fn print_stats(stats: &ExecStats) {
+    io::stdout().flush().unwrap();  // flush the output buffer so that the statistics are printed after the output
+    eprintln!("[STATS] back-edges: {}, bytes read: {}, bytes written: {}", stats.back_edges, stats.bytes_read, stats.bytes_written);
+    for (loop_start, iterations) in &stats.loop_iterations {
+        eprintln!("[STATS] loop at token {}: {} iterations", loop_start, iterations);
+    }
+}
@@ -0,0 +1,170 @@
+//! Registration of the JIT-compiled code with the Linux `perf` profiler.
+//!
+//! The code is described in the perf map (`/tmp/perf-<pid>.map`), which `perf report` uses to name the samples,
+//! and in the jitdump (`/tmp/jit-<pid>.dump`), which `perf inject --jit` uses to annotate the instructions.
+//! On the other systems nothing is written.
+
+
+
+use std::io;
+
+
+
+/// Register the compiled code with the profiler (append it to the perf map and the jitdump of the process).
+/// # Arguments
+/// * `address` - The address where the code is mapped.
+/// * `code` - The machine code.
+/// * `name` - The symbol name of the code.
+/// # Returns
+/// * `()` - If [Ok].
+/// * [io::Error] - The encountered error, if [Err].
+#[cfg(target_os = "linux")]
+pub(crate) fn register(address: *const u8, code: &[u8], name: &str) -> io::Result<()> {
+    linux::write_perf_map(address, code, name)?;
+    linux::write_jitdump(address, code, name)
+}
+
+/// Register the compiled code with the profiler (nothing is written on the other systems than Linux).
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn register(_address: *const u8, _code: &[u8], _name: &str) -> io::Result<()> {
+    Ok(())
+}
+
+
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Write};
+    use std::process;
+    use std::sync::Mutex;
+
+    use memmap2::{Mmap, MmapOptions};
+
+    /// The magic number of the jitdump file ("JiTD").
+    const JITDUMP_MAGIC: u32 = 0x4A69_5444;
+    /// The version of the jitdump format.
+    const JITDUMP_VERSION: u32 = 1;
+    /// The size of the jitdump header.
+    const HEADER_SIZE: u32 = 40;
+    /// The id of the record describing the loaded code.
+    const JIT_CODE_LOAD: u32 = 0;
+    /// The size of the code load record without the name and the code.
+    const CODE_LOAD_SIZE: usize = 56;
+
+    /// The jitdump of the process (created when the first code is registered).
+    static JITDUMP: Mutex<Option<JitDump>> = Mutex::new(None);
+
+    /// The open jitdump file.
+    struct JitDump {
+        /// The file the records are appended to.
+        file: File,
+        /// The executable mapping of the file, which marks the file for `perf record` (kept until the process exits).
+        _marker: Mmap,
+        /// The index of the next registered code.
+        code_index: u64,
+    }
+
+    /// Append the code to the perf map of the process.
+    /// # Arguments
+    /// * `address` - The address where the code is mapped.
+    /// * `code` - The machine code.
+    /// * `name` - The symbol name of the code.
+    pub(super) fn write_perf_map(address: *const u8, code: &[u8], name: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(format!("/tmp/perf-{}.map", process::id()))?;
+        // the whole line is written at once, so the lines of the concurrently compiled programs don't interleave
+        file.write_all(format!("{:x} {:x} {}\n", address as usize, code.len(), name).as_bytes())
+    }
+
+    /// Append the code load record to the jitdump of the process.
+    /// # Arguments
+    /// * `address` - The address where the code is mapped.
+    /// * `code` - The machine code.
+    /// * `name` - The symbol name of the code.
+    pub(super) fn write_jitdump(address: *const u8, code: &[u8], name: &str) -> io::Result<()> {
+        let mut jitdump = JITDUMP.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let jitdump = match &mut *jitdump {
+            Some(jitdump) => jitdump,
+            None => jitdump.insert(create_jitdump()?),
+        };
+
+        let total_size = CODE_LOAD_SIZE + name.len() + 1 + code.len();
+        let mut record = Vec::with_capacity(total_size);
+        record.extend_from_slice(&JIT_CODE_LOAD.to_le_bytes());
+        record.extend_from_slice(&(total_size as u32).to_le_bytes());
+        record.extend_from_slice(&timestamp().to_le_bytes());
+        record.extend_from_slice(&process::id().to_le_bytes());
+        record.extend_from_slice(&(unsafe { libc::gettid() } as u32).to_le_bytes());
+        record.extend_from_slice(&(address as u64).to_le_bytes());  // the virtual address
+        record.extend_from_slice(&(address as u64).to_le_bytes());  // the address of the code
+        record.extend_from_slice(&(code.len() as u64).to_le_bytes());
+        record.extend_from_slice(&jitdump.code_index.to_le_bytes());
+        record.extend_from_slice(name.as_bytes());
+        record.push(0);
+        record.extend_from_slice(code);
+
+        jitdump.file.write_all(&record)?;
+        jitdump.code_index += 1;
+        Ok(())
+    }
+
+    /// Create the jitdump file of the process and write its header.
+    /// # Returns
+    /// * [JitDump] - The open jitdump, if [Ok].
+    /// * [io::Error] - The encountered error, if [Err].
+    fn create_jitdump() -> io::Result<JitDump> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(format!("/tmp/jit-{}.dump", process::id()))?;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(&JITDUMP_MAGIC.to_le_bytes());
+        header.extend_from_slice(&JITDUMP_VERSION.to_le_bytes());
+        header.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+        header.extend_from_slice(&elf_machine().to_le_bytes());
+        header.extend_from_slice(&0_u32.to_le_bytes());  // padding
+        header.extend_from_slice(&process::id().to_le_bytes());
+        header.extend_from_slice(&timestamp().to_le_bytes());
+        header.extend_from_slice(&0_u64.to_le_bytes());  // flags
+        file.write_all(&header)?;
+
+        // perf finds the jitdump by the executable mapping of the file
+        let marker = unsafe { MmapOptions::new().len(HEADER_SIZE as usize).map_exec(&file)? };
+
+        Ok(JitDump { file, _marker: marker, code_index: 0 })
+    }
+
+    /// Get the timestamp of the record (the monotonic clock, used by `perf record -k mono`).
+    /// # Returns
+    /// * [u64] - The time in nanoseconds.
+    fn timestamp() -> u64 {
+        let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        unsafe {
+            libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time);
+        }
+        time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64
+    }
+
+    /// Get the ELF machine of the host architecture.
+    /// # Returns
+    /// * [u32] - The value of the `e_machine` field (0 if the architecture is unknown).
+    fn elf_machine() -> u32 {
+        if cfg!(target_arch = "x86_64") {
+            62
+        } else if cfg!(target_arch = "aarch64") {
+            183
+        } else if cfg!(target_arch = "riscv64") {
+            243
+        } else if cfg!(target_arch = "s390x") {
+            22
+        } else {
+            0
+        }
+    }
+}
@@ -0,0 +1,554 @@
+//! Sandboxed execution of the JIT-compiled programs (available with the `sandbox` feature).
+//!
+//! The program runs in a forked child process with the resource limits applied,
+//! and its output is streamed back to the parent through a pipe.
+//! The sandbox is supported only on Unix (the seccomp mode only on Linux),
+//! on the other platforms [JitProgram::run_sandboxed] fails with [Error::UnsupportedPlatform].
+
+
+
+use std::time::Duration;
+
+use crate::code::FinalState;
+use crate::error::Error;
+use crate::jit::JitProgram;
+
+
+
+/// The limits of the sandboxed execution (see [JitProgram::run_sandboxed]).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SandboxLimits {
+    /// The CPU time of the child process (rounded up to whole seconds), `None` for no limit.
+    pub cpu_time: Option<Duration>,
+    /// The size of the address space of the child process in bytes, `None` for no limit.
+    ///
+    /// The child starts with the mappings of the parent (e.g. the stacks of its threads), so they count towards the limit.
+    /// When an allocation fails, the child is aborted ([Termination::Killed] with `SIGABRT`).
+    pub address_space: Option<u64>,
+    /// The wall-clock time after which the child process is killed, `None` for no limit.
+    pub wall_time: Option<Duration>,
+    /// Whether the program runs in the strict seccomp mode, which allows only the `read`, `write` and `exit` system calls
+    /// (Linux only). The array and the output buffer are allocated before the mode is entered.
+    pub seccomp: bool,
+}
+
+/// The reason why the sandboxed program stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Termination {
+    /// The program finished with the given final state.
+    Finished(FinalState),
+    /// The program failed with the given error (e.g. [Error::PointerOutOfBounds]).
+    Failed(Error),
+    /// The program exceeded the CPU time limit.
+    CpuTimeExceeded,
+    /// The program was killed after the wall-clock time limit.
+    TimedOut,
+    /// The child process was killed by the given signal (e.g. `SIGKILL` for a forbidden system call in the seccomp mode).
+    Killed(i32),
+    /// The child process exited with the given code without reporting the result (e.g. it couldn't apply the limits).
+    Crashed(i32),
+}
+
+/// The result of the sandboxed execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxOutcome {
+    /// The bytes written by the program.
+    ///
+    /// The compiled code buffers its output (see [IoAdapter::write_bytes](crate::io::IoAdapter::write_bytes)),
+    /// so up to 8 KiB of the output written before the child was killed can be missing.
+    pub output: Vec<u8>,
+    /// The reason why the program stopped.
+    pub termination: Termination,
+}
+
+impl JitProgram {
+    /// Run the compiled program in a forked child process with the given limits.
+    ///
+    /// The program reads the given input (the end of input is handled according to [JitOptions::eof](crate::jit::JitOptions::eof))
+    /// and its output is captured. The standard error (the debug dumps) is inherited from the parent,
+    /// the other file descriptors of the parent are closed in the child.
+    /// # Arguments
+    /// * `input` - The input of the program.
+    /// * `limits` - The [SandboxLimits] applied to the child process.
+    /// # Returns
+    /// * [SandboxOutcome] - The output and the reason why the program stopped, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `UnsupportedPlatform` - The platform is not Unix, or the seccomp mode was requested on another system than Linux.
+    /// * `SandboxFailed` - The pipes couldn't be created or the child process couldn't be forked.
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use bfuck::process_code;
+    /// use bfuck::jit::{JitOptions, JitProgram};
+    /// use bfuck::sandbox::{SandboxLimits, Termination};
+    ///
+    /// let tokens = process_code(",[+.,]").unwrap();
+    /// let program = JitProgram::compile(&tokens, &JitOptions::default()).expect("Unsupported platform.");
+    ///
+    /// let limits = SandboxLimits { wall_time: Some(Duration::from_secs(5)), ..SandboxLimits::default() };
+    /// let outcome = program.run_sandboxed(b"abc", &limits).expect("Unsupported platform.");
+    /// assert_eq!(outcome.output, b"bcd");
+    /// assert!(matches!(outcome.termination, Termination::Finished(_)));
+    /// ```
+    pub fn run_sandboxed(&self, input: &[u8], limits: &SandboxLimits) -> Result<SandboxOutcome, Error> {
+        #[cfg(unix)]
+        {
+            unix::run_sandboxed(self, input, limits)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (input, limits);
+            Err(Error::UnsupportedPlatform)
+        }
+    }
+}
+
+
+
+#[cfg(unix)]
+mod unix {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::slice;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::io::IoAdapter;
+    use crate::jit::JitContext;
+
+    /// The tag of the result reported by the child: the program finished (followed by the data pointer and the cell).
+    const FINISHED: u8 = 0;
+    /// The tag of the result reported by the child: the data pointer left the array (followed by the line and the column).
+    const POINTER_OUT_OF_BOUNDS: u8 = 1;
+    /// The length of the result reported by the child (the tag and two 64-bit values, the cell is the first byte of the second one).
+    const RESULT_LEN: usize = 17;
+    /// The exit code of the child process which couldn't report the result.
+    const CHILD_FAILED: i32 = 101;
+
+    /// Run the program in a forked child process (see [JitProgram::run_sandboxed]).
+    pub(super) fn run_sandboxed(program: &JitProgram, input: &[u8], limits: &SandboxLimits) -> Result<SandboxOutcome, Error> {
+        if limits.seccomp && !cfg!(target_os = "linux") {
+            return Err(Error::UnsupportedPlatform);
+        }
+
+        let (output_read, output_write) = pipe()?;
+        let (result_read, result_write) = pipe()?;
+
+        // everything is allocated before the fork (the other threads of the parent may hold the lock of the allocator),
+        // the child only uses it
+        let mut tape = program.new_tape();
+        let mut counters = program.new_counters();
+        let mut io = PipeIo { input: input.iter(), fd: output_write.as_raw_fd() };
+        let mut ctx = JitContext::new(&mut io);
+        let fds = ChildFds { output: output_write.as_raw_fd(), result: result_write.as_raw_fd(), open_max: open_max() };
+
+        // the child process has its own copy of the program, the input and the file descriptors
+        let pid = unsafe { libc::fork() };
+        match pid {
+            -1 => return Err(Error::SandboxFailed(io::Error::last_os_error().kind())),
+            0 => run_child(program, limits, &mut tape, &mut counters, &mut ctx, &fds),
+            _ => {},
+        }
+        drop(ctx);
+
+        // the pipes reach the end of file once the child exits
+        drop(output_write);
+        drop(result_write);
+
+        let deadline = limits.wall_time.map(|wall_time| Instant::now() + wall_time);
+        let mut output = Vec::new();
+        let timed_out = match read_output(&output_read, deadline, &mut output) {
+            Ok(timed_out) => timed_out,
+            Err(err) => {
+                kill_and_wait(pid);
+                return Err(err);
+            },
+        };
+        if timed_out {
+            kill_and_wait(pid);
+            return Ok(SandboxOutcome { output, termination: Termination::TimedOut });
+        }
+
+        let status = wait(pid)?;
+        let termination = if libc::WIFSIGNALED(status) {
+            match libc::WTERMSIG(status) {
+                libc::SIGXCPU => Termination::CpuTimeExceeded,
+                signal => Termination::Killed(signal),
+            }
+        } else {
+            read_result(&result_read).unwrap_or(Termination::Crashed(libc::WEXITSTATUS(status)))
+        };
+
+        Ok(SandboxOutcome { output, termination })
+    }
+
+    /// Read the output of the child until the end of file or the deadline.
+    /// # Arguments
+    /// * `fd` - The reading end of the output pipe.
+    /// * `deadline` - The time when the child is killed, `None` for no limit.
+    /// * `output` - The buffer to which the output is appended.
+    /// # Returns
+    /// * [bool] - Whether the deadline passed, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `SandboxFailed` - The pipe couldn't be read.
+    fn read_output(fd: &OwnedFd, deadline: Option<Instant>, output: &mut Vec<u8>) -> Result<bool, Error> {
+        let mut buffer = [0_u8; 4096];
+        loop {
+            let timeout = match deadline {
+                // rounded up, so the deadline has passed when the poll times out
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) => remaining.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32,
+                    None => return Ok(true),
+                },
+                None => -1,
+            };
+
+            let mut poll_fd = libc::pollfd { fd: fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            match unsafe { libc::poll(&mut poll_fd, 1, timeout) } {
+                -1 => retry_if_interrupted()?,
+                0 => return Ok(true),
+                _ => match unsafe { libc::read(fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) } {
+                    -1 => retry_if_interrupted()?,
+                    0 => return Ok(false),
+                    read => output.extend_from_slice(&buffer[..read as usize]),
+                },
+            }
+        }
+    }
+
+    /// Read the result reported by the child (which has already exited).
+    /// # Arguments
+    /// * `fd` - The reading end of the result pipe.
+    /// # Returns
+    /// * `Some(Termination)` - The reported result.
+    /// * `None` - If the child didn't report the result.
+    fn read_result(fd: &OwnedFd) -> Option<Termination> {
+        // the child writes the whole result at once before it exits, so the read doesn't wait for more
+        unsafe {
+            let flags = libc::fcntl(fd.as_raw_fd(), libc::F_GETFL);
+            libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+        let mut result = [0_u8; RESULT_LEN];
+        let read = unsafe { libc::read(fd.as_raw_fd(), result.as_mut_ptr().cast(), RESULT_LEN) };
+        if read != RESULT_LEN as isize {
+            return None;
+        }
+
+        let first = u64::from_le_bytes(result[1..9].try_into().unwrap()) as usize;
+        let second = u64::from_le_bytes(result[9..17].try_into().unwrap()) as usize;
+        match result[0] {
+            FINISHED => Some(Termination::Finished(FinalState { data_ptr: first, cell: result[9] })),
+            POINTER_OUT_OF_BOUNDS => Some(Termination::Failed(Error::PointerOutOfBounds { row: first, col: second })),
+            _ => None,
+        }
+    }
+
+    /// The file descriptors of the child process.
+    struct ChildFds {
+        /// The writing end of the output pipe.
+        output: RawFd,
+        /// The writing end of the result pipe.
+        result: RawFd,
+        /// The upper bound of the file descriptors (see [close_other_fds]).
+        open_max: RawFd,
+    }
+
+    /// Run the program in the child process and report the result to the parent.
+    /// # Arguments
+    /// * `program` - The compiled program.
+    /// * `limits` - The limits applied before the program runs.
+    /// * `tape` - The array of the program.
+    /// * `counters` - The counters of the instrumented program.
+    /// * `ctx` - The [JitContext] writing the output to the output pipe.
+    /// * `fds` - The file descriptors of the child (the other ones are closed).
+    fn run_child(program: &JitProgram, limits: &SandboxLimits, tape: &mut [u32], counters: &mut [u64], ctx: &mut JitContext, fds: &ChildFds) -> ! {
+        // the panics mustn't unwind into the code of the parent
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            // the pipes of the other sandboxes (forked by the other threads) mustn't stay open until this child exits
+            close_other_fds([libc::STDERR_FILENO, fds.output, fds.result], fds.open_max);
+
+            if let Some(cpu_time) = limits.cpu_time {
+                // the soft limit sends SIGXCPU, the hard limit a second later SIGKILL
+                let seconds = (cpu_time.as_secs() + u64::from(cpu_time.subsec_nanos() > 0)).max(1) as libc::rlim_t;
+                let limit = libc::rlimit { rlim_cur: seconds, rlim_max: seconds + 1 };
+                if unsafe { libc::setrlimit(libc::RLIMIT_CPU, &limit) } != 0 {
+                    exit(CHILD_FAILED);
+                }
+            }
+            if let Some(address_space) = limits.address_space {
+                let limit = libc::rlimit { rlim_cur: address_space as libc::rlim_t, rlim_max: address_space as libc::rlim_t };
+                if unsafe { libc::setrlimit(libc::RLIMIT_AS, &limit) } != 0 {
+                    exit(CHILD_FAILED);
+                }
+            }
+
+            // everything was allocated before the fork, and nothing is freed before the exit
+            #[cfg(target_os = "linux")]
+            if limits.seccomp && unsafe { libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_STRICT) } != 0 {
+                exit(CHILD_FAILED);
+            }
+
+            let mut result = [0_u8; RESULT_LEN];
+            match program.execute(tape.as_mut_ptr().cast(), 0, None, ctx, u64::MAX, counters) {
+                Ok(state) => {
+                    result[0] = FINISHED;
+                    result[1..9].copy_from_slice(&(state.data_ptr as u64).to_le_bytes());
+                    result[9] = state.cell;
+                },
+                Err(Error::PointerOutOfBounds { row, col }) => {
+                    result[0] = POINTER_OUT_OF_BOUNDS;
+                    result[1..9].copy_from_slice(&(row as u64).to_le_bytes());
+                    result[9..17].copy_from_slice(&(col as u64).to_le_bytes());
+                },
+                Err(_) => exit(CHILD_FAILED),
+            }
+            write_all(fds.result, &result);
+            exit(0);
+        }));
+        exit(CHILD_FAILED)
+    }
+
+    /// The [IoAdapter] of the child process, reading the input from memory and writing the output to the pipe.
+    struct PipeIo<'a> {
+        /// The remaining input.
+        input: slice::Iter<'a, u8>,
+        /// The writing end of the output pipe.
+        fd: RawFd,
+    }
+    impl IoAdapter for PipeIo<'_> {
+        fn read_byte(&mut self) -> Option<u8> {
+            self.input.next().copied()
+        }
+
+        fn write_byte(&mut self, byte: u8) {
+            write_all(self.fd, &[byte]);
+        }
+
+        fn write_bytes(&mut self, bytes: &[u8]) {
+            write_all(self.fd, bytes);
+        }
+    }
+
+    /// Get the upper bound of the file descriptors of the process (for [close_other_fds]).
+    /// # Returns
+    /// * [RawFd] - The limit of the open files (1024 if it is unknown).
+    fn open_max() -> RawFd {
+        match unsafe { libc::sysconf(libc::_SC_OPEN_MAX) } {
+            limit if limit > 0 => limit.min(RawFd::MAX as libc::c_long) as RawFd,
+            _ => 1024,
+        }
+    }
+
+    /// Close all the file descriptors of the child process except the given ones
+    /// (the descriptors inherited from the parent stay open after the fork, even if they are closed on exec).
+    /// # Arguments
+    /// * `keep` - The file descriptors which stay open.
+    /// * `open_max` - The upper bound of the file descriptors (used if they can't be closed by ranges).
+    fn close_other_fds<const N: usize>(mut keep: [RawFd; N], open_max: RawFd) {
+        // nothing is allocated in the forked child
+        keep.sort_unstable();
+        let mut first = 0;
+        for end in keep.into_iter().map(Some).chain([None]) {
+            let last = end.map_or(RawFd::MAX, |end| end - 1);
+            if first <= last {
+                close_range(first, last, open_max);
+            }
+            first = end.map_or(RawFd::MAX, |end| end + 1);
+        }
+    }
+
+    /// Close the file descriptors from `first` to `last` (inclusive).
+    /// # Arguments
+    /// * `first` - The first closed file descriptor.
+    /// * `last` - The last closed file descriptor.
+    /// * `open_max` - The upper bound of the file descriptors (the descriptors are closed one by one below it
+    ///   if the system doesn't support `close_range`).
+    fn close_range(first: RawFd, last: RawFd, open_max: RawFd) {
+        #[cfg(target_os = "linux")]
+        if unsafe { libc::syscall(libc::SYS_close_range, first as libc::c_uint, last as libc::c_uint, 0 as libc::c_uint) } == 0 {
+            return;
+        }
+        for fd in first..=last.min(open_max - 1) {
+            unsafe {
+                libc::close(fd);
+            }
+        }
+    }
+
+    /// Create a pipe whose ends are closed on exec
+    /// (so the processes spawned by the other threads don't keep it open).
+    /// # Returns
+    /// * ([OwnedFd], [OwnedFd]) - The reading and the writing end, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `SandboxFailed` - The pipe couldn't be created.
+    pub(super) fn pipe() -> Result<(OwnedFd, OwnedFd), Error> {
+        let mut fds = [0; 2];
+        #[cfg(target_os = "linux")]
+        let created = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == 0;
+        #[cfg(not(target_os = "linux"))]
+        let created = unsafe {
+            libc::pipe(fds.as_mut_ptr()) == 0
+                && libc::fcntl(fds[0], libc::F_SETFD, libc::FD_CLOEXEC) == 0
+                && libc::fcntl(fds[1], libc::F_SETFD, libc::FD_CLOEXEC) == 0
+        };
+        if !created {
+            return Err(Error::SandboxFailed(io::Error::last_os_error().kind()));
+        }
+        unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+    }
+
+    /// Write all bytes to the file descriptor (the errors are ignored, e.g. when the parent stopped reading).
+    fn write_all(fd: RawFd, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            match unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) } {
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {},
+                -1 | 0 => return,
+                written => bytes = &bytes[written as usize..],
+            }
+        }
+    }
+
+    /// Exit the child process immediately (without running the destructors or the exit handlers of the parent).
+    fn exit(code: i32) -> ! {
+        // exit_group is forbidden in the strict seccomp mode, but the child has a single thread, so exit is enough
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::syscall(libc::SYS_exit, code);
+        }
+        unsafe { libc::_exit(code) }
+    }
+
+    /// Wait for the child process to exit.
+    /// # Returns
+    /// * [i32] - The status of the child, if [Ok].
+    /// * [Error] - The encountered error, if [Err].
+    /// # Errors
+    /// * `SandboxFailed` - The child couldn't be waited for.
+    fn wait(pid: libc::pid_t) -> Result<i32, Error> {
+        let mut status = 0;
+        while unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+            retry_if_interrupted()?;
+        }
+        Ok(status)
+    }
+
+    /// Kill the child process and wait for it to exit.
+    fn kill_and_wait(pid: libc::pid_t) {
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+        }
+        let _ = wait(pid);
+    }
+
+    /// Check whether the last failed system call was interrupted by a signal (and should be retried).
+    /// # Errors
+    /// * `SandboxFailed` - The system call failed for another reason.
+    fn retry_if_interrupted() -> Result<(), Error> {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::Interrupted {
+            Ok(())
+        } else {
+            Err(Error::SandboxFailed(err.kind()))
+        }
+    }
+}
+
+
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs::File;
+    use std::io::Read;
+    use std::thread;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::code::{process_code, process_code_with_stats};
+    use crate::jit::JitOptions;
+
+    /// Compile the code with the given options.
+    fn compile(code: &str, options: &JitOptions) -> JitProgram {
+        JitProgram::compile(&process_code(code).unwrap(), options).unwrap()
+    }
+
+    #[test]
+    fn test_run_sandboxed() {
+        //! Test that the output and the final state are reported from the child process.
+
+        let program = compile(",[.,]++++++++[>++++++++<-]>+.", &JitOptions::default());
+        let outcome = program.run_sandboxed(b"hello", &SandboxLimits::default()).unwrap();
+        assert_eq!(outcome.output, b"helloA");
+        assert_eq!(outcome.termination, Termination::Finished(FinalState { data_ptr: 1, cell: 65 }));
+
+        // the output larger than the pipe buffer is streamed
+        let program = compile("-[>-[.-]>-[.-]<<-]", &JitOptions::default());
+        let outcome = program.run_sandboxed(b"", &SandboxLimits::default()).unwrap();
+        assert_eq!(outcome.output.len(), 2 * 255 * 255);
+    }
+
+    #[test]
+    fn test_sandboxed_errors() {
+        //! Test that the program errors are reported from the child process.
+
+        let program = compile("+>+<<+", &JitOptions { strict_bounds: true, ..JitOptions::default() });
+        let outcome = program.run_sandboxed(b"", &SandboxLimits::default()).unwrap();
+        assert_eq!(outcome.termination, Termination::Failed(Error::PointerOutOfBounds { row: 0, col: 3 }));
+
+        // the locations of the tokens are reported
+        let (tokens, stats) = process_code_with_stats("+>+\n<<+").unwrap();
+        let program = JitProgram::compile(&tokens, &JitOptions { strict_bounds: true, ..JitOptions::default() }).unwrap().with_locations(stats.locations);
+        let outcome = program.run_sandboxed(b"", &SandboxLimits::default()).unwrap();
+        assert_eq!(outcome.termination, Termination::Failed(Error::PointerOutOfBounds { row: 2, col: 1 }));
+    }
+
+    #[test]
+    fn test_sandboxed_time_limits() {
+        //! Test that the infinite loop is stopped by the CPU time and the wall-clock time limits.
+
+        let program = compile("+[]", &JitOptions::default());
+
+        let limits = SandboxLimits { wall_time: Some(Duration::from_millis(200)), ..SandboxLimits::default() };
+        assert_eq!(program.run_sandboxed(b"", &limits).unwrap().termination, Termination::TimedOut);
+
+        let limits = SandboxLimits { cpu_time: Some(Duration::from_millis(100)), ..SandboxLimits::default() };
+        assert_eq!(program.run_sandboxed(b"", &limits).unwrap().termination, Termination::CpuTimeExceeded);
+    }
+
+    #[test]
+    fn test_sandboxed_closes_fds() {
+        //! Test that the child doesn't keep open the file descriptors of the parent (e.g. the pipes of the other sandboxes).
+
+        let (read_end, write_end) = unix::pipe().unwrap();
+        let program = compile("+[]", &JitOptions::default());
+        let limits = SandboxLimits { wall_time: Some(Duration::from_secs(5)), ..SandboxLimits::default() };
+        thread::scope(|scope| {
+            let sandbox = scope.spawn(|| program.run_sandboxed(b"", &limits).unwrap().termination);
+
+            // the child is forked while the pipe is open, its end of file is reached only if the child closed its copy
+            thread::sleep(Duration::from_millis(500));
+            drop(write_end);
+            let start = Instant::now();
+            let mut contents = Vec::new();
+            File::from(read_end).read_to_end(&mut contents).unwrap();
+            assert!(start.elapsed() < Duration::from_secs(2), "{:?}", start.elapsed());
+
+            assert_eq!(sandbox.join().unwrap(), Termination::TimedOut);
+        });
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_sandboxed_seccomp() {
+        //! Test that the program runs in the strict seccomp mode.
+
+        let program = compile(",[+.,]", &JitOptions::default());
+        let limits = SandboxLimits { seccomp: true, ..SandboxLimits::default() };
+        let outcome = program.run_sandboxed(b"abc", &limits).unwrap();
+        assert_eq!(outcome.output, b"bcd");
+        assert_eq!(outcome.termination, Termination::Finished(FinalState { data_ptr: 0, cell: 0 }));
+    }
+}
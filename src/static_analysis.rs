@@ -0,0 +1,119 @@
+//! Static analysis of the structure of Brainfuck programs.
+
+
+
+use crate::code::{signed_distance, Token, TokenStream};
+
+
+
+/// The subroutine-like pattern found by [find_subroutine_calls]: the data pointer moves to the call site,
+/// the body (with at least one loop) runs there and the pointer moves back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubroutineCall {
+    /// The index of the move to the call site.
+    pub token_index: usize,
+    /// The distance from the current cell to the call site (negative to the left).
+    pub call_site_offset: isize,
+    /// The tokens run at the call site (between the two moves, the jumps of the brackets are relative, so they remain valid).
+    pub body: TokenStream,
+}
+
+/// Find the subroutine-like patterns in the token stream, the moves to a cell, the body with a loop run there
+/// and the moves back by the same distance (`Move(n) <body> Move(-n)`, e.g. `>[-<+>]<`).
+///
+/// This is a best-effort analysis: the body must not move the data pointer outside of its loops (so it runs at the call site),
+/// it must contain a loop or a loop turned into a single token by the optimizations ([Token::ClearCell], [Token::AddTo], ...),
+/// and its brackets must be matched. The calls are found at any depth, also inside the bodies of other calls.
+/// # Arguments
+/// * token_stream - The [TokenStream] to analyze (e.g. the output of [process_code](crate::code::process_code)).
+/// # Returns
+/// * `Vec<SubroutineCall>` - The calls ordered by the index of their first move.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::static_analysis::{find_subroutine_calls, SubroutineCall};
+///
+/// let tokens = process_code("+>>[-]<<.").unwrap();
+/// assert_eq!(find_subroutine_calls(&tokens), vec![
+///     SubroutineCall { token_index: 1, call_site_offset: 2, body: process_code("[-]").unwrap() },
+/// ]);
+/// ```
+pub fn find_subroutine_calls(token_stream: &TokenStream) -> Vec<SubroutineCall> {
+    let mut calls = Vec::new();
+    for (i, token) in token_stream.iter().enumerate() {
+        let Token::Move(n) = *token else {
+            continue;
+        };
+        if let Some(body) = call_body(&token_stream[i + 1..], n) {
+            calls.push(SubroutineCall { token_index: i, call_site_offset: signed_distance(n), body: body.to_vec() });
+        }
+    }
+    calls
+}
+
+/// Find the body of the call, the tokens before the move back.
+/// # Arguments
+/// * tokens - The tokens after the move to the call site.
+/// * n - The distance of the move to the call site.
+/// # Returns
+/// * `Option<&[Token]>` - The body, if the tokens start with the body of the call followed by the move back.
+fn call_body(tokens: &[Token], n: usize) -> Option<&[Token]> {
+    let mut depth = 0_usize;
+    let mut has_loop = false;
+    for (i, &token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenBr(_) => {
+                depth += 1;
+                has_loop = true;
+            },
+            // the end of the enclosing loop
+            Token::CloseBr(_) if depth == 0 => return None,
+            Token::CloseBr(_) => depth -= 1,
+            Token::ClearCell | Token::AddTo(_) | Token::AddToCopy(_, _) | Token::AddToCopy3(_, _, _) => has_loop = true,
+            Token::Move(m) if depth == 0 => {
+                return (has_loop && signed_distance(m) == -signed_distance(n)).then_some(&tokens[..i]);
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::{process_code, process_code_with_pipeline, OptimizationPipeline, STORAGE_SIZE};
+
+    #[test]
+    fn test_find_subroutine_calls() {
+        //! Test that the find_subroutine_calls function finds the calls.
+
+        // the optimized loop
+        let tokens = process_code(">[-<+>]<").unwrap();
+        assert_eq!(find_subroutine_calls(&tokens), vec![
+            SubroutineCall { token_index: 0, call_site_offset: 1, body: vec![Token::AddTo(STORAGE_SIZE - 1)] },
+        ]);
+
+        // the loop itself (the moves inside it are not a call, there is no loop between them)
+        let raw = OptimizationPipeline { passes: Vec::new() };
+        let tokens = process_code_with_pipeline(">[-<+>]<", &raw).unwrap();
+        assert_eq!(find_subroutine_calls(&tokens), vec![
+            SubroutineCall { token_index: 0, call_site_offset: 1, body: process_code_with_pipeline("[-<+>]", &raw).unwrap() },
+        ]);
+
+        // to the left, nested in a loop and in another call
+        let tokens = process_code("+[<<<[.-]+[-]>>>-]>>[<[-]>-]<<").unwrap();
+        assert_eq!(find_subroutine_calls(&tokens), vec![
+            SubroutineCall { token_index: 2, call_site_offset: -3, body: process_code("[.-]+[-]").unwrap() },
+            SubroutineCall { token_index: 12, call_site_offset: 2, body: process_code("[<[-]>-]").unwrap() },
+            SubroutineCall { token_index: 14, call_site_offset: -1, body: vec![Token::ClearCell] },
+        ]);
+
+        // no loop, no move back, the move back by a different distance, the move back outside of the loop
+        for code in [">+.<", ">[-]", ">[-]<<", "[>[-]]<", ">[-]>[-]<<"] {
+            assert_eq!(find_subroutine_calls(&process_code(code).unwrap()), Vec::new(), "{}", code);
+        }
+    }
+}
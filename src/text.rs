@@ -2,12 +2,134 @@
 
 
 
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::LazyLock;
+use crate::code::{process_code, STORAGE_SIZE};
 use crate::error::Error;
+use crate::interpret::{interpret_from, Stop};
+use crate::io::{EofPolicy, VecIo};
 
 
 
+/// The strategy of the Brainfuck code generated by [text_2_bf_with_options].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TextCodeStyle {
+    /// Store each distinct byte of the text in its own cell, then print the text by moving between the cells.
+    ///
+    /// Short for the texts with few distinct bytes (used by [text_2_bf]).
+    #[default]
+    Table,
+    /// Use a single cell, changed from the previous byte to the next one before each print
+    /// (by a multiplication loop in the cell to its right if the difference is large).
+    ///
+    /// Short for the texts with many distinct bytes, where the table would need a long setup and long pointer walks.
+    SingleCell,
+    /// Generate the code with both strategies and pick the shorter one (the table if they are equally long).
+    Auto,
+}
+
+/// What the Brainfuck code generated by [text_2_bf_with_options] is optimized for.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum CostTarget {
+    /// The fewest commands in the code: the values are built by multiplication loops (`>++++++++[<+++++++++>-]<`)
+    /// and the runs of the same character are printed by counted loops.
+    #[default]
+    CodeSize,
+    /// The fewest commands executed when the code runs: the values are built by plain `+`/`-` commands
+    /// and no loops are generated, so each command runs once and the length of the code is its running time.
+    ///
+    /// The code is longer, but it is faster under the naive interpreters that don't optimize the loops.
+    RunTime,
+}
+
+/// How the line endings of the text are printed by the code generated by [text_2_bf_with_options].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum NewlinePolicy {
+    /// Print the text exactly as given (including the `\r` characters).
+    Preserve,
+    /// Convert the `\r\n` line endings to `\n`, the other `\r` characters are printed (used by [text_2_bf]).
+    #[default]
+    CrlfToLf,
+    /// Skip all the `\r` characters, so the lines end with `\n`.
+    Lf,
+    /// Skip the `\r` characters and print each `\n` as `\r\n`, so all the lines end with `\r\n`.
+    Crlf,
+}
+
+/// The options for the generation of Brainfuck code that prints the text.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TextOptions {
+    /// The strategy of the generated code.
+    pub style: TextCodeStyle,
+    /// Whether to print the non-ASCII characters as their UTF-8 byte sequences (see [text_2_bf_utf8]),
+    /// instead of failing with `Error::NonASCIIChar`.
+    pub utf8: bool,
+    /// What the generated code is optimized for.
+    pub target: CostTarget,
+    /// How the line endings are printed.
+    pub newlines: NewlinePolicy,
+    /// Whether to clear all the cells used by the code and return the data pointer to the first cell after printing,
+    /// so the code can be followed by the code expecting the clean array.
+    pub cleanup: bool,
+    /// Whether to run the generated code and check that it prints the intended bytes before returning it
+    /// (fails with `Error::GenerationMismatch` otherwise).
+    pub verify: bool,
+    /// The most cells the generated code may use (from the first cell), if limited.
+    ///
+    /// If the text has more distinct bytes than fit into the table, the bytes are grouped by their value into as many groups
+    /// as there are cells, and only the base value of each group is stored (the cells are adjusted before printing).
+    /// The table needs two scratch cells after it for [CostTarget::CodeSize] (one for [CostTarget::RunTime]), the single cell
+    /// needs one for its multiplication loop (none for [CostTarget::RunTime]), so the budget must be at least 3 cells
+    /// for [TextCodeStyle::Table] and 2 cells for [TextCodeStyle::SingleCell] (2 and 1 cells for [CostTarget::RunTime]),
+    /// the generation fails with `Error::CellBudgetTooSmall` otherwise.
+    pub max_cells: Option<usize>,
+    /// Whether the generated code should run also under the stricter dialects of Brainfuck.
+    ///
+    /// The default code assumes the 8-bit cells wrapping around (`0 - 1 == 255` and `255 + 1 == 0`): the cells are changed
+    /// the shorter way around, the values above 127 are built from below zero, the cells holding them are cleared by `[+]`
+    /// and the counters of 255 iterations are set to `-1`. The portable code never changes a cell below 0 or above 255
+    /// (not even temporarily, e.g. by the multiplication loops), so it runs the same with the non-wrapping cells
+    /// (which fail or saturate) and with the bigger cells, and the termination of its loops doesn't depend on the wrapping.
+    /// Neither code moves the data pointer left of the first cell, reads the input or depends on its initial position.
+    /// The portable code is longer (the cells are never changed the shorter way around).
+    pub portable: bool,
+    /// Whether to print the blocks of the text repeated right after each other (e.g. `<td></td><td></td>`) by a counted loop,
+    /// so the code printing the block is generated only once (only for [TextCodeStyle::Table] and [CostTarget::CodeSize],
+    /// the loop is used only if the code gets shorter).
+    ///
+    /// The loop needs two more cells after the table (its counter and the multiplication cell),
+    /// so it isn't used if they don't fit into [TextOptions::max_cells].
+    pub reuse_repeats: bool,
+    /// Whether to write the code legibly, one line for each stored cell and for each printed byte, each line followed
+    /// by a comment saying what it does (e.g. `>>.. prints 'l' (108)`), the cleanup on the last line.
+    ///
+    /// The comments never contain the commands (nor `#`, the usual debug instruction), so the code still prints the same text.
+    /// The bytes are printed one by one (the runs and the repeated blocks aren't printed by the loops, [TextOptions::reuse_repeats]
+    /// is ignored), the style is chosen as without the comments.
+    pub annotate: bool,
+    /// The most characters in a line of the generated code, if the code is wrapped (at least one character in a line).
+    ///
+    /// The newlines are comments, so they never change what the code does, but no line ends with `[`
+    /// (the line is broken before it instead). The loops aren't indented, [format_bf](crate::tools::format_bf) formats them.
+    pub wrap_width: Option<usize>,
+}
+
+/// How the placeholders (`{}`) of the template are filled by the code generated by [text_2_bf_template].
+///
+/// The fields are read from the input and printed as they are. The end of input is recognized if it is read as `0`
+/// ([EofPolicy::Zero] or [EofPolicy::Unchanged]), it ends the field.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PlaceholderSpec {
+    /// Each field is a line of the input (the newline is read, but not printed).
+    #[default]
+    Line,
+    /// Each field has the given number of bytes (the `0` bytes are not printed).
+    Fixed(usize),
+}
+
 /// Generate Brainfuck code that prints the provided text.
 /// # Arguments
 /// * `text` - The text that Brainfuck code should print.
@@ -15,303 +137,2445 @@ use crate::error::Error;
 /// * [String] - The Brainfuck code, if OK.
 /// * [Error] - The error.
 /// # Errors
-/// * `Error::NonASCIIChar` - If a non-ASCII character is found.
+/// * `Error::NonASCIIChar` - If a non-ASCII character is found (see [text_2_bf_utf8] for printing any text).
 /// # Example
 /// ```
-/// use bfuck::text::text_2_bf;
+/// use bfuck::text::{bf_2_text, text_2_bf};
 ///
 /// let text = "Brainfuck";
 /// let bf_code = text_2_bf(text).unwrap();
 ///
 /// // Brainfuck code that prints "Brainfuck"
-/// let expected_code = ">++++++[<+++++++++++>-]>++++++++[<++++++++++++>-]<+>>+++++++++[<+++++++++++>-]>++++++++++[<++++++++++>-]<++>>++++++++[<+++++++++++++>-]<+>>+++++++++[<++++++++++++>-]<->>++++++++++[<+++++++++++>-]>++++++++[<++++++++++++++>-]<++>>+++++++++[<+++++++++++++>-]<<<<<<<<<.>>>>>>>.<<<<<<.>>>.>>.<<<.>>>>>.<<<<<<.>>>.";
+/// let expected_code = "->+++++++++[<++++++++++++>-]++>++++++++[<++++++++++++++>-]+>++++++++[<++++++++++++>-]>++++++[<+++++++++++>-]>+++++++++[<+++++++++++>-]+>++++++++[<+++++++++++++>-]>++++++++++[<+++++++++++>-]++>++++++++++[<++++++++++>-]>+++++++++[<+++++++++++++>-]<<<<<<.<<.>.>>>.>.>.>.<<<<.>++.";
 ///
 /// assert_eq!(bf_code, expected_code);
+/// assert_eq!(bf_2_text(&bf_code).unwrap(), text);
 /// ```
 pub fn text_2_bf(text: &str) -> Result<String, Error> {
     // convert text to bytes
     // each character is converted to its ASCII value (single byte)
-    let bytes = text_2_bytes(text)?;
+    let bytes = text_2_bytes(text, NewlinePolicy::CrlfToLf)?;
+
+    Ok(generate_bf(&bytes, &TextOptions::default()))
+}
+
+/// Generate Brainfuck code that prints the provided text, with the table ordered only by the frequency of the characters.
+///
+/// The characters are stored in the ascending order of their frequency, so the most frequent ones are in the last cells,
+/// closest to the data pointer, which starts printing from the cell after the table. The storage is the same as by [text_2_bf],
+/// only the walks while printing differ. [text_2_bf] starts from the order by the frequency as well, but also takes
+/// the transitions between the characters into account and falls back to the sorted order, so it is usually shorter.
+/// # Arguments
+/// * `text` - The text that Brainfuck code should print.
+/// # Returns
+/// * [String] - The Brainfuck code, if OK.
+/// * [Error] - The error.
+/// # Errors
+/// * `Error::NonASCIIChar` - If a non-ASCII character is found.
+/// # Example
+/// ```
+/// use bfuck::text::{bf_2_text, text_2_bf_huffman};
+///
+/// let text = "Hello, World!";
+/// let bf_code = text_2_bf_huffman(text).unwrap();
+/// assert_eq!(bf_2_text(&bf_code).unwrap(), text);
+/// ```
+pub fn text_2_bf_huffman(text: &str) -> Result<String, Error> {
+    let bytes = text_2_bytes(text, NewlinePolicy::CrlfToLf)?;
+
+    let store_order = frequency_order(&bytes);
+    let mut bf_code = store_bf_bytes(&store_order, CostTarget::CodeSize, false);
+    // writing to the string never fails
+    let _ = print_bf_bytes(&bytes, &store_order, store_order.len(), CostTarget::CodeSize, false, &mut bf_code);
+
+    Ok(bf_code)
+}
+
+/// Generate Brainfuck code that prints the provided text, with the given options.
+/// # Arguments
+/// * `text` - The text that Brainfuck code should print.
+/// * `options` - The [TextOptions] of the generated code.
+/// # Returns
+/// * [String] - The Brainfuck code, if OK.
+/// * [Error] - The error.
+/// # Errors
+/// * `Error::NonASCIIChar` - If a non-ASCII character is found (unless [TextOptions::utf8] is set).
+/// * `Error::GenerationMismatch` - If [TextOptions::verify] is set and the generated code doesn't print the text.
+/// # Example
+/// ```
+/// use bfuck::text::{text_2_bf_with_options, TextCodeStyle, TextOptions};
+///
+/// let options = TextOptions { style: TextCodeStyle::SingleCell, ..TextOptions::default() };
+/// let bf_code = text_2_bf_with_options("Hi!", &options).unwrap();
+///
+/// // Brainfuck code that prints "Hi!" from a single cell
+/// assert_eq!(bf_code, ">++++++++[<+++++++++>-]<.>++++[<++++++++>-]<+.>++++++++[<--------->-]<.");
+/// ```
+pub fn text_2_bf_with_options(text: &str, options: &TextOptions) -> Result<String, Error> {
+    let bytes = if options.utf8 { utf8_bytes(text, options.newlines) } else { text_2_bytes(text, options.newlines)? };
+
+    bytes_2_bf_with_options(&bytes, options)
+}
+
+/// Generate Brainfuck code that prints the provided text encoded as UTF-8.
+///
+/// Unlike [text_2_bf], any text is accepted. The non-ASCII characters are printed as their UTF-8 byte sequences,
+/// so the generated code must be run with the byte-transparent output (the output of this crate is,
+/// but some interpreters drop or mangle the bytes above 127). The `\r\n` line endings are converted to `\n`, the same as by [text_2_bf]
+/// (see [NewlinePolicy] for keeping them).
+/// # Arguments
+/// * `text` - The text that Brainfuck code should print.
+/// # Returns
+/// * [String] - The Brainfuck code.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::interpret::interpret;
+/// use bfuck::io::VecIo;
+/// use bfuck::text::text_2_bf_utf8;
+///
+/// let bf_code = text_2_bf_utf8("Čao 👋");
+///
+/// let mut io = VecIo::default();
+/// interpret(&process_code(&bf_code).unwrap(), &mut io);
+/// assert_eq!(String::from_utf8(io.output).unwrap(), "Čao 👋");
+/// ```
+pub fn text_2_bf_utf8(text: &str) -> String {
+    generate_bf(&utf8_bytes(text, NewlinePolicy::CrlfToLf), &TextOptions::default())
+}
+
+/// The maximum number of the jumps back to the start of a loop made by the program run by [bf_2_text] and [bf_2_bytes].
+pub const BF_2_TEXT_STEP_LIMIT: u64 = 100_000_000;
+
+/// Run the Brainfuck code without input and return the text it prints (the inverse of [text_2_bf]).
+///
+/// The output is decoded as UTF-8, the invalid sequences are replaced with `U+FFFD` (see [bf_2_bytes] for the raw output).
+/// # Arguments
+/// * `code` - A string slice that holds the Brainfuck code.
+/// # Returns
+/// * [String] - The printed text, if OK.
+/// * [Error] - The error.
+/// # Errors
+/// * The errors of [process_code].
+/// * `Error::UnexpectedInput` - The code contains the `,` command (there is no input).
+/// * `Error::StepLimitExceeded` - The program made more than [BF_2_TEXT_STEP_LIMIT] jumps back to the start of a loop
+///   (it probably never terminates).
+/// # Example
+/// ```
+/// use bfuck::error::Error;
+/// use bfuck::text::bf_2_text;
+///
+/// assert_eq!(bf_2_text("++++++++[>++++++++<-]>+.+.").unwrap(), "AB");
+/// assert_eq!(bf_2_text(",."), Err(Error::UnexpectedInput(1, 1)));
+/// assert_eq!(bf_2_text("+[]"), Err(Error::StepLimitExceeded));
+/// ```
+pub fn bf_2_text(code: &str) -> Result<String, Error> {
+    Ok(String::from_utf8_lossy(&bf_2_bytes(code)?).into_owned())
+}
+
+/// Run the Brainfuck code without input and return the bytes it prints.
+/// # Arguments
+/// * `code` - A string slice that holds the Brainfuck code.
+/// # Returns
+/// * `Vec<u8>` - The printed bytes, if OK.
+/// * [Error] - The error.
+/// # Errors
+/// * The errors of [process_code].
+/// * `Error::UnexpectedInput` - The code contains the `,` command (there is no input).
+/// * `Error::StepLimitExceeded` - The program made more than [BF_2_TEXT_STEP_LIMIT] jumps back to the start of a loop.
+/// # Example
+/// ```
+/// use bfuck::text::{bf_2_bytes, text_2_bf_utf8};
+///
+/// assert_eq!(bf_2_bytes(&text_2_bf_utf8("€")).unwrap(), [0xE2, 0x82, 0xAC]);
+/// ```
+pub fn bf_2_bytes(code: &str) -> Result<Vec<u8>, Error> {
+    let token_stream = process_code(code)?;
+    for (i, line) in code.lines().enumerate() {
+        if let Some(j) = line.chars().position(|c| c == ',') {
+            return Err(Error::UnexpectedInput(i + 1, j + 1));
+        }
+    }
+
+    let mut storage = [0_u8; STORAGE_SIZE];
+    let mut io = VecIo::default();
+    let mut steps = 0_u64;
+    let mut out_of_steps = || {
+        steps += 1;
+        steps > BF_2_TEXT_STEP_LIMIT
+    };
+    match interpret_from(&token_stream, &mut storage, 0, 0, &mut io, EofPolicy::default(), &mut out_of_steps) {
+        Stop::Finished(_) => Ok(io.output),
+        Stop::Paused { .. } => Err(Error::StepLimitExceeded),
+    }
+}
+
+/// Generate Brainfuck code that prints the provided binary data.
+///
+/// Any bytes are accepted (including `0` and the bytes above 127) and printed exactly as they are, without the newline handling.
+/// The generated code must be run with the byte-transparent output (the output of this crate is,
+/// but some interpreters drop or mangle the bytes above 127 or translate the line endings).
+/// # Arguments
+/// * `data` - The bytes that Brainfuck code should print.
+/// # Returns
+/// * [String] - The Brainfuck code.
+/// # Example
+/// ```
+/// use bfuck::text::{bf_2_bytes, bytes_2_bf};
+///
+/// let data = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n', 0x00];
+/// assert_eq!(bf_2_bytes(&bytes_2_bf(&data)).unwrap(), data);
+/// ```
+pub fn bytes_2_bf(data: &[u8]) -> String {
+    generate_bf(data, &TextOptions::default())
+}
+
+/// Generate Brainfuck code that prints the provided binary data, with the given options.
+/// # Arguments
+/// * `data` - The bytes that Brainfuck code should print.
+/// * `options` - The [TextOptions] of the generated code ([TextOptions::utf8] and [TextOptions::newlines] are ignored,
+///   the bytes are printed as they are).
+/// # Returns
+/// * [String] - The Brainfuck code, if OK.
+/// * [Error] - The error.
+/// # Errors
+/// * `Error::GenerationMismatch` - If [TextOptions::verify] is set and the generated code doesn't print the data.
+pub fn bytes_2_bf_with_options(data: &[u8], options: &TextOptions) -> Result<String, Error> {
+    let mut bf_code = String::new();
+    write_checked_bf(data, options, &mut bf_code)?;
+    Ok(bf_code)
+}
+
+/// Generate the shortest Brainfuck code that sets the current cell to the number.
+///
+/// The number is built by the same constructions as the bytes stored by [text_2_bf] (the multiplication loops,
+/// possibly nested, and the plain `+`/`-` commands, the cell wraps around). The current cell and the two cells to its right
+/// (the counters of the loops) must be zero, the counters are left zero and the data pointer returns to the current cell.
+/// See [number_2_bf_in_place] for the code which doesn't use the other cells.
+/// # Arguments
+/// * `n` - The number.
+/// # Returns
+/// * [String] - The Brainfuck code.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::interpret::run_interpret;
+/// use bfuck::text::{number_2_bf, number_cost};
+///
+/// let bf_code = number_2_bf(72);
+/// assert_eq!(bf_code, ">++++++++[<+++++++++>-]<");
+/// assert_eq!(bf_code.len(), number_cost(72));
+///
+/// let result = run_interpret(process_code(&bf_code).unwrap(), b"").unwrap();
+/// assert_eq!(result.final_data_ptr, 0);
+/// assert_eq!(result.final_tape[..3], [72, 0, 0]);
+/// ```
+pub fn number_2_bf(n: u8) -> String {
+    match CONSTANT_TABLE[n as usize] {
+        // the plain commands don't move to the cell to the right
+        Construction::Plain(diff) => add_bf(i16::from(diff), CostTarget::RunTime, false),
+        construction => format!("{}<", construction.to_bf()),
+    }
+}
+
+/// Calculate the length of the code generated by [number_2_bf] (without generating it),
+/// e.g. to decide where to build the numbers.
+/// # Arguments
+/// * `n` - The number.
+/// # Returns
+/// * `usize` - The number of Brainfuck commands.
+pub fn number_cost(n: u8) -> usize {
+    match CONSTANT_TABLE[n as usize] {
+        Construction::Plain(diff) => diff.unsigned_abs() as usize,
+        construction => construction.cost() + 1,
+    }
+}
+
+/// Generate Brainfuck code that sets the current cell to the number without using any other cell.
+///
+/// Unlike [number_2_bf], the cells around are never changed, so they may hold any data. The current cell must be zero,
+/// the number is reached by the plain `+`/`-` commands the shorter way around (the cell wraps around), so the code is longer
+/// for the numbers around 128.
+/// # Arguments
+/// * `n` - The number.
+/// # Returns
+/// * [String] - The Brainfuck code.
+/// # Example
+/// ```
+/// use bfuck::text::number_2_bf_in_place;
+///
+/// assert_eq!(number_2_bf_in_place(3), "+++");
+/// assert_eq!(number_2_bf_in_place(254), "--");
+/// ```
+pub fn number_2_bf_in_place(n: u8) -> String {
+    add_bf(i16::from(n as i8), CostTarget::RunTime, false)
+}
+
+/// Generate Brainfuck code that prints the provided text, with the given options, and write it to the writer.
+///
+/// The code is written while it is generated (the storage first, then the printing of each character),
+/// so it is never kept in memory as a whole. This is useful for the huge texts, whose code is several times larger.
+/// The written code is the same as the code returned by [text_2_bf_with_options]. Pass the buffered writer
+/// (e.g. [BufWriter](std::io::BufWriter)), the code is written in many small pieces. The writer is flushed at the end.
+/// # Arguments
+/// * `text` - The text that Brainfuck code should print.
+/// * `out` - The writer to which the code is written.
+/// * `options` - The [TextOptions] of the generated code.
+/// # Returns
+/// * `()` - If OK.
+/// * [Error] - The error.
+/// # Errors
+/// * `Error::NonASCIIChar` - If a non-ASCII character is found (unless [TextOptions::utf8] is set), nothing is written then.
+/// * `Error::GenerationMismatch` - If [TextOptions::verify] is set and the generated code doesn't print the text
+///   (the code is verified while it is written, so it is written anyway).
+/// * `Error::EmitFailed` - If the code couldn't be written.
+/// # Example
+/// ```
+/// use bfuck::text::{text_2_bf_to, text_2_bf_with_options, TextOptions};
+///
+/// let mut bf_code = Vec::new();
+/// text_2_bf_to("Brainfuck", &mut bf_code, &TextOptions::default()).unwrap();
+///
+/// assert_eq!(bf_code, text_2_bf_with_options("Brainfuck", &TextOptions::default()).unwrap().as_bytes());
+/// ```
+pub fn text_2_bf_to<W: io::Write>(text: &str, out: W, options: &TextOptions) -> Result<(), Error> {
+    let bytes = if options.utf8 { utf8_bytes(text, options.newlines) } else { text_2_bytes(text, options.newlines)? };
+
+    let mut writer = IoWriter { inner: out, error: None };
+    let result = write_checked_bf(&bytes, options, &mut writer);
+    if let Some(err) = writer.error {
+        return Err(Error::EmitFailed(err.kind()));
+    }
+    result?;
+    writer.inner.flush().map_err(|err| Error::EmitFailed(err.kind()))
+}
+
+/// Generate Brainfuck code that prints the template, with the placeholders (`{}`) filled by the fields read from the input.
+///
+/// The static text is stored in the table (the same as by [text_2_bf]) and printed part by part,
+/// each field is echoed from the input in the scratch cells after the table (they are left zero).
+/// # Arguments
+/// * `text` - The template which Brainfuck code should print, `{}` is the placeholder.
+/// * `placeholder_spec` - The [PlaceholderSpec] of the fields which replace the placeholders.
+/// # Returns
+/// * [String] - The Brainfuck code, if OK.
+/// * [Error] - The error.
+/// # Errors
+/// * `Error::NonASCIIChar` - If a non-ASCII character is found.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::interpret::run_interpret;
+/// use bfuck::text::{text_2_bf_template, PlaceholderSpec};
+///
+/// let bf_code = text_2_bf_template("Hello, {}!", PlaceholderSpec::Line).unwrap();
+///
+/// let result = run_interpret(process_code(&bf_code).unwrap(), b"World\n").unwrap();
+/// assert_eq!(result.output, b"Hello, World!");
+/// ```
+pub fn text_2_bf_template(text: &str, placeholder_spec: PlaceholderSpec) -> Result<String, Error> {
+    let bytes = text_2_bytes(text, NewlinePolicy::CrlfToLf)?;
+
+    // the static parts of the text, between the placeholders
+    let mut parts = Vec::new();
+    let mut part_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"{}") {
+            parts.push(&bytes[part_start..i]);
+            i += 2;
+            part_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    parts.push(&bytes[part_start..]);
+
+    let target = CostTarget::CodeSize;
+    let static_bytes = parts.concat();
+    let store_order = table_order(&static_bytes, target, false);
+    let mut bf_code = store_bf_bytes(&store_order, target, false);
+
+    // the counter of print_bf_bytes and its multiplication cell are right after the table, the scratch cells follow them
+    let scratch = store_order.len() + 2;
+    let mut cells = store_order;
+    let mut position = cells.len();
+    for (i, part) in parts.iter().enumerate() {
+        // writing to the string never fails
+        (cells, position) = print_bf_bytes(part, &cells, position, target, false, &mut bf_code).unwrap_or_default();
+
+        if i + 1 < parts.len() {
+            bf_code.push_str(&move_bf(scratch as isize - position as isize));
+            bf_code.push_str(&field_bf(placeholder_spec));
+            position = scratch;
+        }
+    }
+
+    Ok(bf_code)
+}
+
+/// Generate Brainfuck code that reads a field from the input and prints it (see [PlaceholderSpec]).
+/// The current cell and the cell to its right must be zero and are left zero, the data pointer stays at the current cell.
+/// # Arguments
+/// * `placeholder_spec` - The [PlaceholderSpec] of the field.
+/// # Returns
+/// * [String] - The Brainfuck code.
+fn field_bf(placeholder_spec: PlaceholderSpec) -> String {
+    match placeholder_spec {
+        // while the read byte is not zero: if it isn't a newline, print it and set the flag (the cell to the right)
+        // to read the next one
+        PlaceholderSpec::Line => String::from(",[----------[++++++++++.[-]>+<]>[-<,>]<]"),
+        // the counter (the cell to the right) counts down the bytes, each is read into the cleared current cell
+        // (so the end of input is zero also with EofPolicy::Unchanged) and printed if it isn't zero
+        PlaceholderSpec::Fixed(len) => {
+            let mut bf_code = String::new();
+            let mut remaining = len;
+            while remaining > 0 {
+                let part = remaining.min(u8::MAX as usize);
+                bf_code.push('>');
+                bf_code.push_str(&add_bf(i16::from(part as u8 as i8), CostTarget::CodeSize, false));
+                bf_code.push_str("[<,[.[-]]>-]<");
+                remaining -= part;
+            }
+            bf_code
+        },
+    }
+}
+
+/// The adapter writing the generated code (formatted as [fmt::Write]) to the [io::Write].
+struct IoWriter<W: io::Write> {
+    /// The underlying writer.
+    inner: W,
+    /// The error of the underlying writer (the formatting error carries no details).
+    error: Option<io::Error>,
+}
+impl<W: io::Write> fmt::Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
+
+/// The writer which only counts the length of the generated code (used to pick the shorter code without keeping it).
+#[derive(Default)]
+struct CodeLength(usize);
+impl fmt::Write for CodeLength {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// The writer which breaks the generated code into the lines of at most the given width (see [TextOptions::wrap_width]).
+struct WrappingWriter<'a, W: fmt::Write> {
+    /// The underlying writer.
+    inner: &'a mut W,
+    /// The most characters in a line.
+    width: usize,
+    /// The number of the characters in the current line.
+    column: usize,
+}
+impl<'a, W: fmt::Write> WrappingWriter<'a, W> {
+    /// Create the writer wrapping the code written to the underlying writer.
+    /// # Arguments
+    /// * `inner` - The underlying writer.
+    /// * `width` - The most characters in a line (at least one character is put in a line).
+    /// # Returns
+    /// * [WrappingWriter] - The writer.
+    fn new(inner: &'a mut W, width: usize) -> Self {
+        Self { inner, width: width.max(1), column: 0 }
+    }
+}
+impl<W: fmt::Write> fmt::Write for WrappingWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if c == '\n' {
+                self.column = 0;
+            } else {
+                // the open bracket would end the line, it starts the next one instead
+                let line_full = self.column >= self.width || (c == '[' && self.column > 0 && self.column + 1 >= self.width);
+                if line_full {
+                    self.inner.write_char('\n')?;
+                    self.column = 0;
+                }
+                self.column += 1;
+            }
+            self.inner.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+/// The number of bytes of the generated code that [VerifyingWriter] collects before running them.
+const VERIFY_CHUNK_SIZE: usize = 1 << 16;
+
+/// The writer which runs the generated code while it is written to the underlying writer,
+/// and checks that it prints the expected bytes (see [TextOptions::verify]).
+///
+/// The code is run in chunks (at least [VERIFY_CHUNK_SIZE] bytes long, ending outside of any loop) on the same array,
+/// so only the current chunk is kept in memory. The program may make at most [BF_2_TEXT_STEP_LIMIT] jumps back
+/// to the start of a loop (the same as in [bf_2_bytes]).
+struct VerifyingWriter<'a, W: fmt::Write> {
+    /// The underlying writer.
+    inner: &'a mut W,
+    /// The bytes which the code should print.
+    expected: &'a [u8],
+    /// The code written since the last run.
+    chunk: String,
+    /// The depth of the loops at the end of the chunk.
+    depth: isize,
+    /// The array of the program.
+    storage: Box<[u8; STORAGE_SIZE]>,
+    /// The data pointer of the program.
+    data_ptr: usize,
+    /// The number of the bytes printed so far (all of them as expected).
+    printed: usize,
+    /// The number of the jumps back to the start of a loop made so far.
+    steps: u64,
+    /// The index of the first byte that wasn't printed as expected.
+    mismatch: Option<usize>,
+}
+impl<'a, W: fmt::Write> VerifyingWriter<'a, W> {
+    /// Create the writer verifying the code written to the underlying writer.
+    /// # Arguments
+    /// * `inner` - The underlying writer.
+    /// * `expected` - The bytes which the code should print.
+    /// # Returns
+    /// * [VerifyingWriter] - The writer.
+    fn new(inner: &'a mut W, expected: &'a [u8]) -> Self {
+        Self {
+            inner,
+            expected,
+            chunk: String::new(),
+            depth: 0,
+            storage: Box::new([0; STORAGE_SIZE]),
+            data_ptr: 0,
+            printed: 0,
+            steps: 0,
+            mismatch: None,
+        }
+    }
+
+    /// Run the collected chunk of the code and compare its output with the expected bytes.
+    fn run_chunk(&mut self) {
+        let chunk = std::mem::take(&mut self.chunk);
+        let token_stream = match process_code(&chunk) {
+            Ok(token_stream) => token_stream,
+            Err(_) => {
+                self.mismatch = Some(self.printed);
+                return;
+            },
+        };
+
+        let mut io = VecIo::default();
+        let steps = &mut self.steps;
+        let mut out_of_steps = || {
+            *steps += 1;
+            *steps > BF_2_TEXT_STEP_LIMIT
+        };
+        match interpret_from(&token_stream, &mut self.storage, 0, self.data_ptr, &mut io, EofPolicy::default(), &mut out_of_steps) {
+            Stop::Finished(data_ptr) => self.data_ptr = data_ptr,
+            Stop::Paused { .. } => {
+                self.mismatch = Some(self.printed);
+                return;
+            },
+        }
+
+        let remaining = &self.expected[self.printed..];
+        match io.output.iter().zip(remaining).position(|(printed, expected)| printed != expected) {
+            Some(i) => self.mismatch = Some(self.printed + i),
+            None if io.output.len() > remaining.len() => self.mismatch = Some(self.expected.len()),
+            None => self.printed += io.output.len(),
+        }
+    }
+
+    /// Run the rest of the code and check that all the expected bytes were printed.
+    /// # Returns
+    /// * `()` - If the code printed exactly the expected bytes.
+    /// * [Error] - The error.
+    /// # Errors
+    /// * `Error::GenerationMismatch` - With the index of the first byte that is not printed as expected.
+    fn finish(mut self) -> Result<(), Error> {
+        if self.mismatch.is_none() {
+            self.run_chunk();
+        }
+        if self.mismatch.is_none() && self.printed != self.expected.len() {
+            self.mismatch = Some(self.printed);
+        }
+        match self.mismatch {
+            Some(index) => Err(Error::GenerationMismatch(index)),
+            None => Ok(()),
+        }
+    }
+}
+impl<W: fmt::Write> fmt::Write for VerifyingWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_str(s)?;
+        if self.mismatch.is_none() {
+            for c in s.chars() {
+                match c {
+                    '[' => self.depth += 1,
+                    ']' => self.depth -= 1,
+                    _ => (),
+                }
+            }
+            self.chunk.push_str(s);
+            if self.depth == 0 && self.chunk.len() >= VERIFY_CHUNK_SIZE {
+                self.run_chunk();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write Brainfuck code that prints the provided bytes, with the given options, to the writer
+/// (verified if [TextOptions::verify] is set).
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `options` - The [TextOptions] of the generated code.
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * `()` - If OK.
+/// * [Error] - The error.
+/// # Errors
+/// * `Error::CellBudgetTooSmall` - If [TextOptions::max_cells] is too small for the style, nothing is written then.
+/// * `Error::GenerationMismatch` - If [TextOptions::verify] is set and the generated code doesn't print the bytes.
+/// * `Error::EmitFailed` - If the writer failed (the caller knows the details).
+fn write_checked_bf<W: fmt::Write>(bytes: &[u8], options: &TextOptions, out: &mut W) -> Result<(), Error> {
+    if let Some(max_cells) = options.max_cells {
+        let needed = min_cells(options.style, options.target);
+        if max_cells < needed {
+            return Err(Error::CellBudgetTooSmall(max_cells, needed));
+        }
+    }
+
+    match options.wrap_width {
+        Some(width) => write_verified_bf(bytes, options, &mut WrappingWriter::new(out, width)),
+        None => write_verified_bf(bytes, options, out),
+    }
+}
+
+/// Write Brainfuck code that prints the provided bytes, with the given options, to the writer
+/// (verified if [TextOptions::verify] is set, the budget of the cells is already validated).
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `options` - The [TextOptions] of the generated code.
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * `()` - If OK.
+/// * [Error] - The error.
+/// # Errors
+/// * `Error::GenerationMismatch` - If [TextOptions::verify] is set and the generated code doesn't print the bytes.
+/// * `Error::EmitFailed` - If the writer failed (the caller knows the details).
+fn write_verified_bf<W: fmt::Write>(bytes: &[u8], options: &TextOptions, out: &mut W) -> Result<(), Error> {
+    let write_failed = |_| Error::EmitFailed(io::ErrorKind::Other);
+    if options.verify {
+        let mut writer = VerifyingWriter::new(out, bytes);
+        write_bf(bytes, options, &mut writer).map_err(write_failed)?;
+        writer.finish()
+    } else {
+        write_bf(bytes, options, out).map_err(write_failed)
+    }
+}
+
+/// Generate Brainfuck code that prints the provided bytes, with the given options (without the verification).
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `options` - The [TextOptions] of the generated code.
+/// # Returns
+/// * [String] - The Brainfuck code.
+fn generate_bf(bytes: &[u8], options: &TextOptions) -> String {
+    let mut bf_code = String::new();
+    // writing to the string never fails
+    let _ = write_bf(bytes, options, &mut bf_code);
+    bf_code
+}
+
+/// Write Brainfuck code that prints the provided bytes, with the given options (without the verification), to the writer.
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `options` - The [TextOptions] of the generated code.
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * [fmt::Result] - The result of writing.
+fn write_bf<W: fmt::Write>(bytes: &[u8], options: &TextOptions, out: &mut W) -> fmt::Result {
+    let style = match options.style {
+        TextCodeStyle::Auto => auto_style(bytes, options)?,
+        style => style,
+    };
+    match (style, options.annotate) {
+        (TextCodeStyle::SingleCell, false) => single_cell_bf(bytes, options.target, options.cleanup, options.portable, out),
+        (TextCodeStyle::SingleCell, true) => annotated_single_cell_bf(bytes, options.target, options.cleanup, options.portable, out),
+        (_, false) => table_bf(bytes, options.target, options.cleanup, options.max_cells, options.portable, options.reuse_repeats, out),
+        (_, true) => annotated_table_bf(bytes, options.target, options.cleanup, options.max_cells, options.portable, out),
+    }
+}
+
+/// Choose the style of [TextCodeStyle::Auto], the one with the shorter code (without the comments of [TextOptions::annotate]).
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `options` - The [TextOptions] of the generated code.
+/// # Returns
+/// * [TextCodeStyle] - [TextCodeStyle::Table] or [TextCodeStyle::SingleCell], if OK.
+/// * [fmt::Error] - The error of measuring the code (never happens).
+fn auto_style(bytes: &[u8], options: &TextOptions) -> Result<TextCodeStyle, fmt::Error> {
+    // the table is left out if it doesn't fit into the budget (the single cell always fits after the validation)
+    let table_fits = options.max_cells.is_none_or(|max_cells| max_cells >= min_cells(TextCodeStyle::Table, options.target));
+    if !table_fits {
+        return Ok(TextCodeStyle::SingleCell);
+    }
+
+    // without loops, the shorter code also runs faster (the lengths are measured without keeping the code)
+    let mut table = CodeLength::default();
+    table_bf(bytes, options.target, options.cleanup, options.max_cells, options.portable, options.reuse_repeats, &mut table)?;
+    let mut single_cell = CodeLength::default();
+    single_cell_bf(bytes, options.target, options.cleanup, options.portable, &mut single_cell)?;
+    if single_cell.0 < table.0 {
+        Ok(TextCodeStyle::SingleCell)
+    } else {
+        Ok(TextCodeStyle::Table)
+    }
+}
+
+/// Write the annotated code of [table_bf] (see [TextOptions::annotate]).
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `target` - What the generated code is optimized for.
+/// * `cleanup` - Whether to clear the cells and return the data pointer to the first cell after printing.
+/// * `max_cells` - The most cells the code may use, if limited (at least one cell more than the scratch cells).
+/// * `portable` - Whether the code never wraps the cells around (see [TextOptions::portable]).
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * [fmt::Result] - The result of writing.
+fn annotated_table_bf<W: fmt::Write>(bytes: &[u8], target: CostTarget, cleanup: bool, max_cells: Option<usize>, portable: bool, out: &mut W) -> fmt::Result {
+    let store_order = store_order(bytes, target, max_cells, portable);
+
+    // each byte is stored from the cell where the previous one left the data pointer
+    for (i, &byte) in store_order.iter().enumerate() {
+        out.write_str(&store_bf_bytes(&[byte], target, portable))?;
+        write_comment(&format!("cell {} holds {}", i, byte_label(byte)), out)?;
+    }
+
+    let (mut cells, mut position) = (store_order.clone(), store_order.len());
+    for &byte in bytes {
+        (cells, position) = print_bf_bytes(&[byte], &cells, position, target, portable, out)?;
+        write_comment(&format!("prints {}", byte_label(byte)), out)?;
+    }
+
+    if cleanup && !cells.is_empty() {
+        clear_table_bf(&cells, position, target, portable, out)?;
+        write_comment("clears the cells", out)?;
+    }
+
+    Ok(())
+}
+
+/// Write the annotated code of [single_cell_bf] (see [TextOptions::annotate]).
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `target` - What the generated code is optimized for.
+/// * `cleanup` - Whether to clear the cell after printing (the data pointer stays at it).
+/// * `portable` - Whether the code never wraps the cell around (see [TextOptions::portable]).
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * [fmt::Result] - The result of writing.
+fn annotated_single_cell_bf<W: fmt::Write>(bytes: &[u8], target: CostTarget, cleanup: bool, portable: bool, out: &mut W) -> fmt::Result {
+    let mut cell = 0_u8;
+    for &byte in bytes {
+        out.write_str(&add_bf(cell_change(cell, byte, portable), target, portable))?;
+        out.write_char('.')?;
+        write_comment(&format!("prints {}", byte_label(byte)), out)?;
+        cell = byte;
+    }
+    if cleanup && !bytes.is_empty() {
+        out.write_str(&clear_bf(cell, target, portable))?;
+        write_comment("clears the cell", out)?;
+    }
+    Ok(())
+}
+
+/// Write the comment ending the line of the annotated code (see [TextOptions::annotate]).
+/// The commands (and `#`) in the text are replaced by `_`, so the comment never changes what the code does.
+/// # Arguments
+/// * `text` - The text of the comment.
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * [fmt::Result] - The result of writing.
+fn write_comment<W: fmt::Write>(text: &str, out: &mut W) -> fmt::Result {
+    let sanitized: String = text.chars()
+        .map(|c| if matches!(c, '+' | '-' | '<' | '>' | '[' | ']' | ',' | '.' | '#') { '_' } else { c })
+        .collect();
+    out.write_char(' ')?;
+    out.write_str(&sanitized)?;
+    out.write_char('\n')
+}
+
+/// Describe the byte in the comment of the annotated code (see [TextOptions::annotate]),
+/// the commands and the whitespace by their names (e.g. `plus sign (43)`, `'H' (72)`).
+/// # Arguments
+/// * `byte` - The byte.
+/// # Returns
+/// * [String] - The description.
+fn byte_label(byte: u8) -> String {
+    let name = match byte {
+        b'+' => "plus sign",
+        b'-' => "minus sign",
+        b'<' => "less than sign",
+        b'>' => "greater than sign",
+        b'[' => "left bracket",
+        b']' => "right bracket",
+        b',' => "comma",
+        b'.' => "period",
+        b'#' => "number sign",
+        b' ' => "space",
+        b'\t' => "tab",
+        b'\n' => "newline",
+        b'\r' => "carriage return",
+        byte if byte.is_ascii_graphic() => return format!("'{}' ({})", char::from(byte), byte),
+        _ => "byte",
+    };
+    format!("{} ({})", name, byte)
+}
+
+/// Calculate the fewest cells which the code of the style can use (see [TextOptions::max_cells]).
+/// # Arguments
+/// * `style` - The strategy of the code.
+/// * `target` - What the code is optimized for.
+/// # Returns
+/// * `usize` - The number of cells.
+fn min_cells(style: TextCodeStyle, target: CostTarget) -> usize {
+    let table = 1 + table_scratch_cells(target);
+    // the multiplication loop of the single cell uses the cell to its right
+    let single_cell = if target == CostTarget::CodeSize { 2 } else { 1 };
+    match style {
+        TextCodeStyle::Table => table,
+        TextCodeStyle::SingleCell => single_cell,
+        TextCodeStyle::Auto => table.min(single_cell),
+    }
+}
+
+/// Calculate the number of the scratch cells used after the table.
+/// For [CostTarget::CodeSize], the multiplication loops of the stored bytes and the counted loops of [print_bf_bytes]
+/// use the two cells after the table, for [CostTarget::RunTime] no loops are generated (but the storing leaves the data pointer
+/// at the cell after the table).
+/// # Arguments
+/// * `target` - What the code is optimized for.
+/// # Returns
+/// * `usize` - The number of cells.
+fn table_scratch_cells(target: CostTarget) -> usize {
+    match target {
+        CostTarget::CodeSize => 2,
+        CostTarget::RunTime => 1,
+    }
+}
+
+/// Write Brainfuck code that prints the provided bytes, each distinct byte is stored in its own cell.
+///
+/// If the table doesn't fit into the `max_cells` (with its scratch cells), the base values of the groups of the bytes are stored instead
+/// (see [bucket_bases]), the cells are adjusted to the printed bytes and keep the new values.
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `target` - What the generated code is optimized for.
+/// * `cleanup` - Whether to clear the cells and return the data pointer to the first cell after printing.
+/// * `max_cells` - The most cells the code may use, if limited (at least one cell more than the scratch cells).
+/// * `portable` - Whether the code never wraps the cells around (see [TextOptions::portable]).
+/// * `reuse_repeats` - Whether to print the repeated blocks by the counted loops (see [TextOptions::reuse_repeats]).
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * [fmt::Result] - The result of writing.
+fn table_bf<W: fmt::Write>(
+    bytes: &[u8],
+    target: CostTarget,
+    cleanup: bool,
+    max_cells: Option<usize>,
+    portable: bool,
+    reuse_repeats: bool,
+    out: &mut W,
+) -> fmt::Result {
+    let store_order = store_order(bytes, target, max_cells, portable);
+
+    // write code for storing bytes into array
+    out.write_str(&store_bf_bytes(&store_order, target, portable))?;
+
+    // write code for printing bytes from the array (the repeated blocks by the loops, if they fit after the table)
+    let repeats_fit = max_cells.is_none_or(|max_cells| store_order.len() + REPEAT_CELLS <= max_cells);
+    let (cells, position) = if reuse_repeats && target == CostTarget::CodeSize && repeats_fit {
+        print_bf_repeats(bytes, &store_order, portable, out)?
+    } else {
+        print_bf_bytes(bytes, &store_order, store_order.len(), target, portable, out)?
+    };
+
+    if cleanup {
+        clear_table_bf(&cells, position, target, portable, out)?;
+    }
+
+    Ok(())
+}
+
+/// Choose the bytes stored in the table of [table_bf], in their order.
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `target` - What the generated code is optimized for.
+/// * `max_cells` - The most cells the code may use, if limited (at least one cell more than the scratch cells).
+/// * `portable` - Whether the code never wraps the cells around (see [TextOptions::portable]).
+/// # Returns
+/// * `Vec<u8>` - The stored bytes (the base values of the groups of the bytes, if the distinct bytes don't fit into the `max_cells`).
+fn store_order(bytes: &[u8], target: CostTarget, max_cells: Option<usize>, portable: bool) -> Vec<u8> {
+    let mut distinct = [false; u8::MAX as usize + 1];
+    for &byte in bytes {
+        distinct[byte as usize] = true;
+    }
+    let distinct_count = distinct.iter().filter(|&&present| present).count();
+    match max_cells.map(|max_cells| max_cells - table_scratch_cells(target)) {
+        Some(buckets) if distinct_count > buckets => bucket_bases(bytes, buckets),
+        _ => table_order(bytes, target, portable),
+    }
+}
+
+/// Write the code clearing the cells of the table from the last non-zero one to the first one
+/// (the cells after the table are left zero), the data pointer is left at the first cell.
+/// # Arguments
+/// * `cells` - The current values of the cells of the table.
+/// * `position` - The position of the data pointer.
+/// * `target` - What the generated code is optimized for.
+/// * `portable` - Whether the code never wraps the cells around (see [TextOptions::portable]).
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * [fmt::Result] - The result of writing.
+fn clear_table_bf<W: fmt::Write>(cells: &[u8], position: usize, target: CostTarget, portable: bool, out: &mut W) -> fmt::Result {
+    if cells.is_empty() {
+        return Ok(());
+    }
+    let last = cells.iter().rposition(|&value| value != 0).unwrap_or(0);
+    out.write_str(&move_bf(last as isize - position as isize))?;
+    for i in (0..=last).rev() {
+        out.write_str(&clear_bf(cells[i], target, portable))?;
+        if i > 0 {
+            out.write_char('<')?;
+        }
+    }
+    Ok(())
+}
+
+/// The number of the cells after the table used by [print_bf_repeats] (the counter of [print_bf_bytes] and its multiplication cell,
+/// then the counter of the repeats and its multiplication cell).
+const REPEAT_CELLS: usize = 4;
+
+/// The longest repeated block looked for by [find_repeat].
+const MAX_REPEAT_BLOCK: usize = 64;
+
+/// Write the code printing out the bytes from the table (see [print_bf_bytes]), the blocks repeated right after each other
+/// are printed by a counted loop.
+///
+/// The counter of the repeats is the second cell after the counter of [print_bf_bytes] (the cell after it is used
+/// by its multiplication loop). The body of the loop starts and ends at the counter and restores the cells of the table
+/// which it changed, so each iteration runs the same. The loop is written only if it is shorter than printing
+/// the repeated blocks one by one.
+/// # Arguments
+/// * `bytes` - The slice of bytes to print out.
+/// * `store_ord` - The slice of bytes that are stored in the array (the data pointer is at the cell after them).
+/// * `portable` - Whether the code never wraps the cells around (see [TextOptions::portable]).
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * `Vec<u8>` - The values of the cells of the table after printing, if OK.
+/// * `usize` - The position of the data pointer after printing, if OK.
+/// * [fmt::Error] - The error of the writer.
+fn print_bf_repeats<W: fmt::Write>(bytes: &[u8], store_ord: &[u8], portable: bool, out: &mut W) -> Result<(Vec<u8>, usize), fmt::Error> {
+    let target = CostTarget::CodeSize;
+    let mut cells = store_ord.to_vec();
+    let mut position = store_ord.len();
+    let mut plain_start = 0;
+    // the estimated state before the byte at `estimated_at` (advanced without reprinting the preceding bytes)
+    let (mut estimated_cells, mut estimated_position, mut estimated_at) = (cells.clone(), position, 0);
+    let mut i = 0;
+    while i < bytes.len() {
+        let Some((block_len, count)) = find_repeat(&bytes[i..]) else {
+            i += 1;
+            continue;
+        };
+        let repeated = &bytes[i..i + block_len * count];
+
+        // the loop is compared with printing the blocks one by one (both after the preceding bytes)
+        let mut skipped = CodeLength::default();
+        (estimated_cells, estimated_position) = print_bf_bytes(&bytes[estimated_at..i], &estimated_cells, estimated_position, target, portable, &mut skipped)?;
+        estimated_at = i;
+        let mut plain = CodeLength::default();
+        print_bf_bytes(repeated, &estimated_cells, estimated_position, target, portable, &mut plain)?;
+        let mut looped = CodeLength::default();
+        repeat_bf(&repeated[..block_len], count, &estimated_cells, estimated_position, portable, &mut looped)?;
+        if looped.0 >= plain.0 {
+            i += 1;
+            continue;
+        }
+
+        (cells, position) = print_bf_bytes(&bytes[plain_start..i], &cells, position, target, portable, out)?;
+        position = repeat_bf(&repeated[..block_len], count, &cells, position, portable, out)?;
+        i += repeated.len();
+        plain_start = i;
+        (estimated_cells, estimated_position, estimated_at) = (cells.clone(), position, i);
+    }
+
+    print_bf_bytes(&bytes[plain_start..], &cells, position, target, portable, out)
+}
+
+/// Find the block repeated right after itself at the start of the bytes (the block of at least 2 bytes,
+/// repeated the most bytes, on ties the shortest block).
+/// # Arguments
+/// * `bytes` - The bytes.
+/// # Returns
+/// * `Option<(usize, usize)>` - The length of the block and the number of its repeats (at least 2), if any.
+fn find_repeat(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+    for block_len in 2..=MAX_REPEAT_BLOCK.min(bytes.len() / 2) {
+        let block = &bytes[..block_len];
+        let count = bytes.chunks_exact(block_len).take_while(|&chunk| chunk == block).count();
+        if count >= 2 && best.is_none_or(|(len, repeats)| block_len * (count - 1) > len * (repeats - 1)) {
+            best = Some((block_len, count));
+        }
+    }
+    best
+}
+
+/// Write the counted loop printing the block repeatedly (see [print_bf_repeats]).
+/// The cells of the table are left unchanged and the data pointer is left at the counter of the repeats.
+/// # Arguments
+/// * `block` - The repeated block.
+/// * `count` - The number of the repeats.
+/// * `cells` - The current values of the cells of the table.
+/// * `position` - The position of the data pointer.
+/// * `portable` - Whether the code never wraps the cells around (see [TextOptions::portable]).
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * `usize` - The position of the data pointer after printing, if OK.
+/// * [fmt::Error] - The error of the writer.
+fn repeat_bf<W: fmt::Write>(block: &[u8], count: usize, cells: &[u8], position: usize, portable: bool, out: &mut W) -> Result<usize, fmt::Error> {
+    let counter_pos = cells.len() + 2;
+    out.write_str(&move_bf(counter_pos as isize - position as isize))?;
+
+    // the counter counts down from at most 255 (set to -1 for 255 repeats, unless portable)
+    let mut remaining = count;
+    while remaining > 0 {
+        let part = remaining.min(u8::MAX as usize);
+        let count = if portable { part as i16 } else { i16::from(part as u8 as i8) };
+        out.write_str(&add_bf(count, CostTarget::CodeSize, portable))?;
+        out.write_char('[')?;
+
+        let (body_cells, body_position) = print_bf_bytes(block, cells, counter_pos, CostTarget::CodeSize, portable, out)?;
+        let mut curr_pos = body_position;
+        for (i, (&body_value, &value)) in body_cells.iter().zip(cells).enumerate() {
+            if body_value != value {
+                out.write_str(&move_bf(i as isize - curr_pos as isize))?;
+                out.write_str(&add_bf(cell_change(body_value, value, portable), CostTarget::RunTime, portable))?;
+                curr_pos = i;
+            }
+        }
+        out.write_str(&move_bf(counter_pos as isize - curr_pos as isize))?;
+        out.write_str("-]")?;
+
+        remaining -= part;
+    }
+
+    Ok(counter_pos)
+}
+
+/// Order the distinct bytes stored in the table, so that the data pointer walks less while printing them.
+///
+/// The bytes are first ordered by their frequency (the organ-pipe order, the most frequent in the middle), then the neighbouring
+/// bytes are swapped while that shortens the walk between the consecutively printed bytes (and from the end of the table,
+/// where the printing starts, to the first one). The order is used only if its printing code is shorter than the printing code
+/// of the sorted order, in which the nearby cells hold the close values (see [print_bf_bytes]).
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `target` - What the generated code is optimized for.
+/// * `portable` - Whether the code never wraps the cells around (see [TextOptions::portable]).
+/// # Returns
+/// * `Vec<u8>` - The distinct bytes in the order in which they are stored.
+fn table_order(bytes: &[u8], target: CostTarget, portable: bool) -> Vec<u8> {
+    let mut sorted = bytes.to_vec();
+    sorted.sort();
+    sorted.dedup();
+
+    // how many times each byte is printed and how many times the pointer walks between each two bytes
+    let mut frequency = [0_u64; u8::MAX as usize + 1];
+    let mut transitions = vec![[0_u64; u8::MAX as usize + 1]; u8::MAX as usize + 1];
+    for &byte in bytes {
+        frequency[byte as usize] += 1;
+    }
+    for pair in bytes.windows(2) {
+        if pair[0] != pair[1] {
+            transitions[pair[0] as usize][pair[1] as usize] += 1;
+            transitions[pair[1] as usize][pair[0] as usize] += 1;
+        }
+    }
+
+    // the organ-pipe order (the ties are kept sorted)
+    let mut by_frequency = sorted.clone();
+    by_frequency.sort_by_key(|&byte| Reverse(frequency[byte as usize]));
+    let mut organ_pipe = VecDeque::with_capacity(by_frequency.len());
+    for (i, &byte) in by_frequency.iter().enumerate() {
+        if i % 2 == 0 {
+            organ_pipe.push_back(byte);
+        } else {
+            organ_pipe.push_front(byte);
+        }
+    }
+    let mut order = Vec::from(organ_pipe);
+
+    // swap the neighbours while the walk gets shorter (only the walks to and from the swapped bytes change)
+    let start = order.len() as i64;
+    let first = bytes.first().copied();
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 0..order.len().saturating_sub(1) {
+            let (left, right) = (order[i], order[i + 1]);
+            let (old_left, old_right) = (i as i64, i as i64 + 1);
+            let mut change = 0_i64;
+            for (j, &other) in order.iter().enumerate() {
+                if other == left || other == right {
+                    continue;
+                }
+                let j = j as i64;
+                change += transitions[left as usize][other as usize] as i64 * ((old_right - j).abs() - (old_left - j).abs());
+                change += transitions[right as usize][other as usize] as i64 * ((old_left - j).abs() - (old_right - j).abs());
+            }
+            if first == Some(left) {
+                change += (start - old_right).abs() - (start - old_left).abs();
+            } else if first == Some(right) {
+                change += (start - old_left).abs() - (start - old_right).abs();
+            }
+            if change < 0 {
+                order.swap(i, i + 1);
+                improved = true;
+            }
+        }
+    }
+
+    let print_length = |store_ord: &[u8]| {
+        let mut length = CodeLength::default();
+        // counting never fails
+        let _ = print_bf_bytes(bytes, store_ord, store_ord.len(), target, portable, &mut length);
+        length.0
+    };
+    if print_length(&order) < print_length(&sorted) { order } else { sorted }
+}
+
+/// Group the distinct bytes by their value and calculate the base value of each group, which is stored instead of the bytes.
+///
+/// The groups are the consecutive ranges of the sorted bytes, chosen (by dynamic programming) so that the total adjustment
+/// of the bases to the printed bytes is the smallest, each base is the median of its group weighted by the frequency of the bytes
+/// (the adjusted cells keep the new values, so this only estimates the adjustments of [print_bf_bytes]).
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `buckets` - The most groups (at least one).
+/// # Returns
+/// * `Vec<u8>` - The sorted base values.
+fn bucket_bases(bytes: &[u8], buckets: usize) -> Vec<u8> {
+    let mut frequency = [0_u64; u8::MAX as usize + 1];
+    for &byte in bytes {
+        frequency[byte as usize] += 1;
+    }
+    let values = (0..=u8::MAX).filter(|&byte| frequency[byte as usize] > 0).collect::<Vec<_>>();
+    let n = values.len();
+    let buckets = buckets.min(n);
+
+    // the weighted median of the values[i..=j] and the total adjustment to it
+    let mut groups = vec![vec![(0_u8, 0_u64); n]; n];
+    for i in 0..n {
+        for j in i..n {
+            let total = values[i..=j].iter().map(|&value| frequency[value as usize]).sum::<u64>();
+            let mut count = 0;
+            let median = values[i..=j].iter()
+                .copied()
+                .find(|&value| {
+                    count += frequency[value as usize];
+                    2 * count >= total
+                })
+                .unwrap();
+            let cost = values[i..=j].iter().map(|&value| frequency[value as usize] * value.abs_diff(median) as u64).sum();
+            groups[i][j] = (median, cost);
+        }
+    }
+
+    // the cheapest split of the first j values into g groups, and the start of its last group
+    let mut best = vec![vec![(u64::MAX, 0_usize); n + 1]; buckets + 1];
+    best[0][0] = (0, 0);
+    for g in 1..=buckets {
+        for j in g..=n {
+            for start in (g - 1)..j {
+                let (prev_cost, _) = best[g - 1][start];
+                if prev_cost == u64::MAX {
+                    continue;
+                }
+                let cost = prev_cost + groups[start][j - 1].1;
+                if cost < best[g][j].0 {
+                    best[g][j] = (cost, start);
+                }
+            }
+        }
+    }
+
+    let mut bases = Vec::with_capacity(buckets);
+    let mut end = n;
+    for g in (1..=buckets).rev() {
+        let start = best[g][end].1;
+        bases.push(groups[start][end - 1].0);
+        end = start;
+    }
+    bases.reverse();
+    bases
+}
+
+/// Order the distinct bytes by their frequency in the ascending order (the ties by their value),
+/// so the most frequent byte is stored in the last cell (see [text_2_bf_huffman]).
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// # Returns
+/// * `Vec<u8>` - The distinct bytes in the order in which they are stored.
+fn frequency_order(bytes: &[u8]) -> Vec<u8> {
+    let mut frequency = [0_u64; u8::MAX as usize + 1];
+    for &byte in bytes {
+        frequency[byte as usize] += 1;
+    }
+
+    let mut order = (0..=u8::MAX).filter(|&byte| frequency[byte as usize] > 0).collect::<Vec<_>>();
+    order.sort_by_key(|&byte| frequency[byte as usize]);
+    order
+}
+
+/// Write Brainfuck code that prints the provided bytes from a single cell.
+/// The cell is changed from the previous byte to the next one before each print (it wraps around, unless portable),
+/// the cell to its right is used as the counter of the multiplication loop.
+/// # Arguments
+/// * `bytes` - The bytes that Brainfuck code should print.
+/// * `target` - What the generated code is optimized for.
+/// * `cleanup` - Whether to clear the cell after printing (the data pointer stays at it).
+/// * `portable` - Whether the code never wraps the cell around (see [TextOptions::portable]).
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * [fmt::Result] - The result of writing.
+fn single_cell_bf<W: fmt::Write>(bytes: &[u8], target: CostTarget, cleanup: bool, portable: bool, out: &mut W) -> fmt::Result {
+    let mut cell = 0_u8;
+    for &byte in bytes {
+        // change the cell by the shorter way around (or by the difference, if portable)
+        out.write_str(&add_bf(cell_change(cell, byte, portable), target, portable))?;
+        out.write_char('.')?;
+        cell = byte;
+    }
+    if cleanup {
+        out.write_str(&clear_bf(cell, target, portable))?;
+    }
+    Ok(())
+}
+
+/// Generate Brainfuck code that clears the current cell holding the value.
+/// The cell is cleared by a loop (`[-]`, or `[+]` for the values above 127 unless portable) if that is shorter
+/// than the plain `-`/`+` commands (only for [CostTarget::CodeSize], the loop always runs longer).
+/// # Arguments
+/// * `value` - The value of the cell.
+/// * `target` - What the generated code is optimized for.
+/// * `portable` - Whether the code never wraps the cell around (see [TextOptions::portable]).
+/// # Returns
+/// * [String] - The Brainfuck code.
+fn clear_bf(value: u8, target: CostTarget, portable: bool) -> String {
+    let (adjust, n) = if value < 128 || portable { ("-", value as usize) } else { ("+", 256 - value as usize) };
+    if target == CostTarget::CodeSize && n > 3 {
+        format!("[{}]", adjust)
+    } else {
+        adjust.repeat(n)
+    }
+}
+
+/// Generate Brainfuck code that adds the value to the current cell (the cell to its right must be zero and is left zero).
+/// The value is added by a multiplication loop (`>+++[<++++>-]<`) and the remaining `+`/`-` commands if that is shorter
+/// (only for [CostTarget::CodeSize], the loop always runs longer).
+/// # Arguments
+/// If portable, the loop never adds more than the value (the remainder is added in the same direction),
+/// so the cell changes monotonically and doesn't wrap around if its final value doesn't.
+/// # Arguments
+/// * `value` - The value to add (in the range `-255..=255`, at most `-128..=127` unless portable).
+/// * `target` - What the generated code is optimized for.
+/// * `portable` - Whether the code never wraps the cell around (see [TextOptions::portable]).
+/// # Returns
+/// * [String] - The Brainfuck code.
+fn add_bf(value: i16, target: CostTarget, portable: bool) -> String {
+    let (plus, minus) = if value < 0 { ('-', '+') } else { ('+', '-') };
+    let n = value.unsigned_abs() as usize;
+    if target == CostTarget::RunTime {
+        return plus.to_string().repeat(n);
+    }
+
+    // find the factors for which the loop with the remainder is the shortest (the loop itself takes 6 commands)
+    let mut best: Option<(usize, usize, isize)> = None;
+    let mut best_len = n;
+    for f1 in 2..=n {
+        for f2 in 2..=(2 * n / f1) {
+            let rem = n as isize - (f1 * f2) as isize;
+            if portable && rem < 0 {
+                continue;
+            }
+            let len = f1 + f2 + 6 + rem.unsigned_abs();
+            if len < best_len {
+                best = Some((f1, f2, rem));
+                best_len = len;
+            }
+        }
+    }
+
+    match best {
+        Some((f1, f2, rem)) => {
+            let mut bf_code = String::with_capacity(best_len);
+            bf_code.push('>');
+            bf_code.push_str(&"+".repeat(f1));
+            bf_code.push_str("[<");
+            bf_code.push_str(&plus.to_string().repeat(f2));
+            bf_code.push_str(">-]<");
+            let rem_char = if rem < 0 { minus } else { plus };
+            bf_code.push_str(&rem_char.to_string().repeat(rem.unsigned_abs()));
+            bf_code
+        },
+        None => plus.to_string().repeat(n),
+    }
+}
+
+/// Converts a string to a vector of its UTF-8 bytes.
+/// # Arguments
+/// * `text` - The text to convert.
+/// * `newlines` - How the line endings are converted.
+/// # Returns
+/// * `Vec<u8>` - The UTF-8 bytes.
+fn utf8_bytes(text: &str, newlines: NewlinePolicy) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut text_bytes = text.bytes().peekable();
+    while let Some(byte) = text_bytes.next() {
+        push_byte(&mut bytes, byte, text_bytes.peek().copied(), newlines);
+    }
+    bytes
+}
+
+/// Push the byte of the text converted by the newline policy to the bytes.
+/// # Arguments
+/// * `bytes` - The converted bytes.
+/// * `byte` - The byte of the text.
+/// * `next` - The next byte of the text, if any.
+/// * `newlines` - How the line endings are converted.
+fn push_byte(bytes: &mut Vec<u8>, byte: u8, next: Option<u8>, newlines: NewlinePolicy) {
+    match (byte, newlines) {
+        (b'\r', _) if skips_cr(newlines, next) => (),
+        (b'\n', NewlinePolicy::Crlf) => bytes.extend_from_slice(b"\r\n"),
+        _ => bytes.push(byte),
+    }
+}
+
+/// Check whether the `\r` character of the text is skipped by the newline policy.
+/// # Arguments
+/// * `newlines` - How the line endings are converted.
+/// * `next` - The byte after the `\r`, if any.
+/// # Returns
+/// * `bool` - Whether the `\r` is skipped.
+fn skips_cr(newlines: NewlinePolicy, next: Option<u8>) -> bool {
+    match newlines {
+        NewlinePolicy::Preserve => false,
+        NewlinePolicy::CrlfToLf => next == Some(b'\n'),
+        NewlinePolicy::Lf | NewlinePolicy::Crlf => true,
+    }
+}
+
+/// Converts a string to a vector of bytes.
+/// Each character is converted to its ASCII value (single byte).
+/// Characters that are not ASCII are not allowed.
+/// # Arguments
+/// * `text` - The text to convert.
+/// * `newlines` - How the line endings are converted (the skipped `\r` characters aren't counted as columns).
+/// # Returns
+/// * `Result<Vec<u8>, Error>` - The vector of bytes if the conversion was successful, otherwise an error.
+/// # Errors
+/// * `Error::NonASCIIChar` - If a non-ASCII character is found.
+fn text_2_bytes(text: &str, newlines: NewlinePolicy) -> Result<Vec<u8>, Error> {
+    let mut line = 1;
+    let mut column = 1;
+    let mut bytes = Vec::new();
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii() {
+            let next = chars.peek().and_then(|&next| u8::try_from(next).ok());
+            if c != '\r' || !skips_cr(newlines, next) {
+                push_byte(&mut bytes, c as u8, next, newlines);
+                if c == '\n' {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
+                }
+            }
+        } else {
+            return Err(Error::NonASCIIChar(c, line, column))
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Generate Brainfuck code for storing sequence of bytes into array
+/// Data pointer is left at the index == bytes.len()
+///
+/// For [CostTarget::CodeSize], each byte is stored by its shortest [Construction] (see [CONSTANT_TABLE]).
+/// For [CostTarget::RunTime], the bytes are stored by plain `+`/`-` commands instead of the multiplication loops
+/// (only by `+` if portable).
+/// # Arguments
+/// * `bytes` - The slice of bytes to store into array
+/// * `target` - What the generated code is optimized for.
+/// * `portable` - Whether the code never wraps the cells around (see [TextOptions::portable]).
+/// # Returns
+/// * [String] - The Brainfuck code.
+fn store_bf_bytes(bytes: &[u8], target: CostTarget, portable: bool) -> String {
+    let mut store = String::new();
+
+    for &byte in bytes {
+        match (target, portable) {
+            (CostTarget::CodeSize, false) => store.push_str(&CONSTANT_TABLE[byte as usize].to_bf()),
+            (CostTarget::CodeSize, true) => store.push_str(&PORTABLE_CONSTANT_TABLE[byte as usize].to_bf()),
+            (CostTarget::RunTime, false) => store.push_str(&Construction::Plain(byte as i8).to_bf()),
+            (CostTarget::RunTime, true) => {
+                store.push_str(&"+".repeat(byte as usize));
+                store.push('>');
+            },
+        }
+    }
+
+    store
+}
+
+/// Write the code printing out the text_bytes from the sequence of bytes stored in the array
+/// (the code is written byte by byte of the text, so only the table is kept in memory).
+///
+/// For each byte, the cell for which moving to it and adjusting it to the byte by `+`/`-` takes the fewest commands is printed.
+/// That is usually the cell holding the byte, but a nearby cell holding a close value may be cheaper than a long walk
+/// (the adjusted cell keeps the new value, which may serve the following bytes). In the sorted table, the cells between
+/// two values differ at least by their distance, so the walk is never longer, but in any other order the adjustments pay off.
+///
+/// The runs of the same byte are printed by a counted loop (`[<<.>>-]`) if that is shorter than repeating `.`,
+/// the counter is the cell right after the table (`store_ord.len()`, it must be zero, the same as the cell after it
+/// used by its multiplication loop), and it is left zero. For [CostTarget::RunTime] the loops aren't used,
+/// each of their iterations runs longer than a single `.`.
+/// # Arguments
+/// * `text_bytes` - The slice of bytes to print out.
+/// * `store_ord` - The slice of bytes that are stored in the array.
+/// * `position` - The position of the data pointer in the array.
+/// * `target` - What the generated code is optimized for.
+/// * `portable` - Whether the code never wraps the cells around (see [TextOptions::portable]).
+/// * `out` - The writer to which the code is written.
+/// # Returns
+/// * `Vec<u8>` - The values of the cells of the table after printing, if OK.
+/// * `usize` - The position of the data pointer after printing, if OK.
+/// * [fmt::Error] - The error of the writer.
+fn print_bf_bytes<W: fmt::Write>(text_bytes: &[u8], store_ord: &[u8], position: usize, target: CostTarget, portable: bool, out: &mut W) -> Result<(Vec<u8>, usize), fmt::Error> {
+    // the current values of the cells
+    let mut cells = store_ord.to_vec();
+    let counter_pos = store_ord.len() as isize;
+    let mut curr_pos = position as isize;
+    for run in text_bytes.chunk_by(|a, b| a == b) {
+        let byte = run[0];
+        // the cheapest cell and the change of its value (on ties, the cell changed the least)
+        let (new_pos, delta) = cells.iter()
+            .enumerate()
+            .map(|(i, &value)| (i as isize, cell_change(value, byte, portable)))
+            .min_by_key(|&(i, delta)| ((i - curr_pos).unsigned_abs() + delta.unsigned_abs() as usize, delta.unsigned_abs()))
+            .unwrap();
+
+        // the pointer is moved to the unchanged cell only by the printing (the loop may start from where it is)
+        if delta != 0 {
+            out.write_str(&move_bf(new_pos - curr_pos))?;
+            let adjust = if delta < 0 { "-" } else { "+" };
+            out.write_str(&adjust.repeat(delta.unsigned_abs() as usize))?;
+            cells[new_pos as usize] = byte;
+            curr_pos = new_pos;
+        }
+
+        // the counter counts down from at most 255
+        for part in run.chunks(u8::MAX as usize) {
+            let to_cell = move_bf(new_pos - counter_pos);
+            let mut run_loop = move_bf(counter_pos - curr_pos);
+            // the counter of 255 iterations is set to -1, unless portable
+            let count = if portable { part.len() as i16 } else { i16::from(part.len() as u8 as i8) };
+            run_loop.push_str(&add_bf(count, target, portable));
+            run_loop.push('[');
+            run_loop.push_str(&to_cell);
+            run_loop.push('.');
+            run_loop.push_str(&move_bf(counter_pos - new_pos));
+            run_loop.push_str("-]");
+
+            let mut run_dots = move_bf(new_pos - curr_pos);
+            run_dots.push_str(&".".repeat(part.len()));
+
+            if target == CostTarget::CodeSize && run_loop.len() < run_dots.len() {
+                out.write_str(&run_loop)?;
+                curr_pos = counter_pos;
+            } else {
+                out.write_str(&run_dots)?;
+                curr_pos = new_pos;
+            }
+        }
+    }
+    Ok((cells, curr_pos as usize))
+}
+
+/// Calculate the change of the cell from its value to the byte.
+/// # Arguments
+/// * `value` - The value of the cell.
+/// * `byte` - The new value of the cell.
+/// * `portable` - Whether the cell must not wrap around (otherwise it is changed the shorter way around).
+/// # Returns
+/// * `i16` - The change (in the range `-128..=127` unless portable).
+fn cell_change(value: u8, byte: u8, portable: bool) -> i16 {
+    if portable {
+        i16::from(byte) - i16::from(value)
+    } else {
+        i16::from(byte.wrapping_sub(value) as i8)
+    }
+}
+
+/// Generate Brainfuck code that moves the data pointer by the distance.
+/// # Arguments
+/// * `distance` - The distance to move (negative to the left).
+/// # Returns
+/// * [String] - The Brainfuck code.
+fn move_bf(distance: isize) -> String {
+    let dir = if distance < 0 { "<" } else { ">" };
+    dir.repeat(distance.unsigned_abs())
+}
+
+/// The way of storing a byte into an empty cell (the two cells to its right must be zero and are left zero).
+/// The byte is the product of the factors (if any) plus the difference (wrapping around).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Construction {
+    /// The plain `+`/`-` commands (`+++>`).
+    Plain(i8),
+    /// The difference and the multiplication loop (`++>+++[<++++>-]`).
+    Product(u8, u8, i8),
+    /// The difference and two nested multiplication loops (`-->++[>+++[<<++++>>-]<-]`).
+    NestedProduct(u8, u8, u8, i8),
+}
+
+impl Construction {
+    /// Calculate the value of the stored byte.
+    /// # Returns
+    /// * `u8` - The value of the byte.
+    fn value(self) -> u8 {
+        match self {
+            Construction::Plain(diff) => diff as u8,
+            Construction::Product(a, b, diff) => a.wrapping_mul(b).wrapping_add_signed(diff),
+            Construction::NestedProduct(a, b, c, diff) => a.wrapping_mul(b).wrapping_mul(c).wrapping_add_signed(diff),
+        }
+    }
+
+    /// Calculate the number of Brainfuck commands of the construction (the length of [Construction::to_bf]).
+    /// # Returns
+    /// * `usize` - The number of commands.
+    fn cost(self) -> usize {
+        match self {
+            Construction::Plain(diff) => diff.unsigned_abs() as usize + 1,
+            Construction::Product(a, b, diff) => diff.unsigned_abs() as usize + a as usize + b as usize + 6,
+            Construction::NestedProduct(a, b, c, diff) => diff.unsigned_abs() as usize + a as usize + b as usize + c as usize + 13,
+        }
+    }
+
+    /// Generate Brainfuck code that stores the byte into the current cell.
+    /// The difference is added first (directly to the cell), then the product is added by the loops.
+    /// The data pointer is left at the cell to the right.
+    /// # Returns
+    /// * [String] - The Brainfuck code.
+    fn to_bf(self) -> String {
+        let diff_bf = |diff: i8| {
+            let adjust = if diff < 0 { "-" } else { "+" };
+            adjust.repeat(diff.unsigned_abs() as usize)
+        };
+        match self {
+            Construction::Plain(diff) => format!("{}>", diff_bf(diff)),
+            Construction::Product(a, b, diff) => format!("{}>{}[<{}>-]", diff_bf(diff), "+".repeat(a as usize), "+".repeat(b as usize)),
+            Construction::NestedProduct(a, b, c, diff) => format!(
+                "{}>{}[>{}[<<{}>>-]<-]",
+                diff_bf(diff),
+                "+".repeat(a as usize),
+                "+".repeat(b as usize),
+                "+".repeat(c as usize),
+            ),
+        }
+    }
+}
+
+/// The shortest [Construction] of each byte (indices represent the bytes), computed once by [constant_table].
+static CONSTANT_TABLE: LazyLock<Vec<Construction>> = LazyLock::new(|| constant_table(false));
+
+/// The shortest [Construction] of each byte which doesn't wrap the cell around (see [TextOptions::portable]).
+static PORTABLE_CONSTANT_TABLE: LazyLock<Vec<Construction>> = LazyLock::new(|| constant_table(true));
+
+/// Generate the table of the shortest constructions of the numbers 0 to 255.
+/// Every product of two or three factors (at least 2, the product is at most 255) is considered,
+/// adjusted by the difference to the number. The cheapest factors of each product are found first,
+/// then each number picks the cheapest of the plain commands and the products around it.
+/// On a tie, the construction with fewer loops is picked (it also runs faster).
+///
+/// If portable, only the products not above the number are considered and the plain commands only for the numbers up to 127,
+/// so the difference (added first) is never negative and the cell only grows to the number.
+/// # Arguments
+/// * `portable` - Whether the constructions must not wrap the cell around (see [TextOptions::portable]).
+/// # Returns
+/// * `Vec<Construction>` - The constructions, indices represent the numbers.
+fn constant_table(portable: bool) -> Vec<Construction> {
+    // the factors with the lowest sum of each product (indices represent the products)
+    let mut products = vec![None; u8::MAX as usize + 1];
+    let mut nested_products = vec![None; u8::MAX as usize + 1];
+    for a in 2..=u8::MAX {
+        for b in a..=(u8::MAX / a) {
+            let product = (a * b) as usize;
+            if products[product].is_none_or(|(x, y)| a + b < x + y) {
+                products[product] = Some((a, b));
+            }
+            for c in b..=(u8::MAX / (a * b)) {
+                let product = (a * b * c) as usize;
+                if nested_products[product].is_none_or(|(x, y, z)| a + b + c < x + y + z) {
+                    nested_products[product] = Some((a, b, c));
+                }
+            }
+        }
+    }
+
+    (0..=u8::MAX)
+        .map(|n| {
+            // every number above 127 is at most 127 above a product (e.g. 128)
+            let mut best = if portable && n > i8::MAX as u8 { None } else { Some(Construction::Plain(n as i8)) };
+            for product in 0..=u8::MAX {
+                let diff = n.wrapping_sub(product) as i8;
+                if portable && (product > n || diff < 0) {
+                    continue;
+                }
+                let candidates = [
+                    products[product as usize].map(|(a, b)| Construction::Product(a, b, diff)),
+                    nested_products[product as usize].map(|(a, b, c)| Construction::NestedProduct(a, b, c, diff)),
+                ];
+                for candidate in candidates.into_iter().flatten() {
+                    if best.is_none_or(|best| candidate.cost() < best.cost()) {
+                        best = Some(candidate);
+                    }
+                }
+            }
+            let best = best.unwrap();
+            debug_assert_eq!(best.value(), n);
+            best
+        })
+        .collect()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use super::*;
+    use crate::interpret::{interpret, interpret_profiled, interpret_with_eof, run_interpret};
+
+    /// Checks if a number is prime.
+    /// # Arguments
+    /// * `num` - The number to check.
+    /// # Returns
+    /// * `bool` - Whether the number is prime.
+    /// * `u64` - The smallest divisor if the number is not prime, otherwise 1.
+    fn is_prime(n: u64) -> (bool, u64) {
+        assert!(n >= 2, "Number must be greater than or equal to 2.");
+
+        if n == 2 || n == 3 {
+            (true, 1)
+        } else if n % 2 == 0 {
+            (false, 2)
+        } else if n % 3 == 0 {
+            (false, 3)
+        } else {
+            for i in (5..=((n as f64).sqrt().floor() as u64)).step_by(6) {
+                if n % i == 0 {
+                    return (false, i);
+                } else if n % (i + 2) == 0 {
+                    return (false, i + 2);
+                }
+            }
+
+            (true, 1)
+        }
+    }
+
+    #[test]
+    fn test_text_2_bf() {
+        //! Test the `text_2_bf` function.
+
+        let all_printable_ascii: String = (b' '..=b'~').map(char::from).collect();
+        for (name, text) in [("hello_world", "Hello, World!"), ("brainfuck", "Brainfuck"), ("brain_fuck", "Brain\nFuck"), ("all_printable_ascii", &all_printable_ascii)] {
+            let bf_code = text_2_bf(text).unwrap();
+
+            // the generated code must print the text
+            let mut io = VecIo::default();
+            interpret(&process_code(&bf_code).unwrap(), &mut io);
+            assert_eq!(io.output, text.as_bytes());
+
+            insta::assert_snapshot!(format!("text_2_bf_{}", name), bf_code);
+        }
+    }
+
+    #[test]
+    fn test_text_2_bf_utf8() {
+        //! Test the `text_2_bf_utf8` function.
+
+        for text in ["Hello, World!", "Ćevapi, čaj i šećer", "Grüße aus Köln", "😊 🚀 ∑ 漢字", "Brain\r\nFuck"] {
+            let bf_code = text_2_bf_utf8(text);
+
+            // the generated code must print the UTF-8 bytes of the text (without '\r')
+            let mut io = VecIo::default();
+            interpret(&process_code(&bf_code).unwrap(), &mut io);
+            assert_eq!(String::from_utf8(io.output).unwrap(), text.replace('\r', ""));
+        }
+
+        // the ASCII text is converted the same as by text_2_bf
+        assert_eq!(text_2_bf_utf8("Brain\nFuck"), text_2_bf("Brain\nFuck").unwrap());
+    }
+
+    #[test]
+    fn test_bytes_2_bf() {
+        //! Test the `bytes_2_bf` function with the binary data.
 
-    // generate the ordered bytes that will be stored in the array
-    let mut store_order = bytes.clone();
-    store_order.sort();
-    store_order.dedup();
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        let reversed: Vec<u8> = (0..=255).rev().collect();
+        for data in [&all_bytes[..], &reversed, b"\0\0\r\n\r\xFF\x80\x7F", b"\xC3\x28", b""] {
+            for style in [TextCodeStyle::Table, TextCodeStyle::SingleCell, TextCodeStyle::Auto] {
+                let options = TextOptions { style, newlines: NewlinePolicy::Lf, cleanup: true, ..TextOptions::default() };
+                let bf_code = bytes_2_bf_with_options(data, &options).unwrap();
 
-    // resulting Brainfuck code
-    let mut bf_code = String::new();
+                // the printed bytes must be the same as the data (the '\r' is not dropped)
+                let mut io = VecIo::default();
+                interpret(&process_code(&bf_code).unwrap(), &mut io);
+                assert_eq!(io.output, data);
+            }
+            assert_eq!(bf_2_bytes(&bytes_2_bf(data)).unwrap(), data);
+        }
+    }
 
-    // generate code for storing bytes into array
-    bf_code.push_str(&store_bf_bytes(&store_order));
+    /// Write the code printing out the text bytes from the array (see `print_bf_bytes`).
+    /// # Arguments
+    /// * `text_bytes` - The slice of bytes to print out.
+    /// * `store_ord` - The slice of bytes that are stored in the array.
+    /// * `position` - The position of the data pointer in the array.
+    /// # Returns
+    /// * `(String, Vec<u8>, usize)` - The Brainfuck code, the values of the cells and the position of the data pointer.
+    fn print_code(text_bytes: &[u8], store_ord: &[u8], position: usize) -> (String, Vec<u8>, usize) {
+        let mut bf_code = String::new();
+        let (cells, position) = print_bf_bytes(text_bytes, store_ord, position, CostTarget::CodeSize, false, &mut bf_code).unwrap();
+        (bf_code, cells, position)
+    }
 
-    // generate code for printing bytes from the array
-    bf_code.push_str(&print_bf_bytes(&bytes, &store_order, store_order.len()));
+    /// Check that the Brainfuck code prints the expected bytes (see `VerifyingWriter`).
+    /// # Arguments
+    /// * `bf_code` - The Brainfuck code.
+    /// * `expected` - The bytes that the code should print.
+    /// # Returns
+    /// * `Result<(), Error>` - The result of the verification.
+    fn verify_code(bf_code: &str, expected: &[u8]) -> Result<(), Error> {
+        let mut written = String::new();
+        let mut writer = VerifyingWriter::new(&mut written, expected);
+        writer.write_str(bf_code).unwrap();
+        writer.finish()
+    }
 
-    // return Brainfuck code
-    Ok(bf_code)
-}
+    /// Run the Brainfuck code (command by command, without the optimizations) and count the cells it used.
+    /// # Arguments
+    /// * `bf_code` - The Brainfuck code (without input).
+    /// # Returns
+    /// * `usize` - The highest position of the data pointer plus one.
+    fn cells_used(bf_code: &str) -> usize {
+        let code = bf_code.as_bytes();
+        let mut jumps = vec![0; code.len()];
+        let mut open = Vec::new();
+        for (i, &c) in code.iter().enumerate() {
+            if c == b'[' {
+                open.push(i);
+            } else if c == b']' {
+                let start = open.pop().unwrap();
+                jumps[start] = i;
+                jumps[i] = start;
+            }
+        }
 
-/// Converts a string to a vector of bytes.
-/// Each character is converted to its ASCII value (single byte).
-/// Characters that are not ASCII are not allowed.
-/// # Arguments
-/// * `text` - The text to convert.
-/// # Returns
-/// * `Result<Vec<u8>, Error>` - The vector of bytes if the conversion was successful, otherwise an error.
-/// # Errors
-/// * `Error::NonASCIIChar` - If a non-ASCII character is found.
-fn text_2_bytes(text: &str) -> Result<Vec<u8>, Error> {
-    let mut line = 1;
-    let mut column = 1;
-    let mut bytes = Vec::new();
+        let mut storage = vec![0_u8; STORAGE_SIZE];
+        let (mut ins_ptr, mut data_ptr, mut max_ptr) = (0, 0, 0);
+        while ins_ptr < code.len() {
+            match code[ins_ptr] {
+                b'+' => storage[data_ptr] = storage[data_ptr].wrapping_add(1),
+                b'-' => storage[data_ptr] = storage[data_ptr].wrapping_sub(1),
+                b'>' => {
+                    data_ptr += 1;
+                    max_ptr = max_ptr.max(data_ptr);
+                },
+                b'<' => data_ptr -= 1,
+                b'[' if storage[data_ptr] == 0 => ins_ptr = jumps[ins_ptr],
+                b']' if storage[data_ptr] != 0 => ins_ptr = jumps[ins_ptr],
+                _ => {},
+            }
+            ins_ptr += 1;
+        }
+        max_ptr + 1
+    }
 
-    for c in text.chars() {
-        if c.is_ascii() {
-            if c != '\r' {
-                bytes.push(c as u8);
-                if c == '\n' {
-                    line += 1;
-                    column = 1;
-                } else {
-                    column += 1;
+    /// Run the Brainfuck code under the strict dialect: the cells are bigger and don't wrap around,
+    /// leaving the range `0..=255` and moving left of the first cell are errors.
+    /// # Arguments
+    /// * `bf_code` - The Brainfuck code (without input).
+    /// # Returns
+    /// * `Result<Vec<u8>, String>` - The output, or the description of the violation.
+    fn run_strict(bf_code: &str) -> Result<Vec<u8>, String> {
+        let code = bf_code.as_bytes();
+        let mut jumps = vec![0; code.len()];
+        let mut open = Vec::new();
+        for (i, &c) in code.iter().enumerate() {
+            if c == b'[' {
+                open.push(i);
+            } else if c == b']' {
+                let start = open.pop().unwrap();
+                jumps[start] = i;
+                jumps[i] = start;
+            }
+        }
+
+        let mut storage = vec![0_i32; STORAGE_SIZE];
+        let mut output = Vec::new();
+        let (mut ins_ptr, mut data_ptr) = (0, 0_usize);
+        while ins_ptr < code.len() {
+            match code[ins_ptr] {
+                b'+' => storage[data_ptr] += 1,
+                b'-' => storage[data_ptr] -= 1,
+                b'>' => data_ptr += 1,
+                b'<' => data_ptr = data_ptr.checked_sub(1).ok_or(format!("Moved left of the first cell at {}.", ins_ptr))?,
+                b'.' => output.push(storage[data_ptr] as u8),
+                b'[' if storage[data_ptr] == 0 => ins_ptr = jumps[ins_ptr],
+                b']' if storage[data_ptr] != 0 => ins_ptr = jumps[ins_ptr],
+                _ => {},
+            }
+            if !(0..=u8::MAX as i32).contains(&storage[data_ptr]) {
+                return Err(format!("The cell {} wrapped around at {}.", data_ptr, ins_ptr));
+            }
+            ins_ptr += 1;
+        }
+        Ok(output)
+    }
+
+    #[test]
+    fn test_verify() {
+        //! Test the verification of the generated code.
+
+        let all_printable_ascii: String = (b' '..=b'~').map(char::from).collect();
+        for text in ["Hello, World!", "aaaaaaaaaa", "Brain\r\nFuck", &all_printable_ascii, ""] {
+            for style in [TextCodeStyle::Table, TextCodeStyle::SingleCell, TextCodeStyle::Auto] {
+                for target in [CostTarget::CodeSize, CostTarget::RunTime] {
+                    let options = TextOptions { style, target, verify: true, ..TextOptions::default() };
+                    let verified = text_2_bf_with_options(text, &options).unwrap();
+                    assert_eq!(verified, text_2_bf_with_options(text, &TextOptions { verify: false, ..options }).unwrap());
                 }
             }
-        } else {
-            return Err(Error::NonASCIIChar(c, line, column))
         }
+
+        // the first wrong byte is reported
+        assert_eq!(verify_code("++++++++[>++++++++<-]>+.+.", b"AB"), Ok(()));
+        assert_eq!(verify_code("++++++++[>++++++++<-]>+.+.", b"AC"), Err(Error::GenerationMismatch(1)));
+        assert_eq!(verify_code("++++++++[>++++++++<-]>+.+.", b"ABC"), Err(Error::GenerationMismatch(2)));
+        assert_eq!(verify_code("++++++++[>++++++++<-]>+.+.", b"A"), Err(Error::GenerationMismatch(1)));
+        assert_eq!(verify_code("+[]", b""), Err(Error::GenerationMismatch(0)));
+        assert_eq!(verify_code("+[].", b"\x01"), Err(Error::GenerationMismatch(0)));
     }
 
-    Ok(bytes)
-}
+    #[test]
+    fn test_text_2_bf_to() {
+        //! Test that the `text_2_bf_to` function writes the same code as `text_2_bf_with_options`.
 
-/// Generate Brainfuck code for storing sequence of bytes into array
-/// Data pointer is left at the index == bytes.len()
-/// # Arguments
-/// * `bytes` - The slice of bytes to store into array
-/// # Returns
-/// * [String] - The Brainfuck code.
-fn store_bf_bytes(bytes: &[u8]) -> String {
-    let mut store = String::new();
+        // the code of the long text is verified in multiple chunks
+        let long_text = include_str!("../README.md").repeat(40);
+        assert!(text_2_bf_utf8(&long_text).len() > 2 * VERIFY_CHUNK_SIZE);
+        for text in ["Hello, World!", "Brain\r\nFuck", "aaaaaaaaaa", "Čao 👋", &long_text, ""] {
+            for style in [TextCodeStyle::Table, TextCodeStyle::SingleCell, TextCodeStyle::Auto] {
+                for (target, cleanup) in [(CostTarget::CodeSize, false), (CostTarget::RunTime, true)] {
+                    let options = TextOptions { style, utf8: true, target, cleanup, verify: true, ..TextOptions::default() };
+                    let mut bf_code = Vec::new();
+                    text_2_bf_to(text, &mut bf_code, &options).unwrap();
+                    assert_eq!(String::from_utf8(bf_code).unwrap(), text_2_bf_with_options(text, &options).unwrap());
+                }
+            }
+        }
 
-    let fact_table = factor_table();
+        // the errors of the text and of the writer
+        let mut bf_code = Vec::new();
+        assert_eq!(text_2_bf_to("Čao", &mut bf_code, &TextOptions::default()), Err(Error::NonASCIIChar('Č', 1, 1)));
+        assert!(bf_code.is_empty());
+        let mut full = [0_u8; 10];
+        assert_eq!(
+            text_2_bf_to("Hello, World!", &mut full[..], &TextOptions::default()),
+            Err(Error::EmitFailed(io::ErrorKind::WriteZero)),
+        );
+    }
 
-    for &byte in bytes {
-        match fact_table[byte as usize] {
-            Some((f1, f2, diff)) => {
-                if byte <= 10 {
-                    store.push_str(&"+".repeat(byte as usize));
-                    store.push('>');
-                } else {
-                    store.push('>');
-                    store.push_str(&"+".repeat(f1 as usize));
-                    store.push('[');
-                    store.push('<');
-                    store.push_str(&"+".repeat(f2 as usize));
-                    store.push('>');
-                    store.push('-');
-                    store.push(']');
-                    match diff.cmp(&0) {
-                        Ordering::Greater => {
-                            store.push('<');
-                            store.push_str(&"+".repeat(diff.unsigned_abs() as usize));
-                            store.push('>');
-                        },
-                        Ordering::Less => {
-                            store.push('<');
-                            store.push_str(&"-".repeat(diff.unsigned_abs() as usize));
-                            store.push('>');
-                        },
-                        Ordering::Equal => (),
-                    }
+    #[test]
+    fn test_text_2_bf_template() {
+        //! Test that the `text_2_bf_template` function generates the code filling the placeholders from the input.
+
+        let run = |template: &str, spec: PlaceholderSpec, input: &[u8], eof: EofPolicy| {
+            let bf_code = text_2_bf_template(template, spec).unwrap();
+            let mut io = VecIo::new(input);
+            interpret_with_eof(&process_code(&bf_code).unwrap(), &mut io, eof);
+            io.output
+        };
+
+        for eof in [EofPolicy::Zero, EofPolicy::Unchanged] {
+            let template = "Hello, {}! You are {} years old.\n";
+            assert_eq!(run(template, PlaceholderSpec::Line, b"World\n42\n", eof), b"Hello, World! You are 42 years old.\n");
+            assert_eq!(run(template, PlaceholderSpec::Line, b"World\n", eof), b"Hello, World! You are  years old.\n");
+            assert_eq!(run("{}{}", PlaceholderSpec::Line, b"\nab", eof), b"ab");
+            assert_eq!(run("Code: {}-{}", PlaceholderSpec::Fixed(3), b"abcdef", eof), b"Code: abc-def");
+            assert_eq!(run("Code: {}-{}", PlaceholderSpec::Fixed(3), b"abcd", eof), b"Code: abc-d");
+            assert_eq!(run("[{}]", PlaceholderSpec::Fixed(0), b"abc", eof), b"[]");
+            assert_eq!(run("[{}]", PlaceholderSpec::Fixed(300), &[b'x'; 400], eof), [&b"["[..], &[b'x'; 300], b"]"].concat());
+            assert_eq!(run("No fields\r\n", PlaceholderSpec::Line, b"abc", eof), b"No fields\n");
+        }
+
+        assert_eq!(text_2_bf_template("Čao {}", PlaceholderSpec::Line), Err(Error::NonASCIIChar('Č', 1, 1)));
+    }
+
+    #[test]
+    fn test_text_2_bf_huffman() {
+        //! Test that the `text_2_bf_huffman` function generates the code printing the text.
+
+        let all_printable_ascii = (32..127_u8).map(char::from).collect::<String>();
+        for text in ["Hello, World!", "aaaaaaaaaa", &all_printable_ascii, include_str!("../README.md"), "a\r\nb", ""] {
+            let bf_code = text_2_bf_huffman(text).unwrap();
+            assert_eq!(bf_2_text(&bf_code).unwrap(), text.replace('\r', ""));
+        }
+        assert_eq!(text_2_bf_huffman("Čao"), Err(Error::NonASCIIChar('Č', 1, 1)));
+
+        assert_eq!(frequency_order(b"cbcabc"), b"abc");
+        assert_eq!(frequency_order(b"aaabbc"), b"cba");
+        assert_eq!(frequency_order(b""), b"");
+    }
+
+    #[test]
+    fn test_reuse_repeats() {
+        //! Test that the repeated blocks are printed by the loops, which shortens the code and keeps the text.
+
+        let table = format!("<table>\n{}</table>\n", "  <tr><td>cell</td><td>cell</td></tr>\n".repeat(300));
+        let options = TextOptions { reuse_repeats: true, verify: true, ..TextOptions::default() };
+        let plain = text_2_bf(&table).unwrap();
+        let reused = text_2_bf_with_options(&table, &options).unwrap();
+        assert!(reused.len() * 10 < plain.len(), "{} vs {} commands", reused.len(), plain.len());
+        assert_eq!(bf_2_text(&reused).unwrap(), table);
+
+        let all_printable_ascii = (32..127_u8).map(char::from).collect::<String>();
+        let texts = [&table, "abababab", "xyzxyzxyz-abcabc", include_str!("../README.md"), &"ab".repeat(1000), &all_printable_ascii, ""];
+        for text in texts {
+            for (portable, cleanup, max_cells) in [(false, false, None), (true, true, None), (false, true, Some(12)), (false, false, Some(200))] {
+                let options = TextOptions { portable, cleanup, max_cells, ..options };
+                let bf_code = text_2_bf_with_options(text, &options).unwrap();
+                assert_eq!(bf_2_text(&bf_code).unwrap(), text, "{:?}", options);
+                assert!(bf_code.len() <= text_2_bf_with_options(text, &TextOptions { reuse_repeats: false, ..options }).unwrap().len());
+                if portable {
+                    assert_eq!(run_strict(&bf_code), Ok(text.as_bytes().to_vec()));
                 }
-            },
-            None => {
-                store.push_str(&"+".repeat(byte as usize));
-                store.push('>');
-            },
+                if let Some(max_cells) = max_cells {
+                    assert!(cells_used(&bf_code) <= max_cells);
+                }
+            }
         }
+
+        // the text without the repeats is printed the same, the single cell and the run time target don't use the loops
+        assert_eq!(text_2_bf_with_options(&all_printable_ascii, &options).unwrap(), text_2_bf(&all_printable_ascii).unwrap());
+        for (style, target) in [(TextCodeStyle::SingleCell, CostTarget::CodeSize), (TextCodeStyle::Table, CostTarget::RunTime)] {
+            let options = TextOptions { style, target, ..TextOptions::default() };
+            assert_eq!(text_2_bf_with_options(&table, &TextOptions { reuse_repeats: true, ..options }), text_2_bf_with_options(&table, &options));
+        }
+
+        assert_eq!(find_repeat(b"abababc"), Some((2, 3)));
+        assert_eq!(find_repeat(b"aaaa"), Some((2, 2)));
+        assert_eq!(find_repeat(b"abcabcab"), Some((3, 2)));
+        assert_eq!(find_repeat(b"abcab"), None);
     }
 
-    store
-}
+    #[test]
+    fn test_annotate() {
+        //! Test that the annotated code has a commented line for each cell and each byte and prints the same text.
 
-/// Print out the text_bytes from the sequence of bytes stored in the array
-/// # Arguments
-/// * `text_bytes` - The slice of bytes to print out.
-/// * `store_ord` - The slice of bytes that are stored in the array.
-/// * `position` - The position of the data pointer in the array.
-/// # Returns
-/// * [String] - The Brainfuck code.
-fn print_bf_bytes(text_bytes: &[u8], store_ord: &[u8], position: usize) -> String {
-    let mut curr_pos = position as isize;
-    let mut bf_code = String::new();
-    for byte in text_bytes {
-        let new_pos = store_ord.iter().position(|&x| x == *byte).unwrap() as isize;
-        let diff = new_pos - curr_pos;
-        match diff.cmp(&0) {
-            Ordering::Greater => {
-                bf_code.push_str(&">".repeat(diff.unsigned_abs()));
-            },
-            Ordering::Less => {
-                bf_code.push_str(&"<".repeat(diff.unsigned_abs()));
-            },
-            Ordering::Equal => (),
+        let bf_code = text_2_bf_with_options("Hi!", &TextOptions { style: TextCodeStyle::SingleCell, annotate: true, ..TextOptions::default() }).unwrap();
+        assert_eq!(bf_2_text(&bf_code).unwrap(), "Hi!");
+        let comments = bf_code.lines().map(|line| line.split_once(' ').unwrap().1).collect::<Vec<_>>();
+        assert_eq!(comments, ["prints 'H' (72)", "prints 'i' (105)", "prints '!' (33)"]);
+
+        let all_printable_ascii = (32..127_u8).map(char::from).collect::<String>();
+        let texts = ["Hello, World!\n", "a+b-c <x> [y], z. #1\tend\r\n", include_str!("../README.md"), &all_printable_ascii, "čćž", ""];
+        for text in texts {
+            for style in [TextCodeStyle::Auto, TextCodeStyle::Table, TextCodeStyle::SingleCell] {
+                for target in [CostTarget::CodeSize, CostTarget::RunTime] {
+                    for (portable, cleanup, max_cells) in [(false, false, None), (true, true, None), (false, true, Some(12))] {
+                        let options = TextOptions {
+                            style, target, portable, cleanup, max_cells, utf8: true, newlines: NewlinePolicy::Preserve, verify: true, annotate: true,
+                            ..TextOptions::default()
+                        };
+                        let bf_code = text_2_bf_with_options(text, &options).unwrap();
+                        assert_eq!(bf_2_text(&bf_code).unwrap(), text, "{:?}", options);
+                        if portable {
+                            assert_eq!(run_strict(&bf_code), Ok(text.as_bytes().to_vec()));
+                        }
+                        if let Some(max_cells) = max_cells {
+                            assert!(cells_used(&bf_code) <= max_cells);
+                        }
+
+                        // each line ends with a comment without the commands, one line for each byte (and each cell of the table)
+                        let lines = bf_code.lines().collect::<Vec<_>>();
+                        for line in &lines {
+                            let (_, comment) = line.split_once(' ').unwrap();
+                            assert!(!comment.contains(['+', '-', '<', '>', '[', ']', ',', '.', '#']), "{:?}", line);
+                        }
+                        let printing = lines.iter().filter(|line| line.contains(" prints ")).count();
+                        assert_eq!(printing, text.len());
+                        assert_eq!(lines.len(), printing + lines.iter().filter(|line| line.contains(" holds ")).count() + usize::from(cleanup && !text.is_empty()));
+                        assert!(bf_code.is_empty() || bf_code.ends_with('\n'));
+                    }
+                }
+            }
         }
-        curr_pos = new_pos;
-        bf_code.push('.');
     }
-    bf_code
-}
 
-/// Generate a table of factors and differences for numbers 0 to 255.
-/// To get the value of a number, the factors are multiplied and the difference is added.
-/// Used to minimize the number of operations in Brainfuck code.
-/// None means that the number should be represented just by repeating + commands (numbers less than 10).
-fn factor_table() -> Vec<Option<(u8, u8, i8)>> {
-
-    // working table, fill with (index, factors) where factors are set to u8::MAX
-    let mut work_table = Vec::with_capacity(u8::MAX as usize + 1);
-    for i in 0..=u8::MAX {
-        work_table.push((i, [u8::MAX, u8::MAX]));
-    }
-
-    // iterate over pairs of factors that when multiplied return n <= u8::MAX
-    // we aren't interested in pairs of factors where one of the factors is 1, so we can exclude 1 and u8::MAX
-    // also we can just check i < j because we would just get duplicates if we were to check every j for every i
-    for i in 2..u8::MAX {
-        for j in i..u8::MAX {
-            match i.checked_mul(j) {
-                Some(mul) => {
-                    // if the sum of factors (i, j) is lower than the sum of current factors for n (n = i * j)
-                    // set that factors for that n instead
-                    // (we want to minimize the sum of factors so that the multiplication loop is shorter in Brainfuck)
-                    if (i + j) < (work_table[mul as usize].1[0].saturating_add(work_table[mul as usize].1[1])) {
-                        work_table[mul as usize].1 = [i, j];
+    #[test]
+    fn test_wrap_width() {
+        //! Test that the wrapped code has the lines of at most the width, none ending with `[`, and runs the same.
+
+        let texts = ["Hello, World!\n", include_str!("../README.md"), &"ab".repeat(300), ""];
+        for text in texts {
+            for (style, annotate) in [(TextCodeStyle::Table, false), (TextCodeStyle::SingleCell, false), (TextCodeStyle::Table, true)] {
+                let options = TextOptions { style, annotate, verify: true, ..TextOptions::default() };
+                let unwrapped = text_2_bf_with_options(text, &options).unwrap();
+                for width in [1, 2, 7, 80, 100_000] {
+                    let wrapped = text_2_bf_with_options(text, &TextOptions { wrap_width: Some(width), ..options }).unwrap();
+                    assert_eq!(process_code(&wrapped).unwrap(), process_code(&unwrapped).unwrap());
+                    for line in wrapped.lines() {
+                        assert!(line.len() <= width, "{:?}", line);
+                        assert!(width == 1 || !line.ends_with('['), "{:?}", line);
                     }
-                },
-                None => break,  // break since further j will just give us a larger number (overflow)
+                    if !annotate {
+                        assert_eq!(wrapped.replace('\n', ""), unwrapped);
+                    }
+                }
             }
         }
+
+        // the lines are filled up to the width, the open bracket starts the next line
+        let options = TextOptions { style: TextCodeStyle::SingleCell, ..TextOptions::default() };
+        assert_eq!(text_2_bf_with_options(" ", &options).unwrap(), ">++++[<++++++++>-]<.");
+        assert_eq!(text_2_bf_with_options(" ", &TextOptions { wrap_width: Some(4), ..options }).unwrap(), ">+++\n+[<+\n++++\n+++>\n-]<.");
+        assert_eq!(text_2_bf_with_options(" ", &TextOptions { wrap_width: Some(6), ..options }).unwrap(), ">++++\n[<++++\n++++>-\n]<.");
     }
 
-    // remove numbers that still have original factors set (no actual factors were found)
-    work_table.retain(|(n, pair)| (*pair != [u8::MAX, u8::MAX]) && (*n > 10));
+    /// The shortest known program printing "Hello, World!" (it wraps the cells around and moves left of the first cell).
+    const SHORTEST_HELLO_WORLD: &str = "+[-->-[>>+>-----<<]<--<---]>-.>>>+.>>..+++[.>]<<<<.+++.------.<<-.>>>>+.";
 
-    // for each number in table, check if it can be represented in a shorter manner by
-    // using factors of the previous number and +/- symbols
-    // if it can, remove it
-    let mut i = 1;
-    while i < work_table.len() {
-        if work_table[i].1.iter().sum::<u8>() >= work_table[i - 1].1.iter().sum::<u8>() + (work_table[i].0 - work_table[i - 1].0) {
-            work_table.remove(i);
-        } else {
-            i += 1;
+    /// The length of the code of `text_2_bf` printing "Hello, World!" (the regression baseline, see [BASELINE_TOLERANCE]).
+    // the gap to the shortest known program is 3.86x (278 vs 72 commands),
+    // that one is hand-optimized, reusing the cells built by a single loop for several characters
+    const HELLO_WORLD_LENGTH: usize = 278;
+
+    /// The lengths of the code of `text_2_bf` printing the single characters from `' '` to `'~'` (the regression baselines,
+    /// see [BASELINE_TOLERANCE]).
+    // the gap to the shortest single-loop programs is up to 2.58x,
+    // the table of `text_2_bf` is built for the longer texts, its setup dominates the single characters
+    const SINGLE_CHAR_LENGTHS: [usize; 95] = [
+        20, 21, 21, 20, 20, 21, 22, 22, 21, 22, 21, 22, 23, 22, 23, 23,
+        22, 22, 23, 24, 25, 24, 23, 24, 23, 24, 25, 25, 24, 25, 25, 24,
+        24, 25, 25, 26, 27, 26, 25, 26, 25, 26, 27, 28, 27, 26, 27, 27,
+        26, 26, 27, 28, 27, 28, 29, 28, 27, 28, 27, 28, 29, 30, 30, 29,
+        28, 29, 29, 28, 28, 29, 30, 30, 29, 30, 31, 30, 29, 30, 29, 30,
+        30, 31, 32, 32, 31, 30, 31, 31, 30, 30, 31, 32, 31, 30, 31,
+    ];
+
+    /// The percentage by which the code may be longer than its baseline, so the small changes of the heuristics
+    /// don't require updating the baselines (the shorter code should update them).
+    const BASELINE_TOLERANCE: usize = 5;
+
+    /// Check that the length of the code doesn't exceed the baseline by more than [BASELINE_TOLERANCE].
+    /// # Arguments
+    /// * `len` - The length of the code.
+    /// * `baseline` - The baseline length.
+    /// # Returns
+    /// * `bool` - Whether the length is within the tolerance.
+    fn within_baseline(len: usize, baseline: usize) -> bool {
+        len * 100 <= baseline * (100 + BASELINE_TOLERANCE)
+    }
+
+    /// Find the length of the shortest program printing the byte with at most one loop (`a[>b<c]>d.`, each part repeating
+    /// `+` or `-`, the counts of the loop up to 8 and 24). The programs of this form are the shortest known ones
+    /// for most of the constants (the cell wraps around).
+    /// # Arguments
+    /// * `byte` - The printed byte.
+    /// # Returns
+    /// * `usize` - The number of commands.
+    fn shortest_single_loop(byte: u8) -> usize {
+        // without the loop
+        let mut best = (byte as i8).unsigned_abs() as usize + 1;
+        for a in i8::MIN..=i8::MAX {
+            for c in (-8_i8..=8).filter(|&c| c != 0) {
+                for b in (-24_i8..=24).filter(|&b| b != 0) {
+                    // the counter reaches zero after the iterations (if ever)
+                    let Some(iterations) = (0..=u8::MAX as usize).find(|&i| (a as u8).wrapping_add((c as u8).wrapping_mul(i as u8)) == 0) else {
+                        continue;
+                    };
+                    let value = (b as u8).wrapping_mul(iterations as u8);
+                    let d = byte.wrapping_sub(value) as i8;
+                    let len = a.unsigned_abs() as usize + b.unsigned_abs() as usize + c.unsigned_abs() as usize + d.unsigned_abs() as usize + 6;
+                    best = best.min(len);
+                }
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn test_length_baselines() {
+        //! Test that the code of `text_2_bf` isn't longer than the baselines.
+
+        let bf_code = text_2_bf("Hello, World!").unwrap();
+        assert!(within_baseline(bf_code.len(), HELLO_WORLD_LENGTH), "{} commands", bf_code.len());
+
+        for (byte, baseline) in (b' '..=b'~').zip(SINGLE_CHAR_LENGTHS) {
+            let text = char::from(byte).to_string();
+            let bf_code = text_2_bf(&text).unwrap();
+            assert!(within_baseline(bf_code.len(), baseline), "{:?}: {} commands, the baseline is {}", text, bf_code.len(), baseline);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "slow-tests"), ignore = "the exhaustive search is slow, enable the `slow-tests` feature")]
+    fn test_known_shortest() {
+        //! Test that the baselines aren't shorter than the shortest known programs (they should be updated if they are).
+
+        assert_eq!(bf_2_text(SHORTEST_HELLO_WORLD).unwrap(), "Hello, World!");
+        assert!(HELLO_WORLD_LENGTH >= SHORTEST_HELLO_WORLD.len());
+
+        for (byte, baseline) in (b' '..=b'~').zip(SINGLE_CHAR_LENGTHS) {
+            assert!(baseline >= shortest_single_loop(byte), "{:?}: the baseline is {}", char::from(byte), baseline);
         }
     }
 
-    // for each number in table, check if it can be represented in a shorter manner by
-    // using factors of the next number and +/- symbols
-    // if it can, remove it
-    for i in (0..(work_table.len() - 1)).rev() {
-        if work_table[i].1.iter().sum::<u8>() >= work_table[i + 1].1.iter().sum::<u8>() + (work_table[i + 1].0 - work_table[i].0) {
-            work_table.remove(i);
+    #[test]
+    fn test_max_cells() {
+        //! Test that the generated code uses at most `max_cells` cells and still prints the text.
+
+        let all_printable_ascii = (32..127_u8).map(char::from).collect::<String>();
+        let texts = ["Hello, World!", "aaaaaaaaaa", &all_printable_ascii, include_str!("../README.md"), ""];
+        for text in texts {
+            for style in [TextCodeStyle::Table, TextCodeStyle::SingleCell, TextCodeStyle::Auto] {
+                for (target, cleanup) in [(CostTarget::CodeSize, false), (CostTarget::RunTime, true)] {
+                    let options = TextOptions { style, target, cleanup, utf8: true, verify: true, ..TextOptions::default() };
+                    let needed = min_cells(style, target);
+                    for max_cells in [0, 1, 2, 3, 4, 8, 20, 100] {
+                        let result = text_2_bf_with_options(text, &TextOptions { max_cells: Some(max_cells), ..options });
+                        if max_cells < needed {
+                            assert_eq!(result, Err(Error::CellBudgetTooSmall(max_cells, needed)));
+                            continue;
+                        }
+                        let bf_code = result.unwrap();
+                        assert!(cells_used(&bf_code) <= max_cells, "{:?} {:?} {}", style, target, max_cells);
+                        assert_eq!(bf_2_bytes(&bf_code).unwrap(), text.replace('\r', "").as_bytes());
+                    }
+
+                    // the large budget doesn't change the code
+                    let unlimited = text_2_bf_with_options(text, &options).unwrap();
+                    assert_eq!(text_2_bf_with_options(text, &TextOptions { max_cells: Some(1000), ..options }).unwrap(), unlimited);
+                }
+            }
         }
+
+        assert_eq!(bucket_bases(b"aab", 5), b"ab");
+        assert_eq!(bucket_bases(b"aaabcxyyz", 2), b"ay");
+        assert_eq!(bucket_bases(b"", 1), b"");
     }
 
-    // store the available numbers with factors in a map for easier access
-    let mut map = HashMap::with_capacity(work_table.len());
-    for (i, pair) in work_table {
-        map.insert(i, pair);
+    #[test]
+    fn test_text_2_bf_with_options() {
+        //! Test the `text_2_bf_with_options` function with every strategy.
+
+        let all_printable_ascii: String = (b' '..=b'~').map(char::from).collect();
+        let texts = ["Hello, World!", "aaaaaaaaaa", "Brain\nFuck", &all_printable_ascii, "~ ~ ~ ~ ~ ~", ""];
+        for text in texts {
+            let mut lens = Vec::new();
+            for style in [TextCodeStyle::Table, TextCodeStyle::SingleCell, TextCodeStyle::Auto] {
+                let bf_code = text_2_bf_with_options(text, &TextOptions { style, ..TextOptions::default() }).unwrap();
+
+                // the generated code must print the text
+                let mut io = VecIo::default();
+                interpret(&process_code(&bf_code).unwrap(), &mut io);
+                assert_eq!(io.output, text.as_bytes(), "style: {:?}", style);
+                lens.push(bf_code.len());
+            }
+
+            // the table is the same as text_2_bf, auto picks the shorter code
+            assert_eq!(lens[0], text_2_bf(text).unwrap().len());
+            assert_eq!(lens[2], lens[0].min(lens[1]), "text: {}", text);
+        }
+
+        // the text with many distinct bytes is shorter from a single cell
+        let table = text_2_bf(&all_printable_ascii).unwrap();
+        let single_cell = text_2_bf_with_options(&all_printable_ascii, &TextOptions { style: TextCodeStyle::SingleCell, ..TextOptions::default() }).unwrap();
+        assert!(single_cell.len() < table.len());
+
+        // the UTF-8 text with a single cell
+        let options = TextOptions { style: TextCodeStyle::SingleCell, utf8: true, ..TextOptions::default() };
+        let mut io = VecIo::default();
+        interpret(&process_code(&text_2_bf_with_options("Ťažký 🎒", &options).unwrap()).unwrap(), &mut io);
+        assert_eq!(String::from_utf8(io.output).unwrap(), "Ťažký 🎒");
+        assert_eq!(text_2_bf_with_options("Ťažký 🎒", &TextOptions::default()), Err(Error::NonASCIIChar('Ť', 1, 1)));
     }
 
-    // result table, fill with None
-    // indices represent numbers
-    // values represent the factors of the number, and the third value is the difference between the number and the product of the factors
-    // (+/- symbols can be used to get the number from the product of the factors)
-    let mut table = vec![None; u8::MAX as usize + 1];
+    #[test]
+    fn test_newline_policy() {
+        //! Test printing the text with the mixed line endings by each newline policy.
 
-    // for each number, find best way to get it using the factors in the map
-    // and store it in the result table, don't calculate for n <= 10 (they are just represented with repeated + commands)
-    for i in 11..=u8::MAX {
-        let mut high = i;
-        let mut low = i;
+        let text = "Line 1\r\nLine 2\nLine 3\r\n\r\nŽ\r";
+        for (newlines, expected) in [
+            (NewlinePolicy::Preserve, text.to_string()),
+            (NewlinePolicy::CrlfToLf, text.replace("\r\n", "\n")),
+            (NewlinePolicy::Lf, text.replace('\r', "")),
+            (NewlinePolicy::Crlf, text.replace('\r', "").replace('\n', "\r\n")),
+        ] {
+            for style in [TextCodeStyle::Table, TextCodeStyle::SingleCell] {
+                let options = TextOptions { style, utf8: true, newlines, ..TextOptions::default() };
+                let bf_code = text_2_bf_with_options(text, &options).unwrap();
+                let mut io = VecIo::default();
+                interpret(&process_code(&bf_code).unwrap(), &mut io);
+                assert_eq!(String::from_utf8(io.output).unwrap(), expected, "options: {:?}", options);
+            }
 
-        while !map.contains_key(&low) && !map.contains_key(&high) {
-            high = high.saturating_add(1);
-            low = low.saturating_sub(1);
+            // without UTF-8, the columns of the error follow the policy
+            let options = TextOptions { newlines, ..TextOptions::default() };
+            let column = if matches!(newlines, NewlinePolicy::Preserve | NewlinePolicy::CrlfToLf) { 2 } else { 1 };
+            assert_eq!(text_2_bf_with_options("\r\n\rŽ", &options), Err(Error::NonASCIIChar('Ž', 2, column)));
         }
 
-        // search up and down for the closest number with factors
+        // the Windows line endings are printed as the Unix ones by default, or as they are
+        assert_eq!(text_2_bf("Hello\r\nWorld"), text_2_bf("Hello\nWorld"));
+        let preserved = text_2_bf_with_options("Hello\r\nWorld", &TextOptions { newlines: NewlinePolicy::Preserve, ..TextOptions::default() });
+        assert_eq!(bf_2_text(&preserved.unwrap()).unwrap(), "Hello\r\nWorld");
+    }
+
+    #[test]
+    fn test_cleanup() {
+        //! Test clearing the cells and returning the data pointer to the first cell after printing.
+
+        let all_printable_ascii: String = (b' '..=b'~').map(char::from).collect();
+        let texts = ["Brainfuck", "Hello, World!\n", "=========================================", "\u{1}\u{2}\u{1}", "\u{7f}zz", &all_printable_ascii, ""];
+        for text in texts {
+            for style in [TextCodeStyle::Table, TextCodeStyle::SingleCell, TextCodeStyle::Auto] {
+                for target in [CostTarget::CodeSize, CostTarget::RunTime] {
+                    let options = TextOptions { style, target, cleanup: true, ..TextOptions::default() };
+                    let bf_code = text_2_bf_with_options(text, &options).unwrap();
 
-        // get factors of the closest number
-        let factors =
-            if map.contains_key(&low) {
-                if map.contains_key(&high) {
-                    if map[&low].iter().sum::<u8>() < map[&high].iter().sum::<u8>() {
-                        map[&low]
-                    } else {
-                        map[&high]
+                    let result = run_interpret(process_code(&bf_code).unwrap(), b"").unwrap();
+                    assert_eq!(result.output, text.as_bytes(), "options: {:?}", options);
+                    assert_eq!(result.final_data_ptr, 0, "options: {:?}", options);
+                    assert!(result.final_tape.iter().all(|&cell| cell == 0), "options: {:?}", options);
+                    if target == CostTarget::RunTime {
+                        assert!(!bf_code.contains('['));
                     }
-                } else {
-                    map[&low]
                 }
-            } else {
-                map[&high]
-            };
+            }
+        }
+
+        // only the used cells are cleared, from the last one
+        let options = TextOptions { cleanup: true, ..TextOptions::default() };
+        assert_eq!(text_2_bf_with_options("\u{1}\u{2}\u{1}", &options), Ok(String::from("++>+><.<.>.-<--")));
+    }
+
+    #[test]
+    fn test_table_order() {
+        //! Test that the `table_order` function never makes the code longer than the sorted order.
+
+        let mut shorter = 0;
+        for text in [
+            "Hello, World!\n",
+            include_str!("../README.md"),
+            include_str!("../LICENSE"),
+            include_str!("../examples/fizzbuzz.bf"),
+            "The quick brown fox jumps over the lazy dog.",
+            "abababababzzzzzzab",
+        ] {
+            for target in [CostTarget::CodeSize, CostTarget::RunTime] {
+                let order = table_order(text.as_bytes(), target, false);
+                let mut sorted = order.clone();
+                sorted.sort();
+                assert_eq!(order.len(), sorted.len());
 
-        // calculate difference between the number and the product of the factors
-        let diff = (i as isize - factors.iter().product::<u8>() as isize) as i8;
+                let mut sorted_code = store_bf_bytes(&sorted, target, false);
+                print_bf_bytes(text.as_bytes(), &sorted, sorted.len(), target, false, &mut sorted_code).unwrap();
+                let mut code = String::new();
+                table_bf(text.as_bytes(), target, false, None, false, false, &mut code).unwrap();
+                assert!(code.len() <= sorted_code.len(), "{} > {} for {:?}", code.len(), sorted_code.len(), text);
+                if code.len() < sorted_code.len() {
+                    shorter += 1;
+                }
 
-        // store factors and difference in the result table
-        table[i as usize] = Some((factors[0], factors[1], diff));
+                let mut io = VecIo::default();
+                interpret(&process_code(&code).unwrap(), &mut io);
+                assert_eq!(io.output, text.as_bytes());
+            }
+        }
+        assert!(shorter >= 6, "only {} codes are shorter", shorter);
     }
 
-    // return the result table
-    table
-}
+    #[test]
+    fn test_clear_bf() {
+        //! Test the `clear_bf` function.
 
+        assert_eq!(clear_bf(0, CostTarget::CodeSize, false), "");
+        assert_eq!(clear_bf(3, CostTarget::CodeSize, false), "---");
+        assert_eq!(clear_bf(253, CostTarget::CodeSize, false), "+++");
+        assert_eq!(clear_bf(72, CostTarget::CodeSize, false), "[-]");
+        assert_eq!(clear_bf(200, CostTarget::CodeSize, false), "[+]");
+        assert_eq!(clear_bf(5, CostTarget::RunTime, false), "-----");
+        assert_eq!(clear_bf(250, CostTarget::RunTime, false), "++++++");
+    }
 
+    #[test]
+    fn test_cost_target() {
+        //! Test the code optimized for the size and for the running time.
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // the number of the executed tokens
+        let profile = |bf_code: &str| interpret_profiled::<true>(&process_code(bf_code).unwrap(), &mut VecIo::default()).iter().sum::<u64>();
 
-    /// Checks if a number is prime.
-    /// # Arguments
-    /// * `num` - The number to check.
-    /// # Returns
-    /// * `bool` - Whether the number is prime.
-    /// * `u64` - The smallest divisor if the number is not prime, otherwise 1.
-    fn is_prime(n: u64) -> (bool, u64) {
-        assert!(n >= 2, "Number must be greater than or equal to 2.");
+        // the size-targeted code is the default
+        assert_eq!(
+            text_2_bf_with_options("Brainfuck", &TextOptions { target: CostTarget::CodeSize, ..TextOptions::default() }),
+            text_2_bf("Brainfuck"),
+        );
 
-        if n == 2 || n == 3 {
-            (true, 1)
-        } else if n % 2 == 0 {
-            (false, 2)
-        } else if n % 3 == 0 {
-            (false, 3)
-        } else {
-            for i in (5..=((n as f64).sqrt().floor() as u64)).step_by(6) {
-                if n % i == 0 {
-                    return (false, i);
-                } else if n % (i + 2) == 0 {
-                    return (false, i + 2);
+        let all_printable_ascii: String = (b' '..=b'~').map(char::from).collect();
+        for text in ["Brainfuck", "Hello, World!", "==============================", &all_printable_ascii] {
+            for style in [TextCodeStyle::Table, TextCodeStyle::SingleCell, TextCodeStyle::Auto] {
+                let size = text_2_bf_with_options(text, &TextOptions { style, target: CostTarget::CodeSize, ..TextOptions::default() }).unwrap();
+                let run_time = text_2_bf_with_options(text, &TextOptions { style, target: CostTarget::RunTime, ..TextOptions::default() }).unwrap();
+
+                // both print the text, the run-time-targeted code is longer, but it has no loops and executes fewer tokens
+                for bf_code in [&size, &run_time] {
+                    let mut io = VecIo::default();
+                    interpret(&process_code(bf_code).unwrap(), &mut io);
+                    assert_eq!(io.output, text.as_bytes(), "style: {:?}", style);
                 }
+                assert!(!run_time.contains('['));
+                assert!(size.len() <= run_time.len());
+                assert!(profile(&run_time) < profile(&size), "text: {}, style: {:?}", text, style);
             }
+        }
+    }
 
-            (true, 1)
+    #[test]
+    fn test_bf_2_text() {
+        //! Test the `bf_2_text` and `bf_2_bytes` functions.
+
+        let all_printable_ascii: String = (b' '..=b'~').map(char::from).collect();
+        for text in ["Hello, World!\n", "Brain\nFuck", &all_printable_ascii, ""] {
+            assert_eq!(bf_2_text(&text_2_bf(text).unwrap()), Ok(String::from(text)));
         }
+        assert_eq!(bf_2_text(&text_2_bf_utf8("Ďakujem 🙏")), Ok(String::from("Ďakujem 🙏")));
+        assert_eq!(bf_2_text(include_str!("../examples/hello-world.bf")), Ok(String::from("Hello World!\n")));
+
+        // the invalid UTF-8 is replaced in the text, but not in the bytes
+        assert_eq!(bf_2_text("-.+++++++++[>+++++++<-]>."), Ok(String::from("\u{FFFD}8")));
+        assert_eq!(bf_2_bytes("-.+++++++++[>+++++++<-]>."), Ok(vec![255, 56]));
+
+        // the programs reading the input and the errors of the code are refused
+        assert_eq!(bf_2_bytes("+.\n comment\n  >,."), Err(Error::UnexpectedInput(3, 4)));
+        assert_eq!(bf_2_bytes("+.]"), Err(Error::UnmatchedCloseBr(1, 3)));
+
+        // the infinite loop runs out of steps (but the long loops don't)
+        assert_eq!(bf_2_bytes("+[>+<]"), Err(Error::StepLimitExceeded));
+        assert_eq!(bf_2_bytes("++++++++[>++++++++[>++++++++[>++++++++[>+<-]<-]<-]<-]>>>>."), Ok(vec![0]));
     }
 
     #[test]
-    fn test_text_2_bf() {
-        //! Test the `text_2_bf` function.
+    fn test_add_bf() {
+        //! Test the `add_bf` function.
 
-        let text = "Brain\nFuck";
-        let bf_code = text_2_bf(text).unwrap();
+        assert_eq!(add_bf(0, CostTarget::CodeSize, false), "");
+        assert_eq!(add_bf(3, CostTarget::CodeSize, false), "+++");
+        assert_eq!(add_bf(-3, CostTarget::CodeSize, false), "---");
+        assert_eq!(add_bf(72, CostTarget::CodeSize, false), ">++++++++[<+++++++++>-]<");
+
+        // every value is added and the counter cell is left zero
+        for value in i8::MIN as i16..=i8::MAX as i16 {
+            let bf_code = add_bf(value, CostTarget::CodeSize, false);
+            let mut io = VecIo::default();
+            let state = interpret(&process_code(&format!("{}>.", bf_code)).unwrap(), &mut io);
+            assert_eq!(state.data_ptr, 1);
+            assert_eq!(io.output, [0]);
+            let state = interpret(&process_code(&bf_code).unwrap(), &mut io);
+            assert_eq!(state.cell, value as u8, "value: {}", value);
+            assert!(bf_code.len() <= value.unsigned_abs() as usize);
+        }
+    }
+
+    #[test]
+    fn test_portable() {
+        //! Test that the portable code runs under the strict dialect (and the default code doesn't always).
+
+        let all_bytes = (0..=u8::MAX).collect::<Vec<_>>();
+        let texts: [&[u8]; 5] = [b"Hello, World!", b"aaaaaaaaaaaaaaaaaaaa", "Čao 👋".as_bytes(), &all_bytes, include_bytes!("../README.md")];
+        for text in texts {
+            for style in [TextCodeStyle::Table, TextCodeStyle::SingleCell, TextCodeStyle::Auto] {
+                for (target, cleanup) in [(CostTarget::CodeSize, false), (CostTarget::CodeSize, true), (CostTarget::RunTime, true)] {
+                    for max_cells in [None, Some(4)] {
+                        let options = TextOptions { style, target, cleanup, max_cells, portable: true, verify: true, ..TextOptions::default() };
+                        let bf_code = bytes_2_bf_with_options(text, &options).unwrap();
+                        assert_eq!(run_strict(&bf_code), Ok(text.to_vec()), "{:?}", options);
+                        if cleanup {
+                            assert_eq!(run_interpret(process_code(&bf_code).unwrap(), b"").unwrap().final_tape.iter().max(), Some(&0));
+                        }
+                    }
+                }
+            }
+        }
+
+        // the default code wraps the cells around (the bytes above 127 are built from below zero, 255 is reached from 0)
+        assert!(run_strict(&bytes_2_bf(&all_bytes)).is_err());
+        assert!(run_strict(&bytes_2_bf_with_options(b"\xFA\x00\xFF", &TextOptions { style: TextCodeStyle::SingleCell, ..TextOptions::default() }).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_number_2_bf() {
+        //! Test that the `number_2_bf` function builds every number and leaves the scratch cells zero.
 
-        assert_eq!(bf_code, "++++++++++>>++++++[<+++++++++++>-]>+++++++[<++++++++++>-]>++++++++[<++++++++++++>-]<+>>+++++++++[<+++++++++++>-]>++++++++[<+++++++++++++>-]<+>>+++++++++[<++++++++++++>-]<->>++++++++++[<+++++++++++>-]>++++++++[<++++++++++++++>-]<++>>+++++++++[<+++++++++++++>-]<<<<<<<<<.>>>>>>>.<<<<<.>>.>>.<<<<<<<.>>.>>>>>>>.<<<<<.>>.");
+        for n in 0..=u8::MAX {
+            let bf_code = number_2_bf(n);
+            assert_eq!(bf_code.len(), number_cost(n));
+            assert!(bf_code.len() <= CONSTANT_TABLE[n as usize].cost() + 1);
+            let result = run_interpret(process_code(&bf_code).unwrap(), b"").unwrap();
+            assert_eq!((result.final_data_ptr, &result.final_tape[..3]), (0, &[n, 0, 0][..]), "number: {}", n);
+            assert_eq!(bf_2_bytes(&format!("{}.", number_2_bf_in_place(n))), Ok(vec![n]));
+        }
+    }
+
+    #[test]
+    fn test_add_bf_portable() {
+        //! Test that the `add_bf` function doesn't wrap the cell around if portable.
+
+        for start in (0..=u8::MAX).step_by(15) {
+            for value in 0..=u8::MAX {
+                let change = i16::from(value) - i16::from(start);
+                for target in [CostTarget::CodeSize, CostTarget::RunTime] {
+                    let bf_code = format!("{}{}.>.", "+".repeat(start as usize), add_bf(change, target, true));
+                    assert_eq!(run_strict(&bf_code), Ok(vec![value, 0]), "{} + {}", start, change);
+                }
+            }
+        }
     }
 
     #[test]
@@ -319,12 +2583,24 @@ mod tests {
         //! Test the `text_2_bytes` function.
 
         let text = "Hello, World!";
-        let bytes = text_2_bytes(text).unwrap();
+        let bytes = text_2_bytes(text, NewlinePolicy::Lf).unwrap();
         assert_eq!(bytes, vec![72, 101, 108, 108, 111, 44, 32, 87, 111, 114, 108, 100, 33]);
 
         let text = "😊Hello, World!";
-        let error = text_2_bytes(text).unwrap_err();
+        let error = text_2_bytes(text, NewlinePolicy::Lf).unwrap_err();
         assert_eq!(error, Error::NonASCIIChar('😊', 1, 1));
+
+        // the line endings by the policy (the skipped '\r' isn't a column)
+        let text = "a\r\nb\nc\r";
+        assert_eq!(text_2_bytes(text, NewlinePolicy::Preserve), Ok(b"a\r\nb\nc\r".to_vec()));
+        assert_eq!(text_2_bytes(text, NewlinePolicy::CrlfToLf), Ok(b"a\nb\nc\r".to_vec()));
+        assert_eq!(text_2_bytes(text, NewlinePolicy::Lf), Ok(b"a\nb\nc".to_vec()));
+        assert_eq!(text_2_bytes(text, NewlinePolicy::Crlf), Ok(b"a\r\nb\r\nc".to_vec()));
+        assert_eq!(text_2_bytes("a\r\rč", NewlinePolicy::Preserve), Err(Error::NonASCIIChar('č', 1, 4)));
+        assert_eq!(text_2_bytes("a\r\rč", NewlinePolicy::CrlfToLf), Err(Error::NonASCIIChar('č', 1, 4)));
+        assert_eq!(text_2_bytes("a\r\n\r\nč", NewlinePolicy::CrlfToLf), Err(Error::NonASCIIChar('č', 3, 1)));
+        assert_eq!(text_2_bytes("a\r\rč", NewlinePolicy::Lf), Err(Error::NonASCIIChar('č', 1, 2)));
+        assert_eq!(text_2_bytes("a\r\rč", NewlinePolicy::Crlf), Err(Error::NonASCIIChar('č', 1, 2)));
     }
 
     #[test]
@@ -336,48 +2612,122 @@ mod tests {
             all_bytes.push(i);
         }
 
-        let bf_code = store_bf_bytes(&all_bytes);
+        let bf_code = store_bf_bytes(&all_bytes, CostTarget::CodeSize, false);
+
+        // all bytes are stored in their cells, the data pointer is left after them
+        let mut io = VecIo::default();
+        interpret(&process_code(&format!("{}{}", bf_code, "<.".repeat(all_bytes.len()))).unwrap(), &mut io);
+        assert_eq!(io.output, all_bytes.iter().rev().copied().collect::<Vec<u8>>());
 
-        assert_eq!(bf_code, ">+>++>+++>++++>+++++>++++++>+++++++>++++++++>+++++++++>++++++++++>>+++[<++++>-]<->>+++[<++++>-]>+++[<++++>-]<+>>+++[<+++++>-]<->>+++[<+++++>-]>++++[<++++>-]>++++[<++++>-]<+>>+++[<++++++>-]>++++[<+++++>-]<->>++++[<+++++>-]>++++[<+++++>-]<+>>++++[<+++++>-]<++>>++++[<++++++>-]<->>++++[<++++++>-]>+++++[<+++++>-]>+++++[<+++++>-]<+>>++++[<+++++++>-]<->>++++[<+++++++>-]>+++++[<++++++>-]<->>+++++[<++++++>-]>+++++[<++++++>-]<+>>++++[<++++++++>-]>++++[<++++++++>-]<+>>+++++[<+++++++>-]<->>+++++[<+++++++>-]>++++++[<++++++>-]>++++++[<++++++>-]<+>>++++++[<++++++>-]<++>>+++++[<++++++++>-]<->>+++++[<++++++++>-]>++++++[<+++++++>-]<->>++++++[<+++++++>-]>++++++[<+++++++>-]<+>>+++++[<+++++++++>-]<->>+++++[<+++++++++>-]>+++++[<+++++++++>-]<+>>++++++[<++++++++>-]<->>++++++[<++++++++>-]>+++++++[<+++++++>-]>+++++++[<+++++++>-]<+>>+++++++[<+++++++>-]<++>>++++++[<+++++++++>-]<-->>++++++[<+++++++++>-]<->>++++++[<+++++++++>-]>+++++++[<++++++++>-]<->>+++++++[<++++++++>-]>+++++++[<++++++++>-]<+>>+++++++[<++++++++>-]<++>>++++++[<++++++++++>-]<->>++++++[<++++++++++>-]>++++++[<++++++++++>-]<+>>+++++++[<+++++++++>-]<->>+++++++[<+++++++++>-]>++++++++[<++++++++>-]>++++++++[<++++++++>-]<+>>++++++[<+++++++++++>-]>++++++[<+++++++++++>-]<+>>+++++++[<++++++++++>-]<-->>+++++++[<++++++++++>-]<->>+++++++[<++++++++++>-]>++++++++[<+++++++++>-]<->>++++++++[<+++++++++>-]>++++++++[<+++++++++>-]<+>>++++++++[<+++++++++>-]<++>>+++++++[<+++++++++++>-]<-->>+++++++[<+++++++++++>-]<->>+++++++[<+++++++++++>-]>+++++++[<+++++++++++>-]<+>>++++++++[<++++++++++>-]<->>++++++++[<++++++++++>-]>+++++++++[<+++++++++>-]>+++++++++[<+++++++++>-]<+>>+++++++[<++++++++++++>-]<->>+++++++[<++++++++++++>-]>+++++++[<++++++++++++>-]<+>>++++++++[<+++++++++++>-]<-->>++++++++[<+++++++++++>-]<->>++++++++[<+++++++++++>-]>+++++++++[<++++++++++>-]<->>+++++++++[<++++++++++>-]>+++++++++[<++++++++++>-]<+>>+++++++++[<++++++++++>-]<++>>+++++++++[<++++++++++>-]<+++>>++++++++[<++++++++++++>-]<-->>++++++++[<++++++++++++>-]<->>++++++++[<++++++++++++>-]>++++++++[<++++++++++++>-]<+>>+++++++++[<+++++++++++>-]<->>+++++++++[<+++++++++++>-]>++++++++++[<++++++++++>-]>++++++++++[<++++++++++>-]<+>>++++++++++[<++++++++++>-]<++>>++++++++[<+++++++++++++>-]<->>++++++++[<+++++++++++++>-]>++++++++[<+++++++++++++>-]<+>>+++++++++[<++++++++++++>-]<-->>+++++++++[<++++++++++++>-]<->>+++++++++[<++++++++++++>-]>++++++++++[<+++++++++++>-]<->>++++++++++[<+++++++++++>-]>++++++++++[<+++++++++++>-]<+>>++++++++[<++++++++++++++>-]>++++++++[<++++++++++++++>-]<+>>++++++++[<++++++++++++++>-]<++>>+++++++++[<+++++++++++++>-]<-->>+++++++++[<+++++++++++++>-]<->>+++++++++[<+++++++++++++>-]>+++++++++[<+++++++++++++>-]<+>>++++++++++[<++++++++++++>-]<->>++++++++++[<++++++++++++>-]>+++++++++++[<+++++++++++>-]>+++++++++++[<+++++++++++>-]<+>>+++++++++++[<+++++++++++>-]<++>>+++++++++[<++++++++++++++>-]<-->>+++++++++[<++++++++++++++>-]<->>+++++++++[<++++++++++++++>-]>+++++++++[<++++++++++++++>-]<+>>++++++++[<++++++++++++++++>-]>++++++++++[<+++++++++++++>-]<->>++++++++++[<+++++++++++++>-]>+++++++++++[<++++++++++++>-]<->>+++++++++++[<++++++++++++>-]>+++++++++++[<++++++++++++>-]<+>>+++++++++[<+++++++++++++++>-]<->>+++++++++[<+++++++++++++++>-]>+++++++++[<+++++++++++++++>-]<+>>+++++++++[<+++++++++++++++>-]<++>>++++++++++[<++++++++++++++>-]<-->>++++++++++[<++++++++++++++>-]<->>++++++++++[<++++++++++++++>-]>++++++++++[<++++++++++++++>-]<+>>+++++++++++[<+++++++++++++>-]<->>+++++++++++[<+++++++++++++>-]>++++++++++++[<++++++++++++>-]>++++++++++++[<++++++++++++>-]<+>>++++++++++++[<++++++++++++>-]<++>>++++++++++++[<++++++++++++>-]<+++>>++++++++++[<+++++++++++++++>-]<-->>++++++++++[<+++++++++++++++>-]<->>++++++++++[<+++++++++++++++>-]>++++++++++[<+++++++++++++++>-]<+>>+++++++++++[<++++++++++++++>-]<-->>+++++++++++[<++++++++++++++>-]<->>+++++++++++[<++++++++++++++>-]>++++++++++++[<+++++++++++++>-]<->>++++++++++++[<+++++++++++++>-]>++++++++++++[<+++++++++++++>-]<+>>++++++++++++[<+++++++++++++>-]<++>>++++++++++[<++++++++++++++++>-]<->>++++++++++[<++++++++++++++++>-]>++++++++++[<++++++++++++++++>-]<+>>+++++++++[<++++++++++++++++++>-]>+++++++++[<++++++++++++++++++>-]<+>>+++++++++++[<+++++++++++++++>-]<->>+++++++++++[<+++++++++++++++>-]>+++++++++++[<+++++++++++++++>-]<+>>++++++++++++[<++++++++++++++>-]<->>++++++++++++[<++++++++++++++>-]>+++++++++++++[<+++++++++++++>-]>+++++++++++++[<+++++++++++++>-]<+>>+++++++++++++[<+++++++++++++>-]<++>>+++++++++++++[<+++++++++++++>-]<+++>>+++++++++++[<++++++++++++++++>-]<--->>+++++++++++[<++++++++++++++++>-]<-->>+++++++++++[<++++++++++++++++>-]<->>+++++++++++[<++++++++++++++++>-]>+++++++++++[<++++++++++++++++>-]<+>>++++++++++++[<+++++++++++++++>-]<-->>++++++++++++[<+++++++++++++++>-]<->>++++++++++++[<+++++++++++++++>-]>+++++++++++++[<++++++++++++++>-]<->>+++++++++++++[<++++++++++++++>-]>+++++++++++++[<++++++++++++++>-]<+>>+++++++++++++[<++++++++++++++>-]<++>>+++++++++++[<+++++++++++++++++>-]<-->>+++++++++++[<+++++++++++++++++>-]<->>+++++++++++[<+++++++++++++++++>-]>+++++++++++[<+++++++++++++++++>-]<+>>++++++++++[<+++++++++++++++++++>-]<->>++++++++++[<+++++++++++++++++++>-]>++++++++++++[<++++++++++++++++>-]<->>++++++++++++[<++++++++++++++++>-]>++++++++++++[<++++++++++++++++>-]<+>>+++++++++++++[<+++++++++++++++>-]<->>+++++++++++++[<+++++++++++++++>-]>++++++++++++++[<++++++++++++++>-]>++++++++++++++[<++++++++++++++>-]<+>>+++++++++++[<++++++++++++++++++>-]>+++++++++++[<++++++++++++++++++>-]<+>>++++++++++[<++++++++++++++++++++>-]>++++++++++[<++++++++++++++++++++>-]<+>>++++++++++++[<+++++++++++++++++>-]<-->>++++++++++++[<+++++++++++++++++>-]<->>++++++++++++[<+++++++++++++++++>-]>++++++++++++[<+++++++++++++++++>-]<+>>+++++++++++++[<++++++++++++++++>-]<-->>+++++++++++++[<++++++++++++++++>-]<->>+++++++++++++[<++++++++++++++++>-]>++++++++++++++[<+++++++++++++++>-]<->>++++++++++++++[<+++++++++++++++>-]>++++++++++++++[<+++++++++++++++>-]<+>>++++++++++++++[<+++++++++++++++>-]<++>>++++++++++++++[<+++++++++++++++>-]<+++>>++++++++++++[<++++++++++++++++++>-]<-->>++++++++++++[<++++++++++++++++++>-]<->>++++++++++++[<++++++++++++++++++>-]>++++++++++++[<++++++++++++++++++>-]<+>>++++++++++++[<++++++++++++++++++>-]<++>>+++++++++++++[<+++++++++++++++++>-]<-->>+++++++++++++[<+++++++++++++++++>-]<->>+++++++++++++[<+++++++++++++++++>-]>+++++++++++++[<+++++++++++++++++>-]<+>>++++++++++++++[<++++++++++++++++>-]<->>++++++++++++++[<++++++++++++++++>-]>+++++++++++++++[<+++++++++++++++>-]>+++++++++++++++[<+++++++++++++++>-]<+>>++++++++++++[<+++++++++++++++++++>-]<->>++++++++++++[<+++++++++++++++++++>-]>++++++++++++[<+++++++++++++++++++>-]<+>>+++++++++++[<+++++++++++++++++++++>-]<->>+++++++++++[<+++++++++++++++++++++>-]>+++++++++++[<+++++++++++++++++++++>-]<+>>+++++++++++++[<++++++++++++++++++>-]<->>+++++++++++++[<++++++++++++++++++>-]>+++++++++++++[<++++++++++++++++++>-]<+>>++++++++++++++[<+++++++++++++++++>-]<-->>++++++++++++++[<+++++++++++++++++>-]<->>++++++++++++++[<+++++++++++++++++>-]>+++++++++++++++[<++++++++++++++++>-]<->>+++++++++++++++[<++++++++++++++++>-]>+++++++++++++++[<++++++++++++++++>-]<+>>+++++++++++++++[<++++++++++++++++>-]<++>>+++++++++++++++[<++++++++++++++++>-]<+++>>+++++++++++++[<+++++++++++++++++++>-]<--->>+++++++++++++[<+++++++++++++++++++>-]<-->>+++++++++++++[<+++++++++++++++++++>-]<->>+++++++++++++[<+++++++++++++++++++>-]>+++++++++++++[<+++++++++++++++++++>-]<+>>+++++++++++++[<+++++++++++++++++++>-]<++>>++++++++++++++[<++++++++++++++++++>-]<-->>++++++++++++++[<++++++++++++++++++>-]<->>++++++++++++++[<++++++++++++++++++>-]>++++++++++++++[<++++++++++++++++++>-]<+>>+++++++++++++++[<+++++++++++++++++>-]<->>+++++++++++++++[<+++++++++++++++++>-]");
+        // the code is not longer than with the products of two factors only (7556 commands)
+        assert!(bf_code.len() <= 7556, "The length of the code is {}.", bf_code.len());
+
+        insta::assert_snapshot!(bf_code);
     }
 
     #[test]
     fn test_print_bf_bytes() {
         //! Test the `print_bf_bytes` function.
 
-        let text_bytes = text_2_bytes("Brainfuck").unwrap();
-        let store_ord = [66, 97, 99, 102, 105, 107, 110, 114, 117];
-        let mut data_ptr = 10;
-        let bf_code = print_bf_bytes(&text_bytes, &store_ord, data_ptr);
+        let text_bytes = text_2_bytes("Brainfuck", NewlinePolicy::Lf).unwrap();
+        let mut store_ord = [66, 97, 99, 102, 105, 107, 110, 114, 117];
+        let mut data_ptr = 8;
+        let bf_code = print_code(&text_bytes, &store_ord, data_ptr).0;
 
         let mut out_str = String::new();
         for c in bf_code.chars() {
             match c {
                 '>' => data_ptr += 1,
                 '<' => data_ptr -= 1,
+                '+' => store_ord[data_ptr] += 1,
+                '-' => store_ord[data_ptr] -= 1,
                 '.' => out_str.push(store_ord[data_ptr] as char),
                 _ => panic!("Unexpected Brainfuck command!"),
             }
         }
         assert_eq!(out_str, "Brainfuck");
+
+        // the nearby cell is adjusted instead of walking across the table
+        // (the adjusted cell keeps the new value)
+        assert_eq!(print_code(b"ab", b"bcdefghija", 0), (String::from("-.+."), b"bcdefghija".to_vec(), 0));
+        assert_eq!(print_code(b"a", b"bcd", 2), (String::from("<<-."), b"acd".to_vec(), 0));
+        // but the exact cell is preferred if it is as cheap
+        assert_eq!(print_code(b"ca", b"abc", 0).0, ">>.<<.");
+        assert_eq!(print_code(b"aaa", b"b", 0).0, "-...");
+
+        // the text whose printing adjusts the cells is still printed correctly (from the unsorted table)
+        let text = "ayazbycydy";
+        let store_ord = b"zabcdy";
+        let bf_code = store_bf_bytes(store_ord, CostTarget::CodeSize, false) + &print_code(text.as_bytes(), store_ord, store_ord.len()).0;
+        assert!(bf_code.contains("+."), "{}", bf_code);
+        let mut io = VecIo::default();
+        interpret(&process_code(&bf_code).unwrap(), &mut io);
+        assert_eq!(io.output, text.as_bytes());
+
+        // the runs are printed by a loop with the counter after the table (the short ones by repeating `.`)
+        assert_eq!(print_code(b"aaaa", b"a", 1).0, "<....");
+        assert_eq!(print_code(&[b'a'; 30], b"a", 1).0, ">+++++[<++++++>-]<[<.>-]");
+        assert_eq!(print_code(&[b'b'; 300], b"ab", 0).0, ">>-[<.>-]>+++++[<+++++++++>-]<[<.>-]");
+
+        // the banner full of repeated characters
+        let text = "\
++----------------------------------------+
+|                                        |
+|    ==== bfuck ====    ****************  |
+|                                        |
++----------------------------------------+
+################################################################################
+";
+        let bf_code = text_2_bf(text).unwrap();
+        // the long runs are printed by the loops
+        assert!(bf_code.matches('.').count() < text.len() / 2, "{}", bf_code);
+        for style in [TextCodeStyle::Table, TextCodeStyle::Auto] {
+            let bf_code = text_2_bf_with_options(text, &TextOptions { style, ..TextOptions::default() }).unwrap();
+            let result = run_interpret(process_code(&bf_code).unwrap(), b"").unwrap();
+            assert_eq!(result.output, text.as_bytes());
+            // the counter after the table is left zero
+            let mut distinct = text.as_bytes().to_vec();
+            distinct.sort();
+            distinct.dedup();
+            assert_eq!(result.final_tape[distinct.len()], 0);
+        }
     }
 
     #[test]
-    fn test_factor_table() {
-        //! Test the `factor_table` function.
+    fn test_constant_table() {
+        //! Test the `constant_table` function.
 
         // since the only factors of prime numbers are 1 and the number itself,
-        // the factor_table should not have difference 0 for prime numbers
-
-        for (n, val) in factor_table().into_iter().enumerate() {
-            if let Some((f1, f2, diff)) = val {
-                assert_eq!(n as u8, (f1 * f2).checked_add_signed(diff).unwrap(), "The number {} is not represented correctly.", n);
+        // the products should not have difference 0 for prime numbers
+        for (n, construction) in constant_table(false).into_iter().enumerate() {
+            assert_eq!(construction.value(), n as u8, "The number {} is not represented correctly.", n);
+            assert_eq!(construction.to_bf().len(), construction.cost());
 
-                let (is_prime, _) = is_prime(n as u64);
-                if is_prime {
-                    assert_ne!(diff, 0, "The prime number {} has difference != 0.", n);
-                }
+            match construction {
+                Construction::Product(_, _, 0) | Construction::NestedProduct(_, _, _, 0) => {
+                    assert!(!is_prime(n as u64).0, "The prime number {} has difference 0.", n);
+                },
+                _ => {},
             }
+
+            // the stored byte is the number
+            let bf_code = format!("{}<.", construction.to_bf());
+            let mut io = VecIo::default();
+            interpret(&process_code(&bf_code).unwrap(), &mut io);
+            assert_eq!(io.output, [n as u8]);
+        }
+
+        // some numbers are the shortest as the nested products
+        assert_eq!(CONSTANT_TABLE[125], Construction::NestedProduct(5, 5, 5, 0));
+        assert!(CONSTANT_TABLE.iter().filter(|construction| matches!(construction, Construction::NestedProduct(..))).count() > 20);
+
+        // the portable constructions don't wrap the cells around and are at most as short as the others
+        for (n, construction) in constant_table(true).into_iter().enumerate() {
+            assert_eq!(construction.value(), n as u8);
+            assert_eq!(run_strict(&format!("{}<.", construction.to_bf())), Ok(vec![n as u8]));
+            assert!(construction.cost() >= CONSTANT_TABLE[n].cost());
         }
+        assert_eq!(CONSTANT_TABLE[250], Construction::Plain(-6));
+        assert_eq!(PORTABLE_CONSTANT_TABLE[250], Construction::NestedProduct(5, 5, 10, 0));
     }
 }
@@ -0,0 +1,172 @@
+//! Tiered execution of Brainfuck programs.
+
+
+
+use std::thread;
+
+use crate::code::{FinalState, STORAGE_SIZE, Token};
+use crate::error::Error;
+use crate::interpret::{interpret_from, Stop};
+use crate::io::{EofPolicy, IoAdapter};
+use crate::jit::{JitOptions, JitProgram};
+
+
+
+/// Run given token stream using tiered execution.
+/// The program is interpreted right away, while it is JIT-compiled on a background thread.
+/// When the compilation finishes, the execution continues in the compiled code at the next jump back to the start of a loop
+/// (with the same array, data pointer and [IoAdapter], so the output continues seamlessly).
+/// If the JIT-compilation fails (e.g. on an unsupported platform), the whole program is interpreted.
+/// If the program finishes before the compilation, it returns right away (the compilation is left to finish in the background).
+/// # Arguments
+/// * token_stream - The [TokenStream](crate::code::TokenStream) to run.
+/// * io - The [IoAdapter] used for input and output (e.g. [StdIo](crate::io::StdIo)).
+/// * eof - The [EofPolicy] applied when the `,` command reads past the end of input.
+/// # Returns
+/// * [FinalState] - The final data pointer and the value of the current cell.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::io::{EofPolicy, VecIo};
+/// use bfuck::tiered::run_tiered;
+///
+/// let tokens = process_code("++++++++[>++++++++<-]>+.+.+.").unwrap();
+///
+/// let mut io = VecIo::default();
+/// run_tiered(&tokens, &mut io, EofPolicy::Zero);
+/// assert_eq!(io.output, b"ABC");
+/// ```
+pub fn run_tiered(token_stream: &[Token], io: &mut dyn IoAdapter, eof: EofPolicy) -> FinalState {
+    // the compiler thread is detached, so a program finishing in the interpreter doesn't wait for the compilation
+    let tokens = token_stream.to_vec();
+    let options = JitOptions { eof, ..JitOptions::default() };
+    let mut compiler = Some(thread::spawn(move || JitProgram::compile_resumable(&tokens, &options)));
+
+    // the compiled program is taken once the background thread finishes
+    let poll = || {
+        compiler
+            .take_if(|compiler| compiler.is_finished())
+            .map(|compiler| compiler.join().expect("The JIT-compiler thread panicked."))
+    };
+    run_switching(token_stream, io, eof, poll)
+}
+
+/// Interpret given token stream and switch to the compiled program once it is available.
+/// # Arguments
+/// * token_stream - The [TokenStream](crate::code::TokenStream) to run.
+/// * io - The [IoAdapter] used for input and output.
+/// * eof - The [EofPolicy] applied at the end of input (the compiled program has to use the same policy).
+/// * poll - Called before every jump back to the start of a loop, returns the result of the compilation once it is available.
+/// # Returns
+/// * [FinalState] - The final data pointer and the value of the current cell.
+fn run_switching(token_stream: &[Token], io: &mut dyn IoAdapter, eof: EofPolicy, mut poll: impl FnMut() -> Option<Result<JitProgram, Error>>) -> FinalState {
+    let mut storage = [0_u8; STORAGE_SIZE];
+
+    let mut program = None;
+    let mut pause = || match poll() {
+        Some(Ok(compiled)) => {
+            program = Some(compiled);
+            true
+        },
+        // if the compilation failed, continue interpreting
+        Some(Err(_)) | None => false,
+    };
+
+    match interpret_from(token_stream, &mut storage, 0, 0, io, eof, &mut pause) {
+        Stop::Finished(data_ptr) => FinalState { data_ptr, cell: storage[data_ptr] },
+        Stop::Paused { loop_start, data_ptr } => {
+            // the interpretation is paused only when the program is compiled
+            program.unwrap().resume(&mut storage, data_ptr, loop_start, io)
+        },
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::code::process_code;
+    use crate::interpret::interpret;
+    use crate::io::VecIo;
+
+    #[test]
+    fn test_run_switching() {
+        //! Test that switching to the compiled program at any jump back to the start of a loop matches the interpreter.
+
+        let codes = [
+            // prints before and after every possible switch
+            "++++++++[>++++++++<-]>+.<+++++[>.+<-]>.",
+            ",[.,]",
+            "++[>+++[>,.<-]<-]>>.",
+            "+++[>>+++[-<+.>]<<-]",
+            "<<+[<+>-]<.>>>++[<<<.>>>-]",
+            "-[>-[>+[->+>+<<]>>[-<<+>>]<<<-]<-]>>>>.",
+        ];
+        for code in codes {
+            let tokens = process_code(code).unwrap();
+            let mut expected_io = VecIo::new(b"hello");
+            let expected_state = interpret(&tokens, &mut expected_io);
+
+            // switch after the given number of polls
+            for switch_after in 0..40 {
+                let mut program = Some(JitProgram::compile_resumable(&tokens, &JitOptions::default()).unwrap());
+                let mut polls = 0;
+                let poll = || {
+                    polls += 1;
+                    if polls > switch_after { program.take().map(Ok) } else { None }
+                };
+
+                let mut io = VecIo::new(b"hello");
+                assert_eq!(run_switching(&tokens, &mut io, EofPolicy::Zero, poll), expected_state, "code: {}, switch after: {}", code, switch_after);
+                assert_eq!(io, expected_io, "code: {}, switch after: {}", code, switch_after);
+            }
+        }
+    }
+
+    #[test]
+    fn test_failed_compilation() {
+        //! Test that the program is interpreted if the compilation fails.
+
+        let tokens = process_code("+++[>++<-]>.").unwrap();
+        let mut io = VecIo::default();
+        let state = run_switching(&tokens, &mut io, EofPolicy::Zero, || Some(Err(Error::UnsupportedPlatformJIT)));
+        assert_eq!(state, FinalState { data_ptr: 1, cell: 6 });
+        assert_eq!(io.output, [6]);
+    }
+
+    #[test]
+    fn test_run_tiered() {
+        //! Test the run_tiered function.
+
+        let tokens = process_code(&std::fs::read_to_string("examples/fizzbuzz.bf").unwrap()).unwrap();
+        let mut expected_io = VecIo::default();
+        let expected_state = interpret(&tokens, &mut expected_io);
+
+        let mut io = VecIo::default();
+        assert_eq!(run_tiered(&tokens, &mut io, EofPolicy::Zero), expected_state);
+        assert_eq!(io, expected_io);
+    }
+
+    #[test]
+    fn test_run_tiered_without_waiting() {
+        //! Test that run_tiered returns without waiting for the compilation when the interpreter finishes first.
+
+        // the huge loop is skipped by the interpreter, but it has to be compiled
+        let code = format!("+.-[{}]", ">+>.<<.".repeat(5_000));
+        let tokens = process_code(&code).unwrap();
+
+        let start = Instant::now();
+        let mut io = VecIo::default();
+        assert_eq!(run_tiered(&tokens, &mut io, EofPolicy::Zero), FinalState { data_ptr: 0, cell: 0 });
+        let tiered = start.elapsed();
+        assert_eq!(io.output, [1]);
+
+        let start = Instant::now();
+        JitProgram::compile_resumable(&tokens, &JitOptions::default()).unwrap();
+        let compilation = start.elapsed();
+        assert!(tiered * 10 < compilation, "tiered: {:?}, compilation: {:?}", tiered, compilation);
+    }
+}
@@ -0,0 +1,766 @@
+//! Tools for working with Brainfuck source code.
+
+
+
+use std::fmt::Display;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::code::{process_code, signed_distance, tokens_to_bf, Token, TokenStream, STORAGE_SIZE};
+use crate::error::Error;
+
+
+
+/// Minify the Brainfuck code.
+///
+/// The comments (all the characters that aren't commands) are removed and the commands are joined into a single line.
+/// The adjacent commands cancelling each other (`+-`, `-+`, `<>` and `><`) are removed as well (repeatedly,
+/// so `++--` is removed whole), the other runs are left as they are, [process_code] merges them anyway.
+/// A loop body is never cancelled to nothing (its last cancelled pair is kept), so the minified code has no
+/// [TriviallyInfinite](crate::error::Warning::TriviallyInfinite) loops the original code didn't have.
+/// # Arguments
+/// * code - A string slice that holds the Brainfuck code.
+/// # Returns
+/// * [String] - The minified code, if [Ok].
+/// * [Error] - The error of [process_code], if [Err].
+/// # Errors
+/// * The errors of [process_code] (the minified code is accepted by it if the original code is).
+/// # Example
+/// ```
+/// use bfuck::tools::minify;
+///
+/// assert_eq!(minify("+++ add three\n>+<- move and back\n[.-]").unwrap(), "+++>+<-[.-]");
+/// assert_eq!(minify("+- <>").unwrap(), "");
+/// assert_eq!(minify("[+ - ]").unwrap(), "[+-]");
+/// ```
+pub fn minify(code: &str) -> Result<String, Error> {
+    process_code(code)?;
+
+    let mut minified: Vec<u8> = Vec::with_capacity(code.len());
+    // the last pair cancelled since the last open bracket
+    let mut cancelled: Option<[u8; 2]> = None;
+    for c in code.bytes() {
+        let inverse = match c {
+            b'+' => b'-',
+            b'-' => b'+',
+            b'<' => b'>',
+            b'>' => b'<',
+            b'[' => {
+                cancelled = None;
+                minified.push(c);
+                continue;
+            },
+            b']' => {
+                // restore the last cancelled pair of the loop body cancelled to nothing
+                if let (Some(b'['), Some(pair)) = (minified.last(), cancelled) {
+                    minified.extend(pair);
+                }
+                cancelled = None;
+                minified.push(c);
+                continue;
+            },
+            b'.' | b',' => {
+                minified.push(c);
+                continue;
+            },
+            _ => continue,
+        };
+
+        if minified.last() == Some(&inverse) {
+            minified.pop();
+            cancelled = Some([inverse, c]);
+        } else {
+            minified.push(c);
+        }
+    }
+
+    // only ASCII commands are pushed
+    Ok(String::from_utf8(minified).unwrap())
+}
+
+
+/// Format the Brainfuck code to be readable.
+///
+/// Each bracket is put on its own line and the loop bodies are indented (one level per loop).
+/// The other commands and the comments are kept as they are, but the lines are trimmed and the empty ones are left out,
+/// and the lines longer than `line_width` characters (with the indentation) are broken,
+/// so the formatted code differs only in the whitespace (which is a comment) and runs the same.
+/// # Arguments
+/// * code - A string slice that holds the Brainfuck code.
+/// * line_width - The maximum number of the characters in a line (at least one character is put after the indentation).
+/// * indent - The indentation of one level.
+/// # Returns
+/// * [String] - The formatted code (without the trailing newline), if [Ok].
+/// * [Error] - The error of [process_code], if [Err].
+/// # Errors
+/// * The errors of [process_code].
+/// # Example
+/// ```
+/// use bfuck::tools::format_bf;
+///
+/// assert_eq!(format_bf("++[>+<-] done", 80, "  ").unwrap(), "++\n[\n  >+<-\n]\ndone");
+/// ```
+pub fn format_bf(code: &str, line_width: usize, indent: &str) -> Result<String, Error> {
+    process_code(code)?;
+
+    let mut lines = Vec::new();
+    let mut level = 0;
+    let mut segment_start = 0;
+    for (i, c) in code.char_indices() {
+        if c == '[' || c == ']' {
+            push_lines(&mut lines, &code[segment_start..i], &indent.repeat(level), line_width);
+            if c == ']' {
+                level -= 1;
+            }
+            lines.push(format!("{}{}", indent.repeat(level), c));
+            if c == '[' {
+                level += 1;
+            }
+            segment_start = i + 1;
+        }
+    }
+    push_lines(&mut lines, &code[segment_start..], &indent.repeat(level), line_width);
+
+    Ok(lines.join("\n"))
+}
+
+/// Convert the tokens back to Brainfuck code with a comment explaining each token.
+///
+/// Each token is put on its own line (the loop bodies are indented), followed by `//` and the explanation.
+/// The explanations contain no commands, so the annotated code is the same program as the tokens
+/// (processing it results in the same tokens, see [tokens_to_bf]).
+/// # Arguments
+/// * token_stream - The [TokenStream] to annotate.
+/// # Returns
+/// * [String] - The annotated Brainfuck code.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::tools::annotate_bf;
+///
+/// let tokens = process_code("+++++[->>+<<]").unwrap();
+/// assert_eq!(annotate_bf(&tokens), "+++++ // add 5 to the cell\n[->>+<<] // add the cell to the cell 2 to the right and clear it\n");
+/// ```
+pub fn annotate_bf(token_stream: &TokenStream) -> String {
+    let mut annotated = String::new();
+    let mut level = 0;
+    for token in token_stream {
+        if let Token::CloseBr(_) = token {
+            level -= 1;
+        }
+        let explanation = match *token {
+            Token::Add(n) if n < 128 => format!("add {} to the cell", n),
+            Token::Add(n) => format!("subtract {} from the cell", 256 - n as usize),
+            Token::Move(n) => match signed_distance(n) {
+                d if d < 0 => format!("move the pointer left {}", d.unsigned_abs()),
+                d => format!("move the pointer right {}", d),
+            },
+            Token::Input => String::from("read a byte into the cell"),
+            Token::Output => String::from("print the cell"),
+            Token::OpenBr(_) => String::from("start the loop (skip it if the cell is zero)"),
+            Token::CloseBr(_) => String::from("repeat the loop if the cell is not zero"),
+            Token::ClearCell => String::from("clear the cell"),
+            Token::AddTo(n) => format!("add the cell to the cell {} and clear it", describe_offset(n)),
+            Token::AddToCopy(n1, n2) => format!(
+                "add the cell to the cells {} and {} and clear it", describe_offset(n1), describe_offset(n2),
+            ),
+            Token::Debug => String::from("print the debug dump of the cells"),
+            Token::SetCell(n) => format!("set the cell to {}", n),
+            Token::AddToCopy3(n1, n2, n3) => format!(
+                "add the cell to the cells {} and {} and {} and clear it", describe_offset(n1), describe_offset(n2), describe_offset(n3),
+            ),
+        };
+        annotated.push_str(&"    ".repeat(level));
+        annotated.push_str(&tokens_to_bf(&[*token]));
+        annotated.push_str(" // ");
+        annotated.push_str(&explanation);
+        annotated.push('\n');
+        if let Token::OpenBr(_) = token {
+            level += 1;
+        }
+    }
+    annotated
+}
+
+/// Decompile the tokens to the readable pseudocode (one statement per line, the loop bodies indented).
+///
+/// The array is `cell` and the data pointer is `ptr`, e.g. [Token::Add] is `cell[ptr] += n`,
+/// [Token::AddTo] is `cell[ptr+n] += cell[ptr]; cell[ptr] = 0` and the loop is `while cell[ptr] != 0 { ... }`.
+/// The pseudocode isn't meant to be compiled, the wrapping of the cells and of the data pointer is left implicit.
+/// # Arguments
+/// * token_stream - The [TokenStream] to decompile.
+/// # Returns
+/// * [String] - The pseudocode.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::tools::decompile_to_pseudocode;
+///
+/// let tokens = process_code(",[.,]").unwrap();
+/// assert_eq!(decompile_to_pseudocode(&tokens), "\
+/// cell[ptr] = read_byte()
+/// while cell[ptr] != 0 {
+///     write_byte(cell[ptr])
+///     cell[ptr] = read_byte()
+/// }
+/// ");
+/// ```
+pub fn decompile_to_pseudocode(token_stream: &TokenStream) -> String {
+    let mut pseudocode = String::new();
+    let mut level = 0;
+    for token in token_stream {
+        if let Token::CloseBr(_) = token {
+            level -= 1;
+        }
+        let statement = match *token {
+            Token::Add(n) if n < 128 => format!("cell[ptr] += {}", n),
+            Token::Add(n) => format!("cell[ptr] -= {}", 256 - n as usize),
+            Token::Move(n) => match signed_distance(n) {
+                d if d < 0 => format!("ptr -= {}", d.unsigned_abs()),
+                d => format!("ptr += {}", d),
+            },
+            Token::Input => String::from("cell[ptr] = read_byte()"),
+            Token::Output => String::from("write_byte(cell[ptr])"),
+            Token::OpenBr(_) => String::from("while cell[ptr] != 0 {"),
+            Token::CloseBr(_) => String::from("}"),
+            Token::ClearCell => String::from("cell[ptr] = 0"),
+            Token::AddTo(n) => format!("{}; cell[ptr] = 0", add_to_cell(n)),
+            Token::AddToCopy(n1, n2) => format!("{}; {}; cell[ptr] = 0", add_to_cell(n1), add_to_cell(n2)),
+            Token::Debug => String::from("debug_dump()"),
+            Token::SetCell(n) => format!("cell[ptr] = {}", n),
+            Token::AddToCopy3(n1, n2, n3) => format!("{}; {}; {}; cell[ptr] = 0", add_to_cell(n1), add_to_cell(n2), add_to_cell(n3)),
+        };
+        pseudocode.push_str(&"    ".repeat(level));
+        pseudocode.push_str(&statement);
+        pseudocode.push('\n');
+        if let Token::OpenBr(_) = token {
+            level += 1;
+        }
+    }
+    pseudocode
+}
+
+/// The pseudocode adding the current cell to the cell at the given distance (e.g. `cell[ptr-2] += cell[ptr]`).
+/// # Arguments
+/// * n - The distance (modulo [STORAGE_SIZE]).
+/// # Returns
+/// * [String] - The pseudocode.
+fn add_to_cell(n: usize) -> String {
+    match signed_distance(n) {
+        d if d < 0 => format!("cell[ptr-{}] += cell[ptr]", d.unsigned_abs()),
+        d => format!("cell[ptr+{}] += cell[ptr]", d),
+    }
+}
+
+/// Convert the tokens to the equivalent, but harder to read Brainfuck code.
+///
+/// The code is changed by these (pseudo-random) transformations:
+/// * The additions and moves between the other commands are shuffled (each cell gets the same sum in any order)
+///   and padded with the commands cancelling each other (`+-`, `><`).
+/// * The cells are cleared by `[-]` or `[+]`, and `[-]` is added before setting the cell that is known to be zero.
+/// * The dead loops with random bodies are added where the current cell is known to be zero (they are never entered).
+///
+/// The transformations depend only on the tokens, so the same tokens are always obfuscated the same way.
+/// # Arguments
+/// * token_stream - The [TokenStream] to obfuscate.
+/// # Returns
+/// * [String] - The obfuscated Brainfuck code, if [Ok].
+/// * [Error] - The error, if [Err].
+/// # Errors
+/// * The errors of [process_code] for the obfuscated code (the brackets of the tokens are unmatched).
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::interpret::interpret_from_str;
+/// use bfuck::tools::obfuscate;
+///
+/// let code = "++++++++[>++++++++<-]>+.+.";
+/// let obfuscated = obfuscate(&process_code(code).unwrap()).unwrap();
+/// assert_eq!(interpret_from_str(&obfuscated, b""), Ok(b"AB".to_vec()));
+/// ```
+pub fn obfuscate(token_stream: &TokenStream) -> Result<String, Error> {
+    let mut hasher = DefaultHasher::new();
+    token_stream.hash(&mut hasher);
+    let mut rng = XorShift(hasher.finish() | 1);
+
+    let mut code = String::new();
+    // whether the current cell is known to be zero (all cells are zero at the start)
+    let mut zero = true;
+    let mut tokens = token_stream.iter().peekable();
+    while let Some(&token) = tokens.next() {
+        match token {
+            Token::Add(_) | Token::Move(_) => {
+                // the block of the additions and moves
+                let mut block = vec![token];
+                while let Some(&next) = tokens.next_if(|next| matches!(next, Token::Add(_) | Token::Move(_))) {
+                    block.push(next);
+                }
+
+                // the changes of the cells relative to the data pointer at the start of the block
+                let mut changes: Vec<(isize, u8)> = Vec::new();
+                let mut end = 0;
+                for token in block {
+                    match token {
+                        Token::Add(n) => match changes.iter_mut().find(|(cell, _)| *cell == end) {
+                            Some((_, change)) => *change = change.wrapping_add(n),
+                            None => changes.push((end, n)),
+                        },
+                        Token::Move(n) => end += signed_distance(n),
+                        _ => unreachable!("The block contains only the additions and moves."),
+                    }
+                }
+
+                // change the cells in the shuffled order (Fisher-Yates)
+                for j in (1..changes.len()).rev() {
+                    changes.swap(j, rng.below(j + 1));
+                }
+                let mut offset = 0;
+                for (cell, change) in changes {
+                    code.push_str(&padded_move(cell - offset, &mut rng));
+                    code.push_str(&padded_add(change, &mut rng));
+                    offset = cell;
+                }
+                code.push_str(&padded_move(end - offset, &mut rng));
+                zero = false;
+            },
+            Token::ClearCell => {
+                code.push_str(if rng.below(2) == 0 { "[-]" } else { "[+]" });
+                zero = true;
+            },
+            Token::SetCell(n) => {
+                // the cell is cleared (again)
+                code.push_str(if zero { "[-][-]" } else { "[-]" });
+                code.push_str(&padded_add(n, &mut rng));
+                zero = n == 0;
+            },
+            _ => {
+                code.push_str(&tokens_to_bf(&[token]));
+                zero = match token {
+                    Token::Input | Token::OpenBr(_) => false,
+                    // the loop is left and the current cell is added to the others only when it is zero
+                    Token::CloseBr(_) | Token::AddTo(_) | Token::AddToCopy(_, _) | Token::AddToCopy3(_, _, _) => true,
+                    _ => zero,
+                };
+            },
+        }
+
+        // the dead loop
+        if zero && rng.below(3) == 0 {
+            code.push('[');
+            for _ in 0..(3 + rng.below(6)) {
+                code.push(b"+-<>.,"[rng.below(6)] as char);
+            }
+            code.push(']');
+        }
+    }
+
+    process_code(&code)?;
+    Ok(code)
+}
+
+/// The simple pseudo-random number generator (xorshift64) of [obfuscate].
+struct XorShift(u64);
+impl XorShift {
+    /// Generate the number less than `n`.
+    /// # Arguments
+    /// * n - The upper bound (must be positive).
+    /// # Returns
+    /// * `usize` - The pseudo-random number.
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// Generate the code adding the value to the current cell, with the cancelling commands around it (e.g. `++-+++-`).
+/// # Arguments
+/// * n - The added value.
+/// * rng - The pseudo-random number generator.
+/// # Returns
+/// * [String] - The Brainfuck code.
+fn padded_add(n: u8, rng: &mut XorShift) -> String {
+    let (sign, inverse, count) = if n < 128 { ('+', '-', n as usize) } else { ('-', '+', 256 - n as usize) };
+    let extra = rng.below(3);
+    let mut commands: Vec<char> = std::iter::repeat_n(sign, count + extra).chain(std::iter::repeat_n(inverse, extra)).collect();
+    // the sum is the same in any order
+    for j in (1..commands.len()).rev() {
+        commands.swap(j, rng.below(j + 1));
+    }
+    commands.into_iter().collect()
+}
+
+/// Generate the code moving the data pointer by the distance, with the cancelling moves around it (e.g. `><>>`).
+/// # Arguments
+/// * distance - The distance (negative to the left).
+/// * rng - The pseudo-random number generator.
+/// # Returns
+/// * [String] - The Brainfuck code.
+fn padded_move(distance: isize, rng: &mut XorShift) -> String {
+    let (dir, inverse) = if distance < 0 { ('<', '>') } else { ('>', '<') };
+    let mut code = String::from(match rng.below(3) {
+        0 => "",
+        1 => "><",
+        _ => "<>",
+    });
+    code.extend(std::iter::repeat_n(dir, distance.unsigned_abs()));
+    if distance != 0 && rng.below(2) == 0 {
+        code.push(dir);
+        code.push(inverse);
+    }
+    code
+}
+
+/// The kind of the issue found by [lint].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LintKind {
+    /// The move is immediately followed by the move back (the pair does nothing, [process_code] would merge it).
+    UnnecessaryMove,
+    /// The loop body neither moves the data pointer nor reads the input, and it leaves the current cell
+    /// set to a non-zero value or changed by an even value (including zero), so the loop never terminates
+    /// for some (or all) values of the cell.
+    PossiblyInfiniteLoop,
+    /// The program has no output command (reported once, at the first token).
+    NoOutputProduced,
+    /// The input is read after printing (e.g. a prompt), the interpreters which don't flush their output
+    /// before reading may wait for the input with the prompt still in their buffer.
+    InputWithoutFlush,
+    /// The clear cell follows another clear cell (the second one does nothing).
+    ConsecutiveClears,
+}
+impl Display for LintKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LintKind::UnnecessaryMove => write!(f, "The move is undone by the next move."),
+            LintKind::PossiblyInfiniteLoop => write!(f, "The loop may never terminate."),
+            LintKind::NoOutputProduced => write!(f, "The program produces no output."),
+            LintKind::InputWithoutFlush => write!(f, "The input is read after the output, which may not be flushed yet."),
+            LintKind::ConsecutiveClears => write!(f, "The cell is already cleared."),
+        }
+    }
+}
+
+/// The issue found by [lint].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// The kind of the issue.
+    pub kind: LintKind,
+    /// The index of the token at which the issue is found (the opening bracket for the loops).
+    pub token_index: usize,
+}
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Token {}: {}", self.token_index, self.kind)
+    }
+}
+
+/// Find the style and correctness issues in the token stream.
+///
+/// The issues are found in the token stream, not in the source code, so the ones removed by the optimizations
+/// (e.g. the unnecessary moves, which [process_code] merges) are found only in the token streams built otherwise.
+/// # Arguments
+/// * token_stream - The [TokenStream] to check.
+/// # Returns
+/// * `Vec<Lint>` - The issues ordered by the token index.
+/// # Example
+/// ```
+/// use bfuck::process_code;
+/// use bfuck::tools::{lint, Lint, LintKind};
+///
+/// let tokens = process_code("+[>+<]").unwrap();
+/// assert_eq!(lint(&tokens), vec![Lint { kind: LintKind::NoOutputProduced, token_index: 0 }]);
+/// ```
+pub fn lint(token_stream: &TokenStream) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    if !token_stream.contains(&Token::Output) {
+        lints.push(Lint { kind: LintKind::NoOutputProduced, token_index: 0 });
+    }
+
+    // whether the last input or output command is the output
+    let mut after_output = false;
+    for (i, token) in token_stream.iter().enumerate() {
+        let kind = match (token, token_stream.get(i + 1)) {
+            (&Token::Move(n), Some(&Token::Move(m))) if (n + m) % STORAGE_SIZE == 0 => Some(LintKind::UnnecessaryMove),
+            (Token::ClearCell, Some(Token::ClearCell)) => Some(LintKind::ConsecutiveClears),
+            (Token::OpenBr(_), _) if possibly_infinite(&token_stream[i + 1..]) => Some(LintKind::PossiblyInfiniteLoop),
+            (Token::Input, _) if after_output => Some(LintKind::InputWithoutFlush),
+            _ => None,
+        };
+        match token {
+            Token::Input => after_output = false,
+            Token::Output => after_output = true,
+            _ => {},
+        }
+
+        // the second clear cell is reported
+        let token_index = if kind == Some(LintKind::ConsecutiveClears) { i + 1 } else { i };
+        if let Some(kind) = kind {
+            lints.push(Lint { kind, token_index });
+        }
+    }
+
+    lints.sort_by_key(|lint| lint.token_index);
+    lints
+}
+
+/// Check whether the loop may never terminate (see [LintKind::PossiblyInfiniteLoop]).
+/// # Arguments
+/// * body - The tokens after the opening bracket of the loop.
+/// # Returns
+/// * `bool` - Whether the loop body is straight code leaving the current cell non-zero or changed by an even value.
+fn possibly_infinite(body: &[Token]) -> bool {
+    // the value set to the cell, or the change of the cell
+    let (mut set, mut change) = (None, 0_u8);
+    for token in body {
+        match *token {
+            Token::Add(n) => change = change.wrapping_add(n),
+            Token::ClearCell | Token::AddTo(_) | Token::AddToCopy(_, _) | Token::AddToCopy3(_, _, _) => (set, change) = (Some(0), 0),
+            Token::SetCell(n) => (set, change) = (Some(n), 0),
+            Token::Output | Token::Debug => {},
+            Token::CloseBr(_) => {
+                return match set {
+                    Some(value) => value.wrapping_add(change) != 0,
+                    None => change % 2 == 0,
+                };
+            },
+            Token::Move(_) | Token::Input | Token::OpenBr(_) => return false,
+        }
+    }
+    false
+}
+
+/// Describe the position of the cell at the given distance (e.g. `2 to the right`).
+/// # Arguments
+/// * n - The distance (modulo [STORAGE_SIZE]).
+/// # Returns
+/// * [String] - The description.
+fn describe_offset(n: usize) -> String {
+    match signed_distance(n) {
+        d if d < 0 => format!("{} to the left", d.unsigned_abs()),
+        d => format!("{} to the right", d),
+    }
+}
+
+/// Push the lines of the code between the brackets, trimmed, indented and broken to the line width.
+/// # Arguments
+/// * lines - The formatted lines.
+/// * segment - The code between the brackets.
+/// * prefix - The indentation of the lines.
+/// * line_width - The maximum number of the characters in a line.
+fn push_lines(lines: &mut Vec<String>, segment: &str, prefix: &str, line_width: usize) {
+    let width = line_width.saturating_sub(prefix.chars().count()).max(1);
+    for line in segment.lines() {
+        let chars: Vec<char> = line.trim().chars().collect();
+        for chunk in chars.chunks(width) {
+            let chunk: String = chunk.iter().collect();
+            if !chunk.trim().is_empty() {
+                lines.push(format!("{}{}", prefix, chunk.trim()));
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpret::interpret_from_str;
+
+    #[test]
+    fn test_minify() {
+        //! Test the `minify` function.
+
+        assert_eq!(minify("+- <>"), Ok(String::new()));
+        assert_eq!(minify("[+ - ]"), Ok(String::from("[+-]")));
+        assert_eq!(minify("+>++--<-"), Ok(String::new()));
+        assert_eq!(minify("+[\n-+ print it .\n]"), Ok(String::from("+[.]")));
+        assert_eq!(minify("[[-]+-]"), Ok(String::from("[[-]]")));
+        assert_eq!(minify("[><+-]"), Ok(String::from("[+-]")));
+        assert_eq!(minify("+[-]-"), Ok(String::from("+[-]-")));
+        assert_eq!(minify("+.-"), Ok(String::from("+.-")));
+
+        // the errors of the original code are returned
+        assert_eq!(minify("+- [ comment"), Err(Error::UnmatchedOpenBr(1, 4)));
+        assert_eq!(minify("+-]"), Err(Error::UnmatchedCloseBr(1, 3)));
+
+        // the minified code runs the same as the original
+        for (code, input) in [
+            (include_str!("../examples/factorial.bf"), &b""[..]),
+            (include_str!("../examples/rot13.bf"), &b"Hello, World!"[..]),
+            (include_str!("../examples/hello-world-comma.bf"), &b""[..]),
+            (include_str!("../examples/fizzbuzz.bf"), &b""[..]),
+        ] {
+            let minified = minify(code).unwrap();
+            assert!(minified.len() < code.len());
+            assert_eq!(interpret_from_str(&minified, input), interpret_from_str(code, input));
+        }
+    }
+
+    #[test]
+    fn test_annotate_bf() {
+        //! Test the `annotate_bf` function.
+
+        let tokens = process_code("+++++>>--,.[-]<<<[->>>+<<<][->+>>>+<<<<][-<+>>+>+<<]").unwrap();
+        assert_eq!(annotate_bf(&tokens), "\
++++++ // add 5 to the cell
+>> // move the pointer right 2
+-- // subtract 2 from the cell
+, // read a byte into the cell
+. // print the cell
+[-] // clear the cell
+<<< // move the pointer left 3
+[->>>+<<<] // add the cell to the cell 3 to the right and clear it
+[->+>>>+<<<<] // add the cell to the cells 1 to the right and 4 to the right and clear it
+[-<+>>+>+<<] // add the cell to the cells 1 to the left and 1 to the right and 2 to the right and clear it
+");
+        assert_eq!(annotate_bf(&vec![Token::SetCell(3), Token::Debug]), "[-]+++ // set the cell to 3\n# // print the debug dump of the cells\n");
+
+        // the loop bodies are indented
+        let tokens = process_code("+[>[.>]<-]").unwrap();
+        assert_eq!(annotate_bf(&tokens), "\
++ // add 1 to the cell
+[ // start the loop (skip it if the cell is zero)
+    > // move the pointer right 1
+    [ // start the loop (skip it if the cell is zero)
+        . // print the cell
+        > // move the pointer right 1
+    ] // repeat the loop if the cell is not zero
+    < // move the pointer left 1
+    - // subtract 1 from the cell
+] // repeat the loop if the cell is not zero
+");
+
+        // the annotated code is the same program
+        for code in [
+            include_str!("../examples/factorial.bf"),
+            include_str!("../examples/rot13.bf"),
+            include_str!("../examples/mandelbrot.bf"),
+        ] {
+            let tokens = process_code(code).unwrap();
+            assert_eq!(process_code(&annotate_bf(&tokens)), Ok(tokens));
+        }
+    }
+
+    #[test]
+    fn test_decompile_to_pseudocode() {
+        //! Test the `decompile_to_pseudocode` function.
+
+        let tokens = process_code(",[.,]").unwrap();
+        assert!(decompile_to_pseudocode(&tokens).contains("while cell[ptr] != 0 {\n"));
+
+        let tokens = process_code("+++>>--<<<[-]++[->>+<<][->+>>>+<<<<][-<+>>+>+<<]+[-[>]<]").unwrap();
+        assert_eq!(decompile_to_pseudocode(&tokens), "\
+cell[ptr] += 3
+ptr += 2
+cell[ptr] -= 2
+ptr -= 3
+cell[ptr] = 2
+cell[ptr+2] += cell[ptr]; cell[ptr] = 0
+cell[ptr+1] += cell[ptr]; cell[ptr+4] += cell[ptr]; cell[ptr] = 0
+cell[ptr-1] += cell[ptr]; cell[ptr+1] += cell[ptr]; cell[ptr+2] += cell[ptr]; cell[ptr] = 0
+cell[ptr] += 1
+while cell[ptr] != 0 {
+    cell[ptr] -= 1
+    while cell[ptr] != 0 {
+        ptr += 1
+    }
+    ptr -= 1
+}
+");
+        assert_eq!(decompile_to_pseudocode(&vec![Token::ClearCell, Token::Debug]), "cell[ptr] = 0\ndebug_dump()\n");
+        assert_eq!(decompile_to_pseudocode(&vec![]), "");
+    }
+
+    #[test]
+    fn test_obfuscate() {
+        //! Test the `obfuscate` function.
+
+        for (code, input) in [
+            (include_str!("../examples/factorial.bf"), &b""[..]),
+            (include_str!("../examples/rot13.bf"), &b"Hello, World!"[..]),
+            (include_str!("../examples/hello-world.bf"), &b""[..]),
+            (include_str!("../examples/fizzbuzz.bf"), &b""[..]),
+            ("+++[-]>++[->+>+<<]<[+].>,[.,]", &b"abc"[..]),
+        ] {
+            let tokens = process_code(code).unwrap();
+            let obfuscated = obfuscate(&tokens).unwrap();
+            assert!(obfuscated.len() > tokens_to_bf(&tokens).len());
+            assert_eq!(interpret_from_str(&obfuscated, input), interpret_from_str(code, input));
+
+            // the same tokens are obfuscated the same way
+            assert_eq!(obfuscate(&tokens), Ok(obfuscated));
+        }
+
+        // the tokens set by hand
+        let tokens = vec![Token::SetCell(200), Token::Output, Token::AddTo(STORAGE_SIZE - 1), Token::SetCell(65), Token::Output, Token::Debug];
+        assert_eq!(interpret_from_str(&obfuscate(&tokens).unwrap(), b""), Ok(vec![200, 65]));
+
+        // the unmatched brackets are reported
+        assert_eq!(obfuscate(&vec![Token::OpenBr(0)]).map_err(|err| matches!(err, Error::UnmatchedOpenBr(_, _))), Err(true));
+    }
+
+    #[test]
+    fn test_lint() {
+        //! Test the `lint` function.
+
+        let lints = |tokens: &[Token]| lint(&tokens.to_vec()).into_iter().map(|lint| (lint.kind, lint.token_index)).collect::<Vec<_>>();
+
+        assert_eq!(lints(&[Token::Output]), vec![]);
+        assert_eq!(lints(&[]), vec![(LintKind::NoOutputProduced, 0)]);
+        assert_eq!(lints(&[Token::Output, Token::Move(3), Token::Move(STORAGE_SIZE - 3), Token::Move(2)]), vec![(LintKind::UnnecessaryMove, 1)]);
+        assert_eq!(lints(&[Token::Output, Token::ClearCell, Token::ClearCell, Token::ClearCell]), vec![(LintKind::ConsecutiveClears, 2), (LintKind::ConsecutiveClears, 3)]);
+
+        // the loops which don't reach zero for some values of the cell
+        for (code, infinite) in [
+            ("+[.]", true), ("+[+-.]", true), ("+[-.-]", true), ("+[[-]+]", true), ("+[-]", false), ("+[---]", false),
+            ("+[>]", false), ("+[,]", false), ("+[-[+]]", false), ("+[[-]]", false), ("+[[-]++]", true), ("+[->+<]", false),
+        ] {
+            let mut tokens = process_code(code).unwrap();
+            tokens.push(Token::Output);
+            let expected = if infinite { vec![(LintKind::PossiblyInfiniteLoop, 1)] } else { vec![] };
+            assert_eq!(lints(&tokens), expected, "code: {}", code);
+        }
+
+        // the input after the output (only the first one)
+        let tokens = process_code(",.,,>.+,").unwrap();
+        assert_eq!(lints(&tokens), vec![(LintKind::InputWithoutFlush, 2), (LintKind::InputWithoutFlush, 7)]);
+
+        // the examples are clean
+        for code in [include_str!("../examples/factorial.bf"), include_str!("../examples/hello-world.bf")] {
+            assert_eq!(lints(&process_code(code).unwrap()), vec![]);
+        }
+
+        let lint = Lint { kind: LintKind::ConsecutiveClears, token_index: 4 };
+        assert_eq!(lint.to_string(), "Token 4: The cell is already cleared.");
+    }
+
+    #[test]
+    fn test_format_bf() {
+        //! Test the `format_bf` function.
+
+        assert_eq!(format_bf("[+]", 80, "  "), Ok(String::from("[\n  +\n]")));
+        assert_eq!(format_bf("+[->[-]<]", 80, "\t"), Ok(String::from("+\n[\n\t->\n\t[\n\t\t-\n\t]\n\t<\n]")));
+        assert_eq!(format_bf("", 80, "  "), Ok(String::new()));
+
+        // the comments are kept, the long lines are broken (each line has at least one character after the indentation)
+        assert_eq!(format_bf("  add two ++\n\n[print .]", 80, "  "), Ok(String::from("add two ++\n[\n  print .\n]")));
+        assert_eq!(format_bf("++++++++[----------]", 6, "  "), Ok(String::from("++++++\n++\n[\n  ----\n  ----\n  --\n]")));
+        assert_eq!(format_bf("[[+++]]", 3, "  "), Ok(String::from("[\n  [\n    +\n    +\n    +\n  ]\n]")));
+
+        // the errors of the original code are returned
+        assert_eq!(format_bf("+[", 80, "  "), Err(Error::UnmatchedOpenBr(1, 2)));
+
+        // the formatted code is the same program
+        for code in [
+            include_str!("../examples/factorial.bf"),
+            include_str!("../examples/rot13.bf"),
+            include_str!("../examples/mandelbrot.bf"),
+            include_str!("../examples/fizzbuzz.bf"),
+        ] {
+            let formatted = format_bf(code, 40, "    ").unwrap();
+            assert!(formatted.lines().all(|line| line.chars().count() <= 40 || line.trim_start().chars().count() == 1));
+            assert_eq!(process_code(&formatted), process_code(code));
+        }
+    }
+}
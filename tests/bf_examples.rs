@@ -1,8 +1,12 @@
+use std::ffi::OsStr;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
 use assert_cmd::cmd::Command;
 use predicates::prelude::*;
 
 
-const RUN_TYPES: [&str; 2] = ["--interpret", "--jit"];
+const RUN_TYPES: [&str; 3] = ["--interpret", "--jit", "--tier=auto"];
 
 const HELLO_WORLD_STDOUT: &str = "Hello World!\n";
 const MANDELBROT_STDOUT: &str = "AAAAAAAAAAAAAAAABBBBBBBBBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDEGFFEEEEDDDDDDCCCCCCCCCBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB\nAAAAAAAAAAAAAAABBBBBBBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDEEEFGIIGFFEEEDDDDDDDDCCCCCCCCCBBBBBBBBBBBBBBBBBBBBBBBBBB\nAAAAAAAAAAAAABBBBBBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDEEEEFFFI KHGGGHGEDDDDDDDDDCCCCCCCCCBBBBBBBBBBBBBBBBBBBBBBB\nAAAAAAAAAAAABBBBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDEEEEEFFGHIMTKLZOGFEEDDDDDDDDDCCCCCCCCCBBBBBBBBBBBBBBBBBBBBB\nAAAAAAAAAAABBBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDEEEEEEFGGHHIKPPKIHGFFEEEDDDDDDDDDCCCCCCCCCCBBBBBBBBBBBBBBBBBB\nAAAAAAAAAABBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDDEEEEEEFFGHIJKS  X KHHGFEEEEEDDDDDDDDDCCCCCCCCCCBBBBBBBBBBBBBBBB\nAAAAAAAAABBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDDEEEEEEFFGQPUVOTY   ZQL[MHFEEEEEEEDDDDDDDCCCCCCCCCCCBBBBBBBBBBBBBB\nAAAAAAAABBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDDEEEEEFFFFFGGHJLZ         UKHGFFEEEEEEEEDDDDDCCCCCCCCCCCCBBBBBBBBBBBB\nAAAAAAABBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDEEEEFFFFFFGGGGHIKP           KHHGGFFFFEEEEEEDDDDDCCCCCCCCCCCBBBBBBBBBBB\nAAAAAAABBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDEEEEEFGGHIIHHHHHIIIJKMR        VMKJIHHHGFFFFFFGSGEDDDDCCCCCCCCCCCCBBBBBBBBB\nAAAAAABBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDEEEEEEFFGHK   MKJIJO  N R  X      YUSR PLV LHHHGGHIOJGFEDDDCCCCCCCCCCCCBBBBBBBB\nAAAAABBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDEEEEEEEEEFFFFGH O    TN S                       NKJKR LLQMNHEEDDDCCCCCCCCCCCCBBBBBBB\nAAAAABBCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDEEEEEEEEEEEEFFFFFGHHIN                                 Q     UMWGEEEDDDCCCCCCCCCCCCBBBBBB\nAAAABBCCCCCCCCCCCCCCCCCCCCCCCCCDDDDEEEEEEEEEEEEEEEFFFFFFGHIJKLOT                                     [JGFFEEEDDCCCCCCCCCCCCCBBBBB\nAAAABCCCCCCCCCCCCCCCCCCCCCCDDDDEEEEEEEEEEEEEEEEFFFFFFGGHYV RQU                                     QMJHGGFEEEDDDCCCCCCCCCCCCCBBBB\nAAABCCCCCCCCCCCCCCCCCDDDDDDDEEFJIHFFFFFFFFFFFFFFGGGGGGHIJN                                            JHHGFEEDDDDCCCCCCCCCCCCCBBB\nAAABCCCCCCCCCCCDDDDDDDDDDEEEEFFHLKHHGGGGHHMJHGGGGGGHHHIKRR                                           UQ L HFEDDDDCCCCCCCCCCCCCCBB\nAABCCCCCCCCDDDDDDDDDDDEEEEEEFFFHKQMRKNJIJLVS JJKIIIIIIJLR                                               YNHFEDDDDDCCCCCCCCCCCCCBB\nAABCCCCCDDDDDDDDDDDDEEEEEEEFFGGHIJKOU  O O   PR LLJJJKL                                                OIHFFEDDDDDCCCCCCCCCCCCCCB\nAACCCDDDDDDDDDDDDDEEEEEEEEEFGGGHIJMR              RMLMN                                                 NTFEEDDDDDDCCCCCCCCCCCCCB\nAACCDDDDDDDDDDDDEEEEEEEEEFGGGHHKONSZ                QPR                                                NJGFEEDDDDDDCCCCCCCCCCCCCC\nABCDDDDDDDDDDDEEEEEFFFFFGIPJIIJKMQ                   VX                                                 HFFEEDDDDDDCCCCCCCCCCCCCC\nACDDDDDDDDDDEFFFFFFFGGGGHIKZOOPPS                                                                      HGFEEEDDDDDDCCCCCCCCCCCCCC\nADEEEEFFFGHIGGGGGGHHHHIJJLNY                                                                        TJHGFFEEEDDDDDDDCCCCCCCCCCCCC\nA                                                                                                 PLJHGGFFEEEDDDDDDDCCCCCCCCCCCCC\nADEEEEFFFGHIGGGGGGHHHHIJJLNY                                                                        TJHGFFEEEDDDDDDDCCCCCCCCCCCCC\nACDDDDDDDDDDEFFFFFFFGGGGHIKZOOPPS                                                                      HGFEEEDDDDDDCCCCCCCCCCCCCC\nABCDDDDDDDDDDDEEEEEFFFFFGIPJIIJKMQ                   VX                                                 HFFEEDDDDDDCCCCCCCCCCCCCC\nAACCDDDDDDDDDDDDEEEEEEEEEFGGGHHKONSZ                QPR                                                NJGFEEDDDDDDCCCCCCCCCCCCCC\nAACCCDDDDDDDDDDDDDEEEEEEEEEFGGGHIJMR              RMLMN                                                 NTFEEDDDDDDCCCCCCCCCCCCCB\nAABCCCCCDDDDDDDDDDDDEEEEEEEFFGGHIJKOU  O O   PR LLJJJKL                                                OIHFFEDDDDDCCCCCCCCCCCCCCB\nAABCCCCCCCCDDDDDDDDDDDEEEEEEFFFHKQMRKNJIJLVS JJKIIIIIIJLR                                               YNHFEDDDDDCCCCCCCCCCCCCBB\nAAABCCCCCCCCCCCDDDDDDDDDDEEEEFFHLKHHGGGGHHMJHGGGGGGHHHIKRR                                           UQ L HFEDDDDCCCCCCCCCCCCCCBB\nAAABCCCCCCCCCCCCCCCCCDDDDDDDEEFJIHFFFFFFFFFFFFFFGGGGGGHIJN                                            JHHGFEEDDDDCCCCCCCCCCCCCBBB\nAAAABCCCCCCCCCCCCCCCCCCCCCCDDDDEEEEEEEEEEEEEEEEFFFFFFGGHYV RQU                                     QMJHGGFEEEDDDCCCCCCCCCCCCCBBBB\nAAAABBCCCCCCCCCCCCCCCCCCCCCCCCCDDDDEEEEEEEEEEEEEEEFFFFFFGHIJKLOT                                     [JGFFEEEDDCCCCCCCCCCCCCBBBBB\nAAAAABBCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDEEEEEEEEEEEEFFFFFGHHIN                                 Q     UMWGEEEDDDCCCCCCCCCCCCBBBBBB\nAAAAABBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDEEEEEEEEEFFFFGH O    TN S                       NKJKR LLQMNHEEDDDCCCCCCCCCCCCBBBBBBB\nAAAAAABBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDEEEEEEFFGHK   MKJIJO  N R  X      YUSR PLV LHHHGGHIOJGFEDDDCCCCCCCCCCCCBBBBBBBB\nAAAAAAABBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDEEEEEFGGHIIHHHHHIIIJKMR        VMKJIHHHGFFFFFFGSGEDDDDCCCCCCCCCCCCBBBBBBBBB\nAAAAAAABBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDEEEEFFFFFFGGGGHIKP           KHHGGFFFFEEEEEEDDDDDCCCCCCCCCCCBBBBBBBBBBB\nAAAAAAAABBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDDEEEEEFFFFFGGHJLZ         UKHGFFEEEEEEEEDDDDDCCCCCCCCCCCCBBBBBBBBBBBB\nAAAAAAAAABBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDDEEEEEEFFGQPUVOTY   ZQL[MHFEEEEEEEDDDDDDDCCCCCCCCCCCBBBBBBBBBBBBBB\nAAAAAAAAAABBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDDEEEEEEFFGHIJKS  X KHHGFEEEEEDDDDDDDDDCCCCCCCCCCBBBBBBBBBBBBBBBB\nAAAAAAAAAAABBBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDEEEEEEFGGHHIKPPKIHGFFEEEDDDDDDDDDCCCCCCCCCCBBBBBBBBBBBBBBBBBB\nAAAAAAAAAAAABBBBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDDDEEEEEFFGHIMTKLZOGFEEDDDDDDDDDCCCCCCCCCBBBBBBBBBBBBBBBBBBBBB\nAAAAAAAAAAAAABBBBBBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDDDEEEEFFFI KHGGGHGEDDDDDDDDDCCCCCCCCCBBBBBBBBBBBBBBBBBBBBBBB\nAAAAAAAAAAAAAAABBBBBBBBBBBBBCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCDDDDDDDDDDEEEFGIIGFFEEEDDDDDDDDCCCCCCCCCBBBBBBBBBBBBBBBBBBBBBBBBBB\n";
@@ -12,6 +16,49 @@ const NAME_STDOUT: &str = "\n     #####      #####\n   #########  #########\n  #
 const PUN_STDOUT: &str = "\n\n\n             What do you call a clown in jail?\n\n                       A silicon!\n\n\n";
 
 
+/// The path of a temporary file, the file is removed when dropped (also when the test fails).
+struct TempFile(PathBuf);
+impl TempFile {
+    /// Create the path of the temporary file (the file itself is created by the test or by `bfuck`).
+    /// # Arguments
+    /// * `name` - The name of the file, unique among the tests.
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("bfuck-test-{}-{}", std::process::id(), name)))
+    }
+
+    /// Create the temporary file with the given contents.
+    /// # Arguments
+    /// * `name` - The name of the file, unique among the tests.
+    /// * `contents` - The contents of the file.
+    fn with_contents(name: &str, contents: impl AsRef<[u8]>) -> std::io::Result<Self> {
+        let file = Self::new(name);
+        std::fs::write(&file, contents)?;
+        Ok(file)
+    }
+}
+impl Deref for TempFile {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+impl AsRef<Path> for TempFile {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+impl AsRef<OsStr> for TempFile {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_os_str()
+    }
+}
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
 fn run_simple_test(bf_file: &str, expected_stdout: &str) -> Result<(), Box<dyn std::error::Error>> {
     for test_case in RUN_TYPES {
         let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
@@ -100,3 +147,287 @@ fn test_name() -> Result<(), Box<dyn std::error::Error>> {
 fn test_pun() -> Result<(), Box<dyn std::error::Error>> {
     run_simple_test("examples/pun.bf", PUN_STDOUT)
 }
+
+#[test]
+fn test_debug() -> Result<(), Box<dyn std::error::Error>> {
+    let bf_file = TempFile::with_contents("debug.bf", "++>+++<#>#[-<+>]<.#<<#")?;
+
+    let expected_stderr = "\
+[DEBUG] ptr=0 cell=2 cells[0..5]: [2] 3 0 0 0
+[DEBUG] ptr=1 cell=3 cells[0..6]: 2 [3] 0 0 0 0
+[DEBUG] ptr=0 cell=5 cells[0..5]: [5] 0 0 0 0
+[DEBUG] ptr=29998 cell=0 cells[29994..30000]: 0 0 0 0 [0] 0
+";
+    for test_case in RUN_TYPES {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg(test_case).arg("--debug").arg(&bf_file);
+        cmd.assert()
+            .success()
+            .stdout(predicate::eq("\x05"))
+            .stderr(predicate::eq(expected_stderr));
+
+        // without the flag, '#' is a comment
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg(test_case).arg(&bf_file);
+        cmd.assert()
+            .success()
+            .stdout(predicate::eq("\x05"))
+            .stderr(predicate::eq(""));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_eof() -> Result<(), Box<dyn std::error::Error>> {
+    // the read values are incremented before printing
+    let bf_file = TempFile::with_contents("eof.bf", ",.>+++++,+.>>++<+++[>,+.-<-]")?;
+
+    let expected = [
+        (None, &b"a\x01\x01\x01\x01"[..]),
+        (Some("zero"), b"a\x01\x01\x01\x01"),
+        (Some("max"), b"a\x00\x00\x00\x00"),
+        (Some("unchanged"), b"a\x06\x03\x03\x03"),
+    ];
+    for test_case in RUN_TYPES {
+        for (policy, expected_stdout) in expected {
+            let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+            cmd.arg(test_case).arg(&bf_file).write_stdin("a");
+            if let Some(policy) = policy {
+                cmd.arg(format!("--eof={}", policy));
+            }
+            cmd.assert()
+                .success()
+                .stdout(predicate::eq(expected_stdout));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_strict_bounds() -> Result<(), Box<dyn std::error::Error>> {
+    let bf_file = TempFile::with_contents("strict-bounds.bf", "+.\n<+.")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("--jit").arg("--strict-bounds").arg(&bf_file);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::eq("\x01"))
+        .stderr(predicate::eq("The data pointer left the array at line 2, column 1.\n"));
+
+    // without the flag, the data pointer wraps around
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("--jit").arg(&bf_file);
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq("\x01\x01"));
+    Ok(())
+}
+
+#[test]
+fn test_stats() -> Result<(), Box<dyn std::error::Error>> {
+    let bf_file = TempFile::with_contents("stats.bf", "++[>+++[>,.<-]<-]")?;
+
+    // the interpreter and the JIT-compiled code report the same statistics
+    let expected_stats = "[STATS] back-edges: 5, bytes read: 4, bytes written: 6\n\
+                          [STATS] loop at token 1: 2 iterations\n\
+                          [STATS] loop at token 4: 6 iterations\n";
+    for backend in ["--interpret", "--jit"] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg(backend).arg("--stats").arg(&bf_file).write_stdin("abcd");
+        cmd.assert()
+            .success()
+            .stdout(predicate::eq("abcd\x00\x00"))
+            .stderr(predicate::eq(expected_stats));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_utf8() -> Result<(), Box<dyn std::error::Error>> {
+    let text_file = TempFile::with_contents("utf8.txt", "Žuti 🐝 zuji\n")?;
+    let bf_file = TempFile::new("utf8.bf");
+
+    // the non-ASCII text is rejected without --utf8
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("--text_cvt").arg(&text_file).arg(&bf_file);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::eq(format!("{}: Non-ASCII character 'Ž' at line 1, column 1.\n", text_file.display())));
+    assert!(!bf_file.exists());
+
+    // the generated code prints the UTF-8 bytes unchanged (with every strategy and target)
+    for (style, target) in [("table", "size"), ("single-cell", "size"), ("auto", "size"), ("table", "runtime"), ("auto", "runtime")] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg("--text_cvt").arg("--utf8").arg(format!("--text-style={}", style)).arg(format!("--text-target={}", target)).arg(&text_file).arg(&bf_file);
+        if style == "auto" {
+            cmd.arg("--text-cleanup");
+        }
+        cmd.assert().success();
+        for test_case in RUN_TYPES {
+            let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+            cmd.arg(test_case).arg(&bf_file);
+            cmd.assert()
+                .success()
+                .stdout(predicate::eq("Žuti 🐝 zuji\n"));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_binary() -> Result<(), Box<dyn std::error::Error>> {
+    let data: Vec<u8> = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n', 0x00, 0xFF, 0xC3, 0x28].to_vec();
+    let data_file = TempFile::with_contents("binary.bin", &data)?;
+    let bf_file = TempFile::new("binary.bf");
+
+    // the invalid UTF-8 is rejected without --binary
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("--text_cvt").arg(&data_file).arg(&bf_file);
+    cmd.assert().failure();
+
+    // the generated code prints the bytes unchanged
+    for style in ["table", "single-cell", "auto"] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg("--text_cvt").arg("--binary").arg(format!("--text-style={}", style)).arg(&data_file).arg(&bf_file);
+        if style == "auto" {
+            cmd.arg("--no-verify");
+        }
+        cmd.assert().success();
+        for test_case in RUN_TYPES {
+            let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+            cmd.arg(test_case).arg(&bf_file);
+            cmd.assert()
+                .success()
+                .stdout(predicate::eq(&data[..]));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_format() -> Result<(), Box<dyn std::error::Error>> {
+    let bf_file = TempFile::new("format.bf");
+
+    // the formatted code is written to stdout and runs the same
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("--format").arg("--line-width=20").arg("examples/hello-world.bf");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let formatted = String::from_utf8(output)?;
+    assert!(formatted.lines().all(|line| line.chars().count() <= 20), "{}", formatted);
+    std::fs::write(&bf_file, formatted)?;
+    for test_case in RUN_TYPES {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg(test_case).arg(&bf_file);
+        cmd.assert()
+            .success()
+            .stdout(predicate::eq(HELLO_WORLD_STDOUT));
+    }
+
+    // the unmatched brackets are reported
+    std::fs::write(&bf_file, "+[\n")?;
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("--format").arg(&bf_file);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::eq(format!("{}: Unmatched '[' at line 1, column 2.\n", bf_file.display())));
+
+    // the errors of every mode are prefixed with the file name
+    for test_case in RUN_TYPES {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg(test_case).arg(&bf_file);
+        cmd.assert()
+            .failure()
+            .stderr(predicate::eq(format!("{}: Unmatched '[' at line 1, column 2.\n", bf_file.display())));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_annotate() -> Result<(), Box<dyn std::error::Error>> {
+    let bf_file = TempFile::new("annotate.bf");
+
+    // the annotated code is written to stdout and runs the same
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("--annotate").arg("examples/hello-world.bf");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let annotated = String::from_utf8(output)?;
+    assert!(annotated.lines().all(|line| line.contains(" // ")), "{}", annotated);
+    std::fs::write(&bf_file, annotated)?;
+    for test_case in RUN_TYPES {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg(test_case).arg(&bf_file);
+        cmd.assert()
+            .success()
+            .stdout(predicate::eq(HELLO_WORLD_STDOUT));
+    }
+
+    // the debug instructions are annotated with --debug
+    std::fs::write(&bf_file, "+#")?;
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("--annotate").arg("--debug").arg(&bf_file);
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq("+ // add 1 to the cell\n# // print the debug dump of the cells\n"));
+    Ok(())
+}
+
+#[test]
+fn test_lint() -> Result<(), Box<dyn std::error::Error>> {
+    let bf_file = TempFile::new("lint.bf");
+
+    // the clean program has no issues
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("--lint").arg("examples/hello-world.bf");
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq(""));
+
+    // all the issues are printed
+    std::fs::write(&bf_file, "+[+-]>,")?;
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("--lint").arg(&bf_file);
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq("Token 0: The program produces no output.\nToken 1: The loop may never terminate.\n"));
+    Ok(())
+}
+
+#[test]
+fn test_warnings() -> Result<(), Box<dyn std::error::Error>> {
+    // the loop is never entered, so the program terminates despite the warning
+    let bf_file = TempFile::with_contents("warnings.bf", "+.[-]\n[+-]")?;
+
+    for test_case in RUN_TYPES {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg(test_case).arg(&bf_file);
+        cmd.assert()
+            .success()
+            .stdout(predicate::eq("\x01"))
+            .stderr(predicate::eq(format!("{}: Warning: The loop at line 2, column 1 never terminates once entered.\n", bf_file.display())));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_newlines() -> Result<(), Box<dyn std::error::Error>> {
+    let text_file = TempFile::with_contents("newlines.txt", "Windows\r\nUnix\nMac\r")?;
+    let bf_file = TempFile::new("newlines.bf");
+
+    for (newlines, expected) in [
+        ("preserve", "Windows\r\nUnix\nMac\r"),
+        ("crlf-to-lf", "Windows\nUnix\nMac\r"),
+        ("lf", "Windows\nUnix\nMac"),
+        ("crlf", "Windows\r\nUnix\r\nMac"),
+    ] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg("--text_cvt").arg(format!("--newlines={}", newlines)).arg(&text_file).arg(&bf_file);
+        cmd.assert().success();
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.arg("--interpret").arg(&bf_file);
+        cmd.assert()
+            .success()
+            .stdout(predicate::eq(expected));
+    }
+    Ok(())
+}
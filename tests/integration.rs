@@ -0,0 +1,291 @@
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+use std::thread;
+
+use bfuck::{interpret_from_str, jit_from_str, Error};
+use bfuck::code::{process_code, FinalState, Token};
+use bfuck::interpret::{interpret, interpret_const, interpret_profiled, interpret_with_config, run_interpret, InterpreterConfig, STANDARD_SIZE};
+use bfuck::io::{IoAdapter, VecIo};
+use bfuck::jit::{JitOptions, JitProgram};
+
+
+const ROT13: &str = include_str!("../examples/rot13.bf");
+const FACTORIAL: &str = include_str!("../examples/factorial.bf");
+const HELLO_WORLD_COMMA: &str = include_str!("../examples/hello-world-comma.bf");
+const FIZZBUZZ: &str = include_str!("../examples/fizzbuzz.bf");
+
+
+/// Run the code with the input using both the interpreter and the JIT-compiler and check the outputs.
+fn run_test(code: &str, input: &[u8], expected_output: &str) {
+    let tokens = process_code(code).unwrap();
+
+    let mut io = VecIo::new(input);
+    interpret(&tokens, &mut io);
+    assert_eq!(String::from_utf8_lossy(&io.output), expected_output, "interpreter");
+
+    let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+    let mut io = VecIo::new(input);
+    program.run_with_io(&mut io);
+    assert_eq!(String::from_utf8_lossy(&io.output), expected_output, "JIT");
+}
+
+
+#[test]
+fn test_rot13() {
+    //! Test the ROT13 transformer.
+
+    run_test(ROT13, b"Hello World", "Uryyb Jbeyq");
+    run_test(ROT13, b"Uryyb Jbeyq", "Hello World");
+}
+
+#[test]
+fn test_factorial() {
+    //! Test the factorial of 5.
+
+    run_test(FACTORIAL, b"", "120\n");
+}
+
+#[test]
+fn test_hello_world_comma() {
+    //! Test the hand-written "Hello, World!".
+
+    run_test(HELLO_WORLD_COMMA, b"", "Hello, World!");
+}
+
+#[test]
+fn test_fizzbuzz() {
+    //! Test the FizzBuzz for numbers from 1 to 20.
+
+    let expected_output: String = (1..=20)
+        .map(|i| match (i % 3, i % 5) {
+            (0, 0) => String::from("FizzBuzz\n"),
+            (0, _) => String::from("Fizz\n"),
+            (_, 0) => String::from("Buzz\n"),
+            _ => format!("{}\n", i),
+        })
+        .collect();
+    run_test(FIZZBUZZ, b"", &expected_output);
+}
+
+#[test]
+fn test_concurrent_runs() {
+    //! Test that one compiled program runs correctly from eight threads at once, each with its own input.
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<JitProgram>();
+
+    let program = JitProgram::compile(&process_code(ROT13).unwrap(), &JitOptions::default()).unwrap();
+    let inputs: Vec<String> = (0..8).map(|i| format!("Thread {} says hello to the world", i)).collect();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = inputs.iter()
+            .map(|input| {
+                let program = &program;
+                scope.spawn(move || {
+                    // run repeatedly, so the runs of different threads overlap
+                    (0..50)
+                        .map(|_| {
+                            let mut io = VecIo::new(input.as_bytes());
+                            program.run_with_io(&mut io);
+                            io.output
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        for (input, handle) in inputs.iter().zip(handles) {
+            let mut io = VecIo::new(input.as_bytes());
+            interpret(&process_code(ROT13).unwrap(), &mut io);
+            for output in handle.join().unwrap() {
+                assert_eq!(output, io.output, "input: {}", input);
+            }
+        }
+    });
+}
+
+#[test]
+fn test_interpret_const() {
+    //! Test the interpreter with the array of the size given at compile time.
+
+    let run = |code: &str| {
+        let mut io = VecIo::default();
+        let state = interpret_const::<5>(&process_code(code).unwrap(), &mut io);
+        (state, io.output)
+    };
+
+    // five moves in either direction return to the start
+    assert_eq!(run("+>>>>>."), (FinalState { data_ptr: 0, cell: 1 }, vec![1]));
+    assert_eq!(run("+<<<<<."), (FinalState { data_ptr: 0, cell: 1 }, vec![1]));
+    assert_eq!(run("+>>>>>>>>>>>>"), (FinalState { data_ptr: 2, cell: 0 }, vec![]));
+
+    // the moves to the left wrap to the end of the array, also in the add to loops
+    assert_eq!(run("<+++.>+.<<<<<<."), (FinalState { data_ptr: 4, cell: 3 }, vec![3, 1, 3]));
+    assert_eq!(run("+++[-<<+>>]>>>."), (FinalState { data_ptr: 3, cell: 3 }, vec![3]));
+
+    // the standard size is the same as the default interpreter
+    for code in [FACTORIAL, "<<<+[>+]<."] {
+        let tokens = process_code(code).unwrap();
+        let (mut const_io, mut io) = (VecIo::default(), VecIo::default());
+        assert_eq!(interpret_const::<STANDARD_SIZE>(&tokens, &mut const_io), interpret(&tokens, &mut io));
+        assert_eq!(const_io, io);
+    }
+}
+
+#[test]
+fn test_run_interpret() {
+    //! Test collecting the output and the final machine state in one call.
+
+    let result = run_interpret(process_code(",+.").unwrap(), &[65]).unwrap();
+    assert_eq!(result.output, [66]);
+    assert_eq!(result.final_data_ptr, 0);
+    assert_eq!(result.final_tape[0], 66);
+    assert_eq!(result.final_tape.len(), STANDARD_SIZE);
+
+    // the same output as the interpreter, the final tape holds the cells the program left behind
+    let tokens = process_code(FACTORIAL).unwrap();
+    let mut io = VecIo::default();
+    let state = interpret(&tokens, &mut io);
+    let result = run_interpret(tokens, b"").unwrap();
+    assert_eq!(result.output, io.output);
+    assert_eq!((result.final_data_ptr, result.final_tape[result.final_data_ptr]), (state.data_ptr, state.cell));
+}
+
+#[test]
+fn test_from_str() {
+    //! Test running the code given as a string with the interpreter and the JIT-compiler.
+
+    assert_eq!(interpret_from_str(",[.,]", b"hello"), Ok(b"hello".to_vec()));
+    assert_eq!(jit_from_str(",[.,]", b"hello"), Ok(b"hello".to_vec()));
+    assert_eq!(interpret_from_str(ROT13, b"Hello"), jit_from_str(ROT13, b"Hello"));
+
+    // the parse errors are returned by both
+    assert_eq!(interpret_from_str("+[.", b""), Err(Error::UnmatchedOpenBr(1, 2)));
+    assert_eq!(jit_from_str("+.]", b""), Err(Error::UnmatchedCloseBr(1, 3)));
+}
+
+#[test]
+fn test_interpret_with_config() {
+    //! Test the interpreter starting with the given data pointer and cells.
+
+    let config = InterpreterConfig { initial_data_ptr: 2, initial_tape: vec![0, 0, 5], ..InterpreterConfig::default() };
+    let tokens = process_code(".[-].").unwrap();
+    let mut io = VecIo::default();
+    assert_eq!(interpret_with_config(&tokens, &mut io, &config), Ok(FinalState { data_ptr: 2, cell: 0 }));
+    assert_eq!(io.output, [5, 0]);
+
+    // the data pointer starting at the last cell wraps to the first one
+    let config = InterpreterConfig { initial_data_ptr: STANDARD_SIZE - 1, ..InterpreterConfig::default() };
+    let tokens = process_code("+>++").unwrap();
+    assert_eq!(interpret_with_config(&tokens, &mut VecIo::default(), &config), Ok(FinalState { data_ptr: 0, cell: 2 }));
+
+    // the default configuration is the same as the interpreter
+    let tokens = process_code(FACTORIAL).unwrap();
+    let (mut config_io, mut io) = (VecIo::default(), VecIo::default());
+    assert_eq!(interpret_with_config(&tokens, &mut config_io, &InterpreterConfig::default()), Ok(interpret(&tokens, &mut io)));
+    assert_eq!(config_io, io);
+
+    // the data pointer and the tape must fit in the array
+    let config = InterpreterConfig { initial_data_ptr: STANDARD_SIZE, ..InterpreterConfig::default() };
+    assert_eq!(interpret_with_config(&tokens, &mut VecIo::default(), &config), Err(Error::InvalidDataPointer(STANDARD_SIZE)));
+    let config = InterpreterConfig { initial_tape: vec![1; STANDARD_SIZE + 1], ..InterpreterConfig::default() };
+    assert_eq!(interpret_with_config(&tokens, &mut VecIo::default(), &config), Err(Error::TapeTooLong(STANDARD_SIZE + 1)));
+}
+
+#[test]
+fn test_interpret_profiled() {
+    //! Test counting the executed tokens of each variant.
+
+    // the additions are merged into one token
+    let tokens = process_code("+++.").unwrap();
+    let mut io = VecIo::default();
+    let counts = interpret_profiled::<true>(&tokens, &mut io);
+    for (i, &count) in counts.iter().enumerate() {
+        let expected = u64::from(i == Token::Add(0).variant_index() || i == Token::Output.variant_index());
+        assert_eq!(count, expected, "variant {}", i);
+    }
+    assert_eq!(io.output, [3]);
+
+    // without profiling nothing is counted, but the program runs the same
+    let tokens = process_code(FACTORIAL).unwrap();
+    let (mut profiled_io, mut unprofiled_io, mut io) = (VecIo::default(), VecIo::default(), VecIo::default());
+    let counts = interpret_profiled::<true>(&tokens, &mut profiled_io);
+    assert_eq!(interpret_profiled::<false>(&tokens, &mut unprofiled_io), [0; Token::VARIANT_COUNT]);
+    interpret(&tokens, &mut io);
+    assert_eq!(profiled_io, io);
+    assert_eq!(unprofiled_io, io);
+    assert_eq!(counts[Token::Output.variant_index()], io.output.len() as u64);
+}
+
+/// The [IoAdapter] buffering the output until it is flushed.
+#[derive(Default)]
+struct BufferedIo {
+    /// The output written since the last flush.
+    buffer: Vec<u8>,
+    /// The flushed output.
+    output: Vec<u8>,
+    /// The number of the flushes.
+    flushes: usize,
+    /// The length of the flushed output at each read.
+    flushed_at_reads: Vec<usize>,
+}
+impl IoAdapter for BufferedIo {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.flushed_at_reads.push(self.output.len());
+        None
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.buffer.push(byte);
+    }
+
+    fn flush(&mut self) {
+        self.flushes += 1;
+        self.output.append(&mut self.buffer);
+    }
+}
+
+#[test]
+fn test_flush() {
+    //! Test that the output is flushed when the program finishes (and by the JIT-compiled code also before every read).
+
+    // the end of input clears the cell
+    let tokens = process_code("+.+.,+.").unwrap();
+
+    let mut io = BufferedIo::default();
+    interpret(&tokens, &mut io);
+    assert_eq!((io.output, io.buffer, io.flushes), (vec![1, 2, 1], Vec::new(), 1));
+
+    let program = JitProgram::compile(&tokens, &JitOptions::default()).unwrap();
+    let mut io = BufferedIo::default();
+    program.run_with_io(&mut io);
+    assert_eq!((io.output, io.buffer, io.flushes), (vec![1, 2, 1], Vec::new(), 2));
+    assert_eq!(io.flushed_at_reads, [2]);
+}
+
+#[test]
+fn test_cli_compile() {
+    //! Test compiling the example with the command line tool and running the executable.
+
+    // skip the test if there is no C compiler to link with
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+
+    let src_file = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/hello-world-comma.bf");
+    let dst_file = env::temp_dir().join(format!("bfuck-test-cli-compile-{}", process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_bfuck")).arg("--compile").arg(src_file).arg(&dst_file).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("done"));
+
+    let output = Command::new(&dst_file).output().unwrap();
+    fs::remove_file(&dst_file).unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello, World!");
+
+    // the errors are reported with the failure status
+    let output = Command::new(env!("CARGO_BIN_EXE_bfuck")).arg("--compile").arg(src_file).arg(&dst_file).arg("--target").arg("no-such-target").output().unwrap();
+    assert!(!output.status.success());
+    assert!(!dst_file.exists());
+}
@@ -0,0 +1,305 @@
+use proptest::prelude::*;
+
+use bfuck::code::{process_code, process_code_with_pipeline, rle_decode, rle_encode, split_at_loops, tokens_to_bf, FinalState, OptimizationPipeline, Token, STORAGE_SIZE};
+use bfuck::interpret::{interpret_with_eof, interpret_with_stats, run_interpret};
+use bfuck::io::{EofPolicy, VecIo};
+use bfuck::jit::{CellWidth, JitOptions, JitProgram};
+use bfuck::text::{number_2_bf, number_2_bf_in_place, number_cost};
+use bfuck::tools::obfuscate;
+
+
+/// The inputs on which the programs are compared.
+const INPUTS: [&[u8]; 5] = [b"", b"a", b"\x00\x01\xff", b"hello world", &[200; 8]];
+
+/// The maximal nesting depth of the generated loops (keeps the number of executed instructions small).
+const MAX_DEPTH: usize = 2;
+
+/// Comment characters (ignored by the parser).
+const COMMENTS: [char; 4] = [' ', '\n', 'x', '#'];
+
+/// The maximal number of commands executed by the reference interpreter with the wide cells
+/// (the loop counters which wrap below zero would run for too long).
+const MAX_WIDE_STEPS: usize = 100_000;
+
+
+/// Build a random Brainfuck program from the choices.
+///
+/// Every generated program terminates: each loop changes its counter cell only by a single `-`
+/// (at the start or at the end of the body), and the body only touches the cells to the right of the counter
+/// and returns the data pointer back to it.
+fn build_program(choices: &[u8]) -> String {
+    let mut choices = choices.iter().copied();
+    let mut code = String::new();
+    while let Some(choice) = choices.next() {
+        match choice % 10 {
+            0 => code.push('+'),
+            1 => code.push('-'),
+            2 => code.push('<'),
+            3 => code.push('>'),
+            4 => code.push(','),
+            5 => code.push('.'),
+            6 => code.push(COMMENTS[choice as usize / 10 % COMMENTS.len()]),
+            _ => build_loop(&mut choices, &mut code, MAX_DEPTH),
+        }
+    }
+    code
+}
+
+/// Build a terminating loop (see [build_program]) which consumes some of the choices.
+fn build_loop(choices: &mut impl Iterator<Item = u8>, code: &mut String, depth: usize) {
+    let decrement_first = choices.next().unwrap_or(0) % 2 == 0;
+    let body_length = choices.next().unwrap_or(0) % 8;
+
+    code.push('[');
+    if decrement_first {
+        code.push('-');
+    }
+
+    // offset of the data pointer from the counter cell
+    let mut offset = 0;
+    for _ in 0..body_length {
+        let choice = choices.next().unwrap_or(0);
+        match choice % 9 {
+            0 | 1 => {
+                code.push('>');
+                offset += 1;
+            },
+            2 if offset > 1 => {
+                code.push('<');
+                offset -= 1;
+            },
+            _ if offset == 0 => {
+                code.push('>');
+                offset += 1;
+            },
+            2 | 3 => code.push('+'),
+            4 => code.push('-'),
+            5 => code.push(','),
+            6 => code.push('.'),
+            7 => code.push(COMMENTS[choice as usize / 9 % COMMENTS.len()]),
+            _ => {
+                if depth > 1 {
+                    build_loop(choices, code, depth - 1);
+                } else {
+                    code.push('+');
+                }
+            },
+        }
+    }
+    code.push_str(&"<".repeat(offset));
+
+    if !decrement_first {
+        code.push('-');
+    }
+    code.push(']');
+}
+
+/// Strategy generating arbitrary tokens (with small payloads, so the runs of identical tokens are common).
+fn token() -> impl Strategy<Value = Token> {
+    prop_oneof![
+        (0..3_u8).prop_map(Token::Add),
+        (0..3_usize).prop_map(Token::Move),
+        Just(Token::Input),
+        Just(Token::Output),
+        (0..3_usize).prop_map(Token::OpenBr),
+        (0..3_usize).prop_map(Token::CloseBr),
+        Just(Token::ClearCell),
+        (0..3_usize).prop_map(Token::AddTo),
+        (0..2_usize, 0..2_usize).prop_map(|(n1, n2)| Token::AddToCopy(n1, n2)),
+        Just(Token::Debug),
+        (0..3_u8).prop_map(Token::SetCell),
+        (0..2_usize, 0..2_usize, 0..2_usize).prop_map(|(n1, n2, n3)| Token::AddToCopy3(n1, n2, n3)),
+    ]
+}
+
+/// Strategy generating the EOF policies.
+fn eof_policy() -> impl Strategy<Value = EofPolicy> {
+    prop_oneof![Just(EofPolicy::Zero), Just(EofPolicy::Max), Just(EofPolicy::Unchanged)]
+}
+
+/// Strategy generating the cell widths.
+fn cell_width() -> impl Strategy<Value = CellWidth> {
+    prop_oneof![Just(CellWidth::U8), Just(CellWidth::U16), Just(CellWidth::U32)]
+}
+
+/// Run the program on the input (with the EOF policy) and return the output and the final state.
+fn run(code: &str, input: &[u8], eof: EofPolicy) -> (Vec<u8>, FinalState) {
+    let mut io = VecIo::new(input);
+    let state = interpret_with_eof(&process_code(code).unwrap(), &mut io, eof);
+    (io.output, state)
+}
+
+/// Run the program with the cells of given width directly from the source (the reference for the JIT).
+/// # Returns
+/// * The output and the final state, if the program finished within [MAX_WIDE_STEPS] commands.
+fn run_wide(code: &str, input: &[u8], eof: EofPolicy, cell_width: CellWidth) -> Option<(Vec<u8>, FinalState)> {
+    let mask = (u64::MAX >> (64 - 8 * cell_width.bytes())) as u32;
+    let code = code.as_bytes();
+    let mut storage = vec![0_u32; STORAGE_SIZE];
+    let mut data_ptr = 0;
+    let mut input = input.iter();
+    let mut output = Vec::new();
+
+    let (mut i, mut steps) = (0, 0);
+    while i < code.len() {
+        steps += 1;
+        if steps > MAX_WIDE_STEPS {
+            return None;
+        }
+        match code[i] {
+            b'+' => storage[data_ptr] = storage[data_ptr].wrapping_add(1) & mask,
+            b'-' => storage[data_ptr] = storage[data_ptr].wrapping_sub(1) & mask,
+            b'>' => data_ptr = (data_ptr + 1) % STORAGE_SIZE,
+            b'<' => data_ptr = (data_ptr + STORAGE_SIZE - 1) % STORAGE_SIZE,
+            b'.' => output.push(storage[data_ptr] as u8),
+            b',' => match (input.next(), eof) {
+                (Some(&byte), _) => storage[data_ptr] = u32::from(byte),
+                (None, EofPolicy::Zero) => storage[data_ptr] = 0,
+                (None, EofPolicy::Max) => storage[data_ptr] = mask,
+                (None, EofPolicy::Unchanged) => {},
+            },
+            b'[' if storage[data_ptr] == 0 => i = matching_bracket(code, i),
+            b']' if storage[data_ptr] != 0 => i = matching_bracket(code, i),
+            _ => {},
+        }
+        i += 1;
+    }
+    Some((output, FinalState { data_ptr, cell: storage[data_ptr] as u8 }))
+}
+
+/// Find the bracket matching the bracket at the index (the brackets of the generated programs are always matched).
+fn matching_bracket(code: &[u8], index: usize) -> usize {
+    let (step, mut depth) = if code[index] == b'[' { (1, 0_isize) } else { (-1, 0) };
+    let mut i = index as isize;
+    loop {
+        match code[i as usize] {
+            b'[' => depth += 1,
+            b']' => depth -= 1,
+            _ => {},
+        }
+        if depth == 0 {
+            return i as usize;
+        }
+        i += step;
+    }
+}
+
+
+proptest! {
+    /// Test that processing the valid Brainfuck code succeeds.
+    #[test]
+    fn test_process_code_succeeds(choices in prop::collection::vec(any::<u8>(), 0..64)) {
+        let code = build_program(&choices);
+        prop_assert!(process_code(&code).is_ok());
+    }
+
+    /// Test that the code regenerated from the tokens is observationally equivalent to the original code.
+    #[test]
+    fn test_tokens_to_bf_round_trip(choices in prop::collection::vec(any::<u8>(), 0..64), eof in eof_policy()) {
+        let code = build_program(&choices);
+        let regenerated = tokens_to_bf(&process_code(&code).unwrap());
+
+        for input in INPUTS {
+            prop_assert_eq!(run(&code, input, eof), run(&regenerated, input, eof), "code: {:?}, regenerated: {:?}", code, regenerated);
+        }
+    }
+
+    /// Test that the JIT-compiled programs (with the loops folded or unrolled) match the interpreter (with any EOF policy).
+    #[test]
+    fn test_jit_matches_interpreter(
+        choices in prop::collection::vec(any::<u8>(), 0..64),
+        unroll_limit in 0..20_usize,
+        eof in eof_policy(),
+    ) {
+        let code = build_program(&choices);
+        let tokens = process_code(&code).unwrap();
+        let program = JitProgram::compile(&tokens, &JitOptions { unroll_limit, eof, ..JitOptions::default() }).unwrap();
+
+        for input in INPUTS {
+            let mut io = VecIo::new(input);
+            let state = program.run_with_io(&mut io);
+            prop_assert_eq!((io.output, state), run(&code, input, eof), "code: {:?}, policy: {:?}", code, eof);
+        }
+    }
+
+    /// Test that the instrumented JIT-compiled programs collect the same statistics as the interpreter.
+    #[test]
+    fn test_jit_stats_match_interpreter(choices in prop::collection::vec(any::<u8>(), 0..64), eof in eof_policy()) {
+        let code = build_program(&choices);
+        let tokens = process_code(&code).unwrap();
+        let program = JitProgram::compile(&tokens, &JitOptions { instrument: true, eof, ..JitOptions::default() }).unwrap();
+
+        for input in INPUTS {
+            let mut jit_io = VecIo::new(input);
+            let mut interpret_io = VecIo::new(input);
+            let expected = interpret_with_stats(&tokens, &mut interpret_io, eof);
+            prop_assert_eq!(program.run_instrumented(&mut jit_io).unwrap(), expected, "code: {:?}", code);
+            prop_assert_eq!(jit_io, interpret_io, "code: {:?}", code);
+        }
+    }
+
+    /// Test that the JIT-compiled programs with the cells of any width match the reference interpreter.
+    #[test]
+    fn test_jit_cell_widths(
+        choices in prop::collection::vec(any::<u8>(), 0..64),
+        cell_width in cell_width(),
+        eof in eof_policy(),
+    ) {
+        let code = build_program(&choices);
+        let tokens = process_code_with_pipeline(&code, &OptimizationPipeline::wide_cells()).unwrap();
+        let program = JitProgram::compile(&tokens, &JitOptions { cell_width, eof, ..JitOptions::default() }).unwrap();
+
+        for input in INPUTS {
+            // the programs whose counters wrap below zero run too long with the wide cells
+            let Some(expected) = run_wide(&code, input, eof, cell_width) else {
+                continue;
+            };
+            let mut io = VecIo::new(input);
+            let state = program.run_with_io(&mut io);
+            prop_assert_eq!((io.output, state), expected, "code: {:?}, width: {:?}, policy: {:?}", code, cell_width, eof);
+        }
+    }
+
+    /// Test that the obfuscated code is observationally equivalent to the original code.
+    #[test]
+    fn test_obfuscate_equivalent(choices in prop::collection::vec(any::<u8>(), 0..64), eof in eof_policy()) {
+        let code = build_program(&choices);
+        let obfuscated = obfuscate(&process_code(&code).unwrap()).unwrap();
+
+        for input in INPUTS {
+            prop_assert_eq!(run(&code, input, eof), run(&obfuscated, input, eof), "code: {:?}, obfuscated: {:?}", code, obfuscated);
+        }
+    }
+
+    /// Test that the number is built in the current cell, the scratch cells are left zero and the cells around
+    /// are left unchanged by the in-place code.
+    #[test]
+    fn test_number_2_bf(n in any::<u8>(), left in any::<u8>(), right in any::<u8>()) {
+        let bf_code = number_2_bf(n);
+        prop_assert_eq!(bf_code.len(), number_cost(n));
+        let result = run_interpret(process_code(&format!(">{}", bf_code)).unwrap(), b"").unwrap();
+        prop_assert_eq!(result.final_data_ptr, 1);
+        prop_assert_eq!(&result.final_tape[..4], &[0, n, 0, 0]);
+
+        let around = format!("{}>>{}<", "+".repeat(left as usize), "+".repeat(right as usize));
+        let result = run_interpret(process_code(&format!("{}{}", around, number_2_bf_in_place(n))).unwrap(), b"").unwrap();
+        prop_assert_eq!(result.final_data_ptr, 1);
+        prop_assert_eq!(&result.final_tape[..3], &[left, n, right]);
+    }
+
+    /// Test that the concatenation of the top-level segments equals the original tokens.
+    #[test]
+    fn test_split_at_loops_concat(choices in prop::collection::vec(any::<u8>(), 0..64)) {
+        let tokens = process_code(&build_program(&choices)).unwrap();
+        prop_assert_eq!(split_at_loops(&tokens).concat(), tokens);
+    }
+
+    /// Test that the run-length decoding is the inverse of the encoding and the runs are maximal.
+    #[test]
+    fn test_rle_round_trip(tokens in prop::collection::vec(token(), 0..64)) {
+        let rle = rle_encode(&tokens);
+        prop_assert_eq!(rle_decode(&rle), tokens);
+        prop_assert!(rle.iter().all(|&(_, count)| count > 0));
+        prop_assert!(rle.windows(2).all(|runs| runs[0].0 != runs[1].0));
+    }
+}